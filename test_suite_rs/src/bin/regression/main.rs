@@ -1,15 +1,25 @@
-mod fair_server;
-mod sched_deadline;
-mod sched_fifo;
+use hcbs_test_suite::prelude::SnapshotGuard;
+use hcbs_test_suite::tests::regression::*;
 
 #[derive(clap::Parser, Debug)]
 #[command(about, long_about = None)]
+pub struct Args {
+    #[command(flatten)]
+    results: hcbs_test_suite::prelude::ResultsArgs,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(clap::Subcommand, Debug)]
 pub enum Command {
     /// Stress test the fair deadline servers
     ///
     /// This test creates a set of SCHED_FIFO and SCHED_OTHER cpu-bound tasks,
     /// and asserts that the SCHED_OTHER process get at least the minimum amount
-    /// of bandwidth that is reserved to non-real-time tasks.
+    /// of bandwidth that is reserved to non-real-time tasks, on each CPU. The
+    /// reserved bandwidth is the one of the fair servers, or of the ext
+    /// servers if a sched_ext scheduler is loaded.
     #[command(name = "fair-server", verbatim_doc_comment)]
     FairServer(fair_server::MyArgs),
 
@@ -24,6 +34,61 @@ pub enum Command {
     #[command(name = "deadline", verbatim_doc_comment)]
     SchedDeadline(sched_deadline::MyArgs),
 
+    /// Admission control of cgroups vs SCHED_DEADLINE
+    ///
+    /// This test checks that the bandwidth reserved by a cgroup is accounted
+    /// by the SCHED_DEADLINE admission control: with the cgroup reserving its
+    /// runtime, fewer SCHED_DEADLINE tasks must be admitted, and growing the
+    /// cgroup's runtime must be rejected while SCHED_DEADLINE tasks hold the
+    /// remaining bandwidth.
+    ///
+    /// Constraints: runtime / period + dl-runtime / dl-period <= 1
+    #[command(name = "deadline-admission", verbatim_doc_comment)]
+    DeadlineAdmission(deadline_admission::MyArgs),
+
+    /// Legacy RT sysctls vs root cgroup's bandwidth
+    ///
+    /// This test changes the sched_rt_runtime_us/sched_rt_period_us sysctls
+    /// and the root cgroup's runtime, in both orders, with a child cgroup
+    /// reserving its bandwidth. It checks which writes are rejected, reports
+    /// which ones are propagated between the sysctls and the root cgroup, and
+    /// checks that no accepted write breaks the reservations. It then measures
+    /// a child cgroup's RT task, a root cgroup's RT task and a SCHED_OTHER
+    /// task on the same cpu, expecting them to get, under the resulting
+    /// limits, the child's bandwidth, the rest of the RT bandwidth (net of the
+    /// fair or ext server's one) and the rest of the cpu respectively.
+    ///
+    /// Constraints: runtime / period + server's bandwidth <= global RT bandwidth - 0.02
+    #[command(name = "rt-sysctl", verbatim_doc_comment)]
+    RtSysctl(rt_sysctl::MyArgs),
+
+    /// Priority inheritance across cgroups
+    ///
+    /// This test runs a low priority task inside a cgroup which periodically
+    /// holds a priority inheritance mutex (PTHREAD_PRIO_INHERIT), a middle
+    /// priority task in the same cgroup which runs while the mutex is held,
+    /// and a high priority SCHED_FIFO task in the root cgroup which locks the
+    /// mutex, all on the same cpu. The root task's blocking time must not
+    /// exceed the time needed by the cgroup's server to supply the critical
+    /// section's CPU time: cs + (ceil(cs / runtime) + 1) * (period - runtime).
+    ///
+    /// Constraints: 0 < runtime <= period
+    #[command(name = "priority-inheritance", verbatim_doc_comment)]
+    PriorityInheritance(priority_inheritance::MyArgs),
+
+    /// SCHED_OTHER tasks inside cgroups
+    ///
+    /// This test runs a SCHED_RR task and a SCHED_OTHER task in the same
+    /// cgroup, on the same cpu, optionally setting the cgroup's cpu.max and
+    /// cpu.weight (the latter with a competing SCHED_OTHER task in the root
+    /// cgroup). The RT task must get exactly the cgroup's RT bandwidth, while
+    /// the SCHED_OTHER tasks must share the remaining bandwidth according to
+    /// their weights and to cpu.max, and must not consume the RT reservation.
+    ///
+    /// Constraints: runtime <= period
+    #[command(name = "sched-other", verbatim_doc_comment)]
+    SchedOther(sched_other::MyArgs),
+
     /// Stress test on cgroups vs SCHED_FIFO
     ///
     /// This test creates a number of SCHED_FIFO tasks to run on the global
@@ -39,14 +104,27 @@ pub enum Command {
 fn main() -> anyhow::Result<()> {
     env_logger::init();
 
-    let args = <Command as clap::Parser>::parse();
+    let args = <Args as clap::Parser>::parse();
+    unsafe { args.results.apply()?; }
     hcbs_utils::cgroup::mount_cgroup_cpu()?;
 
+    // restore the scheduler state modified by the test, also on error
+    let snapshot = SnapshotGuard::new()?;
+
     use Command::*;
 
-    match args {
-        FairServer(args) => fair_server::batch_runner(args, None).map(|_| ()),
-        SchedDeadline(args) => sched_deadline::batch_runner(args, None).map(|_| ()),
-        SchedFifo(args) => sched_fifo::batch_runner(args, None).map(|_| ()),
-    }
+    let result =
+        match args.command {
+            FairServer(args) => fair_server::batch_runner(args, None).map(|_| ()),
+            SchedDeadline(args) => sched_deadline::batch_runner(args, None).map(|_| ()),
+            DeadlineAdmission(args) => deadline_admission::batch_runner(args, None).map(|_| ()),
+            RtSysctl(args) => rt_sysctl::batch_runner(args, None).map(|_| ()),
+            PriorityInheritance(args) => priority_inheritance::batch_runner(args, None).map(|_| ()),
+            SchedOther(args) => sched_other::batch_runner(args, None).map(|_| ()),
+            SchedFifo(args) => sched_fifo::batch_runner(args, None).map(|_| ()),
+        };
+
+    snapshot.restore()?;
+
+    result
 }
\ No newline at end of file
@@ -1,111 +0,0 @@
-use hcbs_test_suite::prelude::*;
-
-#[derive(clap::Parser, Debug)]
-pub struct MyArgs {
-    /// cgroup's name
-    #[arg(short = 'c', long = "cgroup", default_value = "g0", value_name = "name")]
-    pub cgroup: String,
-
-    /// cgroup's runtime
-    #[arg(short = 'r', long = "runtime", value_name = "ms: u64")]
-    pub runtime_ms: u64,
-
-    /// cgroup's period
-    #[arg(short = 'p', long = "period", value_name = "ms: u64")]
-    pub period_ms: u64,
-
-    /// max running time
-    #[arg(short = 't', long = "max-time", value_name = "sec: u64")]
-    pub max_time: Option<u64>
-}
-
-pub fn batch_runner(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<()> {
-    if is_batch_test() && args.max_time.is_none() {
-        anyhow::bail!("Batch testing requires a maximum running time");
-    }
-
-    let cpus = CpuSet::all()?.num_cpus();
-    let cgroup_expected_bw = cpus as f64 * args.runtime_ms as f64 / args.period_ms as f64;
-    let fifo_expected_bw = cpus as f64 - cgroup_expected_bw;
-    let cgroup_error = cgroup_expected_bw * 0.025; // 2.5% error
-    let fifo_error = fifo_expected_bw * 0.025; // 2.5% error
-
-    let test_header =
-        if is_batch_test() {
-            "sched_fifo"
-        } else {
-            "sched_fifo (Ctrl+C to stop)"
-        };
-
-    batch_test_header(test_header, "regression");
-
-    let result = main(args, ctrlc_flag)
-        .and_then(|(fifo_bw, cgroup_bw)| {
-            if f64::abs(cgroup_bw - cgroup_expected_bw) >= cgroup_error {
-                anyhow::bail!("Expected cgroup tasks to use {:.2} units of total runtime, but used {:.2} units", cgroup_expected_bw, cgroup_bw);
-            }
-
-            if f64::abs(fifo_bw - fifo_expected_bw) >= fifo_error {
-                anyhow::bail!("Expected SCHED_FIFO tasks to use {:.2} units of total runtime, but used {:.2} units", fifo_expected_bw, fifo_bw);
-            }
-
-            Ok(format!("Cgroup processes got {:.2} units of total runtime, while SCHED_FIFO processes got {:.2} units of total runtime ", cgroup_bw, fifo_bw))
-        });
-
-    if is_batch_test() {
-        batch_test_result(result)
-    } else {
-        batch_test_result_details(result)
-    }
-}
-
-pub fn main(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<(f64, f64)> {
-    assign_pid_to_cgroup(".", std::process::id())?;
-    set_sched_policy(std::process::id(), SchedPolicy::RR(99), SchedFlags::RESET_ON_FORK)?;
-
-    let cpus = CpuSet::all()?.num_cpus();
-    let mut cgroup = HCBSCgroup::new(&args.cgroup)?
-        .with_force_kill(false);
-    cgroup.set_period_us(args.period_ms * 1000)?;
-    cgroup.set_runtime_us(args.runtime_ms * 1000)?;
-
-    let mut fifo_processes = (0..cpus).map(|_| run_yes()).collect::<Result<Vec<_>, _>>()?;
-    let cgroup_processes = (0..cpus).map(|_| run_yes()).collect::<Result<Vec<_>, _>>()?;
-
-    let cgroup_processes =
-        cgroup_processes.into_iter().enumerate()
-            .map(|(cpu, proc)| -> anyhow::Result<_> {
-                let id = proc.id();
-
-                let proc = cgroup.assign_process(proc).map_err(|(_, err)| err)?;
-                proc.set_affinity(CpuSet::single(cpu as u32)?)?;
-                proc.set_sched_policy(SchedPolicy::RR(50), SchedFlags::empty())?;
-
-                Ok(id)
-            })
-            .collect::<Result<Vec<_>, _>>()?;
-
-    fifo_processes.iter_mut().enumerate()
-        .try_for_each(|(cpu, proc)| -> anyhow::Result<()> {
-            proc.set_sched_policy(SchedPolicy::RR(50), SchedFlags::empty())?;
-            proc.set_affinity(CpuSet::single(cpu as u32)?)?;
-
-            Ok(())
-        })?;
-
-    wait_loop(args.max_time, ctrlc_flag)?;
-
-    let mut fifo_total_usage = 0f64;
-    for proc in fifo_processes.iter() {
-        fifo_total_usage += get_process_total_cpu_usage(proc.id())?;
-    }
-
-    let mut cgroup_total_usage = 0f64;
-    for proc in cgroup_processes.iter() {
-        cgroup_total_usage += get_process_total_cpu_usage(*proc)?;
-    }
-
-    cgroup.force_destroy();
-
-    Ok((fifo_total_usage, cgroup_total_usage))
-}
\ No newline at end of file
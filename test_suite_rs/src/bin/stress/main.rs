@@ -1,19 +1,26 @@
-mod cgroup_make_destroy;
-mod change_cgroup_runtime;
-mod change_pinning;
-mod change_priority;
-mod migrate;
-mod run_all;
-mod switch_class;
+use hcbs_test_suite::prelude::SnapshotGuard;
+use hcbs_test_suite::tests::stress::*;
 
 #[derive(clap::Parser, Debug)]
 #[command(about, long_about = None)]
+pub struct Args {
+    #[command(flatten)]
+    results: hcbs_test_suite::prelude::ResultsArgs,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(clap::Subcommand, Debug)]
 pub enum Command {
     /// Run all tests
     ///
     /// This command runs all the listed tests. It generates pseudo-random
     /// parameters for each of these tests and chooses which to run at random,
-    /// totalling to a user specified amount of tests.
+    /// totalling to a user specified amount of tests. The executed tests are
+    /// recorded to a log, which can be replayed with `stress replay`. With
+    /// --minimize, the tests up to the first failure are replayed in smaller
+    /// subsets to find a minimal sequence which still fails.
     #[command(name = "all", verbatim_doc_comment)]
     All(run_all::MyArgs),
 
@@ -74,23 +81,75 @@ pub enum Command {
     /// Constraints: runtime <= period
     #[command(name = "task-sched-class", verbatim_doc_comment)]
     SwitchClass(switch_class::MyArgs),
+
+    /// Stress test on task creation and exit
+    ///
+    /// This test saturates a cgroup with a RT task per CPU, while a higher
+    /// priority task inside the cgroup creates tasks in bursts (forks, threads,
+    /// execs or all of them), which spin briefly and exit. The created tasks
+    /// are RT as well, or SCHED_OTHER with --reset-on-fork. The test fails if
+    /// the RT tasks exceed the cgroup's bandwidth, if tasks are left in the
+    /// cgroup, if the cgroup's runtime cannot be set to zero afterwards or the
+    /// admissible bandwidth shrank (leaked accounting), or on scheduler
+    /// related kernel log events.
+    ///
+    /// Constraints: runtime <= period
+    #[command(name = "task-churn", verbatim_doc_comment)]
+    TaskChurn(churn::MyArgs),
+
+    /// Concurrent random operations on multiple cgroups
+    ///
+    /// This test runs several worker threads which issue random interleaved
+    /// operations on a set of cgroups and tasks: cgroup creation and
+    /// destruction, runtime/period changes (also per-CPU), task spawn, exit,
+    /// migration, affinity, priority and class changes. Every operation is
+    /// recorded, with its timestamp and outcome, to a log file, which can be
+    /// replayed with --replay. The test fails if an operation hangs or takes
+    /// longer than --op-timeout, or if the cgroups cannot be cleaned up.
+    /// With --minimize, the log of a failed run is reduced to a minimal
+    /// sequence of operations which still fails.
+    #[command(name = "fuzz", verbatim_doc_comment)]
+    Fuzz(fuzz::MyArgs),
+
+    /// Replay a stress log
+    ///
+    /// This command replays the log of `stress fuzz` or `stress all` (or a
+    /// minimized one): the fuzzer's operations are issued one at a time in
+    /// their original order and timing, the tests are run one after the
+    /// other until the first failure. With --minimize, if the replay fails,
+    /// subsequences of the log are replayed (delta debugging) to find a
+    /// minimal one which still fails, written to <log>.min.jsonl.
+    #[command(name = "replay", verbatim_doc_comment)]
+    Replay(replay::MyArgs),
 }
 
 fn main() -> anyhow::Result<()> {
     env_logger::init();
 
-    let args = <Command as clap::Parser>::parse();
+    let args = <Args as clap::Parser>::parse();
+    unsafe { args.results.apply()?; }
+
+    // restore the scheduler state modified by the test, also on error
+    let snapshot = SnapshotGuard::new()?;
 
     use Command::*;
 
-    match args {
-        All(args) => run_all::main(args, None),
-        CgroupMakeDestroy(args) => cgroup_make_destroy::batch_runner(args, None, None),
-        ChangeCgroupRuntime(args) => change_cgroup_runtime::batch_runner(args, None),
-        ChangePinning(args) => change_pinning::batch_runner(args, None),
-        ChangePriority(args) => change_priority::batch_runner(args, None),
-        Migrate(args) => migrate::batch_runner(args, None),
-        SwitchClass(args) => switch_class::batch_runner(args, None),
-    }
+    let result =
+        match args.command {
+            All(args) => run_all::main(args, None),
+            CgroupMakeDestroy(args) => cgroup_make_destroy::batch_runner(args, None, None),
+            ChangeCgroupRuntime(args) => change_cgroup_runtime::batch_runner(args, None),
+            ChangePinning(args) => change_pinning::batch_runner(args, None),
+            ChangePriority(args) => change_priority::batch_runner(args, None),
+            Migrate(args) => migrate::batch_runner(args, None),
+            SwitchClass(args) => switch_class::batch_runner(args, None),
+            TaskChurn(args) => churn::batch_runner(args, None),
+            Fuzz(args) => fuzz::batch_runner(args, None),
+            Replay(args) => replay::batch_runner(args, None),
+        };
+
+    snapshot.restore()?;
+
+    result
 }
 
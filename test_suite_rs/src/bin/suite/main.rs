@@ -0,0 +1,49 @@
+mod plan;
+mod run;
+
+#[derive(clap::Parser, Debug)]
+#[command(about, long_about = None)]
+pub struct Args {
+    #[command(flatten)]
+    results: hcbs_test_suite::prelude::ResultsArgs,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum Command {
+    /// Run a declarative test plan
+    ///
+    /// This command reads a JSON (or TOML) test plan, made of a list of suites,
+    /// each containing a list of test executable invocations (with their
+    /// arguments, custom names, timeouts and required capabilities). Every
+    /// test is run in batch mode and the results of all the suites are
+    /// aggregated in a final summary.
+    #[command(name = "plan", verbatim_doc_comment)]
+    Plan(plan::MyArgs),
+
+    /// Run the tests of the built-in catalog
+    ///
+    /// This command runs in-process the standard set of tests of the suite
+    /// (constraints, time, regression and stress tests), one after the other,
+    /// in batch mode. Tests can be selected by category and by glob patterns
+    /// on their id (i.e. category/name), and the selection can be listed with
+    /// --list. Tests whose requirements are not met are skipped.
+    #[command(name = "run", verbatim_doc_comment)]
+    Run(run::MyArgs),
+}
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+
+    let args = <Args as clap::Parser>::parse();
+    unsafe { args.results.apply()?; }
+
+    use Command::*;
+
+    match args.command {
+        Plan(args) => plan::main(args, None),
+        Run(args) => run::main(args, None),
+    }
+}
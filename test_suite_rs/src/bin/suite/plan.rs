@@ -0,0 +1,423 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use hcbs_test_suite::prelude::*;
+
+#[derive(clap::Parser, Debug)]
+pub struct MyArgs {
+    /// test plan file (TOML if its extension is .toml, JSON otherwise)
+    #[arg(short = 'i', long = "plan", value_name = "path")]
+    pub plan: String,
+
+    /// run only the given suites (can be repeated)
+    #[arg(short = 's', long = "suite", value_name = "name")]
+    pub suites: Vec<String>,
+
+    /// directory of the test executables
+    #[arg(long = "bin-dir", value_name = "path", default_value = "/root/test_suite")]
+    pub bin_dir: String,
+
+    /// also write the results as JSON Lines to the given file
+    #[arg(long = "jsonl", value_name = "path")]
+    pub jsonl: Option<String>,
+
+    /// also write the results as a JUnit XML report to the given file
+    #[arg(long = "junit", value_name = "path")]
+    pub junit: Option<String>,
+
+    /// monitor the kernel log during each test (report, sched or all), as
+    /// with the BATCH_TEST_KMSG environment variable
+    #[arg(long = "kmsg", value_name = "mode", value_parser = KmsgMode::from_str)]
+    pub kmsg: Option<KmsgMode>,
+
+    /// only print the tests that would be run
+    #[arg(long = "dry-run")]
+    pub dry_run: bool,
+}
+
+#[derive(serde::Deserialize, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct TestPlan {
+    pub suites: Vec<PlanSuite>,
+}
+
+#[derive(serde::Deserialize, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct PlanSuite {
+    /// suite's name, also used as test category for runner generated results
+    pub name: String,
+
+    /// capabilities required by all the tests of the suite
+    #[serde(default)]
+    pub requires: Vec<String>,
+
+    pub tests: Vec<PlanEntry>,
+}
+
+#[derive(serde::Deserialize, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct PlanEntry {
+    /// test executable (e.g. time, regression, stress, tools)
+    pub exec: String,
+
+    #[serde(default)]
+    pub args: Vec<String>,
+
+    /// custom test name (BATCH_TEST_CUSTOM_NAME)
+    #[serde(default)]
+    pub name: Option<String>,
+
+    /// timeout in seconds, after which the test is killed and failed
+    #[serde(default)]
+    pub timeout: Option<u64>,
+
+    /// capabilities required by this test
+    #[serde(default)]
+    pub requires: Vec<String>,
+
+    /// extra environment variables
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+
+    /// abort the whole plan if this entry fails
+    #[serde(default)]
+    pub fatal: bool,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct CollectedRecord {
+    status: String,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct SuiteOutcome {
+    success: u64,
+    failure: u64,
+    skipped: u64,
+}
+
+impl SuiteOutcome {
+    fn add(&mut self, status: TestStatus) {
+        match status {
+            TestStatus::Success => self.success += 1,
+            TestStatus::Failure => self.failure += 1,
+            TestStatus::Skipped => self.skipped += 1,
+        }
+    }
+
+    fn total(&self) -> u64 {
+        self.success + self.failure + self.skipped
+    }
+}
+
+impl PlanEntry {
+    fn display_name(&self) -> String {
+        self.name.clone()
+            .unwrap_or_else(|| format!("{} {}", self.exec, self.args.join(" ")))
+    }
+}
+
+pub fn parse_plan(file: &str) -> anyhow::Result<TestPlan> {
+    let data = std::fs::read_to_string(file)
+        .map_err(|err| anyhow::format_err!("Error on reading file {file}, reason {err}"))?;
+
+    parse_plan_data(&data, file.ends_with(".toml"))
+}
+
+fn parse_plan_data(data: &str, toml: bool) -> anyhow::Result<TestPlan> {
+    if toml {
+        toml::from_str(data)
+            .map_err(|err| anyhow::format_err!("Test plan parser error: {err}"))
+    } else {
+        serde_json::from_str(data)
+            .map_err(|err| anyhow::format_err!("Test plan parser error: {err}"))
+    }
+}
+
+pub fn main(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<()> {
+    unsafe { set_batch_test(); }
+
+    let ctrlc_flag = match ctrlc_flag {
+        Some(exit) => exit,
+        None => create_ctrlc_handler()?,
+    };
+
+    let plan = parse_plan(&args.plan)?;
+    for suite in args.suites.iter() {
+        if !plan.suites.iter().any(|s| &s.name == suite) {
+            anyhow::bail!("Suite \'{suite}\' not found in test plan {}", args.plan);
+        }
+    }
+
+    // result sinks for the runner and the spawned tests
+    let mut sinks_spec =
+        match std::env::var("BATCH_TEST_SINKS") {
+            Ok(spec) if !spec.is_empty() => spec,
+            _ => "human".to_owned(),
+        };
+    if let Some(jsonl) = &args.jsonl {
+        sinks_spec += &format!(",jsonl={jsonl}");
+    }
+    if let Some(junit) = &args.junit {
+        sinks_spec += &format!(",junit={junit}");
+    }
+    set_result_sinks(&sinks_spec)?;
+
+    let collector_file = format!("/tmp/hcbs_plan_{}.jsonl", std::process::id());
+    let child_sinks_spec = format!("{sinks_spec},jsonl={collector_file}");
+
+    let mut outcomes: Vec<(String, SuiteOutcome)> = Vec::new();
+    let mut aborted = false;
+
+    'suites: for suite in plan.suites.iter() {
+        if !args.suites.is_empty() && !args.suites.contains(&suite.name) {
+            continue;
+        }
+
+        println!("* {} *", suite.name);
+        let mut outcome = SuiteOutcome::default();
+        let suite_missing = missing_requirements(&suite.requires)?;
+
+        for entry in suite.tests.iter() {
+            if ctrlc_flag.is_exit() {
+                aborted = true;
+                outcomes.push((suite.name.clone(), outcome));
+                break 'suites;
+            }
+
+            let mut missing = suite_missing.clone();
+            missing.append(&mut missing_requirements(&entry.requires)?);
+
+            if args.dry_run {
+                println!("  {} {}{}", entry.exec, entry.args.join(" "),
+                    if missing.is_empty() { String::new() } else { format!(" (skipped, missing: {})", missing.join(", ")) });
+                continue;
+            }
+
+            if !missing.is_empty() {
+                batch_test_header(&entry.display_name(), &suite.name);
+                batch_test_skipped(format!("missing requirements: {}", missing.join(", ")));
+                outcome.add(TestStatus::Skipped);
+                continue;
+            }
+
+            let statuses = run_entry(entry, &suite.name, &args, &child_sinks_spec, &collector_file, &ctrlc_flag)?;
+            let failed = statuses.contains(&TestStatus::Failure);
+            statuses.into_iter().for_each(|status| outcome.add(status));
+
+            if failed && entry.fatal {
+                println!("Fatal test \'{}\' failed, aborting test plan.", entry.display_name());
+                aborted = true;
+                outcomes.push((suite.name.clone(), outcome));
+                break 'suites;
+            }
+        }
+
+        outcomes.push((suite.name.clone(), outcome));
+    }
+
+    if std::fs::exists(&collector_file)? {
+        std::fs::remove_file(&collector_file)?;
+    }
+
+    if args.dry_run {
+        return Ok(());
+    }
+
+    let total =
+        outcomes.iter()
+            .fold(SuiteOutcome::default(), |acc, (_, outcome)| SuiteOutcome {
+                success: acc.success + outcome.success,
+                failure: acc.failure + outcome.failure,
+                skipped: acc.skipped + outcome.skipped,
+            });
+
+    println!("[plan] Summary{}", if aborted { " (aborted)" } else { "" });
+    for (name, outcome) in outcomes.iter() {
+        println!("       {name}: {} tests, {} success, {} failures, {} skipped",
+            outcome.total(), outcome.success, outcome.failure, outcome.skipped);
+    }
+    println!("       Total: {} tests, {} success, {} failures, {} skipped",
+        total.total(), total.success, total.failure, total.skipped);
+
+    if total.failure > 0 {
+        anyhow::bail!("{} test(s) failed", total.failure);
+    }
+
+    Ok(())
+}
+
+fn run_entry(
+    entry: &PlanEntry,
+    category: &str,
+    args: &MyArgs,
+    sinks_spec: &str,
+    collector_file: &str,
+    ctrlc_flag: &ExitFlag,
+) -> anyhow::Result<Vec<TestStatus>> {
+    use std::process::*;
+
+    if std::fs::exists(collector_file)? {
+        std::fs::remove_file(collector_file)?;
+    }
+
+    let cmd = local_executable_cmd(&args.bin_dir, &entry.exec)?;
+    let mut command = Command::new(cmd);
+    command
+        .args(entry.args.iter())
+        .stdin(Stdio::null())
+        .env("BATCH_TEST", "1")
+        .env("BATCH_TEST_SINKS", sinks_spec)
+        .env("TESTBINDIR", std::env::var("TESTBINDIR").unwrap_or_else(|_| args.bin_dir.clone()));
+
+    if let Some(kmsg) = args.kmsg {
+        command.env("BATCH_TEST_KMSG", kmsg.as_str());
+    }
+
+    match &entry.name {
+        Some(name) => command.env("BATCH_TEST_CUSTOM_NAME", name),
+        None => command.env_remove("BATCH_TEST_CUSTOM_NAME"),
+    };
+
+    command.envs(entry.env.iter());
+
+    // run the test in its own process group, so that any workload it leaves
+    // behind can be killed along with it.
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+
+    let mut child = command.spawn()
+        .map_err(|err| anyhow::format_err!("Error in starting {}: {err}", entry.exec))?;
+    let group = child.id() as libc::pid_t;
+
+    let start = Instant::now();
+    let timeout = entry.timeout.map(Duration::from_secs);
+    let exit_status =
+        loop {
+            if let Some(status) = child.try_wait()? {
+                break Some(status);
+            }
+
+            if timeout.is_some_and(|timeout| start.elapsed() >= timeout) {
+                unsafe { libc::kill(-group, libc::SIGKILL); }
+                child.wait()?;
+                break None;
+            }
+
+            if ctrlc_flag.is_exit() {
+                // the child is not in the foreground process group, forward
+                // the Ctrl+C and wait for it to finish.
+                unsafe { libc::kill(-group, libc::SIGINT); }
+                break Some(child.wait()?);
+            }
+
+            std::thread::sleep(Duration::from_millis(100));
+        };
+
+    // kill any leftover workload of the test
+    unsafe { libc::kill(-group, libc::SIGKILL); }
+
+    let mut statuses =
+        if std::fs::exists(collector_file)? {
+            std::fs::read_to_string(collector_file)?
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(|line| -> anyhow::Result<TestStatus> {
+                    let record: CollectedRecord = serde_json::from_str(line)
+                        .map_err(|err| anyhow::format_err!("Error in parsing test record: {err}"))?;
+
+                    record.status.parse()
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?
+        } else {
+            Vec::new()
+        };
+
+    match exit_status {
+        None => {
+            println!();
+            batch_test_header(&entry.display_name(), category);
+            batch_test_failure(format!("Timed out after {} secs", entry.timeout.unwrap_or(0)));
+            statuses.push(TestStatus::Failure);
+        },
+        Some(status) if !status.success() && !statuses.contains(&TestStatus::Failure) => {
+            batch_test_header(&entry.display_name(), category);
+            batch_test_failure(format!("{} exited with {status}", entry.exec));
+            statuses.push(TestStatus::Failure);
+        },
+        Some(_) if statuses.is_empty() => {
+            // executables that do not report results (e.g. tools)
+            batch_test_header(&entry.display_name(), category);
+            batch_test_success();
+            statuses.push(TestStatus::Success);
+        },
+        Some(_) => (),
+    };
+
+    Ok(statuses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JSON_PLAN: &str = r#"{
+        "suites": [
+            {
+                "name": "setup",
+                "tests": [
+                    {"exec": "tools", "args": ["cgroup-setup", "-r", "900"], "requires": ["!ext_server"], "fatal": true}
+                ]
+            },
+            {
+                "name": "time-uni",
+                "requires": ["fair_server"],
+                "tests": [
+                    {"exec": "time", "name": "uni-40", "args": ["uni", "-r", "40", "-p", "100", "-t", "10"], "timeout": 60,
+                     "env": {"TERM_COLORS": "1"}}
+                ]
+            }
+        ]
+    }"#;
+
+    const TOML_PLAN: &str = r#"
+        [[suites]]
+        name = "setup"
+
+        [[suites.tests]]
+        exec = "tools"
+        args = ["cgroup-setup", "-r", "900"]
+        requires = ["!ext_server"]
+        fatal = true
+
+        [[suites]]
+        name = "time-uni"
+        requires = ["fair_server"]
+
+        [[suites.tests]]
+        exec = "time"
+        name = "uni-40"
+        args = ["uni", "-r", "40", "-p", "100", "-t", "10"]
+        timeout = 60
+        env = { TERM_COLORS = "1" }
+    "#;
+
+    #[test]
+    fn toml_and_json_plans() {
+        let json = parse_plan_data(JSON_PLAN, false).unwrap();
+        let toml = parse_plan_data(TOML_PLAN, true).unwrap();
+        assert_eq!(json, toml);
+
+        assert_eq!(toml.suites.len(), 2);
+        assert!(toml.suites[0].tests[0].fatal);
+        assert_eq!(toml.suites[1].tests[0].timeout, Some(60));
+        assert_eq!(toml.suites[1].tests[0].display_name(), "uni-40");
+        assert_eq!(toml.suites[0].tests[0].display_name(), "tools cgroup-setup -r 900");
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        assert!(parse_plan_data("[[suites]]\nname = \"a\"\ntests = []\ntimeout = 5\n", true).is_err());
+        assert!(parse_plan_data(r#"{"suites": [], "extra": 1}"#, false).is_err());
+    }
+}
@@ -0,0 +1,86 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use hcbs_test_suite::prelude::*;
+use hcbs_test_suite::tests::prelude::*;
+
+#[derive(clap::Parser, Debug)]
+pub struct MyArgs {
+    /// list the selected tests without running them
+    #[arg(long = "list")]
+    pub list: bool,
+
+    /// run only the tests of the given categories (can be repeated)
+    #[arg(short = 'c', long = "category", value_name = "name")]
+    pub categories: Vec<String>,
+
+    /// run only the tests whose id (category/name) matches the given glob
+    /// pattern (can be repeated)
+    #[arg(short = 'f', long = "filter", value_name = "glob")]
+    pub filters: Vec<String>,
+
+    /// hard wall-clock timeout for each test, after which the test is stopped,
+    /// its workloads killed and the test failed
+    #[arg(long = "timeout", default_value = "600", value_name = "sec: u64")]
+    pub timeout: u64,
+
+    /// monitor the kernel log during each test (report, sched or all), as
+    /// with the BATCH_TEST_KMSG environment variable
+    #[arg(long = "kmsg", value_name = "mode", value_parser = KmsgMode::from_str)]
+    pub kmsg: Option<KmsgMode>,
+}
+
+pub fn main(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<()> {
+    let tests = select_tests(catalog()?, &args.categories, &args.filters);
+
+    if args.list {
+        for test in tests.iter() {
+            let requirements = test.requirements().iter()
+                .map(|capability| capability.as_str())
+                .collect::<Vec<_>>();
+
+            println!("{} [{}] (requires: {})", test.id(), test.parameters(), requirements.join(", "));
+        }
+
+        return Ok(());
+    }
+
+    unsafe { set_batch_test(); }
+
+    if args.kmsg.is_some() {
+        set_kmsg_mode(args.kmsg);
+    }
+
+    let ctrlc_flag = match ctrlc_flag {
+        Some(exit) => exit,
+        None => create_ctrlc_handler()?,
+    };
+
+    mount_cgroup_cpu()?;
+
+    let mut failures = 0u64;
+    let mut skipped = 0u64;
+    let mut total = 0u64;
+    for test in tests {
+        if ctrlc_flag.is_exit() {
+            break;
+        }
+
+        match run_catalog_test(test.into(), Some(ctrlc_flag.clone()), Some(Duration::from_secs(args.timeout))) {
+            TestStatus::Success => (),
+            TestStatus::Failure => failures += 1,
+            TestStatus::Skipped => skipped += 1,
+        };
+        total += 1;
+    }
+
+    println!("[suite] Summary");
+    println!("        {} tests, {} success, {} failures, {} skipped",
+        total, total - failures - skipped, failures, skipped);
+
+    if failures > 0 {
+        anyhow::bail!("{failures} test(s) failed");
+    }
+
+    Ok(())
+}
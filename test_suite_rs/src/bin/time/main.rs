@@ -1,8 +1,17 @@
-mod unicpu;
-mod multicpu;
+use hcbs_test_suite::prelude::SnapshotGuard;
+use hcbs_test_suite::tests::time::*;
 
 #[derive(clap::Parser, Debug)]
 #[command(about, long_about = None)]
+pub struct Args {
+    #[command(flatten)]
+    results: hcbs_test_suite::prelude::ResultsArgs,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(clap::Subcommand, Debug)]
 pub enum Command {
     /// Run multiple yes tasks in a RT cgroup
     ///
@@ -24,19 +33,93 @@ pub enum Command {
     /// Constraints: runtime <= period
     #[command(name = "multi", verbatim_doc_comment)]
     MultiCpu(multicpu::MyArgs),
+
+    /// Run yes tasks in a hierarchy of nested RT cgroups
+    ///
+    /// This command builds a tree of nested RT cgroups, each with its own
+    /// runtime and period, runs a user specified number of yes tasks in the
+    /// leaf cgroups, and reports the bandwidth used by each cgroup. The test
+    /// is successful if every leaf uses its reserved bandwidth (or the one of
+    /// its tasks, if lower), and every inner cgroup the sum of its children.
+    ///
+    /// Constraints: runtime <= period, children's bandwidth <= parent's one
+    #[command(name = "hierarchy", verbatim_doc_comment)]
+    Hierarchy(hierarchy::MyArgs),
+
+    /// Run yes tasks in concurrent sibling RT cgroups
+    ///
+    /// This command creates a number of sibling RT cgroups sharing the same
+    /// CPUs, each with its own runtime and period (possibly non-harmonic),
+    /// and saturates each of them with one yes task per CPU. The test is
+    /// successful if every cgroup uses its reserved bandwidth on every CPU,
+    /// i.e. no cgroup steals bandwidth from its siblings.
+    ///
+    /// Constraints: runtime <= period, sum of the bandwidths <= RT bandwidth
+    #[command(name = "siblings", verbatim_doc_comment)]
+    Siblings(siblings::MyArgs),
+
+    /// Run equal priority SCHED_RR yes tasks in a RT cgroup
+    ///
+    /// This command runs a number of SCHED_RR yes tasks with the same priority
+    /// on each of the given CPUs, inside a RT cgroup, optionally changing
+    /// sched_rr_timeslice_ms for the duration of the test. The test is
+    /// successful if the cgroup's bandwidth on each CPU is split evenly among
+    /// its tasks, the tasks report the configured round-robin interval, and
+    /// they run for slices of about min(time slice, runtime) between two
+    /// preemptions.
+    ///
+    /// Constraints: runtime <= period, at least two tasks per CPU
+    #[command(name = "rr-fairness", verbatim_doc_comment)]
+    RrFairness(rr_fairness::MyArgs),
+
+    /// Observe when a RT cgroup runs and when it is throttled
+    ///
+    /// This command runs a yes task in a RT cgroup and, on the same CPU, a
+    /// lower priority SCHED_FIFO observer in the root cgroup, which timestamps
+    /// its own runs and thus records when the cgroup was running. From these
+    /// intervals the test reconstructs the server's periods, and it is
+    /// successful if each period contains at most runtime of cgroup's
+    /// execution, starting at the period's boundary (replenishment).
+    ///
+    /// Constraints: 0 < runtime < period
+    #[command(name = "throttling", verbatim_doc_comment)]
+    Throttling(throttling::MyArgs),
+
+    /// Run yes tasks in a RT cgroup restricted by the cpuset controller
+    ///
+    /// This command sets the cgroup's runtime only on some CPUs (zero on the
+    /// others) and its cpuset.cpus to a possibly different set of CPUs, runs
+    /// unpinned yes tasks in it and samples the CPU each task runs on. If a
+    /// new cpuset is given, it is written while the tasks run, and the tasks
+    /// are observed again. The test is successful if the tasks never execute
+    /// on CPUs outside of the cpuset or without runtime, and use the
+    /// bandwidth of the cpuset's CPUs with runtime.
+    ///
+    /// Constraints: 0 < runtime <= period
+    #[command(name = "cpuset", verbatim_doc_comment)]
+    Cpuset(cpuset::MyArgs),
 }
 
 fn main() -> anyhow::Result<()> {
     env_logger::init();
 
-    let args = <Command as clap::Parser>::parse();
+    let args = <Args as clap::Parser>::parse();
+    unsafe { args.results.apply()?; }
+
+    // restore the scheduler state modified by the test, also on error
+    let snapshot = SnapshotGuard::new()?;
 
     use Command::*;
 
-    match args {
+    match args.command {
         UniCpu(args) => { unicpu::batch_runner(args, None)?; },
         MultiCpu(args) => { multicpu::batch_runner(args, None)?; },
+        Hierarchy(args) => { hierarchy::batch_runner(args, None)?; },
+        Siblings(args) => { siblings::batch_runner(args, None)?; },
+        RrFairness(args) => { rr_fairness::batch_runner(args, None)?; },
+        Throttling(args) => { throttling::batch_runner(args, None)?; },
+        Cpuset(args) => { cpuset::batch_runner(args, None)?; },
     };
 
-    Ok(())
+    snapshot.restore()
 }
\ No newline at end of file
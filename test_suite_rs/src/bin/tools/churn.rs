@@ -0,0 +1,139 @@
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+use hcbs_test_suite::prelude::*;
+use hcbs_test_suite::tests::stress::churn::{ChurnCounts, ChurnMode};
+
+#[derive(clap::Parser, Debug)]
+pub struct MyArgs {
+    /// kind of the created tasks
+    #[arg(value_enum)]
+    mode: ChurnMode,
+
+    /// tasks created in each burst
+    #[arg(short = 'b', long = "burst", default_value = "16", value_name = "u64")]
+    burst: u64,
+
+    /// time between the start of consecutive bursts
+    #[arg(short = 'P', long = "burst-period", default_value = "100", value_name = "ms: u64")]
+    burst_period_ms: u64,
+
+    /// CPU time consumed by each created task before exiting
+    #[arg(long = "task-time", default_value = "1000", value_name = "us: u64")]
+    task_time_us: u64,
+
+    /// time waited before the first burst
+    #[arg(long = "delay", default_value = "0", value_name = "ms: u64")]
+    delay_ms: u64,
+
+    /// churning time
+    #[arg(short = 'd', long = "duration", value_name = "ms: u64")]
+    duration_ms: u64,
+}
+
+/// Tasks of a burst, reaped after all of them are created.
+#[derive(Default)]
+struct Burst {
+    forks: Vec<libc::pid_t>,
+    threads: Vec<std::thread::JoinHandle<()>>,
+    execs: Vec<Child>,
+}
+
+fn fork(task_time: Duration) -> anyhow::Result<libc::pid_t> {
+    match unsafe { libc::fork() } {
+        -1 => Err(std::io::Error::last_os_error().into()),
+        0 => {
+            // only async-signal-safe calls in the child
+            spin_for_cpu_time(task_time);
+            unsafe { libc::_exit(0) }
+        },
+        pid => Ok(pid),
+    }
+}
+
+impl Burst {
+    fn spawn(&mut self, mode: ChurnMode, task_time: Duration) -> anyhow::Result<()> {
+        match mode {
+            ChurnMode::Fork => self.forks.push(fork(task_time)?),
+            ChurnMode::Thread => self.threads.push(
+                std::thread::Builder::new().spawn(move || spin_for_cpu_time(task_time))?),
+            ChurnMode::Exec => self.execs.push(
+                Command::new("true")
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .spawn()?),
+            ChurnMode::Mixed => anyhow::bail!("Mixed is not a kind of task"),
+        }
+
+        Ok(())
+    }
+
+    fn reap(self, counts: &mut ChurnCounts) {
+        for pid in self.forks {
+            let mut status = 0;
+            let reaped = unsafe { libc::waitpid(pid, &mut status, 0) } == pid;
+
+            if reaped && libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0 {
+                counts.forks += 1;
+            } else {
+                counts.errors += 1;
+            }
+        }
+
+        for thread in self.threads {
+            match thread.join() {
+                Ok(_) => counts.threads += 1,
+                Err(_) => counts.errors += 1,
+            }
+        }
+
+        for mut exec in self.execs {
+            match exec.wait() {
+                Ok(status) if status.success() => counts.execs += 1,
+                _ => counts.errors += 1,
+            }
+        }
+    }
+}
+
+pub fn main(args: MyArgs) -> anyhow::Result<()> {
+    const KINDS: [ChurnMode; 3] = [ChurnMode::Fork, ChurnMode::Thread, ChurnMode::Exec];
+
+    let task_time = Duration::from_micros(args.task_time_us);
+    let burst_period = Duration::from_millis(args.burst_period_ms);
+    let duration = Duration::from_millis(args.duration_ms);
+    let mut counts = ChurnCounts::default();
+
+    std::thread::sleep(Duration::from_millis(args.delay_ms));
+
+    let start = Instant::now();
+    let mut next_burst = start;
+    while start.elapsed() < duration {
+        let mut burst = Burst::default();
+
+        for i in 0..args.burst as usize {
+            let mode =
+                match args.mode {
+                    ChurnMode::Mixed => KINDS[i % KINDS.len()],
+                    mode => mode,
+                };
+
+            if let Err(err) = burst.spawn(mode, task_time) {
+                log::warn!("Error in creating a {mode} task: {err}");
+                counts.errors += 1;
+            }
+        }
+
+        burst.reap(&mut counts);
+
+        next_burst += burst_period;
+        if let Some(wait) = next_burst.checked_duration_since(Instant::now()) {
+            std::thread::sleep(wait);
+        }
+    }
+
+    println!("{counts}");
+
+    Ok(())
+}
@@ -0,0 +1,84 @@
+use hcbs_test_suite::prelude::*;
+
+#[derive(clap::Parser, Debug)]
+pub struct MyArgs {
+    /// print the capability matrix as JSON
+    #[arg(long = "json")]
+    json: bool,
+
+    /// fail if the given capability is missing (can be repeated)
+    #[arg(short = 'r', long = "require", value_name = "capability", value_parser = <Capability as std::str::FromStr>::from_str)]
+    require: Vec<Capability>,
+}
+
+pub fn main(args: MyArgs) -> anyhow::Result<()> {
+    let matrix: Vec<(Capability, anyhow::Result<bool>)> =
+        Capability::ALL.into_iter()
+            .map(|capability| (capability, capability.probe()))
+            .collect();
+
+    let topology = CpuTopology::probe();
+
+    if args.json {
+        let capabilities: serde_json::Map<String, serde_json::Value> =
+            matrix.iter()
+                .map(|(capability, available)| (
+                    capability.as_str().to_owned(),
+                    match available {
+                        Ok(available) => serde_json::Value::Bool(*available),
+                        Err(err) => serde_json::Value::String(format!("error: {err}")),
+                    }
+                ))
+                .collect();
+
+        let topology =
+            match &topology {
+                Ok(topology) => serde_json::json!({
+                    "online": topology.online,
+                    "num_cpus": topology.num_cpus,
+                    "num_packages": topology.num_packages,
+                    "num_cores": topology.num_cores,
+                }),
+                Err(err) => serde_json::Value::String(format!("error: {err}")),
+            };
+
+        println!("{}", serde_json::json!({
+            "capabilities": capabilities,
+            "cpus": topology,
+        }));
+    } else {
+        println!("[doctor] Capabilities");
+        for (capability, available) in matrix.iter() {
+            let available =
+                match available {
+                    Ok(true) => "yes".to_owned(),
+                    Ok(false) => "no".to_owned(),
+                    Err(err) => format!("error ({err})"),
+                };
+
+            println!("         {:<24} {:<32} {}", capability.as_str(), capability.description(), available);
+        }
+
+        println!("[doctor] CPUs");
+        match &topology {
+            Ok(topology) => {
+                println!("         online: {} ({} CPUs)", topology.online, topology.num_cpus);
+                println!("         {} package(s), {} core(s)", topology.num_packages, topology.num_cores);
+            },
+            Err(err) => println!("         error ({err})"),
+        };
+    }
+
+    let missing: Vec<_> =
+        matrix.iter()
+            .filter(|(capability, available)|
+                args.require.contains(capability) && !available.as_ref().is_ok_and(|available| *available))
+            .map(|(capability, _)| capability.as_str())
+            .collect();
+
+    if !missing.is_empty() {
+        anyhow::bail!("Missing required capabilities: {}", missing.join(", "));
+    }
+
+    Ok(())
+}
@@ -3,6 +3,11 @@ mod move_rt_to_root_cgroup;
 mod cgroup_setup;
 mod hrtick;
 mod chrt;
+mod doctor;
+mod snapshot;
+mod pi_task;
+mod observer;
+mod churn;
 
 #[derive(clap::Parser, Debug)]
 #[command(about, long_about = None)]
@@ -46,6 +51,54 @@ pub enum Command {
     /// CHRT process to SCHED_DEADLINE
     #[command(name = "check-multicpu", verbatim_doc_comment)]
     MultiCPUEnabled(()),
+
+    /// Probe the kernel capabilities needed by the tests
+    ///
+    /// This command prints which of the kernel features used by the tests are
+    /// available (cgroup v2 and cpu controller, RT cgroup files, multi-CPU
+    /// runtimes, fair/ext servers, scheduler features, SCHED_DEADLINE
+    /// admission), along with the CPU topology. Tests requiring a missing
+    /// capability are reported as skipped.
+    #[command(name = "doctor", verbatim_doc_comment)]
+    Doctor(doctor::MyArgs),
+
+    /// Save/Restore the scheduler state
+    ///
+    /// This command saves to file the global scheduler state modified by the
+    /// tests and tools (root cgroup runtime/period, fair/ext servers, scheduler
+    /// features, sched_rt_* and sched_rr_timeslice_ms sysctls and tasks'
+    /// cgroups), and restores it later.
+    #[command(name = "snapshot", subcommand, verbatim_doc_comment)]
+    Snapshot(snapshot::MyArgs),
+
+    /// Task sharing a priority inheritance mutex
+    ///
+    /// Helper of the priority inheritance regression test: it opens the shared
+    /// mutex created by the test, and acts as the mutex holder, as the
+    /// interfering task or as the waiter.
+    #[command(name = "pi-task", verbatim_doc_comment)]
+    PiTask(pi_task::MyArgs),
+
+    /// Timestamp the own runs, reporting when the task did not run
+    ///
+    /// Helper of the throttling test: it busy loops for the given time, and
+    /// prints the observed time followed by the intervals (start and end, in
+    /// ns since its start) in which it did not run for at least the given
+    /// threshold. When run as a low priority task, these are the intervals in
+    /// which higher priority tasks were running.
+    #[command(name = "observer", verbatim_doc_comment)]
+    Observer(observer::MyArgs),
+
+    /// Create tasks in bursts, which spin and exit
+    ///
+    /// Helper of the task churn stress test: in each burst it forks processes,
+    /// spawns threads or spawns processes which exec `true` (or all of them in
+    /// turn, in mixed mode), then reaps them. At the end, it prints the number
+    /// of forks, threads and execs which exited successfully, followed by the
+    /// number of failed ones. The created tasks inherit its scheduling policy,
+    /// unless it was set with reset-on-fork.
+    #[command(name = "churn", verbatim_doc_comment)]
+    Churn(churn::MyArgs),
 }
 
 fn main() -> anyhow::Result<()> {
@@ -71,6 +124,11 @@ fn main() -> anyhow::Result<()> {
         CgroupBwChange(args) => cgroup_setup::main(args)?,
         HRTick(args) => hrtick::main(args)?,
         ChrtDeadline(args) => chrt::main(args)?,
+        Doctor(args) => doctor::main(args)?,
+        Snapshot(args) => snapshot::main(args)?,
+        PiTask(args) => pi_task::main(args)?,
+        Observer(args) => observer::main(args)?,
+        Churn(args) => churn::main(args)?,
         MultiCPUEnabled(_) => {
             if hcbs_test_suite::prelude::is_multicpu_enabled()? {
                 println!("Multi CPU available");
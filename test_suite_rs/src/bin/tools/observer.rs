@@ -0,0 +1,43 @@
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+#[derive(clap::Parser, Debug)]
+pub struct MyArgs {
+    /// observation time
+    #[arg(short = 'd', long = "duration", value_name = "ms: u64")]
+    duration_ms: u64,
+
+    /// minimum length of the reported gaps
+    #[arg(long = "threshold", default_value = "20", value_name = "us: u64")]
+    threshold_us: u64,
+}
+
+pub fn main(args: MyArgs) -> anyhow::Result<()> {
+    let threshold = Duration::from_micros(args.threshold_us);
+    let duration = Duration::from_millis(args.duration_ms);
+    let mut gaps = Vec::with_capacity(1 << 16);
+
+    // timestamp the own runs, recording when the task did not run
+    let start = Instant::now();
+    let mut last = start;
+    let observed = loop {
+        let now = Instant::now();
+        if now - last >= threshold {
+            gaps.push((last - start, now - start));
+        }
+
+        if now - start >= duration {
+            break now - start;
+        }
+
+        last = now;
+    };
+
+    let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+    writeln!(out, "{}", observed.as_nanos())?;
+    for (gap_start, gap_end) in gaps {
+        writeln!(out, "{} {}", gap_start.as_nanos(), gap_end.as_nanos())?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,104 @@
+use std::time::{Duration, Instant};
+
+use hcbs_test_suite::prelude::*;
+
+#[derive(clap::Parser, Debug)]
+pub struct MyArgs {
+    /// role of the task
+    #[arg(value_enum)]
+    role: PiRole,
+
+    /// path of the shared mutex, created by the test
+    #[arg(short = 'm', long = "mutex", value_name = "path")]
+    mutex: String,
+
+    /// holder: CPU time spent inside the critical section
+    #[arg(long = "cs", default_value = "10", value_name = "ms: u64")]
+    critical_section_ms: u64,
+
+    /// holder: time slept between critical sections
+    #[arg(long = "gap", default_value = "100", value_name = "ms: u64")]
+    gap_ms: u64,
+
+    /// waiter: time after which it gives up waiting for the mutex
+    #[arg(long = "timeout", default_value = "10000", value_name = "ms: u64")]
+    timeout_ms: u64,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy)]
+pub enum PiRole {
+    /// periodically lock the mutex and consume CPU time inside the critical
+    /// section
+    Holder,
+    /// consume CPU time while the holder is inside the critical section
+    Interferer,
+    /// lock the mutex while the holder is inside the critical section,
+    /// printing the time spent blocked (in us, or "timeout")
+    Waiter,
+}
+
+const POLL_PERIOD: Duration = Duration::from_millis(1);
+
+fn holder(mutex: &SharedPiMutex, args: &MyArgs) -> anyhow::Result<()> {
+    while !mutex.is_stopped() {
+        mutex.lock()?;
+        mutex.set_held(true);
+        spin_for_cpu_time(Duration::from_millis(args.critical_section_ms));
+        mutex.set_held(false);
+        mutex.unlock()?;
+
+        std::thread::sleep(Duration::from_millis(args.gap_ms));
+    }
+
+    Ok(())
+}
+
+fn interferer(mutex: &SharedPiMutex) -> anyhow::Result<()> {
+    while !mutex.is_stopped() {
+        if mutex.is_held() {
+            std::hint::spin_loop();
+        } else {
+            std::thread::sleep(POLL_PERIOD);
+        }
+    }
+
+    Ok(())
+}
+
+fn waiter(mutex: &SharedPiMutex, args: &MyArgs) -> anyhow::Result<()> {
+    while !mutex.is_stopped() {
+        // wait for the next critical section
+        while !mutex.is_held() {
+            if mutex.is_stopped() {
+                return Ok(());
+            }
+
+            std::thread::sleep(POLL_PERIOD);
+        }
+
+        let start = Instant::now();
+        if mutex.lock_timeout(Duration::from_millis(args.timeout_ms))? {
+            let blocked = start.elapsed();
+            mutex.unlock()?;
+            println!("{}", blocked.as_micros());
+        } else {
+            println!("timeout");
+        }
+
+        while mutex.is_held() && !mutex.is_stopped() {
+            std::thread::sleep(POLL_PERIOD);
+        }
+    }
+
+    Ok(())
+}
+
+pub fn main(args: MyArgs) -> anyhow::Result<()> {
+    let mutex = SharedPiMutex::open(&args.mutex)?;
+
+    match args.role {
+        PiRole::Holder => holder(&mutex, &args),
+        PiRole::Interferer => interferer(&mutex),
+        PiRole::Waiter => waiter(&mutex, &args),
+    }
+}
@@ -0,0 +1,42 @@
+use hcbs_test_suite::prelude::*;
+
+#[derive(clap::Parser, Debug)]
+pub enum MyArgs {
+    /// Save the current scheduler state to file
+    #[command(name = "save")]
+    Save {
+        /// snapshot file
+        #[arg(short = 'o', long = "output", value_name = "path", default_value = "/tmp/hcbs_snapshot.json")]
+        output: String,
+    },
+
+    /// Restore the scheduler state from file
+    #[command(name = "restore")]
+    Restore {
+        /// snapshot file
+        #[arg(short = 'i', long = "input", value_name = "path", default_value = "/tmp/hcbs_snapshot.json")]
+        input: String,
+    },
+}
+
+pub fn main(args: MyArgs) -> anyhow::Result<()> {
+    match args {
+        MyArgs::Save { output } => {
+            let snapshot = SchedSnapshot::capture()?;
+            snapshot.save(&output)?;
+
+            if !is_batch_test() {
+                println!("Saved scheduler state to {output}");
+            }
+        },
+        MyArgs::Restore { input } => {
+            SchedSnapshot::load(&input)?.restore()?;
+
+            if !is_batch_test() {
+                println!("Restored scheduler state from {input}");
+            }
+        },
+    };
+
+    Ok(())
+}
@@ -0,0 +1,311 @@
+use crate::prelude::*;
+
+pub mod prelude {
+    pub use super::{
+        Capability,
+        CpuTopology,
+        check_requirement,
+        missing_requirements,
+        batch_test_requires,
+        set_deadline_policy,
+    };
+}
+
+/// Kernel features needed by (some of) the tests.
+///
+/// Capabilities are named by the same strings used as requirements in test
+/// plans and in the test catalog (e.g. `multicpu`, `fair_server`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    /// cgroup v2 filesystem mounted at /sys/fs/cgroup
+    CgroupV2,
+    /// cpu controller enabled for the root cgroup's children
+    CpuController,
+    /// cpuset controller available in the root cgroup
+    CpusetController,
+    /// cpu.rt_runtime_us and cpu.rt_period_us files in the root cgroup
+    RtGroup,
+    /// per-CPU runtime/period syntax for RT cgroups
+    MultiCpu,
+    /// debugfs mounted, with the scheduler's directory
+    DebugFs,
+    /// fair deadline servers in debugfs
+    FairServer,
+    /// ext deadline servers in debugfs
+    ExtServer,
+    /// HRTICK_DL scheduler feature
+    HrtickDl,
+    /// SCHED_DEADLINE tasks can be admitted, bandwidth permitting
+    SchedDeadline,
+    /// privilege to create SCHED_DEADLINE tasks (CAP_SYS_NICE, no
+    /// RLIMIT_RTTIME or affinity restrictions)
+    SchedDeadlinePrivilege,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 11] = [
+        Capability::CgroupV2,
+        Capability::CpuController,
+        Capability::CpusetController,
+        Capability::RtGroup,
+        Capability::MultiCpu,
+        Capability::DebugFs,
+        Capability::FairServer,
+        Capability::ExtServer,
+        Capability::HrtickDl,
+        Capability::SchedDeadline,
+        Capability::SchedDeadlinePrivilege,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Capability::CgroupV2 => "cgroup_v2",
+            Capability::CpuController => "cpu_controller",
+            Capability::CpusetController => "cpuset_controller",
+            Capability::RtGroup => "rt_group",
+            Capability::MultiCpu => "multicpu",
+            Capability::DebugFs => "debugfs",
+            Capability::FairServer => "fair_server",
+            Capability::ExtServer => "ext_server",
+            Capability::HrtickDl => "hrtick_dl",
+            Capability::SchedDeadline => "sched_deadline",
+            Capability::SchedDeadlinePrivilege => "sched_deadline_privilege",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Capability::CgroupV2 => "cgroup v2 filesystem mounted",
+            Capability::CpuController => "cgroup cpu controller enabled",
+            Capability::CpusetController => "cgroup cpuset controller available",
+            Capability::RtGroup => "RT cgroup runtime/period files",
+            Capability::MultiCpu => "per-CPU RT cgroup runtimes",
+            Capability::DebugFs => "debugfs scheduler directory",
+            Capability::FairServer => "fair deadline servers",
+            Capability::ExtServer => "ext deadline servers",
+            Capability::HrtickDl => "HRTICK_DL scheduler feature",
+            Capability::SchedDeadline => "SCHED_DEADLINE admission",
+            Capability::SchedDeadlinePrivilege => "SCHED_DEADLINE task privilege",
+        }
+    }
+
+    /// Check if the capability is available on the running system. Some of
+    /// the probes (multicpu, sched_deadline, sched_deadline_privilege) need
+    /// root privileges, as they create a cgroup or a SCHED_DEADLINE task.
+    pub fn probe(&self) -> anyhow::Result<bool> {
+        let fs = fs_backend();
+
+        match self {
+            Capability::CgroupV2 =>
+                Ok(fs.exists("/sys/fs/cgroup/cgroup.controllers")),
+            Capability::CpuController =>
+                Ok(fs.read_to_string("/sys/fs/cgroup/cgroup.subtree_control")
+                    .is_ok_and(|controllers| controllers.split_whitespace().any(|c| c == "cpu"))),
+            Capability::CpusetController =>
+                Ok(fs.read_to_string("/sys/fs/cgroup/cgroup.controllers")
+                    .is_ok_and(|controllers| controllers.split_whitespace().any(|c| c == "cpuset"))),
+            Capability::RtGroup =>
+                Ok(fs.exists("/sys/fs/cgroup/cpu.rt_runtime_us") &&
+                    fs.exists("/sys/fs/cgroup/cpu.rt_period_us")),
+            Capability::MultiCpu =>
+                Ok(Capability::RtGroup.probe()? && is_multicpu_enabled()?),
+            Capability::DebugFs =>
+                Ok(fs.exists("/sys/kernel/debug/sched")),
+            Capability::FairServer =>
+                Ok(fs.exists("/sys/kernel/debug/sched/fair_server")),
+            Capability::ExtServer =>
+                Ok(fs.exists("/sys/kernel/debug/sched/ext_server")),
+            Capability::HrtickDl =>
+                Ok(fs.read_to_string("/sys/kernel/debug/sched/features")
+                    .is_ok_and(|features| features.split_whitespace()
+                        .any(|f| f == "HRTICK_DL" || f == "NO_HRTICK_DL"))),
+            Capability::SchedDeadline =>
+                Ok(probe_sched_deadline()? != DeadlineProbe::Unsupported),
+            Capability::SchedDeadlinePrivilege =>
+                Ok(probe_sched_deadline()? != DeadlineProbe::NotPermitted),
+        }
+    }
+}
+
+impl std::fmt::Display for Capability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl std::str::FromStr for Capability {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Capability::ALL.into_iter()
+            .find(|cap| cap.as_str() == s)
+            .ok_or_else(|| anyhow::format_err!("Unknown capability \'{s}\'"))
+    }
+}
+
+/// struct sched_attr of sched_setattr(2).
+#[repr(C)]
+struct SchedAttr {
+    size: u32,
+    sched_policy: u32,
+    sched_flags: u64,
+    sched_nice: i32,
+    sched_priority: u32,
+    sched_runtime: u64,
+    sched_deadline: u64,
+    sched_period: u64,
+}
+
+/// Make the given task a SCHED_DEADLINE task (resetting on fork), through a
+/// direct sched_setattr(2) call, so that its errno is preserved: EBUSY means
+/// that admission control rejected the task.
+pub fn set_deadline_policy(pid: Pid, runtime_ns: u64, deadline_ns: u64, period_ns: u64) -> std::io::Result<()> {
+    const SCHED_DEADLINE: u32 = 6;
+    const SCHED_FLAG_RESET_ON_FORK: u64 = 1;
+
+    let attr = SchedAttr {
+        size: std::mem::size_of::<SchedAttr>() as u32,
+        sched_policy: SCHED_DEADLINE,
+        sched_flags: SCHED_FLAG_RESET_ON_FORK,
+        sched_nice: 0,
+        sched_priority: 0,
+        sched_runtime: runtime_ns,
+        sched_deadline: deadline_ns,
+        sched_period: period_ns,
+    };
+
+    let result = unsafe { libc::syscall(libc::SYS_sched_setattr, pid as libc::pid_t, &attr as *const SchedAttr, 0) };
+    if result != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Outcome of trying to admit a SCHED_DEADLINE task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DeadlineProbe {
+    Admitted,
+    /// admission rejected (EBUSY), the deadline bandwidth is saturated
+    Rejected,
+    /// SCHED_DEADLINE not supported by the kernel (ENOSYS, EINVAL)
+    Unsupported,
+    /// missing privilege (EPERM), e.g. no CAP_SYS_NICE, a finite
+    /// RLIMIT_RTTIME or a restricted affinity
+    NotPermitted,
+}
+
+/// Try to admit a SCHED_DEADLINE task. The syscall's errno tells a missing kernel feature (ENOSYS, EINVAL) or privilege (EPERM)
+/// apart from an admission rejection (EBUSY): in the latter case the
+/// capability is there, but the deadline bandwidth is saturated, and the
+/// tests requiring it must run (and fail) rather than be skipped.
+fn probe_sched_deadline() -> anyhow::Result<DeadlineProbe> {
+    let mut proc = run_yes()?;
+
+    let err = set_deadline_policy(proc.id(), 1_000_000, 100_000_000, 100_000_000).err();
+
+    if let HCBSProcess::Child(child) = &mut proc {
+        child.kill()?;
+        child.wait()?;
+    }
+
+    match err {
+        None => Ok(DeadlineProbe::Admitted),
+        Some(err) =>
+            match err.raw_os_error() {
+                Some(libc::EBUSY) => {
+                    log::warn!("SCHED_DEADLINE admission rejected, the deadline bandwidth is saturated");
+                    Ok(DeadlineProbe::Rejected)
+                },
+                Some(libc::ENOSYS | libc::EINVAL) => Ok(DeadlineProbe::Unsupported),
+                Some(libc::EPERM) => Ok(DeadlineProbe::NotPermitted),
+                _ => Err(anyhow::format_err!("Error in probing SCHED_DEADLINE admission: {err}")),
+            },
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CpuTopology {
+    /// online CPUs, in the kernel's list format (e.g. "0-3,5")
+    pub online: String,
+    pub num_cpus: u64,
+    pub num_packages: usize,
+    pub num_cores: usize,
+}
+
+impl CpuTopology {
+    pub fn probe() -> anyhow::Result<Self> {
+        use std::collections::HashSet;
+
+        let online = std::fs::read_to_string("/sys/devices/system/cpu/online")
+            .map_err(|err| anyhow::format_err!("Error in reading /sys/devices/system/cpu/online: {err}"))?
+            .trim().to_owned();
+
+        let cpus = CpuSet::all()?;
+        let mut packages = HashSet::new();
+        let mut cores = HashSet::new();
+        for cpu in cpus.iter() {
+            let topology = format!("/sys/devices/system/cpu/cpu{cpu}/topology");
+            let package = std::fs::read_to_string(format!("{topology}/physical_package_id"))
+                .map(|id| id.trim().to_owned())
+                .unwrap_or_default();
+            let core = std::fs::read_to_string(format!("{topology}/core_id"))
+                .map(|id| id.trim().to_owned())
+                .unwrap_or_else(|_| format!("{cpu}"));
+
+            packages.insert(package.clone());
+            cores.insert((package, core));
+        }
+
+        Ok(Self {
+            online,
+            num_cpus: cpus.num_cpus(),
+            num_packages: packages.len(),
+            num_cores: cores.len(),
+        })
+    }
+}
+
+/// Check if a requirement, i.e. a capability name, is satisfied by the running
+/// system. Requirements can be negated by prefixing them with '!'.
+pub fn check_requirement(requirement: &str) -> anyhow::Result<bool> {
+    let (negated, name) =
+        match requirement.strip_prefix('!') {
+            Some(name) => (true, name),
+            None => (false, requirement),
+        };
+
+    let available = name.parse::<Capability>()?.probe()?;
+
+    Ok(available != negated)
+}
+
+pub fn missing_requirements<S: AsRef<str>>(requirements: &[S]) -> anyhow::Result<Vec<String>> {
+    let mut missing = Vec::new();
+    for requirement in requirements.iter() {
+        if !check_requirement(requirement.as_ref())? {
+            missing.push(requirement.as_ref().to_owned());
+        }
+    }
+
+    Ok(missing)
+}
+
+/// Check the capabilities required by the test started by the last
+/// `batch_test_header`. If any is missing the test is reported as skipped and
+/// false is returned.
+pub fn batch_test_requires(capabilities: &[Capability]) -> anyhow::Result<bool> {
+    let mut missing = Vec::new();
+    for capability in capabilities.iter() {
+        if !capability.probe()? {
+            missing.push(capability.as_str());
+        }
+    }
+
+    if missing.is_empty() {
+        Ok(true)
+    } else {
+        batch_test_skipped(format!("missing capabilities: {}", missing.join(", ")));
+        Ok(false)
+    }
+}
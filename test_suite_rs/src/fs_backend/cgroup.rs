@@ -0,0 +1,424 @@
+//! cgroup v2 operations on top of the filesystem backend.
+//!
+//! Cgroups are named relative to the cgroup filesystem's root, with "." being
+//! the root cgroup itself, as in `hcbs_utils`.
+
+use crate::prelude::*;
+use super::fs_backend;
+
+pub const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+pub fn cgroup_dir(name: &str) -> String {
+    match name.trim_matches('/') {
+        "" | "." => CGROUP_ROOT.to_owned(),
+        name => format!("{CGROUP_ROOT}/{name}"),
+    }
+}
+
+pub fn exists(name: &str) -> bool {
+    fs_backend().is_dir(&cgroup_dir(name))
+}
+
+/// Create a cgroup, which is tracked by the current supervisor (if any).
+pub fn create(name: &str) -> anyhow::Result<()> {
+    fs_backend().create_dir(&cgroup_dir(name))?;
+    supervise_cgroup(name);
+
+    Ok(())
+}
+
+pub fn remove(name: &str) -> anyhow::Result<()> {
+    fs_backend().remove_dir(&cgroup_dir(name))
+}
+
+fn read_first_value(file: &str) -> anyhow::Result<u64> {
+    fs_backend().read_to_string(file)?
+        .split_whitespace().next()
+        .ok_or_else(|| anyhow::format_err!("Empty file {file}"))?
+        .parse::<u64>()
+        .map_err(|err| anyhow::format_err!("Error in parsing {file}: {err}"))
+}
+
+pub fn get_runtime_us(name: &str) -> anyhow::Result<u64> {
+    read_first_value(&format!("{}/cpu.rt_runtime_us", cgroup_dir(name)))
+}
+
+pub fn get_period_us(name: &str) -> anyhow::Result<u64> {
+    read_first_value(&format!("{}/cpu.rt_period_us", cgroup_dir(name)))
+}
+
+pub fn set_runtime_us(name: &str, runtime_us: u64) -> anyhow::Result<()> {
+    set_runtime_us_multi_str(name, &format!("{runtime_us}"))
+}
+
+pub fn set_period_us(name: &str, period_us: u64) -> anyhow::Result<()> {
+    set_period_us_multi_str(name, &format!("{period_us}"))
+}
+
+/// Set per-CPU runtimes, given as a list of "<runtime> <cpu set>" pairs.
+pub fn set_runtime_us_multi_str(name: &str, runtimes_us: &str) -> anyhow::Result<()> {
+    fs_backend().write(&format!("{}/cpu.rt_runtime_us", cgroup_dir(name)), runtimes_us)
+}
+
+/// Set per-CPU periods, given as a list of "<period> <cpu set>" pairs.
+pub fn set_period_us_multi_str(name: &str, periods_us: &str) -> anyhow::Result<()> {
+    fs_backend().write(&format!("{}/cpu.rt_period_us", cgroup_dir(name)), periods_us)
+}
+
+/// Set the CFS bandwidth limit (cpu.max) of a cgroup, or remove it if None.
+pub fn set_cpu_max_us(name: &str, max_us: Option<(u64, u64)>) -> anyhow::Result<()> {
+    let data =
+        match max_us {
+            Some((quota_us, period_us)) => format!("{quota_us} {period_us}"),
+            None => "max".to_owned(),
+        };
+
+    fs_backend().write(&format!("{}/cpu.max", cgroup_dir(name)), &data)
+}
+
+/// Set the CFS weight (cpu.weight) of a cgroup.
+pub fn set_cpu_weight(name: &str, weight: u64) -> anyhow::Result<()> {
+    fs_backend().write(&format!("{}/cpu.weight", cgroup_dir(name)), &format!("{weight}"))
+}
+
+/// Check if a controller (e.g. "cpuset") is enabled for the children of a
+/// cgroup.
+pub fn is_controller_enabled(name: &str, controller: &str) -> anyhow::Result<bool> {
+    Ok(fs_backend().read_to_string(&format!("{}/cgroup.subtree_control", cgroup_dir(name)))?
+        .split_whitespace()
+        .any(|enabled| enabled == controller))
+}
+
+/// Enable or disable a controller (e.g. "cpuset") for the children of a
+/// cgroup.
+pub fn set_controller_enabled(name: &str, controller: &str, enabled: bool) -> anyhow::Result<()> {
+    let sign = if enabled { '+' } else { '-' };
+
+    fs_backend().write(&format!("{}/cgroup.subtree_control", cgroup_dir(name)), &format!("{sign}{controller}"))
+}
+
+/// Controller enabled for the children of a cgroup, which is disabled again
+/// when the guard is dropped (normal exit, error or panic), unless it was
+/// already enabled beforehand.
+pub struct ControllerGuard {
+    name: String,
+    controller: String,
+    restore: bool,
+}
+
+impl ControllerGuard {
+    pub fn enable(name: &str, controller: &str) -> anyhow::Result<Self> {
+        let restore = !is_controller_enabled(name, controller)?;
+        if restore {
+            set_controller_enabled(name, controller, true)?;
+        }
+
+        Ok(Self { name: name.to_owned(), controller: controller.to_owned(), restore })
+    }
+
+    /// Restore the controller now, reporting any error to the caller.
+    pub fn restore(mut self) -> anyhow::Result<()> {
+        self.restore_impl()
+    }
+
+    fn restore_impl(&mut self) -> anyhow::Result<()> {
+        if !std::mem::take(&mut self.restore) {
+            return Ok(());
+        }
+
+        set_controller_enabled(&self.name, &self.controller, false)
+    }
+}
+
+impl Drop for ControllerGuard {
+    fn drop(&mut self) {
+        if let Err(err) = self.restore_impl() {
+            println!("Error in disabling the {} controller: {err}", self.controller);
+        }
+    }
+}
+
+/// Set the cpus allowed to the tasks of a cgroup (cpuset.cpus), given as a
+/// cpu list (e.g. "0-2,4"). An empty list inherits the parent's cpus.
+pub fn set_cpuset_cpus(name: &str, cpus: &str) -> anyhow::Result<()> {
+    fs_backend().write(&format!("{}/cpuset.cpus", cgroup_dir(name)), cpus)
+}
+
+/// Cpus the tasks of a cgroup can actually run on (cpuset.cpus.effective).
+pub fn get_cpuset_cpus_effective(name: &str) -> anyhow::Result<Vec<CpuID>> {
+    let file = format!("{}/cpuset.cpus.effective", cgroup_dir(name));
+
+    parse_cpu_list(&fs_backend().read_to_string(&file)?)
+        .map_err(|err| anyhow::format_err!("Error in parsing {file}: {err}"))
+}
+
+/// Parse a kernel cpu list (e.g. "0-2,4"), as found in the cpuset files and
+/// in /proc/<pid>/status.
+pub fn parse_cpu_list(list: &str) -> anyhow::Result<Vec<CpuID>> {
+    let mut cpus = Vec::new();
+    for range in list.trim().split(',').filter(|range| !range.is_empty()) {
+        match range.split_once('-') {
+            Some((first, last)) => cpus.extend(first.parse::<CpuID>()?..=last.parse::<CpuID>()?),
+            None => cpus.push(range.parse::<CpuID>()?),
+        }
+    }
+
+    Ok(cpus)
+}
+
+pub fn assign_pid(name: &str, pid: Pid) -> anyhow::Result<()> {
+    fs_backend().write(&format!("{}/cgroup.procs", cgroup_dir(name)), &format!("{pid}"))
+}
+
+/// Threads of any process inside the cgroup (cgroup.threads).
+pub fn get_threads(name: &str) -> anyhow::Result<Vec<Pid>> {
+    let file = format!("{}/cgroup.threads", cgroup_dir(name));
+
+    fs_backend().read_to_string(&file)?
+        .split_whitespace()
+        .map(|tid| tid.parse::<Pid>()
+            .map_err(|err| anyhow::format_err!("Error in parsing {file}: {err}")))
+        .collect()
+}
+
+pub fn get_pid_cgroup(pid: Pid) -> anyhow::Result<String> {
+    let file = format!("/proc/{pid}/cgroup");
+    let data = fs_backend().read_to_string(&file)?;
+
+    let path = data.lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .ok_or_else(|| anyhow::format_err!("No cgroup v2 entry in {file}"))?
+        .trim_matches('/');
+
+    if path.is_empty() {
+        Ok(".".to_owned())
+    } else {
+        Ok(path.to_owned())
+    }
+}
+
+/// Scheduling policy number (SCHED_OTHER = 0, SCHED_FIFO = 1, ...) of the
+/// given task, read from /proc/<pid>/stat.
+pub fn get_pid_policy_id(pid: Pid) -> anyhow::Result<u32> {
+    let file = format!("/proc/{pid}/stat");
+    let stats = fs_backend().read_to_string(&file)?;
+
+    // skip the command name, which may contain spaces
+    let (_, stats) = stats.rsplit_once(')')
+        .ok_or_else(|| anyhow::format_err!("Error in reading {file}"))?;
+
+    // the policy is the 41st field, the first after the name being the 3rd.
+    stats.split_whitespace().nth(41 - 3)
+        .ok_or_else(|| anyhow::format_err!("Error in reading {file}"))?
+        .parse::<u32>()
+        .map_err(|err| anyhow::format_err!("Error in parsing {file}: {err}"))
+}
+
+pub fn policy_id(policy: &SchedPolicy) -> u32 {
+    match policy {
+        SchedPolicy::OTHER { .. } => 0,
+        SchedPolicy::FIFO(_) => 1,
+        SchedPolicy::RR(_) => 2,
+        SchedPolicy::BATCH { .. } => 3,
+        SchedPolicy::IDLE => 5,
+        SchedPolicy::DEADLINE { .. } => 6,
+    }
+}
+
+pub fn is_rt_policy_id(policy: u32) -> bool {
+    // SCHED_FIFO, SCHED_RR
+    policy == 1 || policy == 2
+}
+
+/// Format per-CPU values as a list of "<value> <cpu list>" pairs.
+fn multi_str<I, C>(values_us: I) -> String
+    where I: IntoIterator<Item = (u64, C)>, C: IntoIterator<Item = CpuID>
+{
+    values_us.into_iter()
+        .map(|(value_us, cpus)| {
+            let cpus = cpus.into_iter()
+                .map(|cpu| cpu.to_string())
+                .collect::<Vec<_>>()
+                .join(",");
+
+            format!("{value_us} {cpus}")
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// RAII cgroup on top of the filesystem backend, created if missing and
+/// tracked by the current supervisor (if any). When dropped, the processes
+/// assigned to it are killed (or, without force kill, moved back to the root
+/// cgroup), and the cgroup is destroyed.
+pub struct FsCgroup {
+    name: String,
+    procs: Vec<HCBSProcess>,
+    force_kill: bool,
+}
+
+impl FsCgroup {
+    pub fn new(name: &str) -> anyhow::Result<Self> {
+        if !exists(name) {
+            create(name)?;
+        }
+
+        Ok(Self { name: name.to_owned(), procs: Vec::new(), force_kill: true })
+    }
+
+    /// Kill the cgroup's child processes when dropped, instead of moving them
+    /// back to the root cgroup.
+    pub fn with_force_kill(mut self, force_kill: bool) -> Self {
+        self.force_kill = force_kill;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn set_runtime_us(&mut self, runtime_us: u64) -> anyhow::Result<()> {
+        set_runtime_us(&self.name, runtime_us)
+    }
+
+    pub fn set_period_us(&mut self, period_us: u64) -> anyhow::Result<()> {
+        set_period_us(&self.name, period_us)
+    }
+
+    pub fn set_runtime_us_multi<I, C>(&mut self, runtimes_us: I) -> anyhow::Result<()>
+        where I: IntoIterator<Item = (u64, C)>, C: IntoIterator<Item = CpuID>
+    {
+        set_runtime_us_multi_str(&self.name, &multi_str(runtimes_us))
+    }
+
+    pub fn set_period_us_multi<I, C>(&mut self, periods_us: I) -> anyhow::Result<()>
+        where I: IntoIterator<Item = (u64, C)>, C: IntoIterator<Item = CpuID>
+    {
+        set_period_us_multi_str(&self.name, &multi_str(periods_us))
+    }
+
+    pub fn set_runtime_us_multi_str(&mut self, runtimes_us: &str) -> anyhow::Result<()> {
+        set_runtime_us_multi_str(&self.name, runtimes_us)
+    }
+
+    pub fn set_period_us_multi_str(&mut self, periods_us: &str) -> anyhow::Result<()> {
+        set_period_us_multi_str(&self.name, periods_us)
+    }
+
+    pub fn assign_process(&mut self, proc: HCBSProcess) -> Result<&mut HCBSProcess, (HCBSProcess, anyhow::Error)> {
+        match assign_pid(&self.name, proc.id()) {
+            Ok(_) => {
+                self.procs.push(proc);
+                Ok(self.procs.last_mut().unwrap())
+            },
+            Err(err) => Err((proc, err)),
+        }
+    }
+
+    /// Move a process back to the root cgroup, giving back its ownership.
+    pub fn take_process(&mut self, pid: Pid) -> anyhow::Result<HCBSProcess> {
+        let index = self.procs.iter()
+            .position(|proc| proc.id() == pid)
+            .ok_or_else(|| anyhow::format_err!("Process {pid} is not in cgroup {}", self.name))?;
+
+        assign_pid(".", pid)?;
+
+        Ok(self.procs.remove(index))
+    }
+
+    /// Kill the cgroup's child processes and destroy it.
+    pub fn force_destroy(self) {
+        drop(self.with_force_kill(true));
+    }
+}
+
+impl Drop for FsCgroup {
+    fn drop(&mut self) {
+        for mut proc in self.procs.drain(..) {
+            match &mut proc {
+                HCBSProcess::Child(child) if self.force_kill => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                },
+                _ => { let _ = assign_pid(".", proc.id()); },
+            }
+        }
+
+        if self.name != "." {
+            let _ = remove(&self.name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs_backend::fake::FakeFs;
+
+    #[test]
+    fn cgroup_files() {
+        let (_lock, fake) = FakeFs::new(2).install_for_test();
+
+        assert_eq!(cgroup_dir("."), CGROUP_ROOT);
+        assert_eq!(cgroup_dir("/g0/"), format!("{CGROUP_ROOT}/g0"));
+        assert_eq!(get_runtime_us(".").unwrap(), 950_000);
+        assert_eq!(get_period_us(".").unwrap(), 1_000_000);
+
+        assert!(!exists("g0"));
+        create("g0").unwrap();
+        assert!(exists("g0"));
+        assert_eq!(get_runtime_us("g0").unwrap(), 0);
+
+        set_period_us("g0", 100_000).unwrap();
+        set_runtime_us("g0", 40_000).unwrap();
+        assert_eq!(get_runtime_us("g0").unwrap(), 40_000);
+        assert_eq!(get_period_us("g0").unwrap(), 100_000);
+
+        // the first value is the runtime of cpu 0
+        set_runtime_us_multi_str("g0", "10000 0 20000 1").unwrap();
+        assert_eq!(get_runtime_us("g0").unwrap(), 10_000);
+
+        // more than the parent's bandwidth
+        assert!(set_runtime_us("g0", 100_000).is_err());
+        assert_eq!(get_runtime_us("g0").unwrap(), 10_000);
+
+        fake.add_task(1_000_001, 1, "g0").unwrap();
+        assert_eq!(get_threads("g0").unwrap(), vec![1_000_001]);
+        assert_eq!(get_pid_cgroup(1_000_001).unwrap(), "g0");
+        assert_eq!(get_pid_policy_id(1_000_001).unwrap(), 1);
+        assert!(is_rt_policy_id(get_pid_policy_id(1_000_001).unwrap()));
+
+        // busy cgroups cannot be removed
+        assert!(remove("g0").is_err());
+        fake.add_task(1_000_001, 1, ".").unwrap();
+        assert_eq!(get_pid_cgroup(1_000_001).unwrap(), ".");
+        remove("g0").unwrap();
+        assert!(!exists("g0"));
+    }
+
+    #[test]
+    fn fs_cgroup_is_removed_on_drop() {
+        let (_lock, _fake) = FakeFs::new(3).install_for_test();
+
+        {
+            let mut cgroup = FsCgroup::new("g1").unwrap();
+            cgroup.set_period_us(100_000).unwrap();
+            cgroup.set_runtime_us(50_000).unwrap();
+            assert!(exists(cgroup.name()));
+
+            cgroup.set_runtime_us_multi([(10_000, vec![0]), (20_000, vec![1, 2])]).unwrap();
+            assert_eq!(get_runtime_us("g1").unwrap(), 10_000);
+            assert!(cgroup.take_process(1_000_001).is_err());
+        }
+
+        assert!(!exists("g1"));
+    }
+
+    #[test]
+    fn cpu_lists() {
+        assert_eq!(parse_cpu_list("0-2,4\n").unwrap(), vec![0, 1, 2, 4]);
+        assert_eq!(parse_cpu_list("3").unwrap(), vec![3]);
+        assert_eq!(parse_cpu_list("").unwrap(), Vec::<CpuID>::new());
+        assert!(parse_cpu_list("0-a").is_err());
+    }
+}
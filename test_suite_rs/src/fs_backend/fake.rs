@@ -0,0 +1,642 @@
+//! In-memory fake of the kernel's pseudo filesystems, emulating the HCBS
+//! admission rules, to run the suite's logic on a stock kernel without root.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
+
+use crate::prelude::*;
+use super::{FsBackend, set_fs_backend};
+use super::cgroup::{CGROUP_ROOT, is_rt_policy_id, policy_id};
+
+const EINVAL: i32 = 22;
+const EBUSY: i32 = 16;
+const ENOENT: i32 = 2;
+const EEXIST: i32 = 17;
+
+const CGROUP_FILES: [&str; 6] = [
+    "cgroup.controllers",
+    "cgroup.subtree_control",
+    "cgroup.procs",
+    "cgroup.threads",
+    "cpu.rt_runtime_us",
+    "cpu.rt_period_us",
+];
+
+#[derive(Debug, Clone)]
+struct FakeCgroup {
+    runtime_us: Vec<u64>,
+    period_us: Vec<u64>,
+    procs: BTreeSet<Pid>,
+}
+
+#[derive(Debug, Clone)]
+struct FakeTask {
+    policy: u32,
+}
+
+#[derive(Debug)]
+struct FakeState {
+    num_cpus: usize,
+    multicpu: bool,
+    files: BTreeMap<String, String>,
+    dirs: BTreeSet<String>,
+    cgroups: BTreeMap<String, FakeCgroup>,
+    tasks: BTreeMap<Pid, FakeTask>,
+    external_policies: BTreeMap<Pid, u32>,
+}
+
+/// In-memory filesystem backend.
+///
+/// It provides the cgroup v2 filesystem with the cpu controller and the HCBS
+/// files, the fair (and optionally ext) servers and scheduler features in
+/// debugfs, the `sched_rt_*` sysctls and the /proc entries of the fake tasks.
+/// Writes to the cgroup files follow the HCBS admission rules: runtime not
+/// greater than period, runtime of at least 1024ns (or zero), period lower
+/// than 2^63ns, children's bandwidth not greater than the parent's one, no
+/// zero runtime with real-time tasks in the cgroup.
+///
+/// Processes not added with `add_task` (e.g. spawned with `run_yes`) can be
+/// moved into cgroups; their policy is the one last set through the backend,
+/// or else the one read from the running system.
+pub struct FakeFs {
+    state: Mutex<FakeState>,
+}
+
+fn os_error(path: &str, errno: i32) -> anyhow::Error {
+    anyhow::format_err!("{path}: {}", std::io::Error::from_raw_os_error(errno))
+}
+
+fn write_error(path: &str, data: &str, errno: i32) -> anyhow::Error {
+    anyhow::format_err!("Error in writing {data} to {path}: {}", std::io::Error::from_raw_os_error(errno))
+}
+
+fn parent_dir(path: &str) -> &str {
+    match path.rsplit_once('/') {
+        Some(("", _)) => "/",
+        Some((parent, _)) => parent,
+        None => "",
+    }
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit_once('/').map_or(path, |(_, name)| name)
+}
+
+/// Parse a cpu list (e.g. "0-2,5").
+fn parse_cpu_list(list: &str) -> Option<Vec<usize>> {
+    let mut cpus = Vec::new();
+    for range in list.split(',') {
+        match range.split_once('-') {
+            Some((first, last)) => {
+                let (first, last) = (first.parse::<usize>().ok()?, last.parse::<usize>().ok()?);
+                if first > last {
+                    return None;
+                }
+
+                cpus.extend(first..=last);
+            },
+            None => cpus.push(range.parse().ok()?),
+        }
+    }
+
+    Some(cpus)
+}
+
+impl FakeFs {
+    /// New fake system with the given number of CPUs, multi-CPU runtimes
+    /// enabled and the default bandwidths (95% for the root cgroup, 5% for the
+    /// fair servers).
+    pub fn new(num_cpus: usize) -> Self {
+        let mut state = FakeState {
+            num_cpus,
+            multicpu: true,
+            files: BTreeMap::new(),
+            dirs: BTreeSet::new(),
+            cgroups: BTreeMap::new(),
+            tasks: BTreeMap::new(),
+            external_policies: BTreeMap::new(),
+        };
+
+        for dir in ["/", "/proc", "/proc/sys", "/proc/sys/kernel", "/sys", "/sys/fs",
+                    "/sys/kernel", "/sys/kernel/debug", "/sys/kernel/debug/sched",
+                    "/sys/devices", "/sys/devices/system", "/sys/devices/system/cpu"] {
+            state.dirs.insert(dir.to_owned());
+        }
+
+        state.cgroups.insert(CGROUP_ROOT.to_owned(), FakeCgroup {
+            runtime_us: vec![950_000; num_cpus],
+            period_us: vec![1_000_000; num_cpus],
+            procs: BTreeSet::new(),
+        });
+
+        state.files.insert("/proc/sys/kernel/sched_rt_period_us".to_owned(), "1000000".to_owned());
+        state.files.insert("/proc/sys/kernel/sched_rt_runtime_us".to_owned(), "950000".to_owned());
+        state.files.insert("/proc/uptime".to_owned(), "1000.00 1000.00".to_owned());
+        state.files.insert("/sys/kernel/debug/sched/features".to_owned(), "NO_HRTICK HRTICK_DL".to_owned());
+        state.files.insert("/sys/devices/system/cpu/online".to_owned(),
+            if num_cpus > 1 { format!("0-{}", num_cpus - 1) } else { "0".to_owned() });
+        state.add_servers("/sys/kernel/debug/sched/fair_server", 50_000_000, 1_000_000_000);
+
+        Self { state: Mutex::new(state) }
+    }
+
+    pub fn with_multicpu(self, multicpu: bool) -> Self {
+        self.state.lock().unwrap().multicpu = multicpu;
+        self
+    }
+
+    pub fn with_ext_servers(self, runtime_ns: u64, period_ns: u64) -> Self {
+        self.state.lock().unwrap().add_servers("/sys/kernel/debug/sched/ext_server", runtime_ns, period_ns);
+        self
+    }
+
+    /// Add or replace a plain file (e.g. to emulate a sysctl or debugfs entry).
+    pub fn with_file(self, path: &str, data: &str) -> Self {
+        let mut state = self.state.lock().unwrap();
+        state.files.insert(path.to_owned(), data.to_owned());
+        drop(state);
+
+        self
+    }
+
+    /// Add a fake task with the given policy number (SCHED_OTHER = 0,
+    /// SCHED_FIFO = 1, SCHED_RR = 2, ...) to the given cgroup. Admission rules
+    /// are not checked.
+    pub fn add_task(&self, pid: Pid, policy: u32, cgroup: &str) -> anyhow::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let dir = super::cgroup::cgroup_dir(cgroup);
+        if !state.cgroups.contains_key(&dir) {
+            return Err(os_error(&dir, ENOENT));
+        }
+
+        state.remove_from_cgroups(pid);
+        state.cgroups.get_mut(&dir).unwrap().procs.insert(pid);
+        state.tasks.insert(pid, FakeTask { policy });
+
+        Ok(())
+    }
+
+    pub fn remove_task(&self, pid: Pid) {
+        let mut state = self.state.lock().unwrap();
+        state.remove_from_cgroups(pid);
+        state.tasks.remove(&pid);
+    }
+
+    /// Make this fake the current filesystem backend.
+    pub fn install(self) -> Arc<Self> {
+        let fake = Arc::new(self);
+        set_fs_backend(fake.clone());
+        fake
+    }
+
+    /// Install this fake for a unit test. The backend is global, thus the
+    /// tests using it are serialized through the returned guard.
+    #[cfg(test)]
+    pub(crate) fn install_for_test(self) -> (std::sync::MutexGuard<'static, ()>, Arc<Self>) {
+        static TEST_LOCK: Mutex<()> = Mutex::new(());
+
+        let guard = TEST_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+        (guard, self.install())
+    }
+}
+
+impl FakeState {
+    fn add_servers(&mut self, dir: &str, runtime_ns: u64, period_ns: u64) {
+        self.dirs.insert(dir.to_owned());
+        for cpu in 0..self.num_cpus {
+            let cpu_dir = format!("{dir}/cpu{cpu}");
+            self.dirs.insert(cpu_dir.clone());
+            self.files.insert(format!("{cpu_dir}/runtime"), format!("{runtime_ns}"));
+            self.files.insert(format!("{cpu_dir}/period"), format!("{period_ns}"));
+        }
+    }
+
+    fn remove_from_cgroups(&mut self, pid: Pid) {
+        self.cgroups.values_mut()
+            .for_each(|cgroup| { cgroup.procs.remove(&pid); });
+    }
+
+    /// Forget terminated processes of the running system.
+    fn prune_external_tasks(&mut self) {
+        let tasks = &self.tasks;
+        let is_alive = |pid: &Pid| tasks.contains_key(pid) ||
+            std::fs::exists(format!("/proc/{pid}")).unwrap_or(false);
+
+        self.cgroups.values_mut()
+            .for_each(|cgroup| cgroup.procs.retain(is_alive));
+        self.external_policies.retain(|pid, _| is_alive(pid));
+    }
+
+    fn is_rt_task(&self, pid: Pid) -> bool {
+        match (self.tasks.get(&pid), self.external_policies.get(&pid)) {
+            (Some(task), _) => is_rt_policy_id(task.policy),
+            (None, Some(policy)) => is_rt_policy_id(*policy),
+            (None, None) => matches!(get_sched_policy(pid), Ok((SchedPolicy::FIFO(..) | SchedPolicy::RR(..), _))),
+        }
+    }
+
+    fn task_cgroup(&self, pid: Pid) -> Option<&str> {
+        self.cgroups.iter()
+            .find(|(_, cgroup)| cgroup.procs.contains(&pid))
+            .map(|(dir, _)| dir.as_str())
+    }
+
+    fn cgroup_children(&self, dir: &str) -> Vec<String> {
+        self.cgroups.keys()
+            .filter(|child| parent_dir(child) == dir)
+            .cloned()
+            .collect()
+    }
+
+    fn cgroup_file(&self, path: &str) -> Option<(String, &'static str)> {
+        let dir = parent_dir(path);
+        let name = file_name(path);
+
+        CGROUP_FILES.into_iter()
+            .find(|file| *file == name)
+            .filter(|_| self.cgroups.contains_key(dir))
+            .map(|file| (dir.to_owned(), file))
+    }
+
+    fn read_per_cpu(values: &[u64]) -> String {
+        if values.iter().all(|v| *v == values[0]) {
+            format!("{}\n", values[0])
+        } else {
+            values.iter().enumerate()
+                .map(|(cpu, v)| format!("{v} {cpu}"))
+                .collect::<Vec<_>>()
+                .join(" ") + "\n"
+        }
+    }
+
+    /// Parse either a single value or a list of "<value> <cpu list>" pairs,
+    /// returning the new per-CPU values.
+    fn parse_per_cpu(&self, data: &str, current: &[u64]) -> Option<Vec<u64>> {
+        let tokens: Vec<_> = data.split_whitespace().collect();
+
+        match tokens.as_slice() {
+            [value] => Some(vec![value.parse().ok()?; self.num_cpus]),
+            pairs if self.multicpu && !pairs.is_empty() && pairs.len() % 2 == 0 => {
+                let mut values = current.to_vec();
+                for pair in pairs.chunks(2) {
+                    let value = pair[0].parse().ok()?;
+                    for cpu in parse_cpu_list(pair[1])? {
+                        *values.get_mut(cpu)? = value;
+                    }
+                }
+
+                Some(values)
+            },
+            _ => None,
+        }
+    }
+
+    fn bandwidth(runtime_us: u64, period_us: u64) -> f64 {
+        runtime_us as f64 / period_us as f64
+    }
+
+    /// Check that the given cgroup, with the given parameters, fits into its
+    /// parent, and that its children still fit into it.
+    fn check_admission(&self, dir: &str, runtime_us: &[u64], period_us: &[u64]) -> bool {
+        for cpu in 0..self.num_cpus {
+            let bw = Self::bandwidth(runtime_us[cpu], period_us[cpu]);
+
+            if dir != CGROUP_ROOT {
+                let parent = parent_dir(dir);
+                let parent_cgroup = &self.cgroups[parent];
+                let parent_bw = Self::bandwidth(parent_cgroup.runtime_us[cpu], parent_cgroup.period_us[cpu]);
+
+                let siblings_bw: f64 = self.cgroup_children(parent).iter()
+                    .filter(|sibling| sibling.as_str() != dir)
+                    .map(|sibling| &self.cgroups[sibling])
+                    .map(|sibling| Self::bandwidth(sibling.runtime_us[cpu], sibling.period_us[cpu]))
+                    .sum();
+
+                if bw + siblings_bw > parent_bw + 1e-9 {
+                    return false;
+                }
+            }
+
+            let children_bw: f64 = self.cgroup_children(dir).iter()
+                .map(|child| &self.cgroups[child])
+                .map(|child| Self::bandwidth(child.runtime_us[cpu], child.period_us[cpu]))
+                .sum();
+
+            if children_bw > bw + 1e-9 {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    fn has_rt_tasks(&self, dir: &str) -> bool {
+        self.cgroups[dir].procs.iter().any(|pid| self.is_rt_task(*pid))
+    }
+
+    fn write_runtime(&mut self, dir: &str, path: &str, data: &str) -> anyhow::Result<()> {
+        let cgroup = &self.cgroups[dir];
+        let runtime_us = self.parse_per_cpu(data, &cgroup.runtime_us)
+            .ok_or_else(|| write_error(path, data, EINVAL))?;
+
+        for (runtime_us, period_us) in runtime_us.iter().zip(cgroup.period_us.iter()) {
+            let runtime_ns = runtime_us.saturating_mul(1000);
+            if runtime_us > period_us || (runtime_ns > 0 && runtime_ns < 1024) {
+                return Err(write_error(path, data, EINVAL));
+            }
+        }
+
+        if !self.check_admission(dir, &runtime_us, &cgroup.period_us) {
+            return Err(write_error(path, data, EBUSY));
+        }
+
+        if dir != CGROUP_ROOT && runtime_us.iter().all(|r| *r == 0) && self.has_rt_tasks(dir) {
+            return Err(write_error(path, data, EBUSY));
+        }
+
+        self.cgroups.get_mut(dir).unwrap().runtime_us = runtime_us;
+        Ok(())
+    }
+
+    fn write_period(&mut self, dir: &str, path: &str, data: &str) -> anyhow::Result<()> {
+        let cgroup = &self.cgroups[dir];
+        let period_us = self.parse_per_cpu(data, &cgroup.period_us)
+            .ok_or_else(|| write_error(path, data, EINVAL))?;
+
+        for (period_us, runtime_us) in period_us.iter().zip(cgroup.runtime_us.iter()) {
+            // the period in ns must fit in a signed 64-bit integer
+            if *period_us == 0 || *period_us > (i64::MAX as u64) / 1000 || period_us < runtime_us {
+                return Err(write_error(path, data, EINVAL));
+            }
+        }
+
+        if !self.check_admission(dir, &cgroup.runtime_us, &period_us) {
+            return Err(write_error(path, data, EBUSY));
+        }
+
+        self.cgroups.get_mut(dir).unwrap().period_us = period_us;
+        Ok(())
+    }
+
+    fn write_procs(&mut self, dir: &str, path: &str, data: &str) -> anyhow::Result<()> {
+        let pid: Pid = data.trim().parse()
+            .map_err(|_| write_error(path, data, EINVAL))?;
+
+        if !self.tasks.contains_key(&pid) && !std::fs::exists(format!("/proc/{pid}")).unwrap_or(false) {
+            return Err(write_error(path, data, ENOENT));
+        }
+
+        let cgroup = &self.cgroups[dir];
+        if dir != CGROUP_ROOT && cgroup.runtime_us.iter().all(|r| *r == 0) && self.is_rt_task(pid) {
+            return Err(write_error(path, data, EINVAL));
+        }
+
+        self.remove_from_cgroups(pid);
+        self.cgroups.get_mut(dir).unwrap().procs.insert(pid);
+        Ok(())
+    }
+
+    fn write_server(&mut self, path: &str, data: &str) -> anyhow::Result<()> {
+        let value: u64 = data.trim().parse()
+            .map_err(|_| write_error(path, data, EINVAL))?;
+
+        let dir = parent_dir(path);
+        let other = if file_name(path) == "runtime" { "period" } else { "runtime" };
+        let other: u64 = self.files[&format!("{dir}/{other}")].trim().parse()?;
+
+        let (runtime, period) = if file_name(path) == "runtime" { (value, other) } else { (other, value) };
+        if runtime > period {
+            return Err(write_error(path, data, EINVAL));
+        }
+
+        self.files.insert(path.to_owned(), format!("{value}"));
+        Ok(())
+    }
+
+    fn write_features(&mut self, path: &str, data: &str) -> anyhow::Result<()> {
+        let feature = data.trim();
+        let name = feature.strip_prefix("NO_").unwrap_or(feature);
+
+        let features: Vec<String> = self.files[path].split_whitespace().map(|f| f.to_owned()).collect();
+        if !features.iter().any(|f| f.strip_prefix("NO_").unwrap_or(f) == name) {
+            return Err(write_error(path, data, EINVAL));
+        }
+
+        let features = features.into_iter()
+            .map(|f| if f.strip_prefix("NO_").unwrap_or(&f) == name { feature.to_owned() } else { f })
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        self.files.insert(path.to_owned(), features);
+        Ok(())
+    }
+
+    fn proc_entry(&self, path: &str) -> Option<(Pid, Option<&'static str>)> {
+        let rest = path.strip_prefix("/proc/")?;
+        let (pid, file) =
+            match rest.split_once('/') {
+                Some((pid, file)) => (pid, Some(file)),
+                None => (rest, None),
+            };
+
+        let pid: Pid = pid.parse().ok()?;
+        if !self.tasks.contains_key(&pid) {
+            return None;
+        }
+
+        match file {
+            None => Some((pid, None)),
+            Some("stat") => Some((pid, Some("stat"))),
+            Some("cgroup") => Some((pid, Some("cgroup"))),
+            Some(_) => None,
+        }
+    }
+
+    fn read_proc(&self, pid: Pid, file: &str) -> String {
+        match file {
+            "stat" => {
+                // 52 fields, all zeros but pid, name, state and policy
+                let mut fields = vec!["0".to_owned(); 52];
+                fields[0] = format!("{pid}");
+                fields[1] = "(fake)".to_owned();
+                fields[2] = "R".to_owned();
+                fields[40] = format!("{}", self.tasks[&pid].policy);
+                fields.join(" ") + "\n"
+            },
+            _ => {
+                let cgroup = self.task_cgroup(pid).unwrap_or(CGROUP_ROOT);
+                let path = cgroup.strip_prefix(CGROUP_ROOT).unwrap_or("");
+                format!("0::{}\n", if path.is_empty() { "/" } else { path })
+            },
+        }
+    }
+}
+
+impl FsBackend for FakeFs {
+    fn read_to_string(&self, path: &str) -> anyhow::Result<String> {
+        let mut state = self.state.lock().unwrap();
+        state.prune_external_tasks();
+
+        if let Some((dir, file)) = state.cgroup_file(path) {
+            let cgroup = &state.cgroups[&dir];
+            return Ok(match file {
+                "cgroup.controllers" | "cgroup.subtree_control" => "cpu\n".to_owned(),
+                // fake tasks are single-threaded
+                "cgroup.procs" | "cgroup.threads" => cgroup.procs.iter().map(|pid| format!("{pid}\n")).collect(),
+                "cpu.rt_runtime_us" => FakeState::read_per_cpu(&cgroup.runtime_us),
+                _ => FakeState::read_per_cpu(&cgroup.period_us),
+            });
+        }
+
+        if let Some((pid, Some(file))) = state.proc_entry(path) {
+            return Ok(state.read_proc(pid, file));
+        }
+
+        state.files.get(path)
+            .cloned()
+            .ok_or_else(|| anyhow::format_err!("Error in reading {path}: {}", std::io::Error::from_raw_os_error(ENOENT)))
+    }
+
+    fn write(&self, path: &str, data: &str) -> anyhow::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.prune_external_tasks();
+
+        if let Some((dir, file)) = state.cgroup_file(path) {
+            return match file {
+                "cpu.rt_runtime_us" => state.write_runtime(&dir, path, data),
+                "cpu.rt_period_us" => state.write_period(&dir, path, data),
+                "cgroup.procs" => state.write_procs(&dir, path, data),
+                _ => Err(write_error(path, data, EINVAL)),
+            };
+        }
+
+        if !state.files.contains_key(path) {
+            return Err(write_error(path, data, ENOENT));
+        }
+
+        if path.starts_with("/sys/kernel/debug/sched/fair_server/") ||
+            path.starts_with("/sys/kernel/debug/sched/ext_server/")
+        {
+            state.write_server(path, data)
+        } else if path == "/sys/kernel/debug/sched/features" {
+            state.write_features(path, data)
+        } else {
+            state.files.insert(path.to_owned(), data.trim().to_owned());
+            Ok(())
+        }
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        let state = self.state.lock().unwrap();
+
+        state.files.contains_key(path) || state.dirs.contains(path) ||
+            state.cgroups.contains_key(path) || state.cgroup_file(path).is_some() ||
+            state.proc_entry(path).is_some()
+    }
+
+    fn is_dir(&self, path: &str) -> bool {
+        let state = self.state.lock().unwrap();
+
+        state.dirs.contains(path) || state.cgroups.contains_key(path) ||
+            matches!(state.proc_entry(path), Some((_, None)))
+    }
+
+    fn read_dir(&self, path: &str) -> anyhow::Result<Vec<String>> {
+        let state = self.state.lock().unwrap();
+
+        if state.cgroups.contains_key(path) {
+            let mut entries: Vec<String> = CGROUP_FILES.iter().map(|f| f.to_string()).collect();
+            entries.extend(state.cgroup_children(path).iter().map(|child| file_name(child).to_owned()));
+            return Ok(entries);
+        }
+
+        if path == "/proc" {
+            let mut entries: Vec<String> = state.tasks.keys().map(|pid| format!("{pid}")).collect();
+            entries.extend(state.files.keys()
+                .filter(|file| parent_dir(file) == "/proc")
+                .map(|file| file_name(file).to_owned()));
+            entries.push("sys".to_owned());
+            return Ok(entries);
+        }
+
+        if let Some((_, None)) = state.proc_entry(path) {
+            return Ok(vec!["stat".to_owned(), "cgroup".to_owned()]);
+        }
+
+        if !state.dirs.contains(path) {
+            return Err(anyhow::format_err!("Error in reading directory {path}: {}", std::io::Error::from_raw_os_error(ENOENT)));
+        }
+
+        let mut entries: BTreeSet<String> = BTreeSet::new();
+        entries.extend(state.files.keys().chain(state.dirs.iter()).chain(state.cgroups.keys())
+            .filter(|entry| entry.as_str() != "/" && parent_dir(entry) == path)
+            .map(|entry| file_name(entry).to_owned()));
+
+        Ok(entries.into_iter().collect())
+    }
+
+    fn create_dir(&self, path: &str) -> anyhow::Result<()> {
+        let mut state = self.state.lock().unwrap();
+
+        if state.dirs.contains(path) || state.cgroups.contains_key(path) || state.files.contains_key(path) {
+            return Err(os_error(path, EEXIST));
+        }
+
+        let parent = parent_dir(path);
+        if state.cgroups.contains_key(parent) {
+            let num_cpus = state.num_cpus;
+            state.cgroups.insert(path.to_owned(), FakeCgroup {
+                runtime_us: vec![0; num_cpus],
+                period_us: vec![1_000_000; num_cpus],
+                procs: BTreeSet::new(),
+            });
+
+            Ok(())
+        } else if path == CGROUP_ROOT || !state.dirs.contains(parent) {
+            Err(os_error(path, ENOENT))
+        } else {
+            state.dirs.insert(path.to_owned());
+            Ok(())
+        }
+    }
+
+    fn remove_dir(&self, path: &str) -> anyhow::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.prune_external_tasks();
+
+        if path == CGROUP_ROOT {
+            return Err(os_error(path, EBUSY));
+        }
+
+        if let Some(cgroup) = state.cgroups.get(path) {
+            if !cgroup.procs.is_empty() || !state.cgroup_children(path).is_empty() {
+                return Err(os_error(path, EBUSY));
+            }
+
+            state.cgroups.remove(path);
+            return Ok(());
+        }
+
+        Err(os_error(path, ENOENT))
+    }
+
+    fn mount_cgroup_cpu(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn set_sched_policy(&self, pid: Pid, policy: SchedPolicy, _: SchedFlags) -> anyhow::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.prune_external_tasks();
+
+        let policy = policy_id(&policy);
+        if let Some(task) = state.tasks.get_mut(&pid) {
+            task.policy = policy;
+        } else if std::fs::exists(format!("/proc/{pid}")).unwrap_or(false) {
+            state.external_policies.insert(pid, policy);
+        } else {
+            anyhow::bail!("Error in setting the policy of pid {pid}: {}", std::io::Error::from_raw_os_error(3));
+        }
+
+        Ok(())
+    }
+}
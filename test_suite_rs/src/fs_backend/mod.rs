@@ -0,0 +1,140 @@
+use std::sync::{Arc, RwLock};
+
+use crate::prelude::*;
+
+pub mod prelude {
+    pub use super::{
+        FsBackend,
+        HostFs,
+        fake::FakeFs,
+        cgroup::FsCgroup,
+        fs_backend,
+        set_fs_backend,
+    };
+}
+
+pub mod cgroup;
+pub mod fake;
+
+/// Access to the kernel's pseudo filesystems (cgroupfs, debugfs, procfs).
+///
+/// Paths are always the absolute paths of the running system (e.g.
+/// `/sys/kernel/debug/sched/fair_server`); backends decide how to map them.
+/// Directory listings return the entries' names, not their full paths.
+pub trait FsBackend: Send + Sync {
+    fn read_to_string(&self, path: &str) -> anyhow::Result<String>;
+
+    fn write(&self, path: &str, data: &str) -> anyhow::Result<()>;
+
+    fn exists(&self, path: &str) -> bool;
+
+    fn is_dir(&self, path: &str) -> bool;
+
+    fn read_dir(&self, path: &str) -> anyhow::Result<Vec<String>>;
+
+    fn create_dir(&self, path: &str) -> anyhow::Result<()>;
+
+    fn remove_dir(&self, path: &str) -> anyhow::Result<()>;
+
+    /// Mount the cgroup filesystem and enable the cpu controller.
+    fn mount_cgroup_cpu(&self) -> anyhow::Result<()>;
+
+    /// Change the scheduling policy of a task, which the admission checks on
+    /// the cgroup files depend on.
+    fn set_sched_policy(&self, pid: Pid, policy: SchedPolicy, flags: SchedFlags) -> anyhow::Result<()>;
+}
+
+/// Backend on the host filesystem. All the paths are prefixed with the given
+/// root, which is empty for the running system. When a root is given, nothing
+/// gets mounted.
+#[derive(Debug, Clone, Default)]
+pub struct HostFs {
+    root: String,
+}
+
+impl HostFs {
+    pub fn new(root: &str) -> Self {
+        Self { root: root.trim_end_matches('/').to_owned() }
+    }
+
+    /// Use the root given in the HCBS_FS_ROOT environment variable, if any.
+    pub fn from_env() -> Self {
+        Self::new(&std::env::var("HCBS_FS_ROOT").unwrap_or_default())
+    }
+
+    fn path(&self, path: &str) -> String {
+        format!("{}{path}", self.root)
+    }
+}
+
+impl FsBackend for HostFs {
+    fn read_to_string(&self, path: &str) -> anyhow::Result<String> {
+        std::fs::read_to_string(self.path(path))
+            .map_err(|err| anyhow::format_err!("Error in reading {path}: {err}"))
+    }
+
+    fn write(&self, path: &str, data: &str) -> anyhow::Result<()> {
+        std::fs::write(self.path(path), data)
+            .map_err(|err| anyhow::format_err!("Error in writing {data} to {path}: {err}"))
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        std::fs::exists(self.path(path)).unwrap_or(false)
+    }
+
+    fn is_dir(&self, path: &str) -> bool {
+        std::path::Path::new(&self.path(path)).is_dir()
+    }
+
+    fn read_dir(&self, path: &str) -> anyhow::Result<Vec<String>> {
+        std::fs::read_dir(self.path(path))
+            .map_err(|err| anyhow::format_err!("Error in reading directory {path}: {err}"))?
+            .map(|entry| entry
+                .map_err(|err| anyhow::format_err!("Error in reading directory {path}: {err}"))
+                .and_then(|entry| entry.file_name().into_string()
+                    .map_err(|name| anyhow::format_err!("Invalid file name {name:?} in {path}"))
+                )
+            )
+            .collect()
+    }
+
+    fn create_dir(&self, path: &str) -> anyhow::Result<()> {
+        std::fs::create_dir(self.path(path))
+            .map_err(|err| anyhow::format_err!("Error in creating directory {path}: {err}"))
+    }
+
+    fn remove_dir(&self, path: &str) -> anyhow::Result<()> {
+        std::fs::remove_dir(self.path(path))
+            .map_err(|err| anyhow::format_err!("Error in removing directory {path}: {err}"))
+    }
+
+    fn mount_cgroup_cpu(&self) -> anyhow::Result<()> {
+        if self.root.is_empty() {
+            mount_cgroup_cpu()
+        } else {
+            Ok(())
+        }
+    }
+
+    fn set_sched_policy(&self, pid: Pid, policy: SchedPolicy, flags: SchedFlags) -> anyhow::Result<()> {
+        set_sched_policy(pid, policy, flags)
+            .map_err(|err| anyhow::format_err!("Error in setting the policy of pid {pid}: {err}"))
+    }
+}
+
+static FS_BACKEND: RwLock<Option<Arc<dyn FsBackend>>> = RwLock::new(None);
+
+/// Current filesystem backend, by default `HostFs::from_env()`.
+pub fn fs_backend() -> Arc<dyn FsBackend> {
+    if let Some(backend) = FS_BACKEND.read().unwrap().as_ref() {
+        return backend.clone();
+    }
+
+    FS_BACKEND.write().unwrap()
+        .get_or_insert_with(|| Arc::new(HostFs::from_env()))
+        .clone()
+}
+
+pub fn set_fs_backend(backend: Arc<dyn FsBackend>) {
+    *FS_BACKEND.write().unwrap() = Some(backend);
+}
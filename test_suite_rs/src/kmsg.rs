@@ -0,0 +1,496 @@
+use std::io::{Read, Seek};
+use std::str::FromStr;
+
+pub mod prelude {
+    pub use super::{
+        KmsgMode,
+        KmsgLevel,
+        KmsgKind,
+        KmsgMessage,
+        KmsgEvent,
+        KmsgReport,
+        KmsgMonitor,
+    };
+}
+
+/// Which kernel log events fail a test, selected with the `BATCH_TEST_KMSG`
+/// environment variable (`report`, `sched` or `all`). In any mode, the
+/// messages logged during the test are attached to its result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KmsgMode {
+    /// never fail
+    Report,
+    /// fail on scheduler related events
+    Sched,
+    /// fail on any warning, bug, stall, hung task or lockdep splat
+    All,
+}
+
+impl KmsgMode {
+    /// Mode selected by the `BATCH_TEST_KMSG` environment variable, None if
+    /// the monitor is disabled.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        match std::env::var("BATCH_TEST_KMSG") {
+            Ok(mode) if !mode.is_empty() => mode.parse().map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            KmsgMode::Report => "report",
+            KmsgMode::Sched => "sched",
+            KmsgMode::All => "all",
+        }
+    }
+}
+
+impl FromStr for KmsgMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "report" => Ok(KmsgMode::Report),
+            "1" | "sched" => Ok(KmsgMode::Sched),
+            "all" => Ok(KmsgMode::All),
+            _ => Err(anyhow::format_err!("Unknown kernel log monitor mode \'{s}\'")),
+        }
+    }
+}
+
+impl std::fmt::Display for KmsgMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Severity of a kernel message (syslog levels).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum KmsgLevel {
+    Emerg,
+    Alert,
+    Crit,
+    Err,
+    Warning,
+    Notice,
+    Info,
+    Debug,
+}
+
+impl KmsgLevel {
+    pub const ALL: [KmsgLevel; 8] = [
+        KmsgLevel::Emerg,
+        KmsgLevel::Alert,
+        KmsgLevel::Crit,
+        KmsgLevel::Err,
+        KmsgLevel::Warning,
+        KmsgLevel::Notice,
+        KmsgLevel::Info,
+        KmsgLevel::Debug,
+    ];
+
+    pub fn from_priority(priority: u32) -> Self {
+        Self::ALL[(priority & 7) as usize]
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            KmsgLevel::Emerg => "emerg",
+            KmsgLevel::Alert => "alert",
+            KmsgLevel::Crit => "crit",
+            KmsgLevel::Err => "err",
+            KmsgLevel::Warning => "warning",
+            KmsgLevel::Notice => "notice",
+            KmsgLevel::Info => "info",
+            KmsgLevel::Debug => "debug",
+        }
+    }
+}
+
+/// Kind of a kernel message, by pattern. All but `Other` start an event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KmsgKind {
+    Warning,
+    Bug,
+    Oops,
+    RcuStall,
+    HungTask,
+    Lockup,
+    Lockdep,
+    /// "sched: RT throttling activated", informational only
+    RtThrottling,
+    Other,
+}
+
+impl KmsgKind {
+    pub fn classify(text: &str) -> Self {
+        // lockdep splats are also WARNINGs, thus checked first
+        if ["possible circular locking dependency", "possible recursive locking", "inconsistent lock state",
+            "bad unlock balance", "suspicious RCU usage"].iter().any(|pattern| text.contains(pattern))
+        {
+            KmsgKind::Lockdep
+        } else if text.starts_with("WARNING:") {
+            KmsgKind::Warning
+        } else if text.starts_with("BUG:") || text.contains("kernel BUG at") {
+            KmsgKind::Bug
+        } else if text.starts_with("Oops") || text.contains("general protection fault") ||
+            text.contains("Unable to handle kernel") || text.starts_with("Kernel panic")
+        {
+            KmsgKind::Oops
+        } else if text.contains("rcu") && text.contains("detected stall") {
+            KmsgKind::RcuStall
+        } else if text.starts_with("INFO: task ") && text.contains("blocked for more than") {
+            KmsgKind::HungTask
+        } else if text.contains("soft lockup") || text.contains("hard LOCKUP") {
+            KmsgKind::Lockup
+        } else if text.contains("RT throttling activated") {
+            KmsgKind::RtThrottling
+        } else {
+            KmsgKind::Other
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            KmsgKind::Warning => "warning",
+            KmsgKind::Bug => "bug",
+            KmsgKind::Oops => "oops",
+            KmsgKind::RcuStall => "rcu_stall",
+            KmsgKind::HungTask => "hung_task",
+            KmsgKind::Lockup => "lockup",
+            KmsgKind::Lockdep => "lockdep",
+            KmsgKind::RtThrottling => "rt_throttling",
+            KmsgKind::Other => "other",
+        }
+    }
+}
+
+/// A record of /dev/kmsg.
+#[derive(Debug, Clone)]
+pub struct KmsgMessage {
+    pub level: KmsgLevel,
+    /// 0 for kernel messages, 1 for user ones
+    pub facility: u32,
+    pub seq: u64,
+    /// time since boot
+    pub ts_us: u64,
+    pub text: String,
+}
+
+impl KmsgMessage {
+    /// Parse a record, formatted as "priority,seq,timestamp,flags;text",
+    /// dropping its continuation lines (key=value pairs).
+    pub fn parse(record: &str) -> Option<Self> {
+        let (prefix, text) = record.split_once(';')?;
+        let mut fields = prefix.split(',');
+        let priority: u32 = fields.next()?.parse().ok()?;
+
+        Some(Self {
+            level: KmsgLevel::from_priority(priority),
+            facility: priority >> 3,
+            seq: fields.next()?.parse().ok()?,
+            ts_us: fields.next()?.parse().ok()?,
+            text: text.lines().next().unwrap_or_default().to_owned(),
+        })
+    }
+}
+
+/// Patterns of a splat's lines pointing to the scheduler: its source files,
+/// its locks and its own splats.
+const SCHED_PATTERNS: &[&str] = &[
+    "kernel/sched/", "rq->__lock", "rt_runtime_lock", "dl_b->lock", "BUG: scheduling", "sched: RT throttling",
+];
+
+/// Prefixes of the scheduler's functions found in call traces, matched on the
+/// trace entries' symbols (without leading underscores), so that e.g.
+/// `ip_rt_output_key` does not match.
+const SCHED_FUNCTIONS: &[&str] = &[
+    "enqueue_task", "dequeue_task", "enqueue_rt_", "dequeue_rt_", "enqueue_dl_", "dequeue_dl_",
+    "pick_next_task", "put_prev_task", "set_next_task", "task_tick", "update_curr", "dl_server", "rt_se",
+    "sched_rt_", "sched_dl_", "do_sched_rt_period_timer", "dl_task_timer", "replenish_dl_", "start_dl_timer",
+    "inc_rt_", "dec_rt_", "inc_dl_", "dec_dl_", "push_rt_", "pull_rt_", "push_dl_", "pull_dl_",
+    "balance_rt", "balance_dl", "tg_set_rt_", "tg_rt_", "sched_setattr", "sched_setscheduler",
+];
+
+/// Symbol of a call trace's entry (e.g. " ? enqueue_task_rt+0x10/0x20" or
+/// "RIP: 0010:dl_server_start+0x1/0x2"), if the line is one.
+fn trace_symbol(line: &str) -> Option<&str> {
+    let line = line.trim();
+    let line = line.strip_prefix("RIP: ")
+        .map_or(line, |rip| rip.split_once(':').map_or(rip, |(_, symbol)| symbol));
+    let line = line.strip_prefix("? ").unwrap_or(line);
+
+    line.split_once("+0x").map(|(symbol, _)| symbol)
+}
+
+/// Whether a splat's line points to the scheduler.
+fn is_sched_line(line: &str) -> bool {
+    SCHED_PATTERNS.iter().any(|pattern| line.contains(pattern)) ||
+        trace_symbol(line).is_some_and(|symbol| {
+            let symbol = symbol.trim_start_matches('_');
+            SCHED_FUNCTIONS.iter().any(|function| symbol.starts_with(function))
+        })
+}
+
+/// Lines of a splat kept after its first message, e.g. the call trace.
+const MAX_EVENT_LINES: usize = 64;
+
+/// A notable kernel message, along with the following ones up to the end of
+/// its trace or to the next event.
+#[derive(Debug, Clone)]
+pub struct KmsgEvent {
+    pub kind: KmsgKind,
+    pub header: KmsgMessage,
+    pub lines: Vec<String>,
+    /// whether the event relates to the scheduler: stalls, hung tasks and
+    /// lockups always do (starvation), the other splats if they mention the
+    /// scheduler's code
+    pub sched: bool,
+}
+
+impl KmsgEvent {
+    fn new(kind: KmsgKind, header: KmsgMessage) -> Self {
+        Self { kind, header, lines: Vec::new(), sched: false }
+    }
+
+    fn close(mut self) -> Self {
+        self.sched =
+            match self.kind {
+                KmsgKind::RcuStall | KmsgKind::HungTask | KmsgKind::Lockup => true,
+                KmsgKind::RtThrottling | KmsgKind::Other => false,
+                _ => std::iter::once(&self.header.text).chain(self.lines.iter())
+                    .any(|line| is_sched_line(line)),
+            };
+
+        self
+    }
+
+    /// Whether the event fails the test in the given mode.
+    pub fn fails(&self, mode: KmsgMode) -> bool {
+        match (mode, self.kind) {
+            (KmsgMode::Report, _) => false,
+            (_, KmsgKind::RtThrottling | KmsgKind::Other) => false,
+            (KmsgMode::Sched, _) => self.sched,
+            (KmsgMode::All, _) => true,
+        }
+    }
+}
+
+impl std::fmt::Display for KmsgEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sched = if self.sched { ", sched" } else { "" };
+
+        write!(f, "[{:.6}] {} ({}{sched}): {}",
+            self.header.ts_us as f64 / 1e6, self.kind.as_str(), self.header.level.as_str(), self.header.text)
+    }
+}
+
+/// Kernel messages logged during a test.
+#[derive(Debug, Clone, Default)]
+pub struct KmsgReport {
+    pub messages: Vec<KmsgMessage>,
+    pub events: Vec<KmsgEvent>,
+    /// times in which records were overwritten in the kernel's ring buffer
+    /// before being read
+    pub lost: u64,
+}
+
+impl KmsgReport {
+    pub fn new(messages: Vec<KmsgMessage>, lost: u64) -> Self {
+        let mut events = Vec::new();
+        let mut current: Option<KmsgEvent> = None;
+
+        // messages written by user space (e.g. to /dev/kmsg) are neither
+        // splats nor parts of their traces
+        for message in messages.iter().filter(|message| message.facility == 0) {
+            match KmsgKind::classify(&message.text) {
+                KmsgKind::Other => {
+                    let Some(event) = current.as_mut() else { continue; };
+
+                    if event.lines.len() < MAX_EVENT_LINES {
+                        event.lines.push(message.text.clone());
+                    }
+
+                    if message.text.contains("---[ end trace") {
+                        events.extend(current.take().map(KmsgEvent::close));
+                    }
+                },
+                kind => {
+                    events.extend(current.take().map(KmsgEvent::close));
+                    current = Some(KmsgEvent::new(kind, message.clone()));
+                },
+            }
+        }
+
+        events.extend(current.map(KmsgEvent::close));
+
+        Self { messages, events, lost }
+    }
+
+    /// Number of messages of each level, e.g. "3 messages (err 1, info 2)".
+    pub fn level_counts(&self) -> String {
+        let counts: Vec<_> = KmsgLevel::ALL.iter()
+            .map(|level| (level, self.messages.iter().filter(|message| message.level == *level).count()))
+            .filter(|(_, count)| *count > 0)
+            .map(|(level, count)| format!("{} {count}", level.as_str()))
+            .collect();
+
+        let mut summary = format!("{} messages", self.messages.len());
+        if !counts.is_empty() {
+            summary += &format!(" ({})", counts.join(", "));
+        }
+        if self.lost > 0 {
+            summary += &format!(", {} lost", self.lost);
+        }
+
+        summary
+    }
+
+    pub fn failures(&self, mode: KmsgMode) -> Vec<&KmsgEvent> {
+        self.events.iter()
+            .filter(|event| event.fails(mode))
+            .collect()
+    }
+}
+
+/// Reader of the kernel log (/dev/kmsg) from the position at its creation.
+pub struct KmsgMonitor {
+    kmsg: std::fs::File,
+}
+
+impl KmsgMonitor {
+    pub fn start() -> anyhow::Result<Self> {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        let mut kmsg = std::fs::OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open("/dev/kmsg")
+            .map_err(|err| anyhow::format_err!("Error in opening /dev/kmsg: {err}"))?;
+
+        // skip the messages logged so far
+        kmsg.seek(std::io::SeekFrom::End(0))
+            .map_err(|err| anyhow::format_err!("Error in seeking /dev/kmsg: {err}"))?;
+
+        Ok(Self { kmsg })
+    }
+
+    /// Read the messages logged since the last call (or the monitor's start).
+    pub fn collect(&mut self) -> anyhow::Result<KmsgReport> {
+        // each read returns a single record
+        let mut buf = vec![0u8; 16 * 1024];
+        let mut messages = Vec::new();
+        let mut lost = 0;
+
+        loop {
+            match self.kmsg.read(&mut buf) {
+                Ok(0) => break,
+                Ok(len) => {
+                    messages.extend(KmsgMessage::parse(&String::from_utf8_lossy(&buf[..len])));
+                },
+                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => break,
+                Err(err) if err.raw_os_error() == Some(libc::EPIPE) => { lost += 1; },
+                Err(err) => anyhow::bail!("Error in reading /dev/kmsg: {err}"),
+            }
+        }
+
+        Ok(KmsgReport::new(messages, lost))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(records: &[&str]) -> Vec<KmsgMessage> {
+        records.iter()
+            .map(|record| KmsgMessage::parse(record).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn parse_records() {
+        let message = KmsgMessage::parse("4,1234,5678901,-;WARNING: CPU: 1 PID: 42 at kernel/sched/rt.c:123 foo\n SUBSYSTEM=cpu").unwrap();
+        assert_eq!(message.level, KmsgLevel::Warning);
+        assert_eq!(message.facility, 0);
+        assert_eq!(message.seq, 1234);
+        assert_eq!(message.ts_us, 5678901);
+        assert_eq!(message.text, "WARNING: CPU: 1 PID: 42 at kernel/sched/rt.c:123 foo");
+
+        // user facility, info level
+        let message = KmsgMessage::parse("14,7,8,-;hello; world").unwrap();
+        assert_eq!(message.level, KmsgLevel::Info);
+        assert_eq!(message.facility, 1);
+        assert_eq!(message.text, "hello; world");
+
+        assert!(KmsgMessage::parse("no separator").is_none());
+        assert!(KmsgMessage::parse("x,1,2,-;text").is_none());
+        assert!(KmsgMessage::parse("6,1;text").is_none());
+    }
+
+    #[test]
+    fn classify_messages() {
+        assert_eq!(KmsgKind::classify("WARNING: CPU: 0 PID: 1 at kernel/sched/deadline.c:10 dl_server_start+0x1/0x2"), KmsgKind::Warning);
+        assert_eq!(KmsgKind::classify("WARNING: possible circular locking dependency detected"), KmsgKind::Lockdep);
+        assert_eq!(KmsgKind::classify("WARNING: suspicious RCU usage"), KmsgKind::Lockdep);
+        assert_eq!(KmsgKind::classify("rcu: INFO: rcu_preempt detected stalls on CPUs/tasks:"), KmsgKind::RcuStall);
+        assert_eq!(KmsgKind::classify("INFO: task kworker/0:1:12 blocked for more than 122 seconds."), KmsgKind::HungTask);
+        assert_eq!(KmsgKind::classify("BUG: scheduling while atomic: yes/42/0x00000002"), KmsgKind::Bug);
+        assert_eq!(KmsgKind::classify("watchdog: BUG: soft lockup - CPU#3 stuck for 22s!"), KmsgKind::Lockup);
+        assert_eq!(KmsgKind::classify("sched: RT throttling activated"), KmsgKind::RtThrottling);
+        assert_eq!(KmsgKind::classify("Call Trace:"), KmsgKind::Other);
+
+        assert!(is_sched_line("WARNING: CPU: 0 PID: 1 at kernel/sched/deadline.c:10 dl_server_start+0x1/0x2"));
+        assert!(is_sched_line("BUG: scheduling while atomic: yes/42/0x00000002"));
+        assert!(is_sched_line(" enqueue_task_rt+0x10/0x20"));
+        assert!(is_sched_line(" ? __dequeue_dl_entity+0x10/0x20"));
+        assert!(is_sched_line("RIP: 0010:dl_server_start+0x1/0x2"));
+        assert!(is_sched_line(" (&rt_b->rt_runtime_lock){-.-.}-{2:2}, at: tg_set_rt_bandwidth+0x1/0x2"));
+
+        // not the scheduler's, despite _rt_ and _dl_
+        assert!(!is_sched_line("WARNING: CPU: 2 PID: 7 at net/ipv4/route.c:100 ip_rt_output_key+0x1/0x2"));
+        assert!(!is_sched_line(" ip_rt_output_key+0x1/0x2"));
+        assert!(!is_sched_line(" ? usb_dl_probe+0x1/0x2"));
+        assert!(!is_sched_line("rtl_dl_fw: loading firmware rtl_rt_fw.bin"));
+    }
+
+    #[test]
+    fn report_events() {
+        let report = KmsgReport::new(messages(&[
+            "6,1,100,-;eth0: link up",
+            "4,2,200,-;WARNING: CPU: 1 PID: 42 at kernel/sched/rt.c:123 foo+0x1/0x2",
+            "4,3,201,-;Call Trace:",
+            "4,4,202,-; enqueue_task_rt+0x10/0x20",
+            "4,5,203,-;---[ end trace 0000000000000000 ]---",
+            "6,6,300,-;eth0: link down",
+            "4,7,400,-;WARNING: possible circular locking dependency detected",
+            "4,8,401,-;yes/42 is trying to acquire lock:",
+            "3,9,500,-;rcu: INFO: rcu_preempt detected stalls on CPUs/tasks:",
+            "3,10,600,-;INFO: task yes:43 blocked for more than 120 seconds.",
+            // written by user space
+            "12,11,700,-;WARNING: not a kernel splat",
+            "3,12,701,-; dump_stack+0x1/0x2",
+        ]), 0);
+
+        let kinds: Vec<_> = report.events.iter().map(|event| event.kind).collect();
+        assert_eq!(kinds, vec![KmsgKind::Warning, KmsgKind::Lockdep, KmsgKind::RcuStall, KmsgKind::HungTask]);
+
+        let warning = &report.events[0];
+        assert_eq!(warning.header.seq, 2);
+        assert_eq!(warning.lines.len(), 3);
+        assert!(warning.sched);
+
+        // the lockdep splat's lines do not mention the scheduler
+        let lockdep = &report.events[1];
+        assert_eq!(lockdep.lines, vec!["yes/42 is trying to acquire lock:".to_owned()]);
+        assert!(!lockdep.sched);
+
+        assert!(report.events[2].sched && report.events[3].sched);
+        assert_eq!(report.events[3].lines, vec![" dump_stack+0x1/0x2".to_owned()]);
+
+        assert!(report.failures(KmsgMode::Report).is_empty());
+        assert_eq!(report.failures(KmsgMode::Sched).len(), 3);
+        assert_eq!(report.failures(KmsgMode::All).len(), 4);
+        assert_eq!(report.level_counts(), "12 messages (err 3, warning 7, info 2)");
+    }
+}
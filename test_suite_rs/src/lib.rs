@@ -2,6 +2,13 @@ use crate::prelude::*;
 
 pub mod process;
 pub mod utils;
+pub mod results;
+pub mod kmsg;
+pub mod capabilities;
+pub mod snapshot;
+pub mod fs_backend;
+pub mod supervisor;
+pub mod shared_mutex;
 pub mod tests;
 
 pub mod prelude {
@@ -10,6 +17,13 @@ pub mod prelude {
 
     pub use super::process::prelude::*;
     pub use super::utils::prelude::*;
+    pub use super::results::prelude::*;
+    pub use super::kmsg::prelude::*;
+    pub use super::capabilities::prelude::*;
+    pub use super::snapshot::prelude::*;
+    pub use super::fs_backend::prelude::*;
+    pub use super::supervisor::prelude::*;
+    pub use super::shared_mutex::prelude::*;
 
     pub use super::{
         NamedTaskset,
@@ -47,6 +61,7 @@ pub fn cpu_hog() -> anyhow::Result<HCBSProcess> {
         .stderr(Stdio::null())
         .spawn()?;
 
+    supervise_pid(proc.id());
     Ok(HCBSProcess::Child(proc))
 }
 
@@ -59,6 +74,7 @@ pub fn run_yes() -> Result<HCBSProcess, std::io::Error> {
         .stderr(Stdio::null())
         .spawn()?;
 
+    supervise_pid(proc.id());
     Ok(HCBSProcess::Child(proc))
 }
 
@@ -75,15 +91,16 @@ pub fn local_executable_cmd(def_dir: &str, name: &str) -> anyhow::Result<String>
 }
 
 pub fn is_multicpu_enabled() -> anyhow::Result<bool> {
-    mount_cgroup_cpu()?;
+    use fs_backend::cgroup;
+
+    fs_backend().mount_cgroup_cpu()?;
 
     let name = "multicpu_test_cgroup";
-    if cgroup_exists(name) {
+    if cgroup::exists(name) {
         return Ok(false);
     }
 
-    let mut cgroup = HCBSCgroup::new(name)?
-        .with_force_kill(false);
+    let mut cgroup = FsCgroup::new(name)?;
 
     match cgroup.set_period_us_multi_str("100000 1") {
         Ok(_) => Ok(true),
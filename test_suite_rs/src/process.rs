@@ -1,7 +1,22 @@
+use std::time::Instant;
+
+use hcbs_utils::prelude::CpuID;
+
+use crate::fs_backend::{fs_backend, cgroup};
+
 pub mod prelude {
     pub use super::{
         get_process_total_runtime_usage,
         get_process_total_cpu_usage,
+        get_task_runtime_ns,
+        get_process_runtime_ns,
+        get_cgroup_usage_us,
+        get_nonvoluntary_switches,
+        get_task_last_cpu,
+        get_task_allowed_cpus,
+        UsageSource,
+        UsageWindow,
+        Usage,
     };
 }
 
@@ -58,4 +73,190 @@ pub fn get_process_total_cpu_usage(pid: u32) -> anyhow::Result<f64> {
 
     let elapsed = uptime - start_time;
     Ok((utime + stime)/ elapsed)
-}
\ No newline at end of file
+}
+
+/// CPU time, in nanoseconds, consumed by a single thread, read from
+/// /proc/<pid>/schedstat (or /proc/<pid>/task/<tid>/schedstat).
+pub fn get_task_runtime_ns(schedstat_file: &str) -> anyhow::Result<u64> {
+    fs_backend().read_to_string(schedstat_file)?
+        .split_whitespace().next()
+        .ok_or_else(|| anyhow::format_err!("Error in reading {schedstat_file}"))?
+        .parse::<u64>()
+        .map_err(|err| anyhow::format_err!("Error in parsing {schedstat_file}: {err}"))
+}
+
+/// CPU time, in nanoseconds, consumed by all the threads of a process.
+/// Threads exiting while they are being read are skipped.
+pub fn get_process_runtime_ns(pid: u32) -> anyhow::Result<u64> {
+    let fs = fs_backend();
+    let task_dir = format!("/proc/{pid}/task");
+
+    match fs.read_dir(&task_dir) {
+        Ok(threads) => threads.iter()
+            .map(|tid| {
+                let thread_dir = format!("{task_dir}/{tid}");
+                match get_task_runtime_ns(&format!("{thread_dir}/schedstat")) {
+                    Err(_) if !fs.exists(&thread_dir) => Ok(0),
+                    runtime => runtime,
+                }
+            })
+            .sum(),
+        Err(_) => get_task_runtime_ns(&format!("/proc/{pid}/schedstat")),
+    }
+}
+
+/// CPU time, in microseconds, consumed by all the tasks of a cgroup, read
+/// from its cpu.stat file.
+pub fn get_cgroup_usage_us(name: &str) -> anyhow::Result<u64> {
+    let file = format!("{}/cpu.stat", cgroup::cgroup_dir(name));
+
+    fs_backend().read_to_string(&file)?
+        .lines()
+        .find_map(|line| line.strip_prefix("usage_usec "))
+        .ok_or_else(|| anyhow::format_err!("No usage_usec entry in {file}"))?
+        .trim()
+        .parse::<u64>()
+        .map_err(|err| anyhow::format_err!("Error in parsing {file}: {err}"))
+}
+
+/// Number of times a process (its main thread) was preempted, read from
+/// /proc/<pid>/status.
+pub fn get_nonvoluntary_switches(pid: u32) -> anyhow::Result<u64> {
+    let file = format!("/proc/{pid}/status");
+
+    fs_backend().read_to_string(&file)?
+        .lines()
+        .find_map(|line| line.strip_prefix("nonvoluntary_ctxt_switches:"))
+        .ok_or_else(|| anyhow::format_err!("No nonvoluntary_ctxt_switches entry in {file}"))?
+        .trim()
+        .parse::<u64>()
+        .map_err(|err| anyhow::format_err!("Error in parsing {file}: {err}"))
+}
+
+/// Cpu on which a task (or thread) last ran, read from the processor field of
+/// /proc/<pid>/stat (or /proc/<pid>/task/<tid>/stat).
+pub fn get_task_last_cpu(stat_file: &str) -> anyhow::Result<CpuID> {
+    let stats = fs_backend().read_to_string(stat_file)?;
+
+    // skip the command name, which may contain spaces
+    let (_, stats) = stats.rsplit_once(')')
+        .ok_or_else(|| anyhow::format_err!("Error in reading {stat_file}"))?;
+
+    // the processor is the 39th field, the first after the name being the 3rd.
+    stats.split_whitespace().nth(39 - 3)
+        .ok_or_else(|| anyhow::format_err!("Error in reading {stat_file}"))?
+        .parse::<CpuID>()
+        .map_err(|err| anyhow::format_err!("Error in parsing {stat_file}: {err}"))
+}
+
+/// Cpus a process (its main thread) is allowed to run on, read from
+/// /proc/<pid>/status.
+pub fn get_task_allowed_cpus(pid: u32) -> anyhow::Result<Vec<CpuID>> {
+    let file = format!("/proc/{pid}/status");
+
+    let list = fs_backend().read_to_string(&file)?
+        .lines()
+        .find_map(|line| line.strip_prefix("Cpus_allowed_list:"))
+        .ok_or_else(|| anyhow::format_err!("No Cpus_allowed_list entry in {file}"))?
+        .to_owned();
+
+    cgroup::parse_cpu_list(&list)
+        .map_err(|err| anyhow::format_err!("Error in parsing {file}: {err}"))
+}
+
+/// What a `UsageWindow` measures.
+#[derive(Debug, Clone)]
+pub enum UsageSource {
+    /// All the threads of the given processes.
+    Processes(Vec<u32>),
+    /// All the tasks of the given cgroup.
+    Cgroup(String),
+}
+
+impl UsageSource {
+    /// Consumed CPU time in nanoseconds, one value per process (or a single
+    /// value for cgroups).
+    fn read_ns(&self) -> anyhow::Result<Vec<u64>> {
+        match self {
+            UsageSource::Processes(pids) =>
+                pids.iter().map(|pid| get_process_runtime_ns(*pid)).collect(),
+            UsageSource::Cgroup(name) =>
+                Ok(vec![get_cgroup_usage_us(name)? * 1000]),
+        }
+    }
+}
+
+/// CPU time consumed over a measurement window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Usage {
+    pub elapsed_ns: u64,
+    pub usage_ns: u64,
+}
+
+impl Usage {
+    /// Consumed bandwidth, in units of CPU (e.g. 1.5 is one CPU and a half).
+    pub fn bandwidth(&self) -> f64 {
+        if self.elapsed_ns == 0 {
+            0f64
+        } else {
+            self.usage_ns as f64 / self.elapsed_ns as f64
+        }
+    }
+
+    pub fn usage_secs(&self) -> f64 {
+        self.usage_ns as f64 / 1_000_000_000f64
+    }
+}
+
+/// Measurement window of the CPU time consumed by a set of processes or by a
+/// cgroup, with nanosecond precision, from its start to its stop (instead of
+/// since the processes' start, as `get_process_total_cpu_usage`).
+#[derive(Debug, Clone)]
+pub struct UsageWindow {
+    source: UsageSource,
+    start: Instant,
+    start_ns: Vec<u64>,
+}
+
+impl UsageWindow {
+    pub fn start(source: UsageSource) -> anyhow::Result<Self> {
+        let start_ns = source.read_ns()?;
+
+        Ok(Self { source, start: Instant::now(), start_ns })
+    }
+
+    pub fn start_processes(pids: &[u32]) -> anyhow::Result<Self> {
+        Self::start(UsageSource::Processes(pids.to_vec()))
+    }
+
+    pub fn start_cgroup(name: &str) -> anyhow::Result<Self> {
+        Self::start(UsageSource::Cgroup(name.to_owned()))
+    }
+
+    /// Usage since the start of the window, without stopping it.
+    pub fn usage(&self) -> anyhow::Result<Usage> {
+        let now_ns = self.source.read_ns()?;
+        let elapsed_ns = self.start.elapsed().as_nanos() as u64;
+
+        let usage_ns = now_ns.iter().zip(self.start_ns.iter())
+            .map(|(now, start)| now.saturating_sub(*start))
+            .sum();
+
+        Ok(Usage { elapsed_ns, usage_ns })
+    }
+
+    /// Usage of each process since the start of the window (or of the whole
+    /// cgroup).
+    pub fn usage_per_source(&self) -> anyhow::Result<Vec<Usage>> {
+        let now_ns = self.source.read_ns()?;
+        let elapsed_ns = self.start.elapsed().as_nanos() as u64;
+
+        Ok(now_ns.iter().zip(self.start_ns.iter())
+            .map(|(now, start)| Usage { elapsed_ns, usage_ns: now.saturating_sub(*start) })
+            .collect())
+    }
+
+    pub fn stop(self) -> anyhow::Result<Usage> {
+        self.usage()
+    }
+}
@@ -0,0 +1,551 @@
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::kmsg::{KmsgMode, KmsgMonitor};
+
+pub mod prelude {
+    pub use super::{
+        TestStatus,
+        TestRecord,
+        ResultSink,
+        HumanSink,
+        JsonLinesSink,
+        JUnitSink,
+        ResultsArgs,
+        set_result_sinks,
+        add_result_sink,
+        set_kmsg_mode,
+        kmsg_mode,
+    };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TestStatus {
+    Success,
+    Failure,
+    Skipped,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct TestRecord {
+    pub category: String,
+    pub name: String,
+    pub status: TestStatus,
+    pub reason: Option<String>,
+    pub details: Option<String>,
+    #[serde(rename = "duration_secs", serialize_with = "serialize_duration_secs")]
+    pub duration: Duration,
+    /// serialized as an object, in insertion order
+    #[serde(serialize_with = "serialize_parameters")]
+    pub parameters: Vec<(String, String)>,
+}
+
+fn serialize_duration_secs<S: serde::Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    // millisecond resolution
+    serializer.serialize_f64((duration.as_secs_f64() * 1000f64).round() / 1000f64)
+}
+
+fn serialize_parameters<S: serde::Serializer>(parameters: &[(String, String)], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(parameters.iter().map(|(key, value)| (key, value)))
+}
+
+/// Destination for the outcome of batch tests.
+///
+/// A sink is notified when a test starts (i.e. on `batch_test_header`) and
+/// when its result is known. Sinks are selected with the `--sinks` flag of the
+/// test executables or with the `BATCH_TEST_SINKS` environment variable, a
+/// comma separated list of `human`, `jsonl=<path>` and `junit=<path>` entries
+/// (`-` as path means stdout). If neither is given, only the human readable
+/// sink is used.
+pub trait ResultSink: Send {
+    fn test_started(&mut self, _category: &str, _name: &str) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn test_finished(&mut self, record: &TestRecord) -> anyhow::Result<()>;
+}
+
+impl TestStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TestStatus::Success => "success",
+            TestStatus::Failure => "failure",
+            TestStatus::Skipped => "skipped",
+        }
+    }
+}
+
+impl std::fmt::Display for TestStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl std::str::FromStr for TestStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "success" => Ok(TestStatus::Success),
+            "failure" => Ok(TestStatus::Failure),
+            "skipped" => Ok(TestStatus::Skipped),
+            _ => Err(anyhow::format_err!("Unknown test status \'{s}\'")),
+        }
+    }
+}
+
+/* -------------------------------------------------------------------------- */
+
+/// Colored (if TERM_COLORS is set) text output on stdout.
+pub struct HumanSink;
+
+impl ResultSink for HumanSink {
+    fn test_started(&mut self, category: &str, name: &str) -> anyhow::Result<()> {
+        print!("[{}] {}: ", category, name);
+        std::io::stdout().flush()?;
+
+        Ok(())
+    }
+
+    fn test_finished(&mut self, record: &TestRecord) -> anyhow::Result<()> {
+        let colors = crate::utils::is_env_var_set("TERM_COLORS");
+        let (color, reset) =
+            match (colors, record.status) {
+                (false, _) => ("", ""),
+                (true, TestStatus::Success) => ("\x1b[32m", "\x1b[0m"),
+                (true, TestStatus::Failure) => ("\x1b[31m", "\x1b[0m"),
+                (true, TestStatus::Skipped) => ("\x1b[33m", "\x1b[0m"),
+            };
+
+        match record.status {
+            TestStatus::Success => println!("{color}Success ✔{reset}"),
+            TestStatus::Failure => println!("{color}Failure ✖{reset}"),
+            TestStatus::Skipped => println!("{color}Skipped ⛒{reset}"),
+        };
+
+        if let Some(reason) = &record.reason {
+            println!("{color}    Reason: {reset}{reason}");
+        }
+
+        if let Some(details) = &record.details {
+            println!("{color}    Details: {reset}{details}");
+        }
+
+        std::io::stdout().flush()?;
+
+        Ok(())
+    }
+}
+
+/// One JSON object per line, appended to the given file.
+pub struct JsonLinesSink {
+    path: String,
+}
+
+impl JsonLinesSink {
+    pub fn new(path: &str) -> Self {
+        Self { path: path.to_owned() }
+    }
+
+    pub fn serialize(record: &TestRecord) -> anyhow::Result<String> {
+        serde_json::to_string(record)
+            .map_err(|err| anyhow::format_err!("Error in serializing test record: {err}"))
+    }
+}
+
+impl ResultSink for JsonLinesSink {
+    fn test_finished(&mut self, record: &TestRecord) -> anyhow::Result<()> {
+        let line = Self::serialize(record)? + "\n";
+
+        if self.path == "-" {
+            std::io::stdout().write_all(line.as_bytes())?;
+            return Ok(());
+        }
+
+        // the file is reopened for each record, so that multiple test
+        // executables can append to the same file one after the other.
+        std::fs::OpenOptions::new().create(true).append(true).open(&self.path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .map_err(|err| anyhow::format_err!("Error in writing test record to {}: {err}", self.path))
+    }
+}
+
+/// JUnit XML report. Test cases are appended to the given file, which is kept
+/// a valid document after every record.
+pub struct JUnitSink {
+    path: String,
+}
+
+const JUNIT_HEADER: &str =
+    "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+     <testsuites name=\"hcbs-test-suite\">\n\
+     <testsuite name=\"hcbs-test-suite\">\n";
+const JUNIT_FOOTER: &str = "</testsuite>\n</testsuites>\n";
+
+impl JUnitSink {
+    pub fn new(path: &str) -> Self {
+        Self { path: path.to_owned() }
+    }
+
+    pub fn serialize(record: &TestRecord) -> String {
+        let mut out = format!("  <testcase classname=\"{}\" name=\"{}\" time=\"{:.3}\">\n",
+            xml_escape(&record.category), xml_escape(&record.name), record.duration.as_secs_f64());
+
+        if !record.parameters.is_empty() {
+            out += "    <properties>\n";
+            for (key, value) in record.parameters.iter() {
+                out += &format!("      <property name=\"{}\" value=\"{}\"/>\n", xml_escape(key), xml_escape(value));
+            }
+            out += "    </properties>\n";
+        }
+
+        let reason = record.reason.as_deref().unwrap_or("");
+        match record.status {
+            TestStatus::Success => (),
+            TestStatus::Failure =>
+                out += &format!("    <failure message=\"{}\"/>\n", xml_escape(reason)),
+            TestStatus::Skipped =>
+                out += &format!("    <skipped message=\"{}\"/>\n", xml_escape(reason)),
+        };
+
+        if let Some(details) = &record.details {
+            out += &format!("    <system-out>{}</system-out>\n", xml_escape(details));
+        }
+
+        out += "  </testcase>\n";
+        out
+    }
+}
+
+impl ResultSink for JUnitSink {
+    fn test_finished(&mut self, record: &TestRecord) -> anyhow::Result<()> {
+        let current =
+            if std::fs::exists(&self.path)? {
+                std::fs::read_to_string(&self.path)
+                    .map_err(|err| anyhow::format_err!("Error in reading {}: {err}", self.path))?
+            } else {
+                String::new()
+            };
+
+        let mut document =
+            match current.strip_suffix(JUNIT_FOOTER) {
+                Some(body) if body.starts_with(JUNIT_HEADER) => body.to_owned(),
+                _ if current.trim().is_empty() => JUNIT_HEADER.to_owned(),
+                _ => anyhow::bail!("{} is not a JUnit report generated by this suite", self.path),
+            };
+
+        document += &Self::serialize(record);
+        document += JUNIT_FOOTER;
+
+        std::fs::write(&self.path, document)
+            .map_err(|err| anyhow::format_err!("Error in writing {}: {err}", self.path))
+    }
+}
+
+/* -------------------------------------------------------------------------- */
+
+struct PendingTest {
+    category: String,
+    name: String,
+    start: Instant,
+    parameters: Vec<(String, String)>,
+    kmsg: Option<KmsgMonitor>,
+}
+
+struct ResultsState {
+    sinks: Vec<Box<dyn ResultSink>>,
+    current: Option<PendingTest>,
+    kmsg_mode: Option<KmsgMode>,
+}
+
+static RESULTS_STATE: Mutex<Option<ResultsState>> = Mutex::new(None);
+
+fn sinks_from_spec(spec: &str) -> anyhow::Result<Vec<Box<dyn ResultSink>>> {
+    spec.split(',')
+        .map(|entry| entry.trim())
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| -> anyhow::Result<Box<dyn ResultSink>> {
+            match entry.split_once('=') {
+                None if entry == "human" => Ok(Box::new(HumanSink)),
+                Some(("jsonl", path)) => Ok(Box::new(JsonLinesSink::new(path))),
+                Some(("junit", path)) => Ok(Box::new(JUnitSink::new(path))),
+                _ => anyhow::bail!("Unknown result sink \'{entry}\'"),
+            }
+        })
+        .collect()
+}
+
+fn with_results_state<T, F: FnOnce(&mut ResultsState) -> T>(fun: F) -> T {
+    let mut state = RESULTS_STATE.lock().unwrap_or_else(|err| err.into_inner());
+    let state = state.get_or_insert_with(|| {
+        let sinks =
+            match std::env::var("BATCH_TEST_SINKS") {
+                Ok(spec) if !spec.is_empty() =>
+                    sinks_from_spec(&spec).unwrap_or_else(|err| {
+                        println!("Invalid BATCH_TEST_SINKS, using default: {err}");
+                        vec![Box::new(HumanSink) as Box<dyn ResultSink>]
+                    }),
+                _ => vec![Box::new(HumanSink) as Box<dyn ResultSink>],
+            };
+
+        let kmsg_mode = KmsgMode::from_env()
+            .unwrap_or_else(|err| {
+                println!("Invalid BATCH_TEST_KMSG, kernel log monitor disabled: {err}");
+                None
+            });
+
+        ResultsState { sinks, current: None, kmsg_mode }
+    });
+
+    fun(state)
+}
+
+/// Replace the active result sinks with the ones described by the given spec
+/// (same syntax of the `BATCH_TEST_SINKS` environment variable).
+pub fn set_result_sinks(spec: &str) -> anyhow::Result<()> {
+    let sinks = sinks_from_spec(spec)?;
+    with_results_state(|state| state.sinks = sinks);
+
+    Ok(())
+}
+
+/// Result sinks selection, shared by the test executables.
+#[derive(clap::Args, Debug, Clone, Default)]
+#[command(about = None, long_about = None)]
+pub struct ResultsArgs {
+    /// where results are reported: comma separated list of human,
+    /// jsonl=<path> and junit=<path> (- as path means stdout), overriding the
+    /// BATCH_TEST_SINKS environment variable
+    #[arg(long = "sinks", value_name = "spec", global = true)]
+    pub sinks: Option<String>,
+}
+
+impl ResultsArgs {
+    /// Activate the selected sinks, if any, also for the tests spawned by
+    /// this process.
+    ///
+    /// # Safety
+    /// Sets the `BATCH_TEST_SINKS` environment variable, see
+    /// [`std::env::set_var`].
+    pub unsafe fn apply(&self) -> anyhow::Result<()> {
+        if let Some(spec) = &self.sinks {
+            set_result_sinks(spec)?;
+            unsafe { std::env::set_var("BATCH_TEST_SINKS", spec) };
+        }
+
+        Ok(())
+    }
+}
+
+pub fn add_result_sink(sink: Box<dyn ResultSink>) {
+    with_results_state(|state| state.sinks.push(sink));
+}
+
+/// Enable (or disable, with None) the kernel log monitor for the next tests,
+/// overriding the `BATCH_TEST_KMSG` environment variable.
+pub fn set_kmsg_mode(mode: Option<KmsgMode>) {
+    with_results_state(|state| state.kmsg_mode = mode);
+}
+
+/// Mode of the kernel log monitor of the next tests, if enabled.
+pub fn kmsg_mode() -> Option<KmsgMode> {
+    with_results_state(|state| state.kmsg_mode)
+}
+
+pub fn test_started(category: &str, name: &str) {
+    with_results_state(|state| {
+        let mut parameters = Vec::new();
+        let kmsg = state.kmsg_mode
+            .and_then(|_| KmsgMonitor::start()
+                .inspect_err(|err| parameters.push(("kmsg".to_owned(), format!("unavailable: {err}"))))
+                .ok());
+
+        state.current = Some(PendingTest {
+            category: category.to_owned(),
+            name: name.to_owned(),
+            start: Instant::now(),
+            parameters,
+            kmsg,
+        });
+
+        for sink in state.sinks.iter_mut() {
+            if let Err(err) = sink.test_started(category, name) {
+                println!("Result sink error: {err}");
+            }
+        }
+    });
+}
+
+pub fn test_parameter(key: &str, value: String) {
+    with_results_state(|state| {
+        if let Some(test) = state.current.as_mut() {
+            test.parameters.push((key.to_owned(), value));
+        }
+    });
+}
+
+/// Report the result of the test started by the last `test_started`,
+/// returning its final status: a passed test fails if the kernel log monitor
+/// finds events which fail it.
+pub fn test_finished(status: TestStatus, reason: Option<String>, details: Option<String>) -> TestStatus {
+    with_results_state(|state| {
+        let test = state.current.take()
+            .unwrap_or_else(|| PendingTest {
+                category: String::new(),
+                name: String::new(),
+                start: Instant::now(),
+                parameters: Vec::new(),
+                kmsg: None,
+            });
+
+        let mut record = TestRecord {
+            category: test.category,
+            name: test.name,
+            status,
+            reason,
+            details,
+            duration: test.start.elapsed(),
+            parameters: test.parameters,
+        };
+
+        if let (Some(monitor), Some(mode)) = (test.kmsg, state.kmsg_mode) {
+            attach_kmsg(&mut record, monitor, mode);
+        }
+
+        for sink in state.sinks.iter_mut() {
+            if let Err(err) = sink.test_finished(&record) {
+                println!("Result sink error: {err}");
+            }
+        }
+
+        record.status
+    })
+}
+
+/// Attach the kernel messages logged during the test to its record: a
+/// summary as parameter and the events in the details.
+fn attach_kmsg(record: &mut TestRecord, mut monitor: KmsgMonitor, mode: KmsgMode) {
+    let report =
+        match monitor.collect() {
+            Ok(report) => report,
+            Err(err) => {
+                record.parameters.push(("kmsg".to_owned(), format!("error: {err}")));
+                return;
+            },
+        };
+
+    record.parameters.push(("kmsg".to_owned(), report.level_counts()));
+    if report.events.is_empty() {
+        return;
+    }
+
+    let events = report.events.iter()
+        .map(|event| format!("{event}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    record.details = Some(match record.details.take() {
+        Some(details) => format!("{details}\nKernel log events:\n{events}"),
+        None => format!("Kernel log events:\n{events}"),
+    });
+
+    let failures = report.failures(mode);
+    if record.status == TestStatus::Success && let Some(first) = failures.first() {
+        record.status = TestStatus::Failure;
+        record.reason = Some(format!("{} kernel log events ({mode} mode), the first: {first}", failures.len()));
+    }
+}
+
+pub fn xml_escape(str: &str) -> String {
+    let mut out = String::with_capacity(str.len());
+    for ch in str.chars() {
+        match ch {
+            '&' => out += "&amp;",
+            '<' => out += "&lt;",
+            '>' => out += "&gt;",
+            '"' => out += "&quot;",
+            '\'' => out += "&apos;",
+            '\n' => out += "&#10;",
+            ch if (ch as u32) < 0x20 && ch != '\t' => (),
+            ch => out.push(ch),
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(status: TestStatus) -> TestRecord {
+        TestRecord {
+            category: "time".to_owned(),
+            name: "uni \"quoted\" <r40>".to_owned(),
+            status,
+            reason: Some("used 0.5 instead of 0.4\nsecond line".to_owned()),
+            details: None,
+            duration: Duration::from_millis(1500),
+            parameters: vec![
+                ("runtime_ms".to_owned(), "40".to_owned()),
+                ("cgroup".to_owned(), "g&0".to_owned()),
+            ],
+        }
+    }
+
+    #[test]
+    fn json_lines_record() {
+        let line = JsonLinesSink::serialize(&record(TestStatus::Failure)).unwrap();
+        assert!(!line.contains('\n'));
+
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["category"], "time");
+        assert_eq!(value["name"], "uni \"quoted\" <r40>");
+        assert_eq!(value["status"], "failure");
+        assert_eq!(value["reason"], "used 0.5 instead of 0.4\nsecond line");
+        assert_eq!(value["details"], serde_json::Value::Null);
+        assert_eq!(value["duration_secs"], 1.5);
+        assert_eq!(value["parameters"]["runtime_ms"], "40");
+        assert_eq!(value["parameters"]["cgroup"], "g&0");
+
+        // parameters keep their order
+        assert!(line.find("runtime_ms").unwrap() < line.find("cgroup").unwrap());
+    }
+
+    #[test]
+    fn json_lines_status_roundtrip() {
+        for status in [TestStatus::Success, TestStatus::Failure, TestStatus::Skipped] {
+            let line = JsonLinesSink::serialize(&record(status)).unwrap();
+            let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+
+            assert_eq!(value["status"].as_str().unwrap().parse::<TestStatus>().unwrap(), status);
+        }
+    }
+
+    #[test]
+    fn junit_testcase() {
+        let success = JUnitSink::serialize(&TestRecord { reason: None, ..record(TestStatus::Success) });
+        assert!(success.starts_with("  <testcase classname=\"time\" name=\"uni &quot;quoted&quot; &lt;r40&gt;\" time=\"1.500\">\n"));
+        assert!(success.contains("      <property name=\"cgroup\" value=\"g&amp;0\"/>\n"));
+        assert!(!success.contains("<failure") && !success.contains("<skipped"));
+        assert!(success.ends_with("  </testcase>\n"));
+
+        let failure = JUnitSink::serialize(&record(TestStatus::Failure));
+        assert!(failure.contains("    <failure message=\"used 0.5 instead of 0.4&#10;second line\"/>\n"));
+
+        let skipped = JUnitSink::serialize(&TestRecord {
+            details: Some("a < b".to_owned()),
+            ..record(TestStatus::Skipped)
+        });
+        assert!(skipped.contains("    <skipped message="));
+        assert!(skipped.contains("    <system-out>a &lt; b</system-out>\n"));
+    }
+
+    #[test]
+    fn sinks_spec() {
+        assert_eq!(sinks_from_spec("human, jsonl=-,junit=out.xml,").unwrap().len(), 3);
+        assert!(sinks_from_spec("csv=out.csv").is_err());
+        assert!(sinks_from_spec("jsonl").is_err());
+    }
+}
@@ -0,0 +1,182 @@
+//! Priority inheritance mutex shared between processes.
+//!
+//! The mutex lives in a file mapped in memory by all the processes using it
+//! (e.g. in /dev/shm), along with a few flags used to coordinate them. This
+//! allows to share it among tasks living in different cgroups.
+
+use std::os::fd::AsRawFd;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+pub mod prelude {
+    pub use super::{
+        SharedPiMutex,
+        thread_cpu_time,
+        spin_for_cpu_time,
+    };
+}
+
+#[repr(C)]
+struct SharedState {
+    mutex: libc::pthread_mutex_t,
+    /// raised by the holder while it is inside the critical section
+    held: AtomicU32,
+    /// raised to stop the tasks using the mutex
+    stop: AtomicU32,
+}
+
+/// PTHREAD_PRIO_INHERIT, process shared, pthread mutex.
+pub struct SharedPiMutex {
+    path: String,
+    state: *mut SharedState,
+    owner: bool,
+}
+
+unsafe impl Send for SharedPiMutex {}
+unsafe impl Sync for SharedPiMutex {}
+
+fn os_error(what: &str, path: &str, err: i32) -> anyhow::Error {
+    anyhow::format_err!("Error in {what} shared mutex {path}: {}", std::io::Error::from_raw_os_error(err))
+}
+
+impl SharedPiMutex {
+    /// Create and initialize the mutex at the given path. The file is removed
+    /// when the returned object is dropped.
+    pub fn create(path: &str) -> anyhow::Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .read(true).write(true).create_new(true)
+            .open(path)
+            .map_err(|err| anyhow::format_err!("Error in creating shared mutex {path}: {err}"))?;
+        file.set_len(std::mem::size_of::<SharedState>() as u64)?;
+
+        let mutex = Self { path: path.to_owned(), state: Self::map(&file, path)?, owner: true };
+
+        unsafe {
+            let mut attr: libc::pthread_mutexattr_t = std::mem::zeroed();
+            libc::pthread_mutexattr_init(&mut attr);
+
+            let res = libc::pthread_mutexattr_setpshared(&mut attr, libc::PTHREAD_PROCESS_SHARED);
+            let res = if res == 0 { libc::pthread_mutexattr_setprotocol(&mut attr, libc::PTHREAD_PRIO_INHERIT) } else { res };
+            let res = if res == 0 { libc::pthread_mutex_init(&mut (*mutex.state).mutex, &attr) } else { res };
+
+            libc::pthread_mutexattr_destroy(&mut attr);
+
+            if res != 0 {
+                return Err(os_error("initializing", path, res));
+            }
+        }
+
+        Ok(mutex)
+    }
+
+    /// Open a mutex created by another process.
+    pub fn open(path: &str) -> anyhow::Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .read(true).write(true)
+            .open(path)
+            .map_err(|err| anyhow::format_err!("Error in opening shared mutex {path}: {err}"))?;
+
+        Ok(Self { path: path.to_owned(), state: Self::map(&file, path)?, owner: false })
+    }
+
+    fn map(file: &std::fs::File, path: &str) -> anyhow::Result<*mut SharedState> {
+        let state = unsafe {
+            libc::mmap(std::ptr::null_mut(), std::mem::size_of::<SharedState>(),
+                libc::PROT_READ | libc::PROT_WRITE, libc::MAP_SHARED, file.as_raw_fd(), 0)
+        };
+
+        if state == libc::MAP_FAILED {
+            return Err(anyhow::format_err!("Error in mapping shared mutex {path}: {}", std::io::Error::last_os_error()));
+        }
+
+        Ok(state as *mut SharedState)
+    }
+
+    fn state(&self) -> &SharedState {
+        unsafe { &*self.state }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn lock(&self) -> anyhow::Result<()> {
+        match unsafe { libc::pthread_mutex_lock(&mut (*self.state).mutex) } {
+            0 => Ok(()),
+            err => Err(os_error("locking", &self.path, err)),
+        }
+    }
+
+    /// Lock the mutex, giving up after the given timeout. Returns whether the
+    /// mutex was acquired.
+    pub fn lock_timeout(&self, timeout: Duration) -> anyhow::Result<bool> {
+        let mut deadline: libc::timespec = unsafe { std::mem::zeroed() };
+        unsafe { libc::clock_gettime(libc::CLOCK_REALTIME, &mut deadline); }
+
+        let nsec = deadline.tv_nsec as u64 + timeout.subsec_nanos() as u64;
+        deadline.tv_sec += (timeout.as_secs() + nsec / 1_000_000_000) as libc::time_t;
+        deadline.tv_nsec = (nsec % 1_000_000_000) as libc::c_long;
+
+        match unsafe { libc::pthread_mutex_timedlock(&mut (*self.state).mutex, &deadline) } {
+            0 => Ok(true),
+            libc::ETIMEDOUT => Ok(false),
+            err => Err(os_error("locking", &self.path, err)),
+        }
+    }
+
+    pub fn unlock(&self) -> anyhow::Result<()> {
+        match unsafe { libc::pthread_mutex_unlock(&mut (*self.state).mutex) } {
+            0 => Ok(()),
+            err => Err(os_error("unlocking", &self.path, err)),
+        }
+    }
+
+    pub fn is_held(&self) -> bool {
+        self.state().held.load(Ordering::Acquire) != 0
+    }
+
+    pub fn set_held(&self, held: bool) {
+        self.state().held.store(held as u32, Ordering::Release);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.state().stop.load(Ordering::Acquire) != 0
+    }
+
+    pub fn stop(&self) {
+        self.state().stop.store(1, Ordering::Release);
+    }
+}
+
+impl Drop for SharedPiMutex {
+    fn drop(&mut self) {
+        unsafe {
+            if self.owner {
+                libc::pthread_mutex_destroy(&mut (*self.state).mutex);
+            }
+
+            libc::munmap(self.state as *mut libc::c_void, std::mem::size_of::<SharedState>());
+        }
+
+        if self.owner {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// CPU time consumed by the calling thread.
+pub fn thread_cpu_time() -> Duration {
+    let mut time: libc::timespec = unsafe { std::mem::zeroed() };
+    unsafe { libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut time); }
+
+    Duration::new(time.tv_sec as u64, time.tv_nsec as u32)
+}
+
+/// Busy loop until the calling thread has consumed the given CPU time.
+pub fn spin_for_cpu_time(cpu_time: Duration) {
+    let end = thread_cpu_time() + cpu_time;
+
+    while thread_cpu_time() < end {
+        std::hint::spin_loop();
+    }
+}
@@ -0,0 +1,304 @@
+use crate::prelude::*;
+use crate::fs_backend::cgroup;
+
+pub mod prelude {
+    pub use super::{
+        SchedSnapshot,
+        ServerParams,
+        SnapshotGuard,
+    };
+}
+
+const FAIR_SERVER_DIR: &str = "/sys/kernel/debug/sched/fair_server";
+const EXT_SERVER_DIR: &str = "/sys/kernel/debug/sched/ext_server";
+const SCHED_FEATURES_FILE: &str = "/sys/kernel/debug/sched/features";
+const SCHED_RT_SYSCTLS: [&str; 3] = [
+    "/proc/sys/kernel/sched_rt_period_us",
+    "/proc/sys/kernel/sched_rt_runtime_us",
+    "/proc/sys/kernel/sched_rr_timeslice_ms",
+];
+
+/// Parameters of a single (per-CPU) fair or ext deadline server.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ServerParams {
+    pub path: String,
+    pub runtime_ns: u64,
+    pub period_ns: u64,
+}
+
+/// Snapshot of the global scheduler state that the tests and tools modify.
+///
+/// Every part of the state is optional, as it is not captured if the relative
+/// files are not available (e.g. debugfs not mounted).
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct SchedSnapshot {
+    pub root_runtime_us: Option<u64>,
+    pub root_period_us: Option<u64>,
+    pub fair_servers: Vec<ServerParams>,
+    pub ext_servers: Vec<ServerParams>,
+    pub sched_features: Option<String>,
+    pub sysctls: Vec<(String, String)>,
+    pub task_cgroups: Vec<(Pid, String)>,
+}
+
+fn read_value<T: std::str::FromStr>(file: &str) -> anyhow::Result<T>
+    where T::Err: std::fmt::Display
+{
+    fs_backend().read_to_string(file)
+        .and_then(|value| value.trim().parse::<T>()
+            .map_err(|err| anyhow::format_err!("Error in parsing {file}: {err}"))
+        )
+}
+
+fn write_value(file: &str, data: &str) -> anyhow::Result<()> {
+    fs_backend().write(file, data)
+}
+
+fn capture_servers(server_dir: &str) -> anyhow::Result<Vec<ServerParams>> {
+    let fs = fs_backend();
+    if !fs.exists(server_dir) {
+        return Ok(Vec::new());
+    }
+
+    let mut servers = Vec::new();
+    for entry in fs.read_dir(server_dir)? {
+        let path = format!("{server_dir}/{entry}");
+        if fs.is_dir(&path) {
+
+            servers.push(ServerParams {
+                runtime_ns: read_value(&format!("{path}/runtime"))?,
+                period_ns: read_value(&format!("{path}/period"))?,
+                path,
+            });
+        }
+    }
+
+    servers.sort_by(|l, r| l.path.cmp(&r.path));
+    Ok(servers)
+}
+
+fn restore_servers(servers: &[ServerParams]) -> anyhow::Result<()> {
+    for server in servers.iter() {
+        let ServerParams { path, runtime_ns, period_ns } = server;
+
+        let cur_runtime_ns: u64 = read_value(&format!("{path}/runtime"))?;
+        let cur_period_ns: u64 = read_value(&format!("{path}/period"))?;
+        if cur_runtime_ns == *runtime_ns && cur_period_ns == *period_ns {
+            continue;
+        }
+
+        write_value(&format!("{path}/runtime"), "0")?;
+        write_value(&format!("{path}/period"), &format!("{period_ns}"))?;
+        write_value(&format!("{path}/runtime"), &format!("{runtime_ns}"))?;
+    }
+
+    Ok(())
+}
+
+impl SchedSnapshot {
+    pub fn capture() -> anyhow::Result<Self> {
+        let fs = fs_backend();
+        let mut snapshot = Self::default();
+
+        if cgroup::exists(".") {
+            snapshot.root_runtime_us = Some(cgroup::get_runtime_us(".")?);
+            snapshot.root_period_us = Some(cgroup::get_period_us(".")?);
+        }
+
+        snapshot.fair_servers = capture_servers(FAIR_SERVER_DIR)?;
+        snapshot.ext_servers = capture_servers(EXT_SERVER_DIR)?;
+
+        if fs.exists(SCHED_FEATURES_FILE) {
+            snapshot.sched_features = Some(fs.read_to_string(SCHED_FEATURES_FILE)?.trim().to_owned());
+        }
+
+        for sysctl in SCHED_RT_SYSCTLS {
+            if fs.exists(sysctl) {
+                snapshot.sysctls.push((sysctl.to_owned(), fs.read_to_string(sysctl)?.trim().to_owned()));
+            }
+        }
+
+        if cgroup::exists(".") {
+            for entry in fs.read_dir("/proc")? {
+                let Ok(pid) = entry.parse::<Pid>() else { continue; };
+
+                // the process may have already terminated
+                if let Ok(cgroup) = cgroup::get_pid_cgroup(pid) {
+                    snapshot.task_cgroups.push((pid, cgroup));
+                }
+            }
+
+            snapshot.task_cgroups.sort();
+        }
+
+        Ok(snapshot)
+    }
+
+    /// Restore the captured state. Only the values which differ from the
+    /// current ones are written. Restoring goes on even if some of the steps
+    /// fail, returning the first error.
+    pub fn restore(&self) -> anyhow::Result<()> {
+        let mut result = Ok(());
+        let mut step = |res: anyhow::Result<()>| {
+            if result.is_ok() {
+                result = res;
+            }
+        };
+
+        if let Some(features) = &self.sched_features {
+            step((|| {
+                let current = fs_backend().read_to_string(SCHED_FEATURES_FILE)?;
+                for feature in features.split_whitespace() {
+                    if !current.split_whitespace().any(|f| f == feature) {
+                        write_value(SCHED_FEATURES_FILE, feature)?;
+                    }
+                }
+
+                Ok(())
+            })());
+        }
+
+        step(restore_servers(&self.fair_servers));
+        step(restore_servers(&self.ext_servers));
+
+        for (sysctl, value) in self.sysctls.iter() {
+            step((|| {
+                if fs_backend().read_to_string(sysctl)?.trim() != value {
+                    write_value(sysctl, value)?;
+                }
+
+                Ok(())
+            })());
+        }
+
+        if let (Some(runtime_us), Some(period_us)) = (self.root_runtime_us, self.root_period_us) {
+            step((|| {
+                if cgroup::get_period_us(".")? != period_us {
+                    cgroup::set_runtime_us(".", 0)?;
+                    cgroup::set_period_us(".", period_us)?;
+                }
+
+                if cgroup::get_runtime_us(".")? != runtime_us {
+                    cgroup::set_runtime_us(".", runtime_us)?;
+                }
+
+                Ok(())
+            })());
+        }
+
+        for (pid, cgroup) in self.task_cgroups.iter() {
+            // skip terminated tasks and destroyed cgroups
+            let Ok(current) = cgroup::get_pid_cgroup(*pid) else { continue; };
+            if &current == cgroup || !cgroup::exists(cgroup) {
+                continue;
+            }
+
+            step(cgroup::assign_pid(cgroup, *pid));
+        }
+
+        result
+    }
+
+    pub fn save(&self, file: &str) -> anyhow::Result<()> {
+        let data = serde_json::to_string_pretty(self)?;
+
+        std::fs::write(file, data)
+            .map_err(|err| anyhow::format_err!("Error in writing snapshot to {file}: {err}"))
+    }
+
+    pub fn load(file: &str) -> anyhow::Result<Self> {
+        let data = std::fs::read_to_string(file)
+            .map_err(|err| anyhow::format_err!("Error on reading file {file}, reason {err}"))?;
+
+        serde_json::from_str(&data)
+            .map_err(|err| anyhow::format_err!("Snapshot parser error: {err}"))
+    }
+}
+
+/// RAII guard which captures the scheduler state on creation and restores it
+/// when dropped, i.e. on normal exit, on error and on panic. Tests stopped by
+/// Ctrl+C return normally (see `wait_loop`), thus dropping the guard.
+pub struct SnapshotGuard {
+    snapshot: Option<SchedSnapshot>,
+}
+
+impl SnapshotGuard {
+    pub fn new() -> anyhow::Result<Self> {
+        Ok(Self { snapshot: Some(SchedSnapshot::capture()?) })
+    }
+
+    pub fn snapshot(&self) -> &SchedSnapshot {
+        self.snapshot.as_ref().unwrap()
+    }
+
+    /// Restore the state now, reporting any error to the caller.
+    pub fn restore(mut self) -> anyhow::Result<()> {
+        self.snapshot.take().unwrap().restore()
+    }
+}
+
+impl Drop for SnapshotGuard {
+    fn drop(&mut self) {
+        if let Some(snapshot) = self.snapshot.take()
+            && let Err(err) = snapshot.restore()
+        {
+            println!("Error in restoring the scheduler state: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs_backend::fake::FakeFs;
+
+    fn server(cpu: usize, runtime_ns: u64, period_ns: u64) -> ServerParams {
+        ServerParams { path: format!("{FAIR_SERVER_DIR}/cpu{cpu}"), runtime_ns, period_ns }
+    }
+
+    #[test]
+    fn capture_and_restore() {
+        let (_lock, fake) = FakeFs::new(2).install_for_test();
+        cgroup::create("g0").unwrap();
+        fake.add_task(1_000_001, 1, ".").unwrap();
+        fake.add_task(1_000_002, 0, "g0").unwrap();
+
+        let snapshot = SchedSnapshot::capture().unwrap();
+        assert_eq!(snapshot.root_runtime_us, Some(950_000));
+        assert_eq!(snapshot.root_period_us, Some(1_000_000));
+        assert_eq!(snapshot.fair_servers, vec![server(0, 50_000_000, 1_000_000_000), server(1, 50_000_000, 1_000_000_000)]);
+        assert!(snapshot.ext_servers.is_empty());
+        assert_eq!(snapshot.sched_features.as_deref(), Some("NO_HRTICK HRTICK_DL"));
+        assert_eq!(snapshot.sysctls.len(), 2);
+        assert_eq!(snapshot.task_cgroups, vec![(1_000_001, ".".to_owned()), (1_000_002, "g0".to_owned())]);
+
+        cgroup::set_runtime_us(".", 0).unwrap();
+        cgroup::set_period_us(".", 500_000).unwrap();
+        crate::utils::setup_servers(10_000, 100_000, false).unwrap();
+        fake.write(SCHED_FEATURES_FILE, "HRTICK").unwrap();
+        fake.write("/proc/sys/kernel/sched_rt_runtime_us", "900000").unwrap();
+        fake.add_task(1_000_002, 0, ".").unwrap();
+
+        snapshot.restore().unwrap();
+
+        let restored = SchedSnapshot::capture().unwrap();
+        assert_eq!(restored.root_runtime_us, snapshot.root_runtime_us);
+        assert_eq!(restored.root_period_us, snapshot.root_period_us);
+        assert_eq!(restored.fair_servers, snapshot.fair_servers);
+        assert_eq!(restored.sched_features, snapshot.sched_features);
+        assert_eq!(restored.sysctls, snapshot.sysctls);
+        assert_eq!(restored.task_cgroups, snapshot.task_cgroups);
+    }
+
+    #[test]
+    fn guard_restores_on_drop() {
+        let (_lock, _fake) = FakeFs::new(1).install_for_test();
+
+        {
+            let _guard = SnapshotGuard::new().unwrap();
+            cgroup::set_runtime_us(".", 500_000).unwrap();
+        }
+
+        assert_eq!(cgroup::get_runtime_us(".").unwrap(), 950_000);
+    }
+}
@@ -0,0 +1,341 @@
+use std::sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}};
+use std::time::{Duration, Instant};
+
+use crate::prelude::*;
+use crate::fs_backend::cgroup;
+
+pub mod prelude {
+    pub use super::{
+        Supervisor,
+        supervise_pid,
+        supervise_cgroup,
+    };
+}
+
+/// Time given to a test to stop by itself, after its exit flag has been raised
+/// by the watchdog, before its workloads get killed.
+const WATCHDOG_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Default)]
+struct Tracked {
+    pids: Vec<Pid>,
+    cgroups: Vec<String>,
+}
+
+/// Workloads and cgroups of the running supervisors, innermost last.
+static TRACKED: Mutex<Vec<Tracked>> = Mutex::new(Vec::new());
+
+/// Track a spawned child process, which is killed and reaped when the current
+/// supervisor (if any) is cleaned up.
+pub fn supervise_pid(pid: Pid) {
+    if let Some(tracked) = TRACKED.lock().unwrap().last_mut() {
+        tracked.pids.push(pid);
+    }
+}
+
+/// Track a cgroup, which is destroyed, together with its descendants, when the
+/// current supervisor (if any) is cleaned up. Cgroups created through
+/// `FsCgroup` or `fs_backend::cgroup::create` are tracked already, any other
+/// cgroup is left alone.
+pub fn supervise_cgroup(name: &str) {
+    let name = name.trim_matches('/');
+    if name.is_empty() || name == "." {
+        return;
+    }
+
+    if let Some(tracked) = TRACKED.lock().unwrap().last_mut() {
+        tracked.cgroups.push(name.to_owned());
+    }
+}
+
+/// Kill the given child process, and wait for it to terminate, without reaping
+/// it: its owner (e.g. a `HCBSProcess`) may still be waiting for it. Processes
+/// which are not children of this one anymore (i.e. already reaped by their
+/// owner) are skipped, as their pid may have been recycled, and so are the
+/// ones already terminated.
+fn kill_child(pid: Pid) -> bool {
+    let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+    let options = libc::WEXITED | libc::WNOWAIT;
+
+    // still running, as terminated children are returned without reaping them
+    let running = unsafe { libc::waitid(libc::P_PID, pid, &mut info, options | libc::WNOHANG) } == 0
+        && unsafe { info.si_pid() } == 0;
+    if !running {
+        return false;
+    }
+
+    unsafe {
+        libc::kill(pid as libc::pid_t, libc::SIGKILL);
+        libc::waitid(libc::P_PID, pid, &mut info, options);
+    }
+
+    true
+}
+
+fn kill_tracked_pids(pids: &[Pid]) -> Vec<Pid> {
+    pids.iter()
+        .filter(|pid| kill_child(**pid))
+        .copied()
+        .collect()
+}
+
+/// The given cgroup and all its descendants, parents first.
+fn list_cgroups(root: &str) -> anyhow::Result<Vec<String>> {
+    let fs = fs_backend();
+    if !cgroup::exists(root) {
+        return Ok(Vec::new());
+    }
+
+    let mut cgroups = vec![root.to_owned()];
+    let mut to_visit = vec![root.to_owned()];
+    while let Some(parent) = to_visit.pop() {
+        let parent_dir = cgroup::cgroup_dir(&parent);
+
+        for entry in fs.read_dir(&parent_dir)? {
+            if !fs.is_dir(&format!("{parent_dir}/{entry}")) {
+                continue;
+            }
+
+            let name = format!("{parent}/{entry}");
+            cgroups.push(name.clone());
+            to_visit.push(name);
+        }
+    }
+
+    Ok(cgroups)
+}
+
+/// Move any remaining task to the root cgroup and remove the given cgroup.
+fn destroy_cgroup(name: &str) -> anyhow::Result<()> {
+    if !cgroup::exists(name) {
+        return Ok(());
+    }
+
+    let procs = fs_backend().read_to_string(&format!("{}/cgroup.procs", cgroup::cgroup_dir(name)))?;
+    for pid in procs.lines().filter_map(|pid| pid.trim().parse::<Pid>().ok()) {
+        // the task may have already terminated
+        let _ = cgroup::assign_pid(".", pid);
+    }
+
+    cgroup::remove(name)
+}
+
+/// Per-test supervisor of workloads and cgroups.
+///
+/// While the supervisor is alive, the children spawned by `run_yes`,
+/// `cpu_hog` and the other workload launchers are tracked, and so are the
+/// cgroups created through `FsCgroup`. On cleanup, i.e. on `cleanup`
+/// or when dropped (normal exit, error or panic), the tracked children still
+/// running are killed, and the tracked cgroups are destroyed, after moving any
+/// remaining task to the root cgroup. Killed children are left to be reaped by
+/// their owners.
+///
+/// If given a timeout, a watchdog raises the supervisor's exit flag when the
+/// timeout expires, and kills the tracked children if the test has not stopped
+/// within a grace period. The exit flag is also raised on Ctrl+C. Tests run
+/// through `run` are abandoned if they do not stop even then.
+pub struct Supervisor {
+    deadline: Option<Instant>,
+    exit_flag: ExitFlag,
+    timed_out: Arc<AtomicBool>,
+    watchdog: Option<(crossbeam::channel::Sender<()>, std::thread::JoinHandle<()>)>,
+    cleaned_up: bool,
+}
+
+impl Supervisor {
+    pub fn new(ctrlc_flag: Option<ExitFlag>, timeout: Option<Duration>) -> anyhow::Result<Self> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout + 2 * WATCHDOG_GRACE_PERIOD);
+        TRACKED.lock().unwrap().push(Tracked::default());
+
+        let (exit_send, exit_flag) = ExitFlag::channel();
+        let (stop_send, stop_recv) = crossbeam::channel::bounded::<()>(1);
+        let timed_out = Arc::new(AtomicBool::new(false));
+
+        let watchdog = {
+            let timed_out = timed_out.clone();
+            let ctrlc_recv = ctrlc_flag.as_ref()
+                .map(|flag| flag.receiver().clone())
+                .unwrap_or_else(crossbeam::channel::never);
+            let timeout_ch = timeout
+                .map(crossbeam::channel::after)
+                .unwrap_or_else(crossbeam::channel::never);
+
+            std::thread::spawn(move || {
+                crossbeam::channel::select! {
+                    recv(stop_recv) -> _ => { return; },
+                    recv(ctrlc_recv) -> _ => {
+                        if let Some(flag) = ctrlc_flag {
+                            flag.raise();
+                        }
+
+                        let _ = exit_send.try_send(());
+                        return;
+                    },
+                    recv(timeout_ch) -> _ => {
+                        timed_out.store(true, Ordering::Relaxed);
+                        let _ = exit_send.try_send(());
+                    },
+                };
+
+                if stop_recv.recv_timeout(WATCHDOG_GRACE_PERIOD).is_err() {
+                    let pids = TRACKED.lock().unwrap().last()
+                        .map(|tracked| tracked.pids.clone())
+                        .unwrap_or_default();
+
+                    kill_tracked_pids(&pids);
+                }
+            })
+        };
+
+        Ok(Self {
+            deadline,
+            exit_flag,
+            timed_out,
+            watchdog: Some((stop_send, watchdog)),
+            cleaned_up: false,
+        })
+    }
+
+    /// Flag to be given to the supervised test, raised on Ctrl+C or timeout.
+    pub fn exit_flag(&self) -> ExitFlag {
+        self.exit_flag.clone()
+    }
+
+    pub fn timed_out(&self) -> bool {
+        self.timed_out.load(Ordering::Relaxed)
+    }
+
+    /// Run the given test on its own thread, passing it the exit flag. After
+    /// the timeout, the test is given a grace period to stop by itself, and
+    /// another one once its workloads have been killed. If it is still running
+    /// then, it is abandoned, with its thread left running, and None is
+    /// returned.
+    pub fn run<T, F>(&self, test: F) -> anyhow::Result<Option<T>>
+        where T: Send + 'static, F: FnOnce(ExitFlag) -> T + Send + 'static
+    {
+        let (result_send, result_recv) = crossbeam::channel::bounded(1);
+        let exit_flag = self.exit_flag();
+
+        std::thread::Builder::new()
+            .name("supervised-test".to_owned())
+            .spawn(move || { let _ = result_send.send(test(exit_flag)); })?;
+
+        let result =
+            match self.deadline {
+                Some(deadline) => result_recv.recv_deadline(deadline).ok(),
+                None => result_recv.recv().ok(),
+            };
+
+        Ok(result)
+    }
+
+    /// Stop the watchdog, kill the leftover children and destroy the leftover
+    /// cgroups, returning a description of what has been cleaned up, if
+    /// anything. Cleaning up goes on even if some of the steps fail, returning
+    /// the first error.
+    pub fn cleanup(mut self) -> anyhow::Result<Option<String>> {
+        self.cleanup_impl()
+    }
+
+    fn cleanup_impl(&mut self) -> anyhow::Result<Option<String>> {
+        if self.cleaned_up {
+            return Ok(None);
+        }
+        self.cleaned_up = true;
+
+        if let Some((stop, watchdog)) = self.watchdog.take() {
+            let _ = stop.try_send(());
+            let _ = watchdog.join();
+        }
+
+        let tracked = TRACKED.lock().unwrap().pop().unwrap_or_default();
+        let killed = kill_tracked_pids(&tracked.pids);
+
+        let mut result = Ok(());
+        let mut cgroups: Vec<String> = tracked.cgroups.iter()
+            .flat_map(|cgroup| list_cgroups(cgroup)
+                .unwrap_or_else(|err| { result = Err(err); Vec::new() }))
+            .collect();
+
+        // children first
+        cgroups.sort();
+        cgroups.dedup();
+        cgroups.reverse();
+
+        let mut destroyed = Vec::new();
+        for cgroup in cgroups {
+            if !cgroup::exists(&cgroup) {
+                continue;
+            }
+
+            match destroy_cgroup(&cgroup) {
+                Ok(_) => destroyed.push(cgroup),
+                Err(err) => if result.is_ok() { result = Err(err); },
+            }
+        }
+
+        result?;
+
+        let mut cleaned = Vec::new();
+        if !killed.is_empty() {
+            cleaned.push(format!("killed tasks {killed:?}"));
+        }
+
+        if !destroyed.is_empty() {
+            cleaned.push(format!("destroyed cgroups {destroyed:?}"));
+        }
+
+        if cleaned.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(cleaned.join(", ")))
+        }
+    }
+}
+
+impl Drop for Supervisor {
+    fn drop(&mut self) {
+        if let Err(err) = self.cleanup_impl() {
+            println!("Error in cleaning up the test's workloads: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs_backend::fake::FakeFs;
+
+    #[test]
+    fn only_tracked_cgroups_are_destroyed() {
+        let (_lock, fake) = FakeFs::new(1).install_for_test();
+        cgroup::create("before").unwrap();
+
+        let supervisor = Supervisor::new(None, None).unwrap();
+        cgroup::create("g0").unwrap();
+        cgroup::create("g0/a").unwrap();
+        // not created through the suite's helpers
+        fake.create_dir(&cgroup::cgroup_dir("g0/b")).unwrap();
+        fake.create_dir(&cgroup::cgroup_dir("other")).unwrap();
+
+        assert_eq!(supervisor.run(|_| 42).unwrap(), Some(42));
+
+        let cleaned_up = supervisor.cleanup().unwrap().unwrap();
+        assert_eq!(cleaned_up, r#"destroyed cgroups ["g0/b", "g0/a", "g0"]"#);
+        assert!(cgroup::exists("before"));
+        assert!(cgroup::exists("other"));
+        assert!(!cgroup::exists("g0"));
+    }
+
+    #[test]
+    fn killed_children_are_not_reaped() {
+        let mut child = std::process::Command::new("sleep").arg("60").spawn().unwrap();
+        let pid = child.id();
+
+        assert_eq!(kill_tracked_pids(&[pid]), vec![pid]);
+        assert!(!child.wait().unwrap().success());
+
+        // already reaped by its owner
+        assert!(kill_tracked_pids(&[pid]).is_empty());
+    }
+}
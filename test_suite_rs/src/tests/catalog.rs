@@ -0,0 +1,594 @@
+use crate::prelude::*;
+use crate::tests::{constraints, regression, stress, time};
+
+pub mod prelude {
+    pub use super::{
+        HcbsTest,
+        NamedTest,
+        catalog,
+        select_tests,
+        glob_match,
+        run_catalog_test,
+        __opt_arg,
+        __cpu_set_arg,
+    };
+}
+
+/// Common interface of the suite's tests, which allows to list, filter and
+/// run them in-process from a single entry point.
+pub trait HcbsTest: Send + Sync {
+    fn name(&self) -> String;
+
+    fn category(&self) -> &'static str;
+
+    /// Command line arguments of the test, without its subcommand (which the
+    /// test's name already identifies) and without leading whitespace.
+    fn parameters(&self) -> String;
+
+    /// Capabilities checked before running the test, which is skipped if any
+    /// of them is missing.
+    fn requirements(&self) -> Vec<Capability> {
+        vec![Capability::RtGroup]
+    }
+
+    /// Run the test and compute its verdict, without reporting it.
+    fn run(&self, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<String>>;
+
+    fn id(&self) -> String {
+        format!("{}/{}", self.category(), self.name())
+    }
+}
+
+/// Wrapper to give a custom name, and optionally category, to a test.
+pub struct NamedTest<T: HcbsTest> {
+    name: String,
+    category: Option<&'static str>,
+    test: T,
+}
+
+impl<T: HcbsTest> NamedTest<T> {
+    pub fn new(name: &str, test: T) -> Self {
+        Self { name: name.to_owned(), category: None, test }
+    }
+
+    pub fn with_category(self, category: &'static str) -> Self {
+        Self { category: Some(category), ..self }
+    }
+}
+
+impl<T: HcbsTest> HcbsTest for NamedTest<T> {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn category(&self) -> &'static str {
+        self.category.unwrap_or_else(|| self.test.category())
+    }
+
+    fn parameters(&self) -> String {
+        self.test.parameters()
+    }
+
+    fn requirements(&self) -> Vec<Capability> {
+        self.test.requirements()
+    }
+
+    fn run(&self, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<String>> {
+        self.test.run(ctrlc_flag)
+    }
+}
+
+pub fn __opt_arg<T: std::fmt::Display>(flag: &str, value: Option<T>) -> String {
+    value.map_or(String::new(), |value| format!(" {flag} {value}"))
+}
+
+pub fn __cpu_set_arg<I, T>(cpus: I) -> String
+    where I: IntoIterator<Item = T>, T: std::fmt::Display
+{
+    cpus.into_iter()
+        .map(|cpu| cpu.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// The standard set of tests of the suite, in their default running order.
+pub fn catalog() -> anyhow::Result<Vec<Box<dyn HcbsTest>>> {
+    let mut tests: Vec<Box<dyn HcbsTest>> = Vec::new();
+
+    // constraints
+    for test in constraints::cgroup_setup::constraint_tests() {
+        tests.push(Box::new(test));
+    }
+
+    // time
+    let uni_tests = [
+        ("one-task-one-cpu", 1, 40, Some("0")),
+        ("one-task-two-cpus", 1, 30, Some("0-1")),
+        ("one-task-four-cpus", 1, 20, Some("0-3")),
+        ("one-task-eight-cpus", 1, 10, Some("0-7")),
+        ("one-task-all-cpus", 1, 5, None),
+        ("five-tasks-one-cpu", 5, 40, Some("0")),
+        ("five-tasks-two-cpus", 5, 30, Some("0-1")),
+        ("five-tasks-four-cpus", 5, 20, Some("0-3")),
+        ("five-tasks-eight-cpus", 5, 10, Some("0-7")),
+        ("five-tasks-all-cpus", 5, 5, None),
+    ];
+
+    for (name, num_tasks, runtime_ms, cpu_set) in uni_tests {
+        tests.push(Box::new(NamedTest::new(&format!("uni/{name}"), time::unicpu::MyArgs {
+            cgroup: "g0".to_owned(),
+            runtime_ms,
+            period_ms: 100,
+            num_tasks,
+            cpu_set: cpu_set.map(|cpu_set| cpu_set.parse()).transpose()?,
+            max_time: Some(10),
+            verdict: Default::default(),
+            sampling: Default::default(),
+        })));
+    }
+
+    tests.push(Box::new(NamedTest::new("uni/windowed-one-task-one-cpu", time::unicpu::MyArgs {
+        cgroup: "g0".to_owned(),
+        runtime_ms: 40,
+        period_ms: 100,
+        num_tasks: 1,
+        cpu_set: Some("0".parse()?),
+        max_time: Some(10),
+        verdict: Default::default(),
+        sampling: time::sampling::SamplingArgs {
+            sample_window_ms: Some(500),
+            ..Default::default()
+        },
+    })));
+
+    let multi_tests = [
+        ("one-task-one-cpu", 1, "40/100/0"),
+        ("one-task-two-cpus", 1, "30/100/0-1"),
+        ("one-task-four-cpus", 1, "20/100/0-3"),
+        ("one-task-eight-cpus", 1, "10/100/0-7"),
+        ("five-tasks-one-cpu", 5, "40/100/0"),
+        ("five-tasks-two-cpus", 5, "30/100/0-1"),
+        ("five-tasks-four-cpus", 5, "20/100/0-3"),
+        ("five-tasks-eight-cpus", 5, "10/100/0-7"),
+    ];
+
+    for (name, num_tasks, config) in multi_tests {
+        tests.push(Box::new(NamedTest::new(&format!("multi/{name}"), time::multicpu::MyArgs {
+            cgroup: "g0".to_owned(),
+            config: vec![time::multicpu::CgroupConfigSet::parse_config(config)?],
+            num_tasks,
+            per_cpu: false,
+            max_time: Some(10),
+            verdict: Default::default(),
+            sampling: Default::default(),
+        })));
+    }
+
+    let per_cpu_tests: [(&str, &[&str]); 3] = [
+        ("per-cpu-two-cpus", &["30/100/0-1"]),
+        ("per-cpu-four-cpus", &["20/100/0-3"]),
+        ("per-cpu-asymmetric", &["40/100/0", "20/100/1"]),
+    ];
+
+    for (name, configs) in per_cpu_tests {
+        tests.push(Box::new(NamedTest::new(&format!("multi/{name}"), time::multicpu::MyArgs {
+            cgroup: "g0".to_owned(),
+            config: configs.iter()
+                .map(|config| time::multicpu::CgroupConfigSet::parse_config(config))
+                .collect::<anyhow::Result<_>>()?,
+            num_tasks: 1,
+            per_cpu: true,
+            max_time: Some(10),
+            verdict: Default::default(),
+            sampling: Default::default(),
+        })));
+    }
+
+    let hierarchy_tests: [(&str, &[&str]); 2] = [
+        ("two-children", &["g0:60/100", "g0/a:30/100:1", "g0/b:20/100:1"]),
+        ("two-levels", &["g0:60/100", "g0/a:40/100", "g0/a/x:20/100:1", "g0/a/y:10/50:1", "g0/b:10/100:1"]),
+    ];
+
+    for (name, nodes) in hierarchy_tests {
+        tests.push(Box::new(NamedTest::new(&format!("hierarchy/{name}"), time::hierarchy::MyArgs {
+            nodes: nodes.iter()
+                .map(|node| node.parse())
+                .collect::<anyhow::Result<_>>()?,
+            file: None,
+            cpu_set: Some("0".parse()?),
+            max_time: Some(10),
+            verdict: Default::default(),
+        })));
+    }
+
+    let sibling_tests: [(&str, &[&str], &str); 3] = [
+        ("two-groups-one-cpu", &["30/100", "20/100"], "0"),
+        ("three-groups-non-harmonic", &["20/100", "21/70", "9/45"], "0"),
+        ("two-groups-two-cpus", &["40/100", "15/35"], "0-1"),
+    ];
+
+    for (name, groups, cpu_set) in sibling_tests {
+        tests.push(Box::new(NamedTest::new(&format!("siblings/{name}"), time::siblings::MyArgs {
+            cgroup_prefix: "g".to_owned(),
+            groups: groups.iter()
+                .map(|group| group.parse())
+                .collect::<anyhow::Result<_>>()?,
+            cpu_set: cpu_set.parse()?,
+            max_time: Some(10),
+            verdict: Default::default(),
+        })));
+    }
+
+    let rr_fairness_tests = [
+        ("three-tasks-one-cpu", 3, 50, 100, "0", None),
+        ("four-tasks-one-cpu-ts10", 4, 20, 50, "0", Some(10)),
+        ("three-tasks-one-cpu-ts20", 3, 30, 100, "0", Some(20)),
+        ("three-tasks-two-cpus-ts10", 3, 40, 100, "0-1", Some(10)),
+    ];
+
+    for (name, num_tasks, runtime_ms, period_ms, cpu_set, timeslice_ms) in rr_fairness_tests {
+        tests.push(Box::new(NamedTest::new(&format!("rr-fairness/{name}"), time::rr_fairness::MyArgs {
+            cgroup: "g0".to_owned(),
+            runtime_ms,
+            period_ms,
+            num_tasks,
+            cpu_set: cpu_set.parse()?,
+            timeslice_ms,
+            max_time: Some(20),
+            verdict: Default::default(),
+        })));
+    }
+
+    let throttling_tests = [
+        ("r10-p100", 10, 100),
+        ("r30-p50", 30, 50),
+        ("r5-p20", 5, 20),
+    ];
+
+    for (name, runtime_ms, period_ms) in throttling_tests {
+        tests.push(Box::new(NamedTest::new(&format!("throttling/{name}"), time::throttling::MyArgs {
+            cgroup: "g0".to_owned(),
+            runtime_ms,
+            period_ms,
+            cpu: 0,
+            threshold_us: 20,
+            tolerance_us: 1000,
+            hrtick: true,
+            max_time: Some(10),
+        })));
+    }
+
+    let cpuset_tests = [
+        ("partial-runtime", "0-1", "0-3", None, 2),
+        ("subset", "0-3", "1-2", None, 4),
+        ("change", "0-1", "0-3", Some("1-3"), 2),
+        ("shrink", "0-3", "0-3", Some("2"), 3),
+    ];
+
+    for (name, runtime_cpus, cpuset, new_cpuset, num_tasks) in cpuset_tests {
+        tests.push(Box::new(NamedTest::new(&format!("cpuset/{name}"), time::cpuset::MyArgs {
+            cgroup: "g0".to_owned(),
+            runtime_ms: 30,
+            period_ms: 100,
+            runtime_cpus: runtime_cpus.parse()?,
+            cpuset: cpuset.parse()?,
+            new_cpuset: new_cpuset.map(|cpuset| cpuset.parse()).transpose()?,
+            num_tasks,
+            settle_ms: 200,
+            tolerance_us: 1000,
+            max_time: Some(5),
+            verdict: Default::default(),
+        })));
+    }
+
+    // regression
+    let fair_server_tests = [
+        ("fair-server", regression::fair_server::ServerSelect::Auto, false),
+        ("fair-server-zero-runtime", regression::fair_server::ServerSelect::Auto, true),
+        ("ext-server", regression::fair_server::ServerSelect::Ext, false),
+    ];
+
+    for (name, server, zero_runtime) in fair_server_tests {
+        tests.push(Box::new(NamedTest::new(name, regression::fair_server::MyArgs {
+            server,
+            zero_runtime,
+            max_time: Some(60),
+            verdict: Default::default(),
+        })));
+    }
+
+    for runtime_ms in [10, 50, 80] {
+        tests.push(Box::new(NamedTest::new(&format!("fifo-r{runtime_ms}-p100"), regression::sched_fifo::MyArgs {
+            cgroup: "g0".to_owned(),
+            runtime_ms,
+            period_ms: 100,
+            max_time: Some(60),
+            verdict: Default::default(),
+        })));
+    }
+
+    for runtime_ms in [10, 20, 30] {
+        tests.push(Box::new(NamedTest::new(&format!("deadline-r{runtime_ms}-p100"), regression::sched_deadline::MyArgs {
+            cgroup: "g0".to_owned(),
+            runtime_ms,
+            period_ms: 100,
+            max_time: Some(60),
+            verdict: Default::default(),
+        })));
+    }
+
+    let deadline_admission_tests = [
+        ("deadline-admission-r10-p100", 10, None),
+        ("deadline-admission-r30-p100", 30, None),
+        ("deadline-admission-multi-r30-p100-cpu0", 30, Some("0")),
+        ("deadline-admission-multi-r30-p100-cpus0-1", 30, Some("0-1")),
+    ];
+
+    for (name, runtime_ms, cpu_set) in deadline_admission_tests {
+        tests.push(Box::new(NamedTest::new(name, regression::deadline_admission::MyArgs {
+            cgroup: "g0".to_owned(),
+            runtime_ms,
+            period_ms: 100,
+            cpu_set: cpu_set.map(|cpu_set| cpu_set.parse()).transpose()?,
+            dl_runtime_ms: 20,
+            dl_period_ms: 100,
+        })));
+    }
+
+    for runtime_ms in [10, 30] {
+        tests.push(Box::new(NamedTest::new(&format!("rt-sysctl-r{runtime_ms}-p100"), regression::rt_sysctl::MyArgs {
+            cgroup: "g0".to_owned(),
+            runtime_ms,
+            period_ms: 100,
+            cpu: 0,
+            max_time: Some(20),
+            verdict: Default::default(),
+        })));
+    }
+
+    for (runtime_ms, critical_section_ms) in [(10, 5), (30, 20), (10, 25)] {
+        tests.push(Box::new(NamedTest::new(&format!("priority-inheritance-r{runtime_ms}-p100-cs{critical_section_ms}"),
+            regression::priority_inheritance::MyArgs {
+                cgroup: "g0".to_owned(),
+                runtime_ms,
+                period_ms: 100,
+                cpu: 0,
+                critical_section_ms,
+                slack_ms: 5,
+                max_time: Some(30),
+            })));
+    }
+
+    for (runtime_ms, weight, cpu_max) in [(30, None, None), (30, Some(50), None), (30, None, Some((20, 100))), (50, Some(200), Some((30, 100)))] {
+        let cpu_max = cpu_max.map(|(quota_ms, period_ms)| regression::sched_other::CpuMax { quota_ms, period_ms });
+        let name = format!("sched-other-r{runtime_ms}-p100{}{}",
+            weight.map_or(String::new(), |weight| format!("-weight{weight}")),
+            cpu_max.map_or(String::new(), |cpu_max| format!("-max{}", cpu_max.quota_ms)));
+
+        tests.push(Box::new(NamedTest::new(&name, regression::sched_other::MyArgs {
+            cgroup: "g0".to_owned(),
+            runtime_ms,
+            period_ms: 100,
+            cpu: 0,
+            weight,
+            cpu_max,
+            max_time: Some(20),
+            verdict: Default::default(),
+        })));
+    }
+
+    tests.push(Box::new(NamedTest::new("migration-regression", stress::migrate::MyArgs {
+        cgroup: "g0".to_owned(),
+        runtime_ms: 1,
+        period_ms: 100,
+        change_period: 0.1,
+        max_time: Some(300),
+    }).with_category("regression")));
+
+    tests.push(Box::new(NamedTest::new("affinity-regression", stress::change_pinning::MyArgs {
+        cgroup: "g0".to_owned(),
+        runtime_ms: 1,
+        period_ms: 100,
+        change_period: 0.1,
+        cpu_set1: "0".parse()?,
+        cpu_set2: "1".parse()?,
+        max_time: Some(300),
+    }).with_category("regression")));
+
+    // stress
+    tests.push(Box::new(NamedTest::new("cgroup-setup", stress::cgroup_make_destroy::MyArgs {
+        cgroup: "g0".to_owned(),
+        runtime_min_ms: 20,
+        runtime_max_ms: 90,
+        period_ms: 100,
+        seed: None,
+        max_time: Some(60),
+    })));
+
+    tests.push(Box::new(NamedTest::new("cgroup-runtime", stress::change_cgroup_runtime::MyArgs {
+        cgroup: "g0".to_owned(),
+        runtime1_ms: 20,
+        runtime2_ms: 80,
+        period_ms: 100,
+        change_period: 1.0,
+        max_time: Some(60),
+    })));
+
+    tests.push(Box::new(NamedTest::new("task-priority", stress::change_priority::MyArgs {
+        cgroup: "g0".to_owned(),
+        runtime_ms: 50,
+        period_ms: 100,
+        change_period: 1.0,
+        max_time: Some(60),
+    })));
+
+    tests.push(Box::new(NamedTest::new("task-migration", stress::migrate::MyArgs {
+        cgroup: "g0".to_owned(),
+        runtime_ms: 50,
+        period_ms: 100,
+        change_period: 1.0,
+        max_time: Some(60),
+    })));
+
+    tests.push(Box::new(NamedTest::new("task-sched-class", stress::switch_class::MyArgs {
+        cgroup: "g0".to_owned(),
+        runtime_ms: 50,
+        period_ms: 100,
+        change_period: 1.0,
+        max_time: Some(60),
+    })));
+
+    for (name, reset_on_fork) in [("task-churn", false), ("task-churn-reset-on-fork", true)] {
+        tests.push(Box::new(NamedTest::new(name, stress::churn::MyArgs {
+            cgroup: "g0".to_owned(),
+            runtime_ms: 50,
+            period_ms: 100,
+            cpu_set: None,
+            mode: stress::churn::ChurnMode::Mixed,
+            burst: 16,
+            burst_period_ms: 100,
+            task_time_us: 1000,
+            reset_on_fork,
+            tolerance: 0.02,
+            max_time: Some(60),
+        })));
+    }
+
+    tests.push(Box::new(NamedTest::new("fuzz", stress::fuzz::MyArgs {
+        cgroup_prefix: "fuzz".to_owned(),
+        num_cgroups: 4,
+        num_tasks: 8,
+        num_workers: 4,
+        period_max_ms: 100,
+        op_delay_ms: 10,
+        op_timeout_ms: 1000,
+        log: None,
+        replay: None,
+        minimize: Default::default(),
+        seed: 42,
+        max_time: Some(60),
+    })));
+
+    Ok(tests)
+}
+
+/// Simple glob matching, supporting the '*' (any sequence of characters) and
+/// '?' (any single character) wildcards.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    let mut last_star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            last_star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = last_star {
+            p = star_p + 1;
+            t = star_t + 1;
+            last_star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Select the tests belonging to any of the given categories and whose id
+/// (i.e. "category/name") matches any of the given glob patterns. Empty lists
+/// select everything.
+pub fn select_tests(
+    tests: Vec<Box<dyn HcbsTest>>,
+    categories: &[String],
+    filters: &[String],
+) -> Vec<Box<dyn HcbsTest>> {
+    tests.into_iter()
+        .filter(|test| categories.is_empty() ||
+            categories.iter().any(|category| category == test.category()))
+        .filter(|test| filters.is_empty() ||
+            filters.iter().any(|filter| glob_match(filter, &test.id())))
+        .collect()
+}
+
+/// Run a single test, reporting its result to the result sinks. The test runs
+/// under a `Supervisor`, which stops it after the given timeout (abandoning it
+/// if it does not stop) and cleans up its leftover workloads and cgroups. The
+/// scheduler state is restored after the test, whatever its outcome
+/// (including panics). Errors in setting up or tearing down the test are
+/// reported as the test's failure, so that a suite can go on with the next
+/// test.
+pub fn run_catalog_test(
+    test: std::sync::Arc<dyn HcbsTest>,
+    ctrlc_flag: Option<ExitFlag>,
+    timeout: Option<std::time::Duration>,
+) -> TestStatus {
+    batch_test_header(&test.name(), test.category());
+    batch_test_param("parameters", test.parameters());
+
+    let result =
+        match batch_test_requires(&test.requirements()) {
+            Ok(true) => run_supervised(test, ctrlc_flag, timeout),
+            Ok(false) => return TestStatus::Skipped,
+            Err(err) => Err(anyhow::format_err!("Error in probing the test's requirements: {err}")),
+        };
+
+    match result {
+        Ok(Skippable::Result(msg)) if msg.is_empty() => batch_test_success(),
+        Ok(Skippable::Result(msg)) => batch_test_success_details(msg),
+        Ok(Skippable::Skipped(err)) => batch_test_skipped(err),
+        Err(err) => batch_test_failure(err),
+    }
+}
+
+fn run_supervised(
+    test: std::sync::Arc<dyn HcbsTest>,
+    ctrlc_flag: Option<ExitFlag>,
+    timeout: Option<std::time::Duration>,
+) -> anyhow::Result<Skippable<String>> {
+    let snapshot = SnapshotGuard::new()
+        .map_err(|err| anyhow::format_err!("Error in saving the scheduler state: {err}"))?;
+    let supervisor = Supervisor::new(ctrlc_flag, timeout)
+        .map_err(|err| anyhow::format_err!("Error in starting the test's supervisor: {err}"))?;
+
+    let result = supervisor.run({
+        let test = test.clone();
+        move |exit_flag| std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| test.run(Some(exit_flag))))
+    });
+
+    let result =
+        match result {
+            Ok(Some(Ok(result))) => result,
+            Ok(Some(Err(panic))) => {
+                let msg = panic.downcast_ref::<&str>().map(|msg| msg.to_string())
+                    .or_else(|| panic.downcast_ref::<String>().cloned())
+                    .unwrap_or_default();
+
+                Err(anyhow::format_err!("Test panicked: {msg}"))
+            },
+            Ok(None) => Err(anyhow::format_err!("Test did not stop after timing out, abandoned")),
+            Err(err) => Err(anyhow::format_err!("Error in running the test: {err}")),
+        };
+
+    let timed_out = supervisor.timed_out();
+    let cleaned_up = supervisor.cleanup();
+    let restored = snapshot.restore();
+
+    if let Ok(Some(cleaned_up)) = &cleaned_up {
+        batch_test_param("cleanup", cleaned_up);
+    }
+
+    match (result, cleaned_up, restored) {
+        (_, _, _) if timed_out =>
+            Err(anyhow::format_err!("Test timed out after {}s", timeout.unwrap_or_default().as_secs())),
+        (Err(err), _, _) => Err(err),
+        (Ok(_), Err(err), _) => Err(anyhow::format_err!("Error in cleaning up the test's workloads: {err}")),
+        (Ok(_), _, Err(err)) => Err(anyhow::format_err!("Error in restoring the scheduler state: {err}")),
+        (Ok(result), Ok(_), Ok(_)) => Ok(result),
+    }
+}
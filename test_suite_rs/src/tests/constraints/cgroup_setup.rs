@@ -0,0 +1,230 @@
+use crate::prelude::*;
+use crate::tests::prelude::*;
+use crate::fs_backend::cgroup;
+
+fn cgroup_setup_fail(cgroup_name: &str, runtime_us: u64, period_us: u64) -> anyhow::Result<()> {
+    let mut cgroup = FsCgroup::new(cgroup_name)?;
+
+    let failure: Result<(), _> =
+        cgroup.set_period_us(period_us)
+            .and_then(|_| cgroup.set_runtime_us(runtime_us));
+
+    if failure.is_ok() {
+        anyhow::bail!("Cgroup \'{cgroup_name}\' creation with {runtime_us}/{period_us} did not fail")
+    } else {
+        Ok(())
+    }
+}
+
+fn cgroup_setup_fail_multi(cgroup_name: &str, runtimes_us: &str, periods_us: &str) -> anyhow::Result<()> {
+    let mut cgroup = FsCgroup::new(cgroup_name)?;
+
+    let failure: Result<(), _> =
+        cgroup.set_period_us_multi_str(periods_us)
+            .and_then(|_| cgroup.set_runtime_us_multi_str(runtimes_us));
+
+    if failure.is_ok() {
+        anyhow::bail!("Cgroup \'{cgroup_name}\' creation with {runtimes_us:?}/{periods_us:?} did not fail")
+    } else {
+        Ok(())
+    }
+}
+
+fn add_task_to_runtime_zero(cgroup_name: &str) -> anyhow::Result<()> {
+    let mut cgroup = FsCgroup::new(cgroup_name)?;
+    cgroup.set_period_us(100_000)?;
+    cgroup.set_runtime_us(0)?;
+
+    let yes = run_yes()?;
+
+    let failure: anyhow::Result<()> =
+        fs_backend().set_sched_policy(yes.id(), SchedPolicy::RR(50), SchedFlags::empty())
+            .and_then(|_| cgroup.assign_process(yes).map(|_| ()).map_err(|(_, err)| err));
+
+    if failure.is_ok() {
+        anyhow::bail!("Cgroup with 0 runtime must not allow to run tasks")
+    } else {
+        Ok(())
+    }
+}
+
+fn set_runtime_zero_to_active(cgroup_name: &str) -> anyhow::Result<()> {
+    let mut cgroup = FsCgroup::new(cgroup_name)?;
+    cgroup.set_period_us(100_000)?;
+    cgroup.set_runtime_us(10_000)?;
+
+    let yes = run_yes()?;
+
+    fs_backend().set_sched_policy(yes.id(), SchedPolicy::RR(50), SchedFlags::empty())
+        .and_then(|_| cgroup.assign_process(yes).map(|_| ()).map_err(|(_, err)| err))?;
+
+    let failure = cgroup.set_runtime_us(0);
+
+    if failure.is_ok() {
+        anyhow::bail!("Cannot set runtime zero to cgroup with active tasks")
+    } else {
+        Ok(())
+    }
+}
+
+fn set_runtime_zero_to_active_multi(cgroup_name: &str) -> anyhow::Result<()> {
+    let mut cgroup = FsCgroup::new(cgroup_name)?;
+    cgroup.set_period_us(100_000)?;
+    cgroup.set_runtime_us_multi_str("10000 0")?;
+
+    let yes = run_yes()?;
+    fs_backend().set_sched_policy(yes.id(), SchedPolicy::RR(50), SchedFlags::empty())
+        .and_then(|_| cgroup.assign_process(yes).map(|_| ()).map_err(|(_, err)| err))?;
+
+    let failure = cgroup.set_runtime_us_multi_str("0 0");
+
+    if failure.is_ok() {
+        anyhow::bail!("Cannot set runtime zero to cgroup with active tasks")
+    } else {
+        Ok(())
+    }
+}
+
+/// A single cgroup setup constraint check. The checks are run on the cgroup
+/// named "g0", with the calling process being in the root cgroup. They only go
+/// through the filesystem backend, thus they can also be run on a `FakeFs`.
+#[derive(Clone, Copy)]
+pub struct ConstraintTest {
+    pub name: &'static str,
+    pub multicpu: bool,
+    test: fn() -> anyhow::Result<()>,
+}
+
+impl ConstraintTest {
+    const fn new(name: &'static str, test: fn() -> anyhow::Result<()>) -> Self {
+        Self { name, multicpu: false, test }
+    }
+
+    const fn new_multi(name: &'static str, test: fn() -> anyhow::Result<()>) -> Self {
+        Self { name, multicpu: true, test }
+    }
+
+    pub fn run(&self) -> anyhow::Result<()> {
+        cgroup::assign_pid(".", std::process::id())?;
+        fs_backend().set_sched_policy(std::process::id(), SchedPolicy::RR(99), SchedFlags::RESET_ON_FORK)?;
+
+        (self.test)()
+    }
+}
+
+impl HcbsTest for ConstraintTest {
+    fn name(&self) -> String {
+        self.name.to_owned()
+    }
+
+    fn category(&self) -> &'static str {
+        "constraints"
+    }
+
+    fn parameters(&self) -> String {
+        String::new()
+    }
+
+    fn requirements(&self) -> Vec<Capability> {
+        if self.multicpu {
+            vec![Capability::RtGroup, Capability::MultiCpu]
+        } else {
+            vec![Capability::RtGroup]
+        }
+    }
+
+    fn run(&self, _: Option<ExitFlag>) -> anyhow::Result<Skippable<String>> {
+        ConstraintTest::run(self).map(|_| Skippable::Result(String::new()))
+    }
+}
+
+pub fn constraint_tests() -> Vec<ConstraintTest> {
+    vec![
+        // given DL_SCALE = 10, runtime must be at least 1024ns, i.e. > 1us
+        ConstraintTest::new("runtime_too_small",
+            || cgroup_setup_fail("g0", 1, 100_000)),
+
+        // cannot set runtime greater than period
+        ConstraintTest::new("runtime_gt_period",
+            || cgroup_setup_fail("g0", 110_000, 100_000)),
+
+        // period cannot be greater than ~2^53us (i.e. >=2^63ns, which is a negative integer in signed 64-bit)
+        ConstraintTest::new("period_too_big",
+            || cgroup_setup_fail("g0", 110_000, (1u64 << 63) / 1000 + 1)),
+
+        // adding task to cgroup with runtime zero
+        ConstraintTest::new("runtime_0_add_task",
+            || add_task_to_runtime_zero("g0")),
+
+        // set runtime to zero of running cgroup
+        ConstraintTest::new("runtime_0_while_running",
+            || set_runtime_zero_to_active("g0")),
+
+        // multicpu tests
+        // given DL_SCALE = 10, runtime must be at least 1024ns, i.e. > 1us
+        ConstraintTest::new_multi("runtime_too_small_multi_0",
+            || cgroup_setup_fail_multi("g0", "1 0 50000 1", "100000 0-1")),
+
+        ConstraintTest::new_multi("runtime_too_small_multi_1",
+            || cgroup_setup_fail_multi("g0", "50000 0 1 1", "100000 0-1")),
+
+        // cannot set runtime greater than period
+        ConstraintTest::new_multi("runtime_gt_period_multi_0",
+            || cgroup_setup_fail_multi("g0", "110000 0 50000 1", "100000 0-1")),
+
+        ConstraintTest::new_multi("runtime_gt_period_multi_1",
+            || cgroup_setup_fail_multi("g0", "110000 1 50000 0", "100000 0-1")),
+
+        // period cannot be greater than ~2^53us (i.e. >=2^63ns, which is a negative integer in signed 64-bit)
+        ConstraintTest::new_multi("period_too_big_multi_0",
+            || cgroup_setup_fail_multi("g0",
+                "50000 0-1",
+                &format!("{} 0 100000 1", (1u64 << 63) / 1000 + 1)
+            )),
+
+        ConstraintTest::new_multi("period_too_big_multi_1",
+            || cgroup_setup_fail_multi("g0",
+                "50000 0-1",
+                &format!("{} 1 100000 0", (1u64 << 63) / 1000 + 1)
+            )),
+
+        // set runtime to zero of running cgroup
+        ConstraintTest::new_multi("runtime_0_while_running_multi",
+            || set_runtime_zero_to_active_multi("g0")),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs_backend::fake::FakeFs;
+
+    #[test]
+    fn constraints_on_fake_fs() {
+        let (_lock, _fake) = FakeFs::new(2).install_for_test();
+
+        for test in constraint_tests() {
+            // kills the spawned workloads
+            let supervisor = Supervisor::new(None, None).unwrap();
+
+            test.run().unwrap_or_else(|err| panic!("{}: {err}", test.name));
+            assert!(!cgroup::exists("g0"), "{}: g0 not removed", test.name);
+
+            supervisor.cleanup().unwrap();
+        }
+    }
+
+    #[test]
+    fn constraints_fail_without_checks() {
+        // without multi-CPU runtimes, the cgroup cannot be set up
+        let (_lock, _fake) = FakeFs::new(2).with_multicpu(false).install_for_test();
+
+        let test = constraint_tests().into_iter()
+            .find(|test| test.name == "runtime_0_while_running_multi")
+            .unwrap();
+
+        let supervisor = Supervisor::new(None, None).unwrap();
+        assert!(test.run().is_err());
+        supervisor.cleanup().unwrap();
+    }
+}
@@ -0,0 +1 @@
+pub mod cgroup_setup;
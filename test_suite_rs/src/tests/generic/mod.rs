@@ -114,9 +114,11 @@ pub fn can_run_taskset(run: &TasksetRun, args: &RunnerArgsBase) -> bool {
 }
 
 pub fn check_root_cgroup(args: &RunnerArgsBase) -> anyhow::Result<()> {
-    mount_cgroup_cpu()?;
-    let cgroup_period = get_cgroup_period_us(".")?;
-    let cgroup_runtime = get_cgroup_runtime_us(".")?;
+    use crate::fs_backend::{fs_backend, cgroup};
+
+    fs_backend().mount_cgroup_cpu()?;
+    let cgroup_period = cgroup::get_period_us(".")?;
+    let cgroup_runtime = cgroup::get_runtime_us(".")?;
     let cgroup_bw = cgroup_runtime as f64 / cgroup_period as f64;
     if cgroup_bw < args.max_allocable_bw {
         anyhow::bail!("Cannot run tasksets as the maximum allocable bandwidth is {cgroup_bw}, \
@@ -135,4 +137,31 @@ pub fn __os_str_to_str(string: &std::ffi::OsStr) -> anyhow::Result<String> {
 
 pub fn __path_to_str(path: &std::path::Path) -> anyhow::Result<String> {
     __os_str_to_str(path.to_path_buf().as_os_str())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs_backend::{cgroup, fake::FakeFs};
+
+    fn args(max_allocable_bw: f64) -> RunnerArgsBase {
+        RunnerArgsBase {
+            cgroup: "g0".to_owned(),
+            max_num_cpus: 2,
+            max_allocable_bw,
+            num_instances_per_job: 1,
+        }
+    }
+
+    #[test]
+    fn root_cgroup_bandwidth() {
+        let (_lock, _fake) = FakeFs::new(2).install_for_test();
+
+        check_root_cgroup(&args(0.9)).unwrap();
+        check_root_cgroup(&args(0.95)).unwrap();
+        assert!(check_root_cgroup(&args(0.96)).is_err());
+
+        cgroup::set_runtime_us(".", 500_000).unwrap();
+        assert!(check_root_cgroup(&args(0.9)).is_err());
+    }
+}
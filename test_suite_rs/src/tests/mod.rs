@@ -1,6 +1,8 @@
 pub mod prelude {
     pub use super::generic::prelude::*;
     pub use super::skeleton::prelude::*;
+    pub use super::catalog::prelude::*;
+    pub use super::verdict::prelude::*;
     pub use super::periodic_thread::prelude as periodic_thread;
     pub use super::rt_app::prelude as rt_app;
 }
@@ -8,4 +10,10 @@ pub mod prelude {
 pub mod generic;
 pub mod skeleton;
 pub mod periodic_thread;
-pub mod rt_app;
\ No newline at end of file
+pub mod rt_app;
+pub mod catalog;
+pub mod verdict;
+pub mod constraints;
+pub mod regression;
+pub mod stress;
+pub mod time;
@@ -58,7 +58,7 @@ fn run_taskset(run: TasksetRun, args: &RunnerArgsBase, cycles: Option<u64>, mult
     }
 
     let cpu_set = CpuSet::any_subset(run.config.cpus)?;
-    let mut cgroup = HCBSCgroup::new(&args.cgroup)?
+    let mut cgroup = FsCgroup::new(&args.cgroup)?
         .with_force_kill(true);
     cgroup.set_period_us(run.config.period.as_micros() as u64)?;
     if !multi_runtime  {
@@ -243,5 +243,6 @@ pub fn run_periodic_thread(args: PeriodicThreadData) -> anyhow::Result<HCBSProce
         .spawn()
         .map_err(|err| anyhow::format_err!("Error in starting periodic thread: {err}"))?;
 
+    supervise_pid(proc.id());
     Ok(HCBSProcess::Child(proc))
 }
\ No newline at end of file
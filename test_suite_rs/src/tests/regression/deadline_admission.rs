@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+
+use crate::prelude::*;
+use crate::tests::prelude::*;
+
+#[derive(clap::Parser, Debug, Clone)]
+pub struct MyArgs {
+    /// cgroup's name
+    #[arg(short = 'c', long = "cgroup", default_value = "g0", value_name = "name")]
+    pub cgroup: String,
+
+    /// cgroup's runtime
+    #[arg(short = 'r', long = "runtime", value_name = "ms: u64")]
+    pub runtime_ms: u64,
+
+    /// cgroup's period
+    #[arg(short = 'p', long = "period", value_name = "ms: u64")]
+    pub period_ms: u64,
+
+    /// reserve the runtime only on the given cpus, using the per-CPU
+    /// configuration (by default the runtime is reserved on all the cpus)
+    #[arg(long = "cpu-set", value_parser = <CpuSetUnchecked as std::str::FromStr>::from_str)]
+    pub cpu_set: Option<CpuSetUnchecked>,
+
+    /// SCHED_DEADLINE tasks' runtime
+    #[arg(long = "dl-runtime", default_value = "20", value_name = "ms: u64")]
+    pub dl_runtime_ms: u64,
+
+    /// SCHED_DEADLINE tasks' period (and deadline)
+    #[arg(long = "dl-period", default_value = "100", value_name = "ms: u64")]
+    pub dl_period_ms: u64,
+}
+
+fn test_name(args: &MyArgs) -> String {
+    format!("deadline_admission c{} r{} p{} set{:?} dl{}/{}",
+        args.cgroup, args.runtime_ms, args.period_ms, args.cpu_set, args.dl_runtime_ms, args.dl_period_ms)
+}
+
+pub fn batch_runner(args: MyArgs, _ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<()> {
+    batch_test_header(&test_name(&args), "regression");
+    batch_test_param("cgroup", &args.cgroup);
+    batch_test_param("runtime_ms", args.runtime_ms);
+    batch_test_param("period_ms", args.period_ms);
+    batch_test_param("cpu_set", format!("{:?}", args.cpu_set));
+    batch_test_param("dl_runtime_ms", args.dl_runtime_ms);
+    batch_test_param("dl_period_ms", args.dl_period_ms);
+
+    if !batch_test_requires(&args.requirements())? {
+        return Ok(());
+    }
+
+    let result = run(args);
+
+    if is_batch_test() {
+        batch_test_result_skippable(result)
+    } else {
+        batch_test_result_skippable_details(result)
+    }
+}
+
+pub fn run(args: MyArgs) -> anyhow::Result<Skippable<String>> {
+    let dl_bw = args.dl_runtime_ms as f64 / args.dl_period_ms as f64;
+    let cgroup_bw = args.runtime_ms as f64 / args.period_ms as f64;
+
+    if cgroup_bw + dl_bw > 1f64 {
+        anyhow::bail!("The cgroup's bandwidth plus the one of a SCHED_DEADLINE task must not exceed 1");
+    }
+
+    main(args)
+        .and_then(|counts| {
+            let counts =
+                match counts {
+                    Skippable::Result(counts) => counts,
+                    Skippable::Skipped(err) => { return Ok(Skippable::Skipped(err)); },
+                };
+
+            // the cgroup's reservation must take the place of as many
+            // SCHED_DEADLINE tasks (the last one may or may not fit)
+            let reserved_bw = cgroup_bw * counts.cgroup_cpus as f64;
+            let expected_drop = reserved_bw / dl_bw;
+            let drop = counts.baseline_tasks as f64 - counts.reserved_tasks as f64;
+
+            let report = format!("Admitted SCHED_DEADLINE tasks: {} without reservation, {} with {:.2} units reserved \
+                on {} cpus (expected {:.2} less).",
+                counts.baseline_tasks, counts.reserved_tasks, reserved_bw, counts.cgroup_cpus, expected_drop);
+
+            if f64::abs(drop - expected_drop) > 1f64 {
+                anyhow::bail!("The cgroup's reservation is not accounted by SCHED_DEADLINE admission control. {report}");
+            }
+
+            if counts.growth_admitted {
+                anyhow::bail!("Growing the cgroup's runtime was allowed while SCHED_DEADLINE tasks held the bandwidth. {report}");
+            }
+
+            if !counts.growth_admitted_after_release {
+                anyhow::bail!("Growing the cgroup's runtime was rejected after releasing the SCHED_DEADLINE tasks' bandwidth. {report}");
+            }
+
+            Ok(Skippable::Result(report))
+        })
+}
+
+impl HcbsTest for MyArgs {
+    fn name(&self) -> String {
+        test_name(self)
+    }
+
+    fn category(&self) -> &'static str {
+        "regression"
+    }
+
+    fn parameters(&self) -> String {
+        let cpu_set = self.cpu_set.as_ref()
+            .map_or(String::new(), |cpu_set| format!(" --cpu-set {}", __cpu_set_arg(cpu_set.iter())));
+
+        format!("-c {} -r {} -p {}{} --dl-runtime {} --dl-period {}",
+            self.cgroup, self.runtime_ms, self.period_ms, cpu_set, self.dl_runtime_ms, self.dl_period_ms)
+    }
+
+    fn requirements(&self) -> Vec<Capability> {
+        if self.cpu_set.is_some() {
+            vec![Capability::RtGroup, Capability::MultiCpu, Capability::SchedDeadline, Capability::SchedDeadlinePrivilege]
+        } else {
+            vec![Capability::RtGroup, Capability::SchedDeadline, Capability::SchedDeadlinePrivilege]
+        }
+    }
+
+    fn run(&self, _: Option<ExitFlag>) -> anyhow::Result<Skippable<String>> {
+        run(self.clone())
+    }
+}
+
+/// Outcome of the admission checks.
+#[derive(Debug, Clone)]
+pub struct AdmissionCounts {
+    /// number of cpus the cgroup's runtime is reserved on
+    pub cgroup_cpus: u64,
+    /// SCHED_DEADLINE tasks admitted with a zero runtime cgroup
+    pub baseline_tasks: u64,
+    /// SCHED_DEADLINE tasks admitted with the cgroup's reservation
+    pub reserved_tasks: u64,
+    /// whether the cgroup's runtime could grow while the SCHED_DEADLINE tasks
+    /// held the bandwidth
+    pub growth_admitted: bool,
+    /// whether the cgroup's runtime could grow after killing them
+    pub growth_admitted_after_release: bool,
+}
+
+fn kill_tasks(tasks: Vec<HCBSProcess>) -> anyhow::Result<()> {
+    for mut task in tasks.into_iter() {
+        if let HCBSProcess::Child(child) = &mut task {
+            child.kill()?;
+            child.wait()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Admit as many SCHED_DEADLINE tasks as possible, until admission control
+/// rejects one (EBUSY). Any other error (e.g. EPERM, EINVAL) means that the
+/// tasks cannot be created at all, and fails the test.
+fn fill_deadline_bandwidth(args: &MyArgs, max_tasks: u64) -> anyhow::Result<Vec<HCBSProcess>> {
+    let mut tasks = Vec::new();
+
+    loop {
+        if tasks.len() as u64 > max_tasks {
+            kill_tasks(tasks)?;
+            anyhow::bail!("Admission control did not reject any of {max_tasks} SCHED_DEADLINE tasks");
+        }
+
+        let task = run_yes()?;
+        let pid = task.id();
+        let result = set_deadline_policy(pid,
+            args.dl_runtime_ms * 1_000_000, args.dl_period_ms * 1_000_000, args.dl_period_ms * 1_000_000);
+
+        match result {
+            Ok(_) => tasks.push(task),
+            Err(err) if err.raw_os_error() == Some(libc::EBUSY) => {
+                kill_tasks(vec![task])?;
+                return Ok(tasks);
+            },
+            Err(err) => {
+                kill_tasks(vec![task])?;
+                kill_tasks(tasks)?;
+                anyhow::bail!("Error in making pid {pid} a SCHED_DEADLINE task: {err}");
+            },
+        }
+    }
+}
+
+fn set_cgroup_runtime(cgroup: &mut FsCgroup, runtime_us: u64, cpus: &Option<Vec<CpuID>>) -> anyhow::Result<()> {
+    match cpus {
+        Some(cpus) => cgroup.set_runtime_us_multi(HashMap::from([(runtime_us, cpus.clone())])),
+        None => cgroup.set_runtime_us(runtime_us),
+    }
+}
+
+pub fn main(args: MyArgs) -> anyhow::Result<Skippable<AdmissionCounts>> {
+    let all_cpus = CpuSet::all()?.num_cpus();
+
+    // check if the cpu_set is valid
+    let cpus =
+        match &args.cpu_set {
+            Some(cpu_set) => {
+                for &cpu in cpu_set.iter() {
+                    match CpuSet::single(cpu) {
+                        Ok(_) => (),
+                        Err(err @ CpuSetBuildError::UnavailableCPU(_)) =>
+                            { return Ok(Skippable::Skipped(err.into())); },
+                        Err(err) =>
+                            { return Err(err.into()); },
+                    }
+                }
+
+                Some(cpu_set.iter().copied().collect::<Vec<_>>())
+            },
+            None => None,
+        };
+    let cgroup_cpus = cpus.as_ref().map_or(all_cpus, |cpus| cpus.len() as u64);
+
+    // upper bound on the number of admissible tasks, given the global limit
+    let fs = fs_backend();
+    let rt_runtime: i64 = fs.read_to_string("/proc/sys/kernel/sched_rt_runtime_us")?.trim().parse()?;
+    let rt_period: i64 = fs.read_to_string("/proc/sys/kernel/sched_rt_period_us")?.trim().parse()?;
+    if rt_runtime < 0 {
+        return Ok(Skippable::Skipped(anyhow::format_err!("SCHED_DEADLINE admission control is disabled (sched_rt_runtime_us = -1)")));
+    }
+
+    let dl_bw = args.dl_runtime_ms as f64 / args.dl_period_ms as f64;
+    let max_tasks = (rt_runtime as f64 / rt_period as f64 * all_cpus as f64 / dl_bw).ceil() as u64 + 1;
+
+    assign_pid_to_cgroup(".", std::process::id())?;
+    set_sched_policy(std::process::id(), SchedPolicy::RR(99), SchedFlags::RESET_ON_FORK)?;
+
+    let mut cgroup = FsCgroup::new(&args.cgroup)?
+        .with_force_kill(true);
+    cgroup.set_period_us(args.period_ms * 1000)?;
+    set_cgroup_runtime(&mut cgroup, 0, &cpus)?;
+
+    // baseline, without any reservation
+    let tasks = fill_deadline_bandwidth(&args, max_tasks)?;
+    let baseline_tasks = tasks.len() as u64;
+    kill_tasks(tasks)?;
+
+    if baseline_tasks == 0 {
+        cgroup.force_destroy();
+        anyhow::bail!("No SCHED_DEADLINE task could be admitted");
+    }
+
+    // with the cgroup's reservation
+    set_cgroup_runtime(&mut cgroup, args.runtime_ms * 1000, &cpus)?;
+    let tasks = fill_deadline_bandwidth(&args, max_tasks)?;
+    let reserved_tasks = tasks.len() as u64;
+
+    // the increase is at least the bandwidth of a SCHED_DEADLINE task, which
+    // does not fit anymore
+    let grown_runtime_us = (args.runtime_ms * 1000) + (dl_bw * args.period_ms as f64 * 1000f64).ceil() as u64;
+    let growth_admitted = set_cgroup_runtime(&mut cgroup, grown_runtime_us, &cpus).is_ok();
+
+    kill_tasks(tasks)?;
+    let growth_admitted_after_release = growth_admitted ||
+        set_cgroup_runtime(&mut cgroup, grown_runtime_us, &cpus).is_ok();
+
+    cgroup.force_destroy();
+
+    Ok(Skippable::Result(AdmissionCounts {
+        cgroup_cpus,
+        baseline_tasks,
+        reserved_tasks,
+        growth_admitted,
+        growth_admitted_after_release,
+    }))
+}
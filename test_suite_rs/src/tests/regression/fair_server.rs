@@ -0,0 +1,264 @@
+use crate::prelude::*;
+use crate::tests::prelude::*;
+
+#[derive(clap::Parser, Debug, Clone)]
+pub struct MyArgs {
+    /// deadline servers serving the non real-time tasks: ext servers if a
+    /// sched_ext scheduler is loaded, fair servers otherwise (auto)
+    #[arg(short = 's', long = "server", default_value = "auto", value_enum)]
+    pub server: ServerSelect,
+
+    /// set the servers' runtime to zero (restoring it afterwards), expecting
+    /// the non real-time tasks to get no bandwidth
+    #[arg(long = "zero-runtime")]
+    pub zero_runtime: bool,
+
+    /// max running time
+    #[arg(short = 't', long = "max-time", value_name = "sec: u64")]
+    pub max_time: Option<u64>,
+
+    #[command(flatten)]
+    pub verdict: VerdictArgs,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq)]
+pub enum ServerSelect {
+    #[default]
+    Auto,
+    Fair,
+    Ext,
+}
+
+impl ServerSelect {
+    /// Whether the non real-time tasks are served by the ext servers, or the
+    /// reason why the selected servers cannot be tested.
+    fn ext_servers(&self) -> Skippable<bool> {
+        let sched_ext = is_sched_ext_enabled();
+
+        match self {
+            ServerSelect::Auto => Skippable::Result(sched_ext),
+            ServerSelect::Fair if sched_ext =>
+                Skippable::Skipped(anyhow::format_err!("A sched_ext scheduler is loaded, non real-time tasks are served by the ext servers")),
+            ServerSelect::Ext if !sched_ext =>
+                Skippable::Skipped(anyhow::format_err!("No sched_ext scheduler is loaded, non real-time tasks are served by the fair servers")),
+            ServerSelect::Fair => Skippable::Result(false),
+            ServerSelect::Ext => Skippable::Result(true),
+        }
+    }
+}
+
+impl std::fmt::Display for ServerSelect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServerSelect::Auto => write!(f, "auto"),
+            ServerSelect::Fair => write!(f, "fair"),
+            ServerSelect::Ext => write!(f, "ext"),
+        }
+    }
+}
+
+pub fn batch_runner(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<()> {
+    if is_batch_test() && args.max_time.is_none() {
+        anyhow::bail!("Batch testing requires a maximum running time");
+    }
+
+    let test_header =
+        if is_batch_test() {
+            "fair_server"
+        } else {
+            "fair_server (Ctrl+C to stop)"
+        };
+
+    batch_test_header(test_header, "regression");
+    batch_test_param("server", args.server);
+    batch_test_param("zero_runtime", args.zero_runtime);
+    batch_test_param("max_time", format!("{:?}", args.max_time));
+    args.verdict.batch_test_params();
+
+    if !batch_test_requires(&args.requirements())? {
+        return Ok(());
+    }
+
+    let result = run(args, ctrlc_flag);
+
+    if is_batch_test() {
+        batch_test_result_skippable(result)
+    } else {
+        batch_test_result_skippable_details(result)
+    }
+}
+
+pub fn run(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<String>> {
+    let verdict_args = args.verdict.clone();
+
+    let trials =
+        match verdict_args.run_trials(ctrlc_flag, |ctrlc_flag| main(args.clone(), ctrlc_flag))? {
+            Skippable::Result(trials) => trials,
+            Skippable::Skipped(err) => { return Ok(Skippable::Skipped(err)); },
+        };
+
+    let ServerUsage { ext_servers, per_cpu } = &trials[0];
+    let server_name = if *ext_servers { "ext" } else { "fair" };
+
+    // the average first, then each CPU
+    let server_bw = per_cpu.iter().map(|cpu| cpu.server_bw).sum::<f64>() / per_cpu.len() as f64;
+    let mut measurements = vec![
+        Measurement::new("sched_other", server_bw)
+            .with_samples(trials.iter().map(|usage| usage.average())),
+    ];
+
+    for (i, cpu) in per_cpu.iter().enumerate() {
+        measurements.push(Measurement::new(&format!("cpu{}", cpu.cpu), cpu.server_bw)
+            .with_samples(trials.iter().map(|usage| usage.per_cpu[i].used_bw)));
+    }
+
+    let verdict = verdict_args.verdict(Tolerance::absolute(0.01), measurements);
+    verdict.batch_test_params();
+
+    let used_bw = verdict.results[0].mean;
+    let failed_cpus: Vec<_> = per_cpu.iter().zip(verdict.results[1..].iter())
+        .filter(|(_, result)| !result.passed())
+        .map(|(cpu, _)| cpu.cpu)
+        .collect();
+
+    if !verdict.results[0].passed() {
+        Err(anyhow::format_err!("Expected SCHED_OTHER tasks to use {:.2} % of total runtime ({server_name} servers), but used {:.2} %\n{verdict}", server_bw * 100.0, used_bw * 100.0))
+    } else if !failed_cpus.is_empty() {
+        Err(anyhow::format_err!("SCHED_OTHER tasks on CPUs {failed_cpus:?} did not get their {server_name} server's bandwidth\n{verdict}"))
+    } else {
+        Ok(Skippable::Result(format!("SCHED_OTHER processes got {:.2} % of total runtime ({server_name} servers).\n{verdict}", used_bw * 100f64)))
+    }
+}
+
+impl HcbsTest for MyArgs {
+    fn name(&self) -> String {
+        "fair_server".to_owned()
+    }
+
+    fn category(&self) -> &'static str {
+        "regression"
+    }
+
+    fn parameters(&self) -> String {
+        let zero_runtime = if self.zero_runtime { " --zero-runtime" } else { "" };
+
+        format!("-s {}{}{}{}",
+            self.server, zero_runtime, __opt_arg("-t", self.max_time), self.verdict.parameters())
+    }
+
+    fn requirements(&self) -> Vec<Capability> {
+        match self.server {
+            ServerSelect::Ext => vec![Capability::RtGroup, Capability::DebugFs, Capability::ExtServer],
+            _ => vec![Capability::RtGroup, Capability::DebugFs, Capability::FairServer],
+        }
+    }
+
+    fn run(&self, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<String>> {
+        run(self.clone(), ctrlc_flag)
+    }
+}
+
+/// Bandwidth reserved by a CPU's server, and used by the SCHED_OTHER task on
+/// that CPU.
+#[derive(Debug, Clone)]
+pub struct ServerCpuUsage {
+    pub cpu: CpuID,
+    pub server_bw: f64,
+    pub used_bw: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct ServerUsage {
+    pub ext_servers: bool,
+    pub per_cpu: Vec<ServerCpuUsage>,
+}
+
+impl ServerUsage {
+    pub fn average(&self) -> f64 {
+        self.per_cpu.iter().map(|cpu| cpu.used_bw).sum::<f64>() / self.per_cpu.len() as f64
+    }
+}
+
+pub fn main(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<ServerUsage>> {
+    let ext_servers =
+        match args.server.ext_servers() {
+            Skippable::Result(ext_servers) => ext_servers,
+            Skippable::Skipped(err) => { return Ok(Skippable::Skipped(err)); },
+        };
+
+    let cpus = CpuSet::all()?.num_cpus();
+
+    assign_pid_to_cgroup(".", std::process::id())?;
+    set_sched_policy(std::process::id(), SchedPolicy::RR(99), SchedFlags::RESET_ON_FORK)?;
+
+    // the servers' parameters are restored at the end of the test
+    let snapshot = args.zero_runtime.then(SnapshotGuard::new).transpose()?;
+    if let Some(snapshot) = &snapshot {
+        let servers =
+            if ext_servers { &snapshot.snapshot().ext_servers } else { &snapshot.snapshot().fair_servers };
+        let period_ns = servers.first()
+            .map(|server| server.period_ns)
+            .ok_or_else(|| anyhow::format_err!("No deadline servers found"))?;
+
+        setup_servers(0, period_ns / 1000, ext_servers)?;
+    }
+
+    let servers_bw = get_servers_bw(ext_servers)?;
+    if let Some(cpu) = (0..cpus as CpuID).find(|cpu| !servers_bw.contains_key(cpu)) {
+        let server = if ext_servers { "ext" } else { "fair" };
+        anyhow::bail!("No {server} server found for CPU {cpu}");
+    }
+
+    let mut fifo_processes = (0..cpus).map(|_| cpu_hog()).collect::<Result<Vec<_>, _>>()?;
+    let mut non_fifo_processes = (0..cpus).map(|_| cpu_hog()).collect::<Result<Vec<_>, _>>()?;
+
+    non_fifo_processes.iter_mut()
+        .enumerate()
+        .try_for_each(|(i, proc)| proc.set_affinity(CpuSet::single(i as u32)?))?;
+
+    fifo_processes.iter_mut()
+        .enumerate()
+        .try_for_each::<_, anyhow::Result<_>>(|(i, proc)| {
+            proc.set_affinity(CpuSet::single(i as u32)?)?;
+            proc.set_sched_policy(SchedPolicy::RR(50), SchedFlags::empty())?;
+
+            Ok(())
+        })?;
+
+    let fifo_pids: Vec<_> = fifo_processes.iter().map(|proc| proc.id()).collect();
+    let non_fifo_pids: Vec<_> = non_fifo_processes.iter().map(|proc| proc.id()).collect();
+    let fifo_window = UsageWindow::start_processes(&fifo_pids)?;
+    let non_fifo_window = UsageWindow::start_processes(&non_fifo_pids)?;
+
+    wait_loop(args.max_time, ctrlc_flag)?;
+
+    let fifo_usage = fifo_window.usage_per_source()?;
+    let non_fifo_usage = non_fifo_window.usage_per_source()?;
+
+    for mut proc in fifo_processes.into_iter().chain(non_fifo_processes) {
+        if let HCBSProcess::Child(child) = &mut proc {
+            child.kill()?;
+            child.wait()?;
+        }
+    }
+
+    if let Some(snapshot) = snapshot {
+        snapshot.restore()?;
+    }
+
+    let per_cpu = fifo_usage.iter().zip(non_fifo_usage.iter()).enumerate()
+        .map(|(cpu, (fifo, non_fifo))| {
+            let cpu = cpu as CpuID;
+            let fifo_secs = fifo.usage_secs();
+            let non_fifo_secs = non_fifo.usage_secs();
+
+            ServerCpuUsage {
+                cpu,
+                server_bw: servers_bw[&cpu],
+                used_bw: non_fifo_secs / (non_fifo_secs + fifo_secs),
+            }
+        })
+        .collect();
+
+    Ok(Skippable::Result(ServerUsage { ext_servers, per_cpu }))
+}
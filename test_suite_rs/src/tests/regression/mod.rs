@@ -0,0 +1,7 @@
+pub mod fair_server;
+pub mod sched_deadline;
+pub mod deadline_admission;
+pub mod rt_sysctl;
+pub mod priority_inheritance;
+pub mod sched_other;
+pub mod sched_fifo;
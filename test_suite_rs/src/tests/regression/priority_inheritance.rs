@@ -0,0 +1,233 @@
+use std::process::{Child, Command, Stdio};
+
+use crate::prelude::*;
+use crate::tests::prelude::*;
+
+#[derive(clap::Parser, Debug, Clone)]
+pub struct MyArgs {
+    /// cgroup's name
+    #[arg(short = 'c', long = "cgroup", default_value = "g0", value_name = "name")]
+    pub cgroup: String,
+
+    /// cgroup's runtime
+    #[arg(short = 'r', long = "runtime", value_name = "ms: u64")]
+    pub runtime_ms: u64,
+
+    /// cgroup's period
+    #[arg(short = 'p', long = "period", value_name = "ms: u64")]
+    pub period_ms: u64,
+
+    /// cpu on which all the tasks run
+    #[arg(long = "cpu", default_value = "0", value_name = "id")]
+    pub cpu: CpuID,
+
+    /// CPU time spent by the holder inside the critical section
+    #[arg(long = "cs", default_value = "10", value_name = "ms: u64")]
+    pub critical_section_ms: u64,
+
+    /// extra blocking time allowed over the computed bound
+    #[arg(long = "slack", default_value = "5", value_name = "ms: u64")]
+    pub slack_ms: u64,
+
+    /// max running time
+    #[arg(short = 't', long = "max-time", value_name = "sec: u64")]
+    pub max_time: Option<u64>,
+}
+
+fn test_name(args: &MyArgs) -> String {
+    format!("priority_inheritance c{} r{} p{} cpu{} cs{}",
+        args.cgroup, args.runtime_ms, args.period_ms, args.cpu, args.critical_section_ms)
+}
+
+pub fn batch_runner(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<()> {
+    if is_batch_test() && args.max_time.is_none() {
+        anyhow::bail!("Batch testing requires a maximum running time");
+    }
+
+    let test_header = test_name(&args);
+    let test_header =
+        if is_batch_test() {
+            test_header
+        } else {
+            test_header + " (Ctrl+C to stop)"
+        };
+
+    batch_test_header(&test_header, "regression");
+    batch_test_param("cgroup", &args.cgroup);
+    batch_test_param("runtime_ms", args.runtime_ms);
+    batch_test_param("period_ms", args.period_ms);
+    batch_test_param("cpu", args.cpu);
+    batch_test_param("critical_section_ms", args.critical_section_ms);
+    batch_test_param("slack_ms", args.slack_ms);
+    batch_test_param("max_time", format!("{:?}", args.max_time));
+
+    if !batch_test_requires(&args.requirements())? {
+        return Ok(());
+    }
+
+    let result = run(args, ctrlc_flag);
+
+    if is_batch_test() {
+        batch_test_result_skippable(result)
+    } else {
+        batch_test_result_skippable_details(result)
+    }
+}
+
+pub fn run(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<String>> {
+    if args.runtime_ms == 0 || args.runtime_ms > args.period_ms {
+        anyhow::bail!("The cgroup's runtime must be positive and must not exceed its period");
+    }
+
+    main(args, ctrlc_flag)
+        .and_then(|blocking| {
+            let blocking =
+                match blocking {
+                    Skippable::Result(blocking) => blocking,
+                    Skippable::Skipped(err) => { return Ok(Skippable::Skipped(err)); },
+                };
+
+            let samples = blocking.blocking_us.len();
+            let max_us = blocking.blocking_us.iter().copied().max().unwrap_or(0);
+            let avg_us = blocking.blocking_us.iter().sum::<u64>() as f64 / samples.max(1) as f64;
+            let over_bound = blocking.blocking_us.iter().filter(|blocked| **blocked > blocking.bound_us).count();
+
+            let report = format!("Blocking time of the root task over {samples} critical sections: \
+                avg {:.3} ms, max {:.3} ms, bound {:.3} ms ({} timeouts).",
+                avg_us / 1000f64, max_us as f64 / 1000f64, blocking.bound_us as f64 / 1000f64, blocking.timeouts);
+
+            if blocking.timeouts > 0 {
+                Err(anyhow::format_err!("Unbounded priority inversion, the root task gave up waiting for the mutex. {report}"))
+            } else if samples == 0 {
+                Err(anyhow::format_err!("The root task never blocked on the mutex. {report}"))
+            } else if over_bound > 0 {
+                Err(anyhow::format_err!("The root task was blocked longer than the bound {over_bound} times. {report}"))
+            } else {
+                Ok(Skippable::Result(report))
+            }
+        })
+}
+
+impl HcbsTest for MyArgs {
+    fn name(&self) -> String {
+        test_name(self)
+    }
+
+    fn category(&self) -> &'static str {
+        "regression"
+    }
+
+    fn parameters(&self) -> String {
+        format!("-c {} -r {} -p {} --cpu {} --cs {} --slack {}{}",
+            self.cgroup, self.runtime_ms, self.period_ms, self.cpu, self.critical_section_ms, self.slack_ms,
+            __opt_arg("-t", self.max_time))
+    }
+
+    fn run(&self, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<String>> {
+        run(self.clone(), ctrlc_flag)
+    }
+}
+
+/// Blocking times of the root task on the mutex.
+#[derive(Debug, Clone)]
+pub struct PiBlocking {
+    pub bound_us: u64,
+    pub blocking_us: Vec<u64>,
+    pub timeouts: u64,
+}
+
+/// Worst-case time needed by the boosted holder to consume the critical
+/// section's CPU time, i.e. the time needed by the cgroup's server to supply
+/// it, including the initial blackout of a periodic resource.
+pub fn blocking_bound_us(args: &MyArgs) -> u64 {
+    let cs_us = args.critical_section_ms * 1000;
+    let runtime_us = args.runtime_ms * 1000;
+    let period_us = args.period_ms * 1000;
+
+    cs_us + (cs_us.div_ceil(runtime_us) + 1) * (period_us - runtime_us) + args.slack_ms * 1000
+}
+
+fn pi_task(mutex: &SharedPiMutex, role: &str, args: &[String], stdout: Stdio) -> anyhow::Result<Child> {
+    let cmd = local_executable_cmd("/root/test_suite", "tools")?;
+
+    let proc = Command::new(cmd)
+        .args(["pi-task", role, "-m", mutex.path()])
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(stdout)
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    supervise_pid(proc.id());
+    Ok(proc)
+}
+
+/// Run a SCHED_FIFO root task which repeatedly locks a priority inheritance
+/// mutex held by a lower priority task inside the cgroup, while a middle
+/// priority task of the same cgroup consumes the cgroup's runtime whenever the
+/// mutex is held. Without priority inheritance, the holder is starved by the
+/// middle priority task, and the root task is blocked indefinitely.
+pub fn main(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<PiBlocking>> {
+    // check if the cpu is available
+    let cpu_set =
+        match CpuSet::single(args.cpu) {
+            Ok(cpu_set) => cpu_set,
+            Err(err @ CpuSetBuildError::UnavailableCPU(_)) =>
+                { return Ok(Skippable::Skipped(err.into())); },
+            Err(err) =>
+                { return Err(err.into()); },
+        };
+
+    let bound_us = blocking_bound_us(&args);
+    let timeout_ms = u64::max(10 * bound_us / 1000, 1000);
+
+    assign_pid_to_cgroup(".", std::process::id())?;
+    set_sched_policy(std::process::id(), SchedPolicy::RR(99), SchedFlags::RESET_ON_FORK)?;
+
+    let mut cgroup = FsCgroup::new(&args.cgroup)?
+        .with_force_kill(true);
+    cgroup.set_period_us(args.period_ms * 1000)?;
+    cgroup.set_runtime_us(args.runtime_ms * 1000)?;
+
+    let mutex = SharedPiMutex::create(&format!("/dev/shm/hcbs_pi_{}", std::process::id()))?;
+
+    // low and middle priority tasks, inside the cgroup
+    let cgroup_tasks = [
+        ("holder", vec!["--cs".to_owned(), args.critical_section_ms.to_string(), "--gap".to_owned(), args.period_ms.to_string()], 10),
+        ("interferer", vec![], 50),
+    ];
+
+    for (role, role_args, priority) in cgroup_tasks {
+        let mut task = HCBSProcess::Child(pi_task(&mutex, role, &role_args, Stdio::null())?);
+        task.set_affinity(cpu_set.clone())?;
+
+        let task = cgroup.assign_process(task).map_err(|(_, err)| err)?;
+        task.set_sched_policy(SchedPolicy::FIFO(priority), SchedFlags::empty())?;
+    }
+
+    // high priority task, in the root cgroup
+    let waiter = pi_task(&mutex, "waiter", &["--timeout".to_owned(), timeout_ms.to_string()], Stdio::piped())?;
+    let waiter_pid = waiter.id();
+    set_cpuset_to_pid(waiter_pid, &cpu_set)?;
+    set_sched_policy(waiter_pid, SchedPolicy::FIFO(90), SchedFlags::empty())?;
+
+    wait_loop(args.max_time, ctrlc_flag)?;
+
+    // the waiter stops after its current critical section
+    mutex.stop();
+    let output = waiter.wait_with_output()?;
+
+    cgroup.force_destroy();
+
+    let mut blocking_us = Vec::new();
+    let mut timeouts = 0;
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        match line.trim() {
+            "timeout" => timeouts += 1,
+            blocked => blocking_us.push(blocked.parse()
+                .map_err(|err| anyhow::format_err!("Invalid waiter output '{blocked}': {err}"))?),
+        }
+    }
+
+    Ok(Skippable::Result(PiBlocking { bound_us, blocking_us, timeouts }))
+}
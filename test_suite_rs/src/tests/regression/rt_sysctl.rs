@@ -0,0 +1,532 @@
+use crate::prelude::*;
+use crate::tests::prelude::*;
+use crate::fs_backend::cgroup;
+
+const SCHED_RT_RUNTIME: &str = "/proc/sys/kernel/sched_rt_runtime_us";
+const SCHED_RT_PERIOD: &str = "/proc/sys/kernel/sched_rt_period_us";
+
+#[derive(clap::Parser, Debug, Clone)]
+pub struct MyArgs {
+    /// child cgroup's name
+    #[arg(short = 'c', long = "cgroup", default_value = "g0", value_name = "name")]
+    pub cgroup: String,
+
+    /// child cgroup's runtime
+    #[arg(short = 'r', long = "runtime", value_name = "ms: u64")]
+    pub runtime_ms: u64,
+
+    /// child cgroup's period
+    #[arg(short = 'p', long = "period", value_name = "ms: u64")]
+    pub period_ms: u64,
+
+    /// cpu on which the tasks' bandwidth is measured
+    #[arg(long = "cpu", default_value = "0", value_name = "id")]
+    pub cpu: CpuID,
+
+    /// running time of each measurement (one for each writing order)
+    #[arg(short = 't', long = "max-time", value_name = "sec: u64")]
+    pub max_time: Option<u64>,
+
+    #[command(flatten)]
+    pub verdict: VerdictArgs,
+}
+
+fn test_name(args: &MyArgs) -> String {
+    format!("rt_sysctl c{} r{} p{} cpu{}", args.cgroup, args.runtime_ms, args.period_ms, args.cpu)
+}
+
+pub fn batch_runner(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<()> {
+    if is_batch_test() && args.max_time.is_none() {
+        anyhow::bail!("Batch testing requires a maximum running time");
+    }
+
+    let test_header = test_name(&args);
+    let test_header =
+        if is_batch_test() {
+            test_header
+        } else {
+            test_header + " (Ctrl+C to stop each measurement)"
+        };
+
+    batch_test_header(&test_header, "regression");
+    batch_test_param("cgroup", &args.cgroup);
+    batch_test_param("runtime_ms", args.runtime_ms);
+    batch_test_param("period_ms", args.period_ms);
+    batch_test_param("cpu", args.cpu);
+    batch_test_param("max_time", format!("{:?}", args.max_time));
+    args.verdict.batch_test_params();
+
+    if !batch_test_requires(&args.requirements())? {
+        return Ok(());
+    }
+
+    let result = run(args, ctrlc_flag);
+
+    if is_batch_test() {
+        batch_test_result_skippable(result)
+    } else {
+        batch_test_result_skippable_details(result)
+    }
+}
+
+pub fn run(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<String>> {
+    if args.runtime_ms > args.period_ms {
+        anyhow::bail!("The cgroup's runtime must not exceed its period");
+    }
+
+    let verdict_args = args.verdict.clone();
+
+    let trials =
+        match verdict_args.run_trials(ctrlc_flag, |ctrlc_flag| main(args.clone(), ctrlc_flag))? {
+            Skippable::Result(trials) => trials,
+            Skippable::Skipped(err) => { return Ok(Skippable::Skipped(err)); },
+        };
+
+    // the writes' outcomes must be the same on every trial
+    let writes = trials.iter()
+        .flat_map(|trial| trial.orders.iter())
+        .flat_map(|order| order.writes.iter());
+    let mut errors: Vec<String> = Vec::new();
+    for write in writes {
+        for error in write.errors() {
+            if !errors.contains(&error) {
+                errors.push(error);
+            }
+        }
+    }
+
+    let mut measurements = Vec::new();
+    for (i, order) in trials[0].orders.iter().enumerate() {
+        let usage = &order.usage;
+        let tasks = [
+            ("child", usage.child_expected),
+            ("root", usage.root_expected),
+            ("sched_other", usage.other_expected),
+        ];
+
+        for (j, (task, expected)) in tasks.into_iter().enumerate() {
+            measurements.push(Measurement::new(&format!("{}.{task}", order.order), expected)
+                .with_samples(trials.iter().map(|trial| trial.orders[i].usage.used_bws[j])));
+        }
+    }
+
+    let verdict = verdict_args.verdict(Tolerance::absolute(0.01), measurements);
+    verdict.batch_test_params();
+
+    let table = writes_table(&trials[0]);
+
+    if !errors.is_empty() {
+        Err(anyhow::format_err!("Unexpected outcome of the RT bandwidth writes:\n{}\n{table}\n{verdict}", errors.join("\n")))
+    } else if !verdict.passed() {
+        let failed = verdict.failed().iter()
+            .map(|result| result.name.clone())
+            .collect::<Vec<_>>();
+
+        Err(anyhow::format_err!("Tasks not throttled according to the effective RT limits: {failed:?}\n{table}\n{verdict}"))
+    } else {
+        Ok(Skippable::Result(format!("The RT sysctls and the root cgroup's bandwidth were updated consistently.\n{table}\n{verdict}")))
+    }
+}
+
+impl HcbsTest for MyArgs {
+    fn name(&self) -> String {
+        test_name(self)
+    }
+
+    fn category(&self) -> &'static str {
+        "regression"
+    }
+
+    fn parameters(&self) -> String {
+        format!("-c {} -r {} -p {} --cpu {}{}{}",
+            self.cgroup, self.runtime_ms, self.period_ms, self.cpu, __opt_arg("-t", self.max_time),
+            self.verdict.parameters())
+    }
+
+    fn requirements(&self) -> Vec<Capability> {
+        vec![Capability::RtGroup, Capability::DebugFs, Capability::FairServer]
+    }
+
+    fn run(&self, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<String>> {
+        run(self.clone(), ctrlc_flag)
+    }
+}
+
+/// Global (sysctl) and root cgroup's RT bandwidth parameters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RtLimits {
+    pub sysctl_runtime_us: i64,
+    pub sysctl_period_us: i64,
+    pub root_runtime_us: u64,
+    pub root_period_us: u64,
+}
+
+impl RtLimits {
+    pub fn read() -> anyhow::Result<Self> {
+        let fs = fs_backend();
+
+        Ok(Self {
+            sysctl_runtime_us: fs.read_to_string(SCHED_RT_RUNTIME)?.trim().parse()?,
+            sysctl_period_us: fs.read_to_string(SCHED_RT_PERIOD)?.trim().parse()?,
+            root_runtime_us: cgroup::get_runtime_us(".")?,
+            root_period_us: cgroup::get_period_us(".")?,
+        })
+    }
+
+    pub fn sysctl_bw(&self) -> f64 {
+        self.sysctl_runtime_us as f64 / self.sysctl_period_us as f64
+    }
+
+    pub fn root_bw(&self) -> f64 {
+        self.root_runtime_us as f64 / self.root_period_us as f64
+    }
+
+    fn sysctl_changed(&self, other: &Self) -> bool {
+        (self.sysctl_runtime_us, self.sysctl_period_us) != (other.sysctl_runtime_us, other.sysctl_period_us)
+    }
+
+    fn root_changed(&self, other: &Self) -> bool {
+        (self.root_runtime_us, self.root_period_us) != (other.root_runtime_us, other.root_period_us)
+    }
+}
+
+impl std::fmt::Display for RtLimits {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "sysctl {}/{} root {}/{}",
+            self.sysctl_runtime_us, self.sysctl_period_us, self.root_runtime_us, self.root_period_us)
+    }
+}
+
+/// A write to one of the RT bandwidth files.
+#[derive(Debug, Clone, Copy)]
+pub enum RtWrite {
+    SysctlRuntime(i64),
+    SysctlPeriod(i64),
+    RootRuntime(u64),
+}
+
+impl RtWrite {
+    fn is_sysctl(&self) -> bool {
+        !matches!(self, RtWrite::RootRuntime(_))
+    }
+
+    fn apply(&self) -> bool {
+        match *self {
+            RtWrite::SysctlRuntime(runtime_us) => fs_backend().write(SCHED_RT_RUNTIME, &format!("{runtime_us}")).is_ok(),
+            RtWrite::SysctlPeriod(period_us) => fs_backend().write(SCHED_RT_PERIOD, &format!("{period_us}")).is_ok(),
+            RtWrite::RootRuntime(runtime_us) => cgroup::set_runtime_us(".", runtime_us).is_ok(),
+        }
+    }
+}
+
+impl std::fmt::Display for RtWrite {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RtWrite::SysctlRuntime(runtime_us) => write!(f, "sched_rt_runtime_us = {runtime_us}"),
+            RtWrite::SysctlPeriod(period_us) => write!(f, "sched_rt_period_us = {period_us}"),
+            RtWrite::RootRuntime(runtime_us) => write!(f, "root cpu.rt_runtime_us = {runtime_us}"),
+        }
+    }
+}
+
+/// Expected outcome of a write. The outcome of the `Observed` ones is only
+/// reported, as it depends on whether the kernel propagates the values
+/// between the sysctls and the root cgroup.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Expect {
+    Accepted,
+    Rejected,
+    Observed,
+}
+
+impl std::fmt::Display for Expect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expect::Accepted => write!(f, "accepted"),
+            Expect::Rejected => write!(f, "rejected"),
+            Expect::Observed => write!(f, "any"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WriteOutcome {
+    pub order: &'static str,
+    pub write: RtWrite,
+    pub expect: Expect,
+    pub accepted: bool,
+    pub before: RtLimits,
+    pub after: RtLimits,
+    /// bandwidth reserved on each CPU by the child cgroup and the servers,
+    /// which must fit into the effective limits
+    pub reserved_bw: f64,
+    pub child_bw: f64,
+}
+
+impl WriteOutcome {
+    /// Which of the other values changed because of the write, if any.
+    pub fn propagated(&self) -> Option<&'static str> {
+        if self.write.is_sysctl() && self.before.root_changed(&self.after) {
+            Some("root")
+        } else if !self.write.is_sysctl() && self.before.sysctl_changed(&self.after) {
+            Some("sysctl")
+        } else {
+            None
+        }
+    }
+
+    pub fn errors(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        let write = format!("{}: {}", self.order, self.write);
+
+        match (self.expect, self.accepted) {
+            (Expect::Accepted, false) =>
+                errors.push(format!("{write} was rejected ({})", self.before)),
+            (Expect::Rejected, true) =>
+                errors.push(format!("{write} was accepted ({} -> {})", self.before, self.after)),
+            _ => (),
+        }
+
+        if !self.accepted && self.before != self.after {
+            errors.push(format!("{write} was rejected, but changed the limits ({} -> {})", self.before, self.after));
+        }
+
+        if self.child_bw > self.after.root_bw() + 1e-9 {
+            errors.push(format!("after {write}, the child cgroup's bandwidth {:.5} exceeds the root's {:.5}",
+                self.child_bw, self.after.root_bw()));
+        }
+
+        if self.reserved_bw > self.after.sysctl_bw() + 1e-9 {
+            errors.push(format!("after {write}, the reserved bandwidth {:.5} exceeds the global limit {:.5}",
+                self.reserved_bw, self.after.sysctl_bw()));
+        }
+
+        errors
+    }
+}
+
+/// Bandwidth used on the measured CPU by the child cgroup's RT task, the root
+/// cgroup's RT task and a SCHED_OTHER task, along with the expected ones.
+#[derive(Debug, Clone)]
+pub struct RtUsage {
+    pub child_expected: f64,
+    pub root_expected: f64,
+    pub other_expected: f64,
+    pub used_bws: [f64; 3],
+}
+
+#[derive(Debug, Clone)]
+pub struct OrderOutcome {
+    pub order: &'static str,
+    pub writes: Vec<WriteOutcome>,
+    pub usage: RtUsage,
+}
+
+#[derive(Debug, Clone)]
+pub struct RtSysctlRun {
+    pub initial: RtLimits,
+    pub orders: Vec<OrderOutcome>,
+}
+
+fn writes_table(run: &RtSysctlRun) -> String {
+    let mut table = format!("Initial limits: {}", run.initial);
+    table += &format!("\n{:<13} {:<40} {:>9} {:>9} {:>10}", "order", "write", "expected", "result", "propagated");
+
+    for write in run.orders.iter().flat_map(|order| order.writes.iter()) {
+        let result = if write.accepted { "accepted" } else { "rejected" };
+
+        table += &format!("\n{:<13} {:<40} {:>9} {:>9} {:>10}",
+            write.order, write.write.to_string(), write.expect.to_string(), result, write.propagated().unwrap_or("-"));
+    }
+
+    table
+}
+
+/// Target values of the writes, derived from the initial limits.
+struct WritePlan {
+    initial: RtLimits,
+    /// bandwidth of the child cgroup
+    child_bw: f64,
+    /// bandwidth reserved on each CPU by the child cgroup and the servers
+    reserved_bw: f64,
+    /// lowered global bandwidth, halfway between the reserved and the initial
+    /// one
+    low_bw: f64,
+    /// global bandwidth which cannot fit the reservations
+    under_bw: f64,
+}
+
+impl WritePlan {
+    fn sysctl_runtime(&self, bw: f64) -> RtWrite {
+        RtWrite::SysctlRuntime((bw * self.initial.sysctl_period_us as f64).floor() as i64)
+    }
+
+    fn root_runtime(&self, bw: f64) -> RtWrite {
+        RtWrite::RootRuntime((bw * self.initial.root_period_us as f64).floor() as u64)
+    }
+
+    fn sysctl_first(&self) -> Vec<(RtWrite, Expect)> {
+        let low_runtime_us = (self.low_bw * self.initial.sysctl_period_us as f64).floor() as i64;
+
+        vec![
+            (RtWrite::SysctlRuntime(self.initial.sysctl_period_us + 1), Expect::Rejected),
+            (RtWrite::SysctlPeriod(self.initial.sysctl_runtime_us - 1), Expect::Rejected),
+            (self.sysctl_runtime(self.under_bw), Expect::Rejected),
+            (RtWrite::SysctlRuntime(low_runtime_us), Expect::Accepted),
+            (self.root_runtime(self.low_bw), Expect::Accepted),
+            (self.root_runtime(self.child_bw / 2f64), Expect::Rejected),
+            (self.root_runtime(self.initial.sysctl_bw()), Expect::Observed),
+        ]
+    }
+
+    fn root_first(&self) -> Vec<(RtWrite, Expect)> {
+        let low_runtime_us = (self.low_bw * self.initial.sysctl_period_us as f64).floor() as i64;
+
+        vec![
+            (self.root_runtime(self.child_bw / 2f64), Expect::Rejected),
+            (self.root_runtime(self.low_bw), Expect::Accepted),
+            (self.sysctl_runtime(self.under_bw), Expect::Rejected),
+            (RtWrite::SysctlRuntime(low_runtime_us), Expect::Accepted),
+            (RtWrite::SysctlPeriod(low_runtime_us - 1), Expect::Rejected),
+            (RtWrite::SysctlRuntime(self.initial.sysctl_runtime_us), Expect::Accepted),
+        ]
+    }
+}
+
+/// Create the child cgroup, perform the given writes and measure the
+/// bandwidth of the tasks with the resulting limits. The scheduler state is
+/// restored afterwards.
+fn run_order(args: &MyArgs, plan: &WritePlan, order: &'static str, writes: Vec<(RtWrite, Expect)>,
+    servers_bw: f64, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<OrderOutcome>
+{
+    let snapshot = SnapshotGuard::new()?;
+
+    let mut child = FsCgroup::new(&args.cgroup)?
+        .with_force_kill(true);
+    child.set_period_us(args.period_ms * 1000)?;
+    child.set_runtime_us(args.runtime_ms * 1000)?;
+
+    let mut outcomes = Vec::new();
+    for (write, expect) in writes {
+        let before = RtLimits::read()?;
+        let accepted = write.apply();
+        let after = RtLimits::read()?;
+
+        outcomes.push(WriteOutcome {
+            order,
+            write,
+            expect,
+            accepted,
+            before,
+            after,
+            reserved_bw: plan.reserved_bw,
+            child_bw: plan.child_bw,
+        });
+    }
+
+    // the child cgroup's task gets its reservation, the SCHED_OTHER task the
+    // server's one, and the root cgroup's task the rest of the cpu
+    let cpu_set = CpuSet::single(args.cpu)?;
+
+    let mut child_task = run_yes()?;
+    child_task.set_affinity(cpu_set.clone())?;
+    let child_task = child.assign_process(child_task).map_err(|(_, err)| err)?;
+    child_task.set_sched_policy(SchedPolicy::RR(50), SchedFlags::empty())?;
+    let child_pid = child_task.id();
+
+    let mut root_task = run_yes()?;
+    root_task.set_affinity(cpu_set.clone())?;
+    root_task.set_sched_policy(SchedPolicy::RR(50), SchedFlags::empty())?;
+
+    let mut other_task = cpu_hog()?;
+    other_task.set_affinity(cpu_set)?;
+
+    let window = UsageWindow::start_processes(&[child_pid, root_task.id(), other_task.id()])?;
+
+    wait_loop(args.max_time, ctrlc_flag)?;
+
+    let usage = window.usage_per_source()?;
+
+    for mut proc in [root_task, other_task] {
+        if let HCBSProcess::Child(child) = &mut proc {
+            child.kill()?;
+            child.wait()?;
+        }
+    }
+
+    child.force_destroy();
+    snapshot.restore()?;
+
+    // the expectations follow the limits in effect during the measurement:
+    // the RT tasks share the lower of the sysctls' and the root cgroup's
+    // bandwidth, without eating into the servers' one, and the SCHED_OTHER
+    // task gets the rest of the cpu
+    let limits = outcomes.last().map_or(plan.initial, |outcome| outcome.after);
+    let sysctl_bw = if limits.sysctl_runtime_us < 0 { 1f64 } else { limits.sysctl_bw() };
+    let rt_bw = f64::min(f64::min(sysctl_bw, limits.root_bw()), 1f64 - servers_bw);
+
+    let child_expected = f64::min(plan.child_bw, rt_bw);
+    let root_expected = rt_bw - child_expected;
+
+    Ok(OrderOutcome {
+        order,
+        writes: outcomes,
+        usage: RtUsage {
+            child_expected,
+            root_expected,
+            other_expected: 1f64 - child_expected - root_expected,
+            used_bws: [usage[0].bandwidth(), usage[1].bandwidth(), usage[2].bandwidth()],
+        },
+    })
+}
+
+pub fn main(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<RtSysctlRun>> {
+    // check if the cpu is available
+    match CpuSet::single(args.cpu) {
+        Ok(_) => (),
+        Err(err @ CpuSetBuildError::UnavailableCPU(_)) =>
+            { return Ok(Skippable::Skipped(err.into())); },
+        Err(err) =>
+            { return Err(err.into()); },
+    }
+
+    let initial = RtLimits::read()?;
+    if initial.sysctl_runtime_us < 0 {
+        return Ok(Skippable::Skipped(anyhow::format_err!("RT bandwidth control is disabled (sched_rt_runtime_us = -1)")));
+    }
+
+    let servers_bw = get_servers_bw(is_sched_ext_enabled())?;
+    let max_servers_bw = servers_bw.values().copied().fold(0f64, f64::max);
+    let cpu_servers_bw = servers_bw.get(&args.cpu).copied().unwrap_or(0f64);
+
+    let child_bw = args.runtime_ms as f64 / args.period_ms as f64;
+    let reserved_bw = child_bw + max_servers_bw;
+    if initial.sysctl_bw() - reserved_bw < 0.02 || initial.root_bw() < child_bw {
+        anyhow::bail!("The child cgroup's bandwidth {child_bw:.5} plus the servers' {max_servers_bw:.5} \
+            must be at least 0.02 lower than the RT limits ({initial})");
+    }
+
+    let low_bw = reserved_bw + (initial.sysctl_bw() - reserved_bw) / 2f64;
+    let plan = WritePlan {
+        initial,
+        child_bw,
+        reserved_bw,
+        low_bw,
+        under_bw: max_servers_bw + child_bw / 2f64,
+    };
+
+    assign_pid_to_cgroup(".", std::process::id())?;
+    set_sched_policy(std::process::id(), SchedPolicy::RR(99), SchedFlags::RESET_ON_FORK)?;
+
+    let exit = match ctrlc_flag {
+        Some(exit) => exit,
+        None => create_ctrlc_handler()?,
+    };
+
+    let orders = vec![
+        run_order(&args, &plan, "sysctl-first", plan.sysctl_first(), cpu_servers_bw, Some(exit.clone()))?,
+        run_order(&args, &plan, "root-first", plan.root_first(), cpu_servers_bw, Some(exit))?,
+    ];
+
+    Ok(Skippable::Result(RtSysctlRun { initial, orders }))
+}
@@ -0,0 +1,156 @@
+use crate::prelude::*;
+use crate::tests::prelude::*;
+
+#[derive(clap::Parser, Debug, Clone)]
+pub struct MyArgs {
+    /// cgroup's name
+    #[arg(short = 'c', long = "cgroup", default_value = "g0", value_name = "name")]
+    pub cgroup: String,
+
+    /// cgroup's runtime
+    #[arg(short = 'r', long = "runtime", value_name = "ms: u64")]
+    pub runtime_ms: u64,
+
+    /// cgroup's period
+    #[arg(short = 'p', long = "period", value_name = "ms: u64")]
+    pub period_ms: u64,
+
+    /// max running time
+    #[arg(short = 't', long = "max-time", value_name = "sec: u64")]
+    pub max_time: Option<u64>,
+
+    #[command(flatten)]
+    pub verdict: VerdictArgs,
+}
+
+pub fn batch_runner(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<()> {
+    if is_batch_test() && args.max_time.is_none() {
+        anyhow::bail!("Batch testing requires a maximum running time");
+    }
+
+    let test_header =
+        if is_batch_test() {
+            "sched_fifo"
+        } else {
+            "sched_fifo (Ctrl+C to stop)"
+        };
+
+    batch_test_header(test_header, "regression");
+    batch_test_param("cgroup", &args.cgroup);
+    batch_test_param("runtime_ms", args.runtime_ms);
+    batch_test_param("period_ms", args.period_ms);
+    batch_test_param("max_time", format!("{:?}", args.max_time));
+    args.verdict.batch_test_params();
+
+    if !batch_test_requires(&args.requirements())? {
+        return Ok(());
+    }
+
+    let result = run(args, ctrlc_flag);
+
+    if is_batch_test() {
+        batch_test_result(result)
+    } else {
+        batch_test_result_details(result)
+    }
+}
+
+pub fn run(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<String> {
+    let cpus = CpuSet::all()?.num_cpus();
+    let cgroup_expected_bw = cpus as f64 * args.runtime_ms as f64 / args.period_ms as f64;
+    let fifo_expected_bw = cpus as f64 - cgroup_expected_bw;
+    let verdict_args = args.verdict.clone();
+
+    let trials =
+        match verdict_args.run_trials(ctrlc_flag, |ctrlc_flag| main(args.clone(), ctrlc_flag).map(Skippable::Result))? {
+            Skippable::Result(trials) => trials,
+            Skippable::Skipped(err) => { return Err(err); },
+        };
+
+    let verdict = verdict_args.verdict(Tolerance::relative(0.025), vec![
+        Measurement::new("cgroup", cgroup_expected_bw)
+            .with_samples(trials.iter().map(|(_, cgroup_bw)| *cgroup_bw)),
+        Measurement::new("fifo", fifo_expected_bw)
+            .with_samples(trials.iter().map(|(fifo_bw, _)| *fifo_bw)),
+    ]);
+    verdict.batch_test_params();
+
+    let (cgroup, fifo) = (&verdict.results[0], &verdict.results[1]);
+    if !cgroup.passed() {
+        anyhow::bail!("Expected cgroup tasks to use {:.2} units of total runtime, but used {:.2} units\n{verdict}", cgroup_expected_bw, cgroup.mean);
+    }
+
+    if !fifo.passed() {
+        anyhow::bail!("Expected SCHED_FIFO tasks to use {:.2} units of total runtime, but used {:.2} units\n{verdict}", fifo_expected_bw, fifo.mean);
+    }
+
+    Ok(format!("Cgroup processes got {:.2} units of total runtime, while SCHED_FIFO processes got {:.2} units of total runtime\n{verdict}", cgroup.mean, fifo.mean))
+}
+
+impl HcbsTest for MyArgs {
+    fn name(&self) -> String {
+        "sched_fifo".to_owned()
+    }
+
+    fn category(&self) -> &'static str {
+        "regression"
+    }
+
+    fn parameters(&self) -> String {
+        format!("-c {} -r {} -p {}{}{}",
+            self.cgroup, self.runtime_ms, self.period_ms, __opt_arg("-t", self.max_time),
+            self.verdict.parameters())
+    }
+
+    fn run(&self, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<String>> {
+        run(self.clone(), ctrlc_flag).map(Skippable::Result)
+    }
+}
+
+pub fn main(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<(f64, f64)> {
+    assign_pid_to_cgroup(".", std::process::id())?;
+    set_sched_policy(std::process::id(), SchedPolicy::RR(99), SchedFlags::RESET_ON_FORK)?;
+
+    let cpus = CpuSet::all()?.num_cpus();
+    let mut cgroup = FsCgroup::new(&args.cgroup)?
+        .with_force_kill(false);
+    cgroup.set_period_us(args.period_ms * 1000)?;
+    cgroup.set_runtime_us(args.runtime_ms * 1000)?;
+
+    let mut fifo_processes = (0..cpus).map(|_| run_yes()).collect::<Result<Vec<_>, _>>()?;
+    let cgroup_processes = (0..cpus).map(|_| run_yes()).collect::<Result<Vec<_>, _>>()?;
+
+    let cgroup_processes =
+        cgroup_processes.into_iter().enumerate()
+            .map(|(cpu, proc)| -> anyhow::Result<_> {
+                let id = proc.id();
+
+                let proc = cgroup.assign_process(proc).map_err(|(_, err)| err)?;
+                proc.set_affinity(CpuSet::single(cpu as u32)?)?;
+                proc.set_sched_policy(SchedPolicy::RR(50), SchedFlags::empty())?;
+
+                Ok(id)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+    fifo_processes.iter_mut().enumerate()
+        .try_for_each(|(cpu, proc)| -> anyhow::Result<()> {
+            proc.set_sched_policy(SchedPolicy::RR(50), SchedFlags::empty())?;
+            proc.set_affinity(CpuSet::single(cpu as u32)?)?;
+
+            Ok(())
+        })?;
+
+    let fifo_pids: Vec<_> = fifo_processes.iter().map(|proc| proc.id()).collect();
+    let fifo_window = UsageWindow::start_processes(&fifo_pids)?;
+    let cgroup_window = UsageWindow::start_processes(&cgroup_processes)?;
+
+    wait_loop(args.max_time, ctrlc_flag)?;
+
+    let fifo_total_usage = fifo_window.stop()?.bandwidth();
+    let cgroup_total_usage = cgroup_window.stop()?.bandwidth();
+
+    cgroup.force_destroy();
+
+    Ok((fifo_total_usage, cgroup_total_usage))
+}
\ No newline at end of file
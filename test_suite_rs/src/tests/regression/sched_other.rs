@@ -0,0 +1,274 @@
+use std::str::FromStr;
+
+use crate::prelude::*;
+use crate::tests::prelude::*;
+use crate::fs_backend::cgroup;
+
+#[derive(clap::Parser, Debug, Clone)]
+pub struct MyArgs {
+    /// cgroup's name
+    #[arg(short = 'c', long = "cgroup", default_value = "g0", value_name = "name")]
+    pub cgroup: String,
+
+    /// cgroup's runtime
+    #[arg(short = 'r', long = "runtime", value_name = "ms: u64")]
+    pub runtime_ms: u64,
+
+    /// cgroup's period
+    #[arg(short = 'p', long = "period", value_name = "ms: u64")]
+    pub period_ms: u64,
+
+    /// cpu on which all the tasks run
+    #[arg(long = "cpu", default_value = "0", value_name = "id")]
+    pub cpu: CpuID,
+
+    /// cgroup's cpu.weight, with a competing SCHED_OTHER task in the root
+    /// cgroup
+    #[arg(short = 'w', long = "weight", value_name = "1-10000")]
+    pub weight: Option<u64>,
+
+    /// cgroup's cpu.max, as quota_ms/period_ms
+    #[arg(long = "cpu-max", value_name = "quota_ms/period_ms", value_parser = CpuMax::from_str)]
+    pub cpu_max: Option<CpuMax>,
+
+    /// max running time
+    #[arg(short = 't', long = "max-time", value_name = "sec: u64")]
+    pub max_time: Option<u64>,
+
+    #[command(flatten)]
+    pub verdict: VerdictArgs,
+}
+
+/// CFS bandwidth limit of a cgroup.
+#[derive(Debug, Clone, Copy)]
+pub struct CpuMax {
+    pub quota_ms: u64,
+    pub period_ms: u64,
+}
+
+impl CpuMax {
+    pub fn bandwidth(&self) -> f64 {
+        self.quota_ms as f64 / self.period_ms as f64
+    }
+}
+
+impl FromStr for CpuMax {
+    type Err = anyhow::Error;
+
+    fn from_str(cpu_max: &str) -> Result<Self, Self::Err> {
+        let err = || anyhow::format_err!("Invalid cpu.max \'{cpu_max}\', expected quota_ms/period_ms");
+
+        let (quota_ms, period_ms) = cpu_max.split_once('/').ok_or_else(err)?;
+
+        Ok(Self {
+            quota_ms: quota_ms.parse().map_err(|_| err())?,
+            period_ms: period_ms.parse().map_err(|_| err())?,
+        })
+    }
+}
+
+impl std::fmt::Display for CpuMax {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.quota_ms, self.period_ms)
+    }
+}
+
+fn test_name(args: &MyArgs) -> String {
+    format!("sched_other c{} r{} p{} cpu{} w{:?} max{:?}",
+        args.cgroup, args.runtime_ms, args.period_ms, args.cpu, args.weight, args.cpu_max.map(|cpu_max| cpu_max.to_string()))
+}
+
+pub fn batch_runner(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<()> {
+    if is_batch_test() && args.max_time.is_none() {
+        anyhow::bail!("Batch testing requires a maximum running time");
+    }
+
+    let test_header = test_name(&args);
+    let test_header =
+        if is_batch_test() {
+            test_header
+        } else {
+            test_header + " (Ctrl+C to stop)"
+        };
+
+    batch_test_header(&test_header, "regression");
+    batch_test_param("cgroup", &args.cgroup);
+    batch_test_param("runtime_ms", args.runtime_ms);
+    batch_test_param("period_ms", args.period_ms);
+    batch_test_param("cpu", args.cpu);
+    batch_test_param("weight", format!("{:?}", args.weight));
+    batch_test_param("cpu_max", format!("{:?}", args.cpu_max.map(|cpu_max| cpu_max.to_string())));
+    batch_test_param("max_time", format!("{:?}", args.max_time));
+    args.verdict.batch_test_params();
+
+    if !batch_test_requires(&args.requirements())? {
+        return Ok(());
+    }
+
+    let result = run(args, ctrlc_flag);
+
+    if is_batch_test() {
+        batch_test_result_skippable(result)
+    } else {
+        batch_test_result_skippable_details(result)
+    }
+}
+
+/// Expected bandwidth of the RT task, of the cgroup's SCHED_OTHER task and of
+/// the competing root SCHED_OTHER task (if any). The SCHED_OTHER tasks share
+/// the time left by the RT reservation according to their weights, the
+/// cgroup's one being limited by cpu.max.
+pub fn expected_bws(args: &MyArgs) -> (f64, f64, Option<f64>) {
+    let rt_bw = args.runtime_ms as f64 / args.period_ms as f64;
+    let free_bw = 1f64 - rt_bw;
+
+    let cgroup_share = args.weight
+        .map_or(free_bw, |weight| free_bw * weight as f64 / (weight as f64 + 100f64));
+    let cgroup_bw = args.cpu_max
+        .map_or(cgroup_share, |cpu_max| f64::min(cgroup_share, cpu_max.bandwidth()));
+
+    (rt_bw, cgroup_bw, args.weight.map(|_| free_bw - cgroup_bw))
+}
+
+pub fn run(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<String>> {
+    if args.runtime_ms > args.period_ms {
+        anyhow::bail!("The cgroup's runtime must not exceed its period");
+    }
+
+    let (rt_bw, cgroup_bw, root_bw) = expected_bws(&args);
+    let verdict_args = args.verdict.clone();
+
+    let trials =
+        match verdict_args.run_trials(ctrlc_flag, |ctrlc_flag| main(args.clone(), ctrlc_flag))? {
+            Skippable::Result(trials) => trials,
+            Skippable::Skipped(err) => { return Ok(Skippable::Skipped(err)); },
+        };
+
+    let mut measurements = vec![
+        Measurement::new("rt", rt_bw)
+            .with_samples(trials.iter().map(|usage| usage.rt_bw)),
+        Measurement::new("cgroup_other", cgroup_bw)
+            .with_samples(trials.iter().map(|usage| usage.cgroup_other_bw)),
+    ];
+
+    if let Some(root_bw) = root_bw {
+        measurements.push(Measurement::new("root_other", root_bw)
+            .with_samples(trials.iter().map(|usage| usage.root_other_bw.unwrap_or(0f64))));
+    }
+
+    let verdict = verdict_args.verdict(Tolerance::absolute(0.02), measurements);
+    verdict.batch_test_params();
+
+    let rt = &verdict.results[0];
+    let others_failed = verdict.results[1..].iter().any(|result| !result.passed());
+
+    if !rt.passed() && rt.error() < 0f64 {
+        Err(anyhow::format_err!("The RT reservation was consumed by the SCHED_OTHER tasks: the RT task used {:.5} instead of {:.5}\n{verdict}", rt.mean, rt.expected))
+    } else if !rt.passed() {
+        Err(anyhow::format_err!("The RT task used more than its reservation: {:.5} instead of {:.5}\n{verdict}", rt.mean, rt.expected))
+    } else if others_failed {
+        Err(anyhow::format_err!("The SCHED_OTHER tasks were not scheduled according to their weights and limits\n{verdict}"))
+    } else {
+        Ok(Skippable::Result(format!("The RT task used its reservation, the SCHED_OTHER tasks the remaining bandwidth.\n{verdict}")))
+    }
+}
+
+impl HcbsTest for MyArgs {
+    fn name(&self) -> String {
+        test_name(self)
+    }
+
+    fn category(&self) -> &'static str {
+        "regression"
+    }
+
+    fn parameters(&self) -> String {
+        format!("-c {} -r {} -p {} --cpu {}{}{}{}{}",
+            self.cgroup, self.runtime_ms, self.period_ms, self.cpu, __opt_arg("-w", self.weight),
+            __opt_arg("--cpu-max", self.cpu_max), __opt_arg("-t", self.max_time), self.verdict.parameters())
+    }
+
+    fn requirements(&self) -> Vec<Capability> {
+        vec![Capability::RtGroup, Capability::CpuController]
+    }
+
+    fn run(&self, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<String>> {
+        run(self.clone(), ctrlc_flag)
+    }
+}
+
+/// Bandwidth used by the RT and SCHED_OTHER tasks of the cgroup and, if any,
+/// by the competing root SCHED_OTHER task.
+#[derive(Debug, Clone)]
+pub struct SchedOtherUsage {
+    pub rt_bw: f64,
+    pub cgroup_other_bw: f64,
+    pub root_other_bw: Option<f64>,
+}
+
+pub fn main(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<SchedOtherUsage>> {
+    // check if the cpu is available
+    let cpu_set =
+        match CpuSet::single(args.cpu) {
+            Ok(cpu_set) => cpu_set,
+            Err(err @ CpuSetBuildError::UnavailableCPU(_)) =>
+                { return Ok(Skippable::Skipped(err.into())); },
+            Err(err) =>
+                { return Err(err.into()); },
+        };
+
+    assign_pid_to_cgroup(".", std::process::id())?;
+    set_sched_policy(std::process::id(), SchedPolicy::RR(99), SchedFlags::RESET_ON_FORK)?;
+
+    let mut cgroup = FsCgroup::new(&args.cgroup)?
+        .with_force_kill(true);
+    cgroup.set_period_us(args.period_ms * 1000)?;
+    cgroup.set_runtime_us(args.runtime_ms * 1000)?;
+
+    cgroup::set_cpu_max_us(&args.cgroup, args.cpu_max.map(|cpu_max| (cpu_max.quota_ms * 1000, cpu_max.period_ms * 1000)))?;
+    cgroup::set_cpu_weight(&args.cgroup, args.weight.unwrap_or(100))?;
+
+    // RT and SCHED_OTHER tasks in the same cgroup
+    let mut rt_task = run_yes()?;
+    rt_task.set_affinity(cpu_set.clone())?;
+    let rt_task = cgroup.assign_process(rt_task).map_err(|(_, err)| err)?;
+    rt_task.set_sched_policy(SchedPolicy::RR(50), SchedFlags::empty())?;
+    let mut pids = vec![rt_task.id()];
+
+    let mut other_task = cpu_hog()?;
+    other_task.set_affinity(cpu_set.clone())?;
+    let other_task = cgroup.assign_process(other_task).map_err(|(_, err)| err)?;
+    pids.push(other_task.id());
+
+    // competing SCHED_OTHER task, in the root cgroup
+    let mut root_task =
+        match args.weight {
+            Some(_) => {
+                let mut root_task = cpu_hog()?;
+                root_task.set_affinity(cpu_set)?;
+                pids.push(root_task.id());
+
+                Some(root_task)
+            },
+            None => None,
+        };
+
+    let window = UsageWindow::start_processes(&pids)?;
+
+    wait_loop(args.max_time, ctrlc_flag)?;
+
+    let usage = window.usage_per_source()?;
+
+    if let Some(HCBSProcess::Child(child)) = &mut root_task {
+        child.kill()?;
+        child.wait()?;
+    }
+
+    cgroup.force_destroy();
+
+    Ok(Skippable::Result(SchedOtherUsage {
+        rt_bw: usage[0].bandwidth(),
+        cgroup_other_bw: usage[1].bandwidth(),
+        root_other_bw: usage.get(2).map(|usage| usage.bandwidth()),
+    }))
+}
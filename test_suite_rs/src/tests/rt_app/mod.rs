@@ -67,7 +67,7 @@ fn run_taskset(run: TasksetRun, args: &RunnerArgsBase, cycles: Option<u64>, mult
     )?;
 
     let cpu_set = CpuSet::any_subset(run.config.cpus)?;
-    let mut cgroup = HCBSCgroup::new(&args.cgroup)?
+    let mut cgroup = FsCgroup::new(&args.cgroup)?
         .with_force_kill(true);
     cgroup.set_period_us(run.config.period.as_micros() as u64)?;
     if !multi_runtime  {
@@ -153,5 +153,6 @@ fn run_rt_app(config_file: &str, stdout_file: &str) -> anyhow::Result<HCBSProces
         .spawn()
         .map_err(|err| anyhow::format_err!("Error in starting rt-app: {err}"))?;
 
+    supervise_pid(proc.id());
     Ok(HCBSProcess::Child(proc))
 }
\ No newline at end of file
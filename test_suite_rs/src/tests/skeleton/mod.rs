@@ -262,6 +262,9 @@ fn run_taskset_one<FnRun>(
             taskset_header + &format!(" (~{:.2} secs)", insights.expected_runtime.as_secs())
         };
     batch_test_header(&taskset_header, "taskset");
+    batch_test_param("taskset", &run.taskset.name);
+    batch_test_param("config", &run.config.name);
+    batch_test_param("results_file", &run.results_file);
 
     if !can_run_taskset(&run, &args) {
         batch_test_skipped("cannot run on current config");
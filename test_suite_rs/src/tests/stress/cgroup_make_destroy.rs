@@ -1,7 +1,8 @@
-use hcbs_test_suite::prelude::*;
-use rand::RngExt as _;
+use crate::prelude::*;
+use crate::tests::prelude::*;
+use rand::{RngExt as _, SeedableRng as _};
 
-#[derive(clap::Parser, Debug)]
+#[derive(clap::Parser, Debug, Clone)]
 pub struct MyArgs {
     /// cgroup's name
     #[arg(short = 'c', long = "cgroup", default_value = "g0", value_name = "name")]
@@ -19,30 +20,66 @@ pub struct MyArgs {
     #[arg(short = 'p', long = "period", value_name = "ms: u64")]
     pub period_ms: u64,
 
+    /// RNG's seed (by default a random one)
+    #[arg(long = "seed", value_name = "u64")]
+    pub seed: Option<u64>,
+
     /// max running time
     #[arg(short = 't', long = "max-time", value_name = "sec: u64")]
     pub max_time: Option<u64>,
 }
 
+fn test_name(args: &MyArgs) -> String {
+    format!("cgroup_make_destroy c{} r{} R{} p{}", args.cgroup, args.runtime_min_ms, args.runtime_max_ms, args.period_ms)
+}
+
 pub fn batch_runner(args: MyArgs, rng: Option<&mut dyn rand::Rng>, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<()> {
     if is_batch_test() && args.max_time.is_none() {
         anyhow::bail!("Batch testing requires a maximum running time");
     }
 
-    batch_test_header(&format!("cgroup_make_destroy c{} r{} R{} p{}", args.cgroup, args.runtime_min_ms, args.runtime_max_ms, args.period_ms), "stress");
+    batch_test_header(&test_name(&args), "stress");
+    if !batch_test_requires(&args.requirements())? {
+        return Ok(());
+    }
+
     batch_test_result(main(args, rng, ctrlc_flag))?;
 
     Ok(())
 }
 
+impl HcbsTest for MyArgs {
+    fn name(&self) -> String {
+        test_name(self)
+    }
+
+    fn category(&self) -> &'static str {
+        "stress"
+    }
+
+    fn parameters(&self) -> String {
+        format!("-c {} -r {} -R {} -p {}{}{}",
+            self.cgroup, self.runtime_min_ms, self.runtime_max_ms, self.period_ms, __opt_arg("--seed", self.seed),
+            __opt_arg("-t", self.max_time))
+    }
+
+    fn run(&self, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<String>> {
+        main(self.clone(), None, ctrlc_flag).map(|_| Skippable::Result(String::new()))
+    }
+}
+
 pub fn main(args: MyArgs, rng: Option<&mut dyn rand::Rng>, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<()> {
-    let mut thread_rng = rand::rng();
-    let rng = rng.unwrap_or_else(|| &mut thread_rng);
+    let mut default_rng: Box<dyn rand::Rng> =
+        match args.seed {
+            Some(seed) => Box::new(rand::rngs::StdRng::seed_from_u64(seed)),
+            None => Box::new(rand::rng()),
+        };
+    let rng = rng.unwrap_or_else(|| default_rng.as_mut());
 
     wait_loop_periodic_fn(0f32, args.max_time, ctrlc_flag,
         || {
             let runtime_ms = rng.random_range(args.runtime_min_ms ..= args.runtime_max_ms);
-            let mut cgroup = HCBSCgroup::new(&args.cgroup)?
+            let mut cgroup = FsCgroup::new(&args.cgroup)?
                 .with_force_kill(true);
             cgroup.set_period_us(args.period_ms * 1000)?;
             cgroup.set_runtime_us(runtime_ms * 1000)?;
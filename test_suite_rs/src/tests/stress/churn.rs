@@ -0,0 +1,403 @@
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+
+use crate::prelude::*;
+use crate::tests::prelude::*;
+use crate::fs_backend::cgroup;
+
+#[derive(clap::Parser, Debug, Clone)]
+pub struct MyArgs {
+    /// cgroup's name
+    #[arg(short = 'c', long = "cgroup", default_value = "g0", value_name = "name")]
+    pub cgroup: String,
+
+    /// cgroup's runtime
+    #[arg(short = 'r', long = "runtime", value_name = "ms: u64")]
+    pub runtime_ms: u64,
+
+    /// cgroup's period
+    #[arg(short = 'p', long = "period", value_name = "ms: u64")]
+    pub period_ms: u64,
+
+    /// cpus on which the tasks run, all by default
+    #[arg(long = "cpu-set", value_parser = <CpuSetUnchecked as std::str::FromStr>::from_str)]
+    pub cpu_set: Option<CpuSetUnchecked>,
+
+    /// kind of the created tasks
+    #[arg(short = 'm', long = "mode", default_value = "mixed", value_enum)]
+    pub mode: ChurnMode,
+
+    /// tasks created in each burst
+    #[arg(short = 'b', long = "burst", default_value = "16", value_name = "u64")]
+    pub burst: u64,
+
+    /// time between the start of consecutive bursts
+    #[arg(short = 'P', long = "burst-period", default_value = "100", value_name = "ms: u64")]
+    pub burst_period_ms: u64,
+
+    /// CPU time consumed by each created task before exiting
+    #[arg(long = "task-time", default_value = "1000", value_name = "us: u64")]
+    pub task_time_us: u64,
+
+    /// create the tasks with SCHED_FLAG_RESET_ON_FORK, so that they start as
+    /// SCHED_OTHER tasks
+    #[arg(long = "reset-on-fork")]
+    pub reset_on_fork: bool,
+
+    /// maximum error allowed on the bandwidth used by the cgroup's RT tasks
+    #[arg(long = "tolerance", default_value = "0.02", value_name = "f64")]
+    pub tolerance: f64,
+
+    /// max running time
+    #[arg(short = 't', long = "max-time", value_name = "sec: u64")]
+    pub max_time: Option<u64>,
+}
+
+/// Kind of the tasks created by `tools churn`.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum ChurnMode {
+    /// fork processes which spin and exit
+    Fork,
+    /// spawn threads which spin and are joined
+    Thread,
+    /// spawn processes which exec `true`
+    Exec,
+    /// all of the above, in turn
+    Mixed,
+}
+
+impl ChurnMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChurnMode::Fork => "fork",
+            ChurnMode::Thread => "thread",
+            ChurnMode::Exec => "exec",
+            ChurnMode::Mixed => "mixed",
+        }
+    }
+}
+
+impl std::fmt::Display for ChurnMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Tasks created by `tools churn` which exited successfully, by kind, and
+/// failed creations or exits. Printed as "forks threads execs errors".
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ChurnCounts {
+    pub forks: u64,
+    pub threads: u64,
+    pub execs: u64,
+    pub errors: u64,
+}
+
+impl ChurnCounts {
+    pub fn total(&self) -> u64 {
+        self.forks + self.threads + self.execs
+    }
+}
+
+impl FromStr for ChurnCounts {
+    type Err = anyhow::Error;
+
+    fn from_str(counts: &str) -> Result<Self, Self::Err> {
+        let err = || anyhow::format_err!("Invalid churn counts \'{}\'", counts.trim());
+
+        let counts = counts.split_whitespace()
+            .map(|count| count.parse::<u64>().map_err(|_| err()))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let [forks, threads, execs, errors] = counts[..] else {
+            return Err(err());
+        };
+
+        Ok(Self { forks, threads, execs, errors })
+    }
+}
+
+impl std::fmt::Display for ChurnCounts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {} {}", self.forks, self.threads, self.execs, self.errors)
+    }
+}
+
+fn test_name(args: &MyArgs) -> String {
+    format!("task_churn c{} r{} p{} set{:?} m{} b{} P{} rof{}",
+        args.cgroup, args.runtime_ms, args.period_ms, args.cpu_set, args.mode, args.burst, args.burst_period_ms,
+        args.reset_on_fork)
+}
+
+pub fn batch_runner(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<()> {
+    if is_batch_test() && args.max_time.is_none() {
+        anyhow::bail!("Batch testing requires a maximum running time");
+    }
+
+    let test_header = test_name(&args);
+    let test_header =
+        if is_batch_test() {
+            test_header
+        } else {
+            test_header + " (Ctrl+C to stop)"
+        };
+
+    batch_test_header(&test_header, "stress");
+    batch_test_param("cgroup", &args.cgroup);
+    batch_test_param("runtime_ms", args.runtime_ms);
+    batch_test_param("period_ms", args.period_ms);
+    batch_test_param("cpu_set", format!("{:?}", args.cpu_set));
+    batch_test_param("mode", args.mode);
+    batch_test_param("burst", args.burst);
+    batch_test_param("burst_period_ms", args.burst_period_ms);
+    batch_test_param("task_time_us", args.task_time_us);
+    batch_test_param("reset_on_fork", args.reset_on_fork);
+    batch_test_param("tolerance", args.tolerance);
+    batch_test_param("max_time", format!("{:?}", args.max_time));
+
+    if !batch_test_requires(&args.requirements())? {
+        return Ok(());
+    }
+
+    let result = run(args, ctrlc_flag);
+
+    if is_batch_test() {
+        batch_test_result_skippable(result)
+    } else {
+        batch_test_result_skippable_details(result)
+    }
+}
+
+pub fn run(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<String>> {
+    if args.runtime_ms > args.period_ms {
+        anyhow::bail!("The cgroup's runtime must not exceed its period");
+    }
+
+    let reserved_bw = args.runtime_ms as f64 / args.period_ms as f64;
+    let resolution_us = probe_resolution_us(args.period_ms * 1000);
+
+    let outcome =
+        match main(args.clone(), ctrlc_flag)? {
+            Skippable::Result(outcome) => outcome,
+            Skippable::Skipped(err) => { return Ok(Skippable::Skipped(err)); },
+        };
+
+    let counts = outcome.counts;
+    let summary = format!("Created {} tasks ({} forks, {} threads, {} execs, {} errors), RT bandwidth {:.5}/{:.5} per CPU, admissible runtime {}/{} us",
+        counts.total(), counts.forks, counts.threads, counts.execs, counts.errors, outcome.rt_bw, reserved_bw,
+        outcome.probe_after_us, outcome.probe_before_us);
+
+    let kmsg_failures = outcome.kmsg.as_ref()
+        .map_or(Vec::new(), |report| report.failures(KmsgMode::Sched));
+
+    if counts.total() == 0 {
+        Err(anyhow::format_err!("The churner did not create any task\n{summary}"))
+    } else if !outcome.leftover.is_empty() {
+        Err(anyhow::format_err!("Tasks left in the cgroup after the churn: {:?}\n{summary}", outcome.leftover))
+    } else if let Some(err) = &outcome.zero_runtime_err {
+        Err(anyhow::format_err!("Cannot set the cgroup's runtime to zero after its tasks exited, leaked RT tasks accounting: {err}\n{summary}"))
+    } else if outcome.probe_after_us + resolution_us < outcome.probe_before_us {
+        Err(anyhow::format_err!("The admissible runtime dropped from {} to {} us, leaked bandwidth\n{summary}",
+            outcome.probe_before_us, outcome.probe_after_us))
+    } else if outcome.rt_bw > reserved_bw + args.tolerance {
+        Err(anyhow::format_err!("The cgroup's RT tasks used more than the reserved bandwidth\n{summary}"))
+    } else if !kmsg_failures.is_empty() {
+        let events: Vec<_> = kmsg_failures.iter().map(|event| event.to_string()).collect();
+        Err(anyhow::format_err!("Scheduler related kernel log events during the churn:\n{}\n{summary}", events.join("\n")))
+    } else {
+        let kmsg = outcome.kmsg.as_ref()
+            .map_or("kernel log unavailable".to_owned(), |report| report.level_counts());
+
+        Ok(Skippable::Result(format!("{summary}, kernel log: {kmsg}")))
+    }
+}
+
+impl HcbsTest for MyArgs {
+    fn name(&self) -> String {
+        test_name(self)
+    }
+
+    fn category(&self) -> &'static str {
+        "stress"
+    }
+
+    fn parameters(&self) -> String {
+        let cpu_set = self.cpu_set.as_ref()
+            .map_or(String::new(), |cpu_set| format!(" --cpu-set {}", __cpu_set_arg(cpu_set.iter())));
+        let reset_on_fork = if self.reset_on_fork { " --reset-on-fork" } else { "" };
+
+        format!("-c {} -r {} -p {}{} -m {} -b {} -P {} --task-time {}{} --tolerance {}{}",
+            self.cgroup, self.runtime_ms, self.period_ms, cpu_set, self.mode, self.burst, self.burst_period_ms,
+            self.task_time_us, reset_on_fork, self.tolerance, __opt_arg("-t", self.max_time))
+    }
+
+    fn requirements(&self) -> Vec<Capability> {
+        if self.cpu_set.is_some() {
+            vec![Capability::RtGroup, Capability::MultiCpu]
+        } else {
+            vec![Capability::RtGroup]
+        }
+    }
+
+    fn run(&self, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<String>> {
+        run(self.clone(), ctrlc_flag)
+    }
+}
+
+/// Resolution of the admissible runtime's search, for the given period.
+fn probe_resolution_us(period_us: u64) -> u64 {
+    u64::max(period_us / 128, 1)
+}
+
+/// Largest runtime accepted by a new cgroup with the given period, found by
+/// bisection. Bandwidth leaked by the admission control lowers it.
+pub fn max_admissible_runtime_us(name: &str, period_us: u64) -> anyhow::Result<u64> {
+    let mut cgroup = FsCgroup::new(name)?
+        .with_force_kill(true);
+    cgroup.set_period_us(period_us)?;
+
+    // runtimes up to low are accepted, from high on are rejected
+    let (mut low, mut high) = (0, period_us + 1);
+    while high - low > probe_resolution_us(period_us) {
+        let runtime_us = (low + high) / 2;
+
+        if cgroup.set_runtime_us(runtime_us).is_ok() {
+            low = runtime_us;
+        } else {
+            high = runtime_us;
+        }
+    }
+
+    cgroup.set_runtime_us(0)?;
+    cgroup.force_destroy();
+
+    Ok(low)
+}
+
+/// Outcome of the churn, checked by [`run`].
+#[derive(Debug, Clone)]
+pub struct ChurnOutcome {
+    pub counts: ChurnCounts,
+    /// bandwidth used by the cgroup's RT tasks, per CPU
+    pub rt_bw: f64,
+    /// threads still in the cgroup after the churner and the saturating tasks
+    /// exited
+    pub leftover: Vec<Pid>,
+    pub zero_runtime_err: Option<String>,
+    pub probe_before_us: u64,
+    pub probe_after_us: u64,
+    /// None if /dev/kmsg is not available
+    pub kmsg: Option<KmsgReport>,
+}
+
+/// Saturate the cgroup with a RT task per CPU, while a higher priority
+/// churner (`tools churn`) creates tasks inside it in bursts. The churner's
+/// tasks are RT as well, unless reset-on-fork is requested.
+pub fn main(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<ChurnOutcome>> {
+    let Some(max_time) = args.max_time else {
+        anyhow::bail!("The churner requires a maximum running time");
+    };
+
+    // check if the cpu_set is valid
+    let cpu_set =
+        match args.cpu_set.clone().map(CpuSet::try_from).transpose() {
+            Ok(cpu_set) => cpu_set.map_or_else(CpuSet::all, Ok)?,
+            Err(err @ CpuSetBuildError::UnavailableCPU(_)) =>
+                { return Ok(Skippable::Skipped(err.into())); },
+            Err(err) =>
+                { return Err(err.into()); },
+        };
+
+    assign_pid_to_cgroup(".", std::process::id())?;
+    set_sched_policy(std::process::id(), SchedPolicy::RR(99), SchedFlags::RESET_ON_FORK)?;
+
+    let probe_name = format!("{}_probe", args.cgroup);
+    let period_us = args.period_ms * 1000;
+    let probe_before_us = max_admissible_runtime_us(&probe_name, period_us)?;
+
+    let mut kmsg = KmsgMonitor::start()
+        .inspect_err(|err| log::warn!("Kernel log not checked: {err}"))
+        .ok();
+
+    let mut cgroup = FsCgroup::new(&args.cgroup)?
+        .with_force_kill(true);
+    cgroup.set_period_us(period_us)?;
+    cgroup.set_runtime_us(args.runtime_ms * 1000)?;
+
+    let mut rt_pids = Vec::new();
+    for &cpu in cpu_set.iter() {
+        let mut yes = run_yes()?;
+        yes.set_affinity(CpuSet::single(cpu)?)?;
+        let yes = cgroup.assign_process(yes).map_err(|(_, err)| err)?;
+        yes.set_sched_policy(SchedPolicy::RR(50), SchedFlags::empty())?;
+        rt_pids.push(yes.id());
+    }
+    let yes_pids = rt_pids.clone();
+
+    // the churner waits to be moved into the cgroup, and keeps churning a
+    // second longer than the measurement
+    let cmd = local_executable_cmd("/root/test_suite", "tools")?;
+    let churner = Command::new(cmd)
+        .args(["churn", args.mode.as_str(), "-b", &args.burst.to_string(), "-P", &args.burst_period_ms.to_string(),
+            "--task-time", &args.task_time_us.to_string(), "--delay", "100", "-d", &(max_time * 1000 + 1000).to_string()])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    supervise_pid(churner.id());
+
+    let churner_pid = churner.id();
+    set_cpuset_to_pid(churner_pid, &cpu_set)?;
+    cgroup::assign_pid(&args.cgroup, churner_pid)?;
+    let flags = if args.reset_on_fork { SchedFlags::RESET_ON_FORK } else { SchedFlags::empty() };
+    set_sched_policy(churner_pid, SchedPolicy::RR(60), flags)?;
+    rt_pids.push(churner_pid);
+
+    // with reset-on-fork the churned tasks are SCHED_OTHER, and they are not
+    // accounted to the RT reservation
+    let window =
+        if args.reset_on_fork {
+            UsageWindow::start_processes(&rt_pids)?
+        } else {
+            UsageWindow::start_cgroup(&args.cgroup)?
+        };
+
+    wait_loop(args.max_time, ctrlc_flag)?;
+
+    let rt_bw = window.stop()?.bandwidth() / cpu_set.num_cpus() as f64;
+
+    let output = churner.wait_with_output()?;
+
+    for pid in yes_pids {
+        if let HCBSProcess::Child(mut child) = cgroup.take_process(pid)? {
+            child.kill()?;
+            child.wait()?;
+        }
+    }
+
+    // all the tasks exited: nothing must be left, and the cgroup must not
+    // account any RT task
+    let leftover = cgroup::get_threads(&args.cgroup)?;
+    let zero_runtime_err = cgroup::set_runtime_us(&args.cgroup, 0).err().map(|err| err.to_string());
+
+    cgroup.force_destroy();
+
+    let probe_after_us = max_admissible_runtime_us(&probe_name, period_us)?;
+    let kmsg = kmsg.as_mut().map(|kmsg| kmsg.collect()).transpose()?;
+
+    if !output.status.success() {
+        anyhow::bail!("The churner failed: {}", output.status);
+    }
+
+    let counts = String::from_utf8_lossy(&output.stdout).parse()?;
+
+    Ok(Skippable::Result(ChurnOutcome {
+        counts,
+        rt_bw,
+        leftover,
+        zero_runtime_err,
+        probe_before_us,
+        probe_after_us,
+        kmsg,
+    }))
+}
@@ -0,0 +1,697 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::{Arc, Mutex, RwLock, atomic::{AtomicU64, Ordering}};
+use std::time::{Duration, Instant};
+
+use rand::{RngExt as _, SeedableRng as _};
+
+use crate::prelude::*;
+use crate::tests::prelude::*;
+use crate::fs_backend::cgroup;
+use super::replay::{MinimizeArgs, ReplayLog, create_jsonl, minimized_path, read_jsonl, replay_and_minimize};
+
+#[derive(clap::Parser, Debug, Clone)]
+pub struct MyArgs {
+    /// prefix of the cgroups' names, which are numbered from zero
+    #[arg(short = 'c', long = "cgroup-prefix", default_value = "fuzz", value_name = "name")]
+    pub cgroup_prefix: String,
+
+    /// number of cgroups
+    #[arg(short = 'g', long = "cgroups", default_value = "4", value_name = "#num")]
+    pub num_cgroups: usize,
+
+    /// number of task slots
+    #[arg(short = 'n', long = "tasks", default_value = "8", value_name = "#num")]
+    pub num_tasks: usize,
+
+    /// number of worker threads
+    #[arg(short = 'w', long = "workers", default_value = "4", value_name = "#num")]
+    pub num_workers: usize,
+
+    /// maximum period (and runtime) written to the cgroups
+    #[arg(short = 'p', long = "period-max", default_value = "100", value_name = "ms: u64")]
+    pub period_max_ms: u64,
+
+    /// maximum delay between two operations of the same worker
+    #[arg(long = "op-delay", default_value = "10", value_name = "ms: u64")]
+    pub op_delay_ms: u64,
+
+    /// operations taking longer than this are reported as stuck
+    #[arg(long = "op-timeout", default_value = "1000", value_name = "ms: u64")]
+    pub op_timeout_ms: u64,
+
+    /// operation log (by default /tmp/hcbs_fuzz_<seed>.jsonl, or none when
+    /// replaying)
+    #[arg(short = 'l', long = "log", value_name = "path")]
+    pub log: Option<String>,
+
+    /// replay the operations of the given log, one at a time in their
+    /// original order and timing, instead of generating new ones
+    #[arg(long = "replay", value_name = "path")]
+    pub replay: Option<String>,
+
+    #[command(flatten)]
+    pub minimize: MinimizeArgs,
+
+    /// RNG's seed
+    #[arg(long = "seed", default_value = "42", value_name = "u64")]
+    pub seed: u64,
+
+    /// max running time
+    #[arg(short = 't', long = "max-time", value_name = "sec: u64")]
+    pub max_time: Option<u64>,
+}
+
+fn test_name(args: &MyArgs) -> String {
+    match &args.replay {
+        Some(replay) => format!("fuzz replay {replay}"),
+        None => format!("fuzz c{} g{} n{} w{} seed{}",
+            args.cgroup_prefix, args.num_cgroups, args.num_tasks, args.num_workers, args.seed),
+    }
+}
+
+pub fn batch_runner(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<()> {
+    if is_batch_test() && args.max_time.is_none() && args.replay.is_none() {
+        anyhow::bail!("Batch testing requires a maximum running time");
+    }
+
+    let test_header = test_name(&args);
+    let test_header =
+        if is_batch_test() {
+            test_header
+        } else {
+            test_header + " (Ctrl+C to stop)"
+        };
+
+    batch_test_header(&test_header, "stress");
+    batch_test_param("cgroup_prefix", &args.cgroup_prefix);
+    batch_test_param("num_cgroups", args.num_cgroups);
+    batch_test_param("num_tasks", args.num_tasks);
+    batch_test_param("num_workers", args.num_workers);
+    batch_test_param("period_max_ms", args.period_max_ms);
+    batch_test_param("op_delay_ms", args.op_delay_ms);
+    batch_test_param("op_timeout_ms", args.op_timeout_ms);
+    batch_test_param("seed", args.seed);
+    batch_test_param("log", format!("{:?}", args.log));
+    batch_test_param("replay", format!("{:?}", args.replay));
+    batch_test_param("max_time", format!("{:?}", args.max_time));
+
+    if !batch_test_requires(&args.requirements())? {
+        return Ok(());
+    }
+
+    batch_test_result_details(main(args, ctrlc_flag))
+}
+
+impl HcbsTest for MyArgs {
+    fn name(&self) -> String {
+        test_name(self)
+    }
+
+    fn category(&self) -> &'static str {
+        "stress"
+    }
+
+    fn parameters(&self) -> String {
+        format!("-c {} -g {} -n {} -w {} -p {} --op-delay {} --op-timeout {} --seed {}{}{}{}{}",
+            self.cgroup_prefix, self.num_cgroups, self.num_tasks, self.num_workers, self.period_max_ms,
+            self.op_delay_ms, self.op_timeout_ms, self.seed, __opt_arg("-l", self.log.as_ref()),
+            __opt_arg("--replay", self.replay.as_ref()), self.minimize.parameters(), __opt_arg("-t", self.max_time))
+    }
+
+    fn run(&self, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<String>> {
+        main(self.clone(), ctrlc_flag).map(Skippable::Result)
+    }
+}
+
+/// Scheduling policy set by the fuzzer.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "class", rename_all = "snake_case")]
+pub enum FuzzPolicy {
+    Other { nice: i32 },
+    Batch { nice: i32 },
+    Idle,
+    Fifo { priority: u64 },
+    Rr { priority: u64 },
+}
+
+impl From<&FuzzPolicy> for SchedPolicy {
+    fn from(policy: &FuzzPolicy) -> Self {
+        match *policy {
+            FuzzPolicy::Other { nice } => SchedPolicy::OTHER { nice },
+            FuzzPolicy::Batch { nice } => SchedPolicy::BATCH { nice },
+            FuzzPolicy::Idle => SchedPolicy::IDLE,
+            FuzzPolicy::Fifo { priority } => SchedPolicy::FIFO(priority),
+            FuzzPolicy::Rr { priority } => SchedPolicy::RR(priority),
+        }
+    }
+}
+
+/// A fuzzer's operation. Cgroups and tasks are referred to by their index, so
+/// that the operations can be replayed on a different run.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum FuzzOp {
+    CreateCgroup { cgroup: usize },
+    DestroyCgroup { cgroup: usize },
+    SetRuntime { cgroup: usize, runtime_us: u64 },
+    SetPeriod { cgroup: usize, period_us: u64 },
+    SetRuntimeMulti { cgroup: usize, runtimes_us: String },
+    SetPeriodMulti { cgroup: usize, periods_us: String },
+    Spawn { task: usize },
+    Exit { task: usize },
+    /// move the task to the given cgroup, or to the root one if None
+    Migrate { task: usize, cgroup: Option<usize> },
+    SetAffinity { task: usize, cpus: Vec<CpuID> },
+    SetPolicy { task: usize, policy: FuzzPolicy },
+}
+
+impl FuzzOp {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            FuzzOp::CreateCgroup { .. } => "create_cgroup",
+            FuzzOp::DestroyCgroup { .. } => "destroy_cgroup",
+            FuzzOp::SetRuntime { .. } => "set_runtime",
+            FuzzOp::SetPeriod { .. } => "set_period",
+            FuzzOp::SetRuntimeMulti { .. } => "set_runtime_multi",
+            FuzzOp::SetPeriodMulti { .. } => "set_period_multi",
+            FuzzOp::Spawn { .. } => "spawn",
+            FuzzOp::Exit { .. } => "exit",
+            FuzzOp::Migrate { .. } => "migrate",
+            FuzzOp::SetAffinity { .. } => "set_affinity",
+            FuzzOp::SetPolicy { .. } => "set_policy",
+        }
+    }
+
+    /// Random operation. Runtimes may exceed the periods, and the operations
+    /// may refer to missing cgroups or empty task slots: the kernel (or the
+    /// fuzzer) is expected to reject them.
+    pub fn random<R: rand::Rng + ?Sized>(rng: &mut R, args: &MyArgs, cpus: &[CpuID], multicpu: bool) -> Self {
+        let cgroup = rng.random_range(0..args.num_cgroups);
+        let task = rng.random_range(0..args.num_tasks);
+        let random_us = |rng: &mut R| rng.random_range(0..=args.period_max_ms * 10) * 100;
+        let cpus_all = cpus;
+        let random_cpus = |rng: &mut R| {
+            let cpus: Vec<_> = cpus_all.iter().copied().filter(|_| rng.random_bool(0.5)).collect();
+            if cpus.is_empty() { vec![cpus_all[0]] } else { cpus }
+        };
+        let random_multi = |rng: &mut R| {
+            (0..rng.random_range(1..=3))
+                .map(|_| format!("{} {}", random_us(rng), __cpu_set_arg(random_cpus(rng))))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+
+        match rng.random_range(0..20) {
+            0 => FuzzOp::CreateCgroup { cgroup },
+            1 => FuzzOp::DestroyCgroup { cgroup },
+            2..=3 => FuzzOp::SetRuntime { cgroup, runtime_us: random_us(rng) },
+            4 => FuzzOp::SetPeriod { cgroup, period_us: random_us(rng) },
+            5 if multicpu => FuzzOp::SetRuntimeMulti { cgroup, runtimes_us: random_multi(rng) },
+            6 if multicpu => FuzzOp::SetPeriodMulti { cgroup, periods_us: random_multi(rng) },
+            5..=6 => FuzzOp::SetRuntime { cgroup, runtime_us: random_us(rng) },
+            7..=8 => FuzzOp::Spawn { task },
+            9 => FuzzOp::Exit { task },
+            10..=12 => FuzzOp::Migrate { task, cgroup: rng.random_bool(0.75).then_some(cgroup) },
+            13..=15 => FuzzOp::SetAffinity { task, cpus: random_cpus(rng) },
+            _ => {
+                let policy =
+                    match rng.random_range(0..5) {
+                        0 => FuzzPolicy::Other { nice: rng.random_range(-20..=19) },
+                        1 => FuzzPolicy::Batch { nice: rng.random_range(-20..=19) },
+                        2 => FuzzPolicy::Idle,
+                        3 => FuzzPolicy::Fifo { priority: rng.random_range(1..=98) },
+                        _ => FuzzPolicy::Rr { priority: rng.random_range(1..=98) },
+                    };
+
+                FuzzOp::SetPolicy { task, policy }
+            },
+        }
+    }
+}
+
+/// First line of a fuzzer's log, with the configuration needed to replay it.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct FuzzHeader {
+    pub seed: u64,
+    pub cgroup_prefix: String,
+    pub num_cgroups: usize,
+    pub num_tasks: usize,
+    pub num_workers: usize,
+}
+
+/// An executed operation, as recorded in the log. Operations are numbered
+/// when issued and recorded when completed, thus the log is not sorted.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct FuzzRecord {
+    pub seq: u64,
+    /// issue time, since the start of the run
+    pub t_us: u64,
+    pub duration_us: u64,
+    pub worker: usize,
+    #[serde(flatten)]
+    pub op: FuzzOp,
+    /// None if the operation succeeded
+    pub error: Option<String>,
+}
+
+/// Read a fuzzer's log, returning its records sorted by issue order.
+pub fn read_log(path: &str) -> anyhow::Result<(FuzzHeader, Vec<FuzzRecord>)> {
+    let (header, mut records): (FuzzHeader, Vec<FuzzRecord>) = read_jsonl(path)?;
+    records.sort_by_key(|record| record.seq);
+
+    Ok((header, records))
+}
+
+/// Shared state of the fuzzer's workers.
+pub struct Fuzzer {
+    header: FuzzHeader,
+    op_timeout: Duration,
+    /// task slots; the ones using a task's pid hold the read lock, so that the
+    /// task cannot exit (and its pid be recycled) in the meantime
+    tasks: RwLock<Vec<Option<HCBSProcess>>>,
+    seq: AtomicU64,
+    start: Instant,
+    log: Option<Mutex<std::io::LineWriter<std::fs::File>>>,
+    /// operation being executed by each worker
+    in_flight: Mutex<Vec<Option<(u64, FuzzOp, Instant)>>>,
+    /// successful and failed operations of each kind
+    outcomes: Mutex<BTreeMap<&'static str, (u64, u64)>>,
+    slow: Mutex<Vec<FuzzRecord>>,
+}
+
+impl Fuzzer {
+    pub fn new(header: FuzzHeader, op_timeout: Duration, log: Option<&str>) -> anyhow::Result<Self> {
+        let log = log
+            .map(|path| create_jsonl(path, &header).map(Mutex::new))
+            .transpose()?;
+
+        Ok(Self {
+            tasks: RwLock::new((0..header.num_tasks).map(|_| None).collect()),
+            in_flight: Mutex::new(vec![None; header.num_workers.max(1)]),
+            header,
+            op_timeout,
+            seq: AtomicU64::new(0),
+            start: Instant::now(),
+            log,
+            outcomes: Mutex::new(BTreeMap::new()),
+            slow: Mutex::new(Vec::new()),
+        })
+    }
+
+    fn cgroup_name(&self, cgroup: usize) -> String {
+        format!("{}{}", self.header.cgroup_prefix, cgroup)
+    }
+
+    fn with_task<F>(&self, task: usize, fun: F) -> anyhow::Result<()>
+        where F: FnOnce(Pid) -> anyhow::Result<()>
+    {
+        let tasks = self.tasks.read().unwrap();
+        let pid = tasks.get(task)
+            .and_then(|proc| proc.as_ref())
+            .map(|proc| proc.id())
+            .ok_or_else(|| anyhow::format_err!("Task slot {task} is empty"))?;
+
+        fun(pid)
+    }
+
+    fn apply(&self, op: &FuzzOp) -> anyhow::Result<()> {
+        match op {
+            FuzzOp::CreateCgroup { cgroup } =>
+                cgroup::create(&self.cgroup_name(*cgroup)),
+            FuzzOp::DestroyCgroup { cgroup } =>
+                cgroup::remove(&self.cgroup_name(*cgroup)),
+            FuzzOp::SetRuntime { cgroup, runtime_us } =>
+                cgroup::set_runtime_us(&self.cgroup_name(*cgroup), *runtime_us),
+            FuzzOp::SetPeriod { cgroup, period_us } =>
+                cgroup::set_period_us(&self.cgroup_name(*cgroup), *period_us),
+            FuzzOp::SetRuntimeMulti { cgroup, runtimes_us } =>
+                cgroup::set_runtime_us_multi_str(&self.cgroup_name(*cgroup), runtimes_us),
+            FuzzOp::SetPeriodMulti { cgroup, periods_us } =>
+                cgroup::set_period_us_multi_str(&self.cgroup_name(*cgroup), periods_us),
+            FuzzOp::Spawn { task } => {
+                let mut tasks = self.tasks.write().unwrap();
+                let slot = tasks.get_mut(*task)
+                    .ok_or_else(|| anyhow::format_err!("Invalid task slot {task}"))?;
+
+                if slot.is_some() {
+                    anyhow::bail!("Task slot {task} is in use");
+                }
+
+                *slot = Some(run_yes()?);
+                Ok(())
+            },
+            FuzzOp::Exit { task } => {
+                let proc = self.tasks.write().unwrap()
+                    .get_mut(*task)
+                    .and_then(|proc| proc.take())
+                    .ok_or_else(|| anyhow::format_err!("Task slot {task} is empty"))?;
+
+                kill_task(proc)
+            },
+            FuzzOp::Migrate { task, cgroup } => {
+                let cgroup = cgroup.map_or(".".to_owned(), |cgroup| self.cgroup_name(cgroup));
+                self.with_task(*task, |pid| cgroup::assign_pid(&cgroup, pid))
+            },
+            FuzzOp::SetAffinity { task, cpus } => {
+                let cpu_set: CpuSet = __cpu_set_arg(cpus).parse()?;
+                self.with_task(*task, |pid| set_cpuset_to_pid(pid, &cpu_set))
+            },
+            FuzzOp::SetPolicy { task, policy } =>
+                self.with_task(*task, |pid| fs_backend().set_sched_policy(pid, policy.into(), SchedFlags::empty())),
+        }
+    }
+
+    /// Execute an operation, recording its outcome.
+    pub fn execute(&self, worker: usize, op: FuzzOp) -> anyhow::Result<()> {
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed);
+        let issued = Instant::now();
+        self.in_flight.lock().unwrap()[worker] = Some((seq, op.clone(), issued));
+
+        let result = self.apply(&op);
+        let duration = issued.elapsed();
+        self.in_flight.lock().unwrap()[worker] = None;
+
+        let mut outcomes = self.outcomes.lock().unwrap();
+        let (ok, failed) = outcomes.entry(op.kind()).or_insert((0, 0));
+        if result.is_ok() { *ok += 1; } else { *failed += 1; }
+        drop(outcomes);
+
+        let record = FuzzRecord {
+            seq,
+            t_us: (issued - self.start).as_micros() as u64,
+            duration_us: duration.as_micros() as u64,
+            worker,
+            op,
+            error: result.err().map(|err| format!("{err}")),
+        };
+
+        if let Some(log) = &self.log {
+            writeln!(log.lock().unwrap(), "{}", serde_json::to_string(&record)?)?;
+        }
+
+        if duration > self.op_timeout {
+            self.slow.lock().unwrap().push(record);
+        }
+
+        Ok(())
+    }
+
+    /// Operations in flight for longer than the timeout.
+    pub fn stuck(&self) -> Vec<(u64, FuzzOp, Duration)> {
+        self.in_flight.lock().unwrap().iter()
+            .flatten()
+            .filter(|(_, _, issued)| issued.elapsed() > self.op_timeout)
+            .map(|(seq, op, issued)| (*seq, op.clone(), issued.elapsed()))
+            .collect()
+    }
+
+    /// Kill the remaining tasks and destroy the cgroups, returning the errors.
+    pub fn cleanup(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+
+        let procs: Vec<_> = self.tasks.write().unwrap().iter_mut()
+            .filter_map(|proc| proc.take())
+            .collect();
+
+        for proc in procs {
+            if let Err(err) = kill_task(proc) {
+                errors.push(format!("{err}"));
+            }
+        }
+
+        for cgroup in (0..self.header.num_cgroups).map(|cgroup| self.cgroup_name(cgroup)) {
+            if cgroup::exists(&cgroup) && let Err(err) = cgroup::remove(&cgroup) {
+                errors.push(format!("{err}"));
+            }
+        }
+
+        errors
+    }
+
+    pub fn summary(&self) -> String {
+        let outcomes = self.outcomes.lock().unwrap();
+        let (ok, failed) = outcomes.values()
+            .fold((0, 0), |(ok, failed), (kind_ok, kind_failed)| (ok + kind_ok, failed + kind_failed));
+
+        let mut summary = format!("Executed {} operations ({ok} succeeded, {failed} rejected)", ok + failed);
+        for (kind, (ok, failed)) in outcomes.iter() {
+            summary += &format!("\n{kind:>18}: {ok:>7} ok, {failed:>7} rejected");
+        }
+
+        summary
+    }
+}
+
+fn kill_task(mut proc: HCBSProcess) -> anyhow::Result<()> {
+    if let HCBSProcess::Child(child) = &mut proc {
+        child.kill()?;
+        child.wait()?;
+    }
+
+    Ok(())
+}
+
+fn worker_loop(fuzzer: &Fuzzer, worker: usize, args: &MyArgs, cpus: &[CpuID], multicpu: bool, deadline: Option<Instant>, exit: &ExitFlag) -> anyhow::Result<()> {
+    let mut rng = rand::rngs::StdRng::seed_from_u64(args.seed.wrapping_add(worker as u64));
+
+    while !exit.is_exit() && deadline.is_none_or(|deadline| Instant::now() < deadline) {
+        fuzzer.execute(worker, FuzzOp::random(&mut rng, args, cpus, multicpu))?;
+        std::thread::sleep(Duration::from_millis(rng.random_range(0..=args.op_delay_ms)));
+    }
+
+    Ok(())
+}
+
+fn replay_loop(fuzzer: &Fuzzer, records: Vec<FuzzRecord>, pacing: bool, exit: &ExitFlag) -> anyhow::Result<()> {
+    let start = Instant::now();
+    let first_t_us = records.first().map_or(0, |record| record.t_us);
+
+    for record in records {
+        if exit.is_exit() {
+            break;
+        }
+
+        // keep the original pace between the replayed operations, which may
+        // be a subsequence of the log starting late into the run
+        if pacing {
+            let issue = start + Duration::from_micros(record.t_us.saturating_sub(first_t_us));
+            std::thread::sleep(issue.saturating_duration_since(Instant::now()));
+        }
+
+        fuzzer.execute(0, record.op)?;
+    }
+
+    Ok(())
+}
+
+/// Operations executed by a fuzzer's run.
+pub enum FuzzSource {
+    /// generated at random by the workers
+    Random(MyArgs),
+    /// replayed one at a time from a log, optionally keeping their timing
+    Replay { records: Vec<FuzzRecord>, pacing: bool },
+}
+
+/// Replay the given operations on a single worker. No log is written.
+pub fn replay(header: FuzzHeader, records: Vec<FuzzRecord>, op_timeout_ms: u64, pacing: bool, exit: ExitFlag) -> anyhow::Result<String> {
+    let header = FuzzHeader { num_workers: 1, ..header };
+
+    run_fuzzer(header, FuzzSource::Replay { records, pacing }, op_timeout_ms, None, None, exit)
+}
+
+/// Run the fuzzer and, if requested, minimize the log of a failed run.
+pub fn main(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<String> {
+    let exit = match ctrlc_flag {
+        Some(exit) => exit,
+        None => create_ctrlc_handler()?,
+    };
+
+    let (header, source, log) =
+        match &args.replay {
+            Some(replay) => {
+                let (header, records) = read_log(replay)?;
+                let header = FuzzHeader { num_workers: 1, ..header };
+
+                (header, FuzzSource::Replay { records, pacing: true }, args.log.clone())
+            },
+            None => {
+                let header = FuzzHeader {
+                    seed: args.seed,
+                    cgroup_prefix: args.cgroup_prefix.clone(),
+                    num_cgroups: args.num_cgroups,
+                    num_tasks: args.num_tasks,
+                    num_workers: args.num_workers,
+                };
+
+                let log = args.log.clone().unwrap_or_else(|| format!("/tmp/hcbs_fuzz_{}.jsonl", args.seed));
+                (header, FuzzSource::Random(args.clone()), Some(log))
+            },
+        };
+
+    let err =
+        match run_fuzzer(header, source, args.op_timeout_ms, args.max_time, log.clone(), exit.clone()) {
+            Ok(summary) => { return Ok(summary); },
+            Err(err) => err,
+        };
+
+    // minimize the replayed log, or else the one just written
+    let Some(log) = args.replay.or(log).filter(|_| args.minimize.minimize && !exit.is_exit()) else {
+        return Err(err);
+    };
+
+    let output = minimized_path(&log);
+    match ReplayLog::read(&log).and_then(|replay_log| replay_and_minimize(&replay_log, &output, &args.minimize, args.op_timeout_ms, &exit)) {
+        Ok(report) => Err(anyhow::format_err!("{err}\n{report}")),
+        Err(min_err) => Err(anyhow::format_err!("{err}\nCannot minimize the operation log: {min_err}")),
+    }
+}
+
+/// Run the worker threads (or the replay) until the end of the test, while
+/// checking for stuck operations. Workers which are stuck in the kernel are
+/// abandoned after the test's end.
+fn run_fuzzer(header: FuzzHeader, source: FuzzSource, op_timeout_ms: u64, max_time: Option<u64>, log: Option<String>, exit: ExitFlag) -> anyhow::Result<String> {
+    if header.num_cgroups == 0 || header.num_tasks == 0 {
+        anyhow::bail!("The fuzzer requires at least one cgroup and one task slot");
+    }
+
+    for cgroup in 0..header.num_cgroups {
+        if cgroup::exists(&format!("{}{cgroup}", header.cgroup_prefix)) {
+            anyhow::bail!("Cgroup {}{cgroup} already exists", header.cgroup_prefix);
+        }
+    }
+
+    let cpus: Vec<_> = CpuSet::all()?.iter().copied().collect();
+    let multicpu = is_multicpu_enabled()?;
+
+    let root_runtime_us = fs_backend().read_to_string(&format!("{}/cpu.rt_runtime_us", cgroup::CGROUP_ROOT))?;
+    let root_period_us = fs_backend().read_to_string(&format!("{}/cpu.rt_period_us", cgroup::CGROUP_ROOT))?;
+
+    assign_pid_to_cgroup(".", std::process::id())?;
+    set_sched_policy(std::process::id(), SchedPolicy::RR(99), SchedFlags::RESET_ON_FORK)?;
+
+    let fuzzer = Arc::new(Fuzzer::new(header.clone(), Duration::from_millis(op_timeout_ms), log.as_deref())?);
+    let deadline = max_time.map(|max_time| Instant::now() + Duration::from_secs(max_time));
+
+    let workers: Vec<_> =
+        match source {
+            FuzzSource::Replay { records, pacing } => {
+                let (fuzzer, exit) = (fuzzer.clone(), exit.clone());
+                vec![std::thread::spawn(move || replay_loop(&fuzzer, records, pacing, &exit))]
+            },
+            FuzzSource::Random(args) => (0..header.num_workers)
+                .map(|worker| {
+                    let (fuzzer, exit, args, cpus) = (fuzzer.clone(), exit.clone(), args.clone(), cpus.clone());
+                    std::thread::spawn(move || worker_loop(&fuzzer, worker, &args, &cpus, multicpu, deadline, &exit))
+                })
+                .collect(),
+        };
+    while !workers.iter().all(|worker| worker.is_finished()) {
+        let ended = exit.is_exit() || deadline.is_some_and(|deadline| Instant::now() > deadline);
+        let stuck = fuzzer.stuck();
+
+        if ended && stuck.iter().any(|(_, _, elapsed)| *elapsed > 2 * fuzzer.op_timeout) {
+            anyhow::bail!("Operations stuck in the kernel (seq, op, time): {stuck:?}\n{}", fuzzer.summary());
+        }
+
+        std::thread::sleep(Duration::from_millis(100));
+    }
+
+    for worker in workers {
+        worker.join()
+            .map_err(|_| anyhow::format_err!("Fuzzer worker panicked"))??;
+    }
+
+    let cleanup_errors = fuzzer.cleanup();
+    let mut summary = fuzzer.summary();
+    if let Some(log) = &log {
+        summary += &format!("\nOperation log: {log}");
+    }
+
+    let slow = fuzzer.slow.lock().unwrap();
+    let root_changed =
+        fs_backend().read_to_string(&format!("{}/cpu.rt_runtime_us", cgroup::CGROUP_ROOT))? != root_runtime_us ||
+        fs_backend().read_to_string(&format!("{}/cpu.rt_period_us", cgroup::CGROUP_ROOT))? != root_period_us;
+
+    if !slow.is_empty() {
+        let slow: Vec<_> = slow.iter().map(|record| (record.seq, &record.op, record.duration_us)).collect();
+        Err(anyhow::format_err!("Operations slower than {op_timeout_ms} ms (seq, op, us): {slow:?}\n{summary}"))
+    } else if !cleanup_errors.is_empty() {
+        Err(anyhow::format_err!("Cannot clean up the fuzzer's tasks and cgroups: {cleanup_errors:?}\n{summary}"))
+    } else if root_changed {
+        Err(anyhow::format_err!("The root cgroup's runtime or period changed during the run\n{summary}"))
+    } else {
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser as _;
+
+    #[test]
+    fn records_roundtrip() {
+        let ops = [
+            FuzzOp::CreateCgroup { cgroup: 1 },
+            FuzzOp::SetRuntimeMulti { cgroup: 0, runtimes_us: "10000 0 20000 1-2".to_owned() },
+            FuzzOp::Migrate { task: 3, cgroup: None },
+            FuzzOp::SetAffinity { task: 2, cpus: vec![0, 2] },
+            FuzzOp::SetPolicy { task: 1, policy: FuzzPolicy::Other { nice: -5 } },
+            FuzzOp::SetPolicy { task: 1, policy: FuzzPolicy::Idle },
+            FuzzOp::SetPolicy { task: 0, policy: FuzzPolicy::Rr { priority: 42 } },
+        ];
+
+        for (seq, op) in ops.into_iter().enumerate() {
+            let record = FuzzRecord {
+                seq: seq as u64, t_us: 1000 * seq as u64, duration_us: 10, worker: 0, op,
+                error: (seq % 2 == 0).then(|| "Invalid argument".to_owned()),
+            };
+
+            let line = serde_json::to_string(&record).unwrap();
+            let parsed: FuzzRecord = serde_json::from_str(&line).unwrap();
+            assert_eq!(parsed.op, record.op);
+            assert_eq!((parsed.seq, parsed.t_us, parsed.error), (record.seq, record.t_us, record.error));
+        }
+
+        let line = r#"{"seq":0,"t_us":0,"duration_us":1,"worker":0,"op":"set_policy","task":1,"policy":{"class":"fifo","priority":10},"error":null}"#;
+        let parsed: FuzzRecord = serde_json::from_str(line).unwrap();
+        assert_eq!(parsed.op, FuzzOp::SetPolicy { task: 1, policy: FuzzPolicy::Fifo { priority: 10 } });
+    }
+
+    #[test]
+    fn random_ops() {
+        let args = MyArgs::try_parse_from(["fuzz", "-g", "3", "-n", "5", "-p", "50"]).unwrap();
+        let cpus = [0, 1, 3];
+
+        let generate = |seed, multicpu| {
+            let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+            (0..500).map(|_| FuzzOp::random(&mut rng, &args, &cpus, multicpu)).collect::<Vec<_>>()
+        };
+
+        // the same seed gives the same operations
+        assert_eq!(generate(7, true), generate(7, true));
+
+        for op in generate(7, false) {
+            match &op {
+                FuzzOp::CreateCgroup { cgroup } | FuzzOp::DestroyCgroup { cgroup } => assert!(*cgroup < 3),
+                FuzzOp::SetRuntime { runtime_us: value_us, .. } | FuzzOp::SetPeriod { period_us: value_us, .. } =>
+                    assert!(*value_us <= 50_000),
+                FuzzOp::SetRuntimeMulti { .. } | FuzzOp::SetPeriodMulti { .. } =>
+                    panic!("Per-CPU operation without multicpu: {op:?}"),
+                FuzzOp::Spawn { task } | FuzzOp::Exit { task } => assert!(*task < 5),
+                FuzzOp::Migrate { task, cgroup } => assert!(*task < 5 && cgroup.is_none_or(|cgroup| cgroup < 3)),
+                FuzzOp::SetAffinity { cpus: op_cpus, .. } =>
+                    assert!(!op_cpus.is_empty() && op_cpus.iter().all(|cpu| cpus.contains(cpu))),
+                FuzzOp::SetPolicy { policy, .. } =>
+                    match policy {
+                        FuzzPolicy::Other { nice } | FuzzPolicy::Batch { nice } => assert!((-20..=19).contains(nice)),
+                        FuzzPolicy::Fifo { priority } | FuzzPolicy::Rr { priority } => assert!((1..=98).contains(priority)),
+                        FuzzPolicy::Idle => (),
+                    },
+            }
+        }
+
+        assert!(generate(7, true).iter().any(|op| matches!(op, FuzzOp::SetRuntimeMulti { .. })));
+    }
+}
@@ -1,6 +1,7 @@
-use hcbs_test_suite::prelude::*;
+use crate::prelude::*;
+use crate::tests::prelude::*;
 
-#[derive(clap::Parser, Debug)]
+#[derive(clap::Parser, Debug, Clone)]
 pub struct MyArgs {
     /// cgroup's name
     #[arg(short = 'c', long = "cgroup", default_value = "g0", value_name = "name")]
@@ -23,13 +24,17 @@ pub struct MyArgs {
     pub max_time: Option<u64>,
 }
 
+fn test_name(args: &MyArgs) -> String {
+    format!("migrate c{} r{} p{} P{:.2}",
+        args.cgroup, args.runtime_ms, args.period_ms, args.change_period)
+}
+
 pub fn batch_runner(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<()> {
     if is_batch_test() && args.max_time.is_none() {
         anyhow::bail!("Batch testing requires a maximum running time");
     }
 
-    let test_header = format!("migrate c{} r{} p{} P{:.2}",
-        args.cgroup, args.runtime_ms, args.period_ms, args.change_period);
+    let test_header = test_name(&args);
     let test_header =
         if is_batch_test() {
             test_header
@@ -38,13 +43,36 @@ pub fn batch_runner(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Resul
         };
 
     batch_test_header(&test_header, "stress");
+    if !batch_test_requires(&args.requirements())? {
+        return Ok(());
+    }
+
     batch_test_result(main(args, ctrlc_flag))?;
 
     Ok(())
 }
 
+impl HcbsTest for MyArgs {
+    fn name(&self) -> String {
+        test_name(self)
+    }
+
+    fn category(&self) -> &'static str {
+        "stress"
+    }
+
+    fn parameters(&self) -> String {
+        format!("-c {} -r {} -p {} -P {}{}",
+            self.cgroup, self.runtime_ms, self.period_ms, self.change_period, __opt_arg("-t", self.max_time))
+    }
+
+    fn run(&self, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<String>> {
+        main(self.clone(), ctrlc_flag).map(|_| Skippable::Result(String::new()))
+    }
+}
+
 pub fn main(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<()> {
-    let mut cgroup = HCBSCgroup::new(&args.cgroup)?
+    let mut cgroup = FsCgroup::new(&args.cgroup)?
         .with_force_kill(true);
     cgroup.set_period_us(args.period_ms * 1000)?;
     cgroup.set_runtime_us(args.runtime_ms * 1000)?;
@@ -0,0 +1,10 @@
+pub mod cgroup_make_destroy;
+pub mod change_cgroup_runtime;
+pub mod change_pinning;
+pub mod change_priority;
+pub mod churn;
+pub mod migrate;
+pub mod run_all;
+pub mod switch_class;
+pub mod fuzz;
+pub mod replay;
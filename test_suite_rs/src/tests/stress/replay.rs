@@ -0,0 +1,415 @@
+use std::io::{BufRead, Write};
+
+use crate::prelude::*;
+use crate::tests::prelude::*;
+use super::fuzz::{FuzzHeader, FuzzRecord};
+use super::run_all::{StressAllHeader, StressStep};
+
+#[derive(clap::Parser, Debug, Clone)]
+pub struct MyArgs {
+    /// log of `stress fuzz` or `stress all`, or a minimized one
+    #[arg(value_name = "path")]
+    pub log: String,
+
+    /// issue the fuzzer's operations back to back, instead of keeping their
+    /// original timing
+    #[arg(long = "no-pacing")]
+    pub no_pacing: bool,
+
+    /// fuzzer's operations taking longer than this are reported as stuck
+    #[arg(long = "op-timeout", default_value = "1000", value_name = "ms: u64")]
+    pub op_timeout_ms: u64,
+
+    #[command(flatten)]
+    pub minimize: MinimizeArgs,
+}
+
+/// Search for a minimal reproducer of a failed stress run.
+#[derive(clap::Args, Debug, Clone)]
+pub struct MinimizeArgs {
+    /// on failure, replay subsequences of the log to find a minimal one which
+    /// still fails, written next to the log as <log>.min.jsonl
+    #[arg(long = "minimize")]
+    pub minimize: bool,
+
+    /// a replay reproduces the failure if its error contains this string (by
+    /// default, if it fails with the same kind of error)
+    #[arg(long = "match", value_name = "string")]
+    pub matching: Option<String>,
+
+    /// maximum number of replays while minimizing
+    #[arg(long = "max-runs", default_value = "100", value_name = "#num")]
+    pub max_runs: usize,
+}
+
+impl Default for MinimizeArgs {
+    fn default() -> Self {
+        Self { minimize: false, matching: None, max_runs: 100 }
+    }
+}
+
+impl MinimizeArgs {
+    /// Command line arguments, as in `HcbsTest::parameters`.
+    pub fn parameters(&self) -> String {
+        let minimize = if self.minimize { " --minimize" } else { "" };
+
+        format!("{minimize}{} --max-runs {}", __opt_arg("--match", self.matching.as_ref()), self.max_runs)
+    }
+}
+
+fn test_name(args: &MyArgs) -> String {
+    format!("replay {}", args.log)
+}
+
+pub fn batch_runner(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<()> {
+    let test_header = test_name(&args);
+    let test_header =
+        if is_batch_test() {
+            test_header
+        } else {
+            test_header + " (Ctrl+C to stop)"
+        };
+
+    batch_test_header(&test_header, "stress");
+    if !batch_test_requires(&args.requirements())? {
+        return Ok(());
+    }
+
+    batch_test_result_details(main(args, ctrlc_flag))
+}
+
+impl HcbsTest for MyArgs {
+    fn name(&self) -> String {
+        test_name(self)
+    }
+
+    fn category(&self) -> &'static str {
+        "stress"
+    }
+
+    fn parameters(&self) -> String {
+        let no_pacing = if self.no_pacing { " --no-pacing" } else { "" };
+
+        format!("{}{no_pacing} --op-timeout {}{}", self.log, self.op_timeout_ms, self.minimize.parameters())
+    }
+
+    fn run(&self, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<String>> {
+        main(self.clone(), ctrlc_flag).map(Skippable::Result)
+    }
+}
+
+/// Read a JSON lines file made of a header followed by records.
+pub fn read_jsonl<H, R>(path: &str) -> anyhow::Result<(H, Vec<R>)>
+    where H: serde::de::DeserializeOwned, R: serde::de::DeserializeOwned
+{
+    let file = std::fs::File::open(path)
+        .map_err(|err| anyhow::format_err!("Error in opening {path}: {err}"))?;
+    let mut lines = std::io::BufReader::new(file).lines();
+
+    let header = lines.next()
+        .ok_or_else(|| anyhow::format_err!("Empty log {path}"))??;
+    let header = serde_json::from_str(&header)
+        .map_err(|err| anyhow::format_err!("Invalid log header in {path}: {err}"))?;
+
+    let records = lines
+        .map(|line| -> anyhow::Result<R> {
+            let line = line?;
+            serde_json::from_str(&line)
+                .map_err(|err| anyhow::format_err!("Invalid log record '{line}': {err}"))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    Ok((header, records))
+}
+
+/// Create a JSON lines log, writing its header. Records are flushed line by
+/// line, so that the log is complete up to the last record even if the run
+/// hangs or crashes.
+pub fn create_jsonl<H: serde::Serialize>(path: &str, header: &H) -> anyhow::Result<std::io::LineWriter<std::fs::File>> {
+    let file = std::fs::File::create(path)
+        .map_err(|err| anyhow::format_err!("Error in creating {path}: {err}"))?;
+    let mut log = std::io::LineWriter::new(file);
+    writeln!(log, "{}", serde_json::to_string(header)?)?;
+
+    Ok(log)
+}
+
+/// Path of the minimized version of the given log.
+pub fn minimized_path(log: &str) -> String {
+    format!("{}.min.jsonl", log.strip_suffix(".jsonl").unwrap_or(log))
+}
+
+/// A replayable log: the operations of a fuzzer's run, or the tests executed
+/// by `stress all`.
+#[derive(Debug, Clone)]
+pub enum ReplayLog {
+    Fuzz { header: FuzzHeader, records: Vec<FuzzRecord> },
+    StressAll { header: StressAllHeader, steps: Vec<StressStep> },
+}
+
+impl ReplayLog {
+    pub fn read(path: &str) -> anyhow::Result<Self> {
+        let (header, _) = read_jsonl::<serde_json::Value, serde::de::IgnoredAny>(path)?;
+
+        // only the fuzzer's logs have a cgroup prefix
+        if header.get("cgroup_prefix").is_some() {
+            let (header, records) = super::fuzz::read_log(path)?;
+            Ok(ReplayLog::Fuzz { header, records })
+        } else {
+            let (header, steps) = super::run_all::read_log(path)?;
+            Ok(ReplayLog::StressAll { header, steps })
+        }
+    }
+
+    pub fn write(&self, path: &str) -> anyhow::Result<()> {
+        let mut log =
+            match self {
+                ReplayLog::Fuzz { header, .. } => create_jsonl(path, header)?,
+                ReplayLog::StressAll { header, .. } => create_jsonl(path, header)?,
+            };
+
+        let records: Vec<_> =
+            match self {
+                ReplayLog::Fuzz { records, .. } =>
+                    records.iter().map(serde_json::to_string).collect::<Result<_, _>>()?,
+                ReplayLog::StressAll { steps, .. } =>
+                    steps.iter().map(serde_json::to_string).collect::<Result<_, _>>()?,
+            };
+
+        for record in records {
+            writeln!(log, "{record}")?;
+        }
+
+        Ok(())
+    }
+
+    /// Number of operations, or tests, in the log.
+    pub fn len(&self) -> usize {
+        match self {
+            ReplayLog::Fuzz { records, .. } => records.len(),
+            ReplayLog::StressAll { steps, .. } => steps.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The log restricted to the operations, or tests, at the given indices.
+    pub fn select(&self, indices: &[usize]) -> Self {
+        match self {
+            ReplayLog::Fuzz { header, records } => ReplayLog::Fuzz {
+                header: header.clone(),
+                records: indices.iter().map(|&k| records[k].clone()).collect(),
+            },
+            ReplayLog::StressAll { header, steps } => ReplayLog::StressAll {
+                header: header.clone(),
+                steps: indices.iter().map(|&k| steps[k].clone()).collect(),
+            },
+        }
+    }
+
+    /// Replay the log: the fuzzer's operations are issued one at a time in
+    /// their original order, the tests are run one after the other until the
+    /// first failure.
+    pub fn replay(&self, op_timeout_ms: u64, pacing: bool, exit: &ExitFlag) -> anyhow::Result<String> {
+        match self {
+            ReplayLog::Fuzz { header, records } =>
+                super::fuzz::replay(header.clone(), records.clone(), op_timeout_ms, pacing, exit.clone()),
+            ReplayLog::StressAll { steps, .. } =>
+                super::run_all::replay(steps, exit),
+        }
+    }
+}
+
+/// Delta debugging: find a 1-minimal subsequence of the items for which
+/// `fails` holds, assuming that it holds for the whole sequence. Chunks of
+/// the current sequence, and then their complements, are tried with
+/// increasing granularity. Stops after `max_runs` calls of `fails`, returning
+/// the smallest failing subsequence found so far.
+pub fn ddmin<T, F>(items: Vec<T>, max_runs: usize, mut fails: F) -> Vec<T>
+    where T: Clone, F: FnMut(&[T]) -> bool
+{
+    let mut items = items;
+    let mut granularity = 2;
+    let mut runs = 0;
+
+    while items.len() >= 2 && runs < max_runs {
+        let chunks: Vec<_> = items.chunks(items.len().div_ceil(granularity)).collect();
+
+        let mut reduced = None;
+        for chunk in chunks.iter() {
+            if runs >= max_runs {
+                break;
+            }
+
+            runs += 1;
+            if fails(chunk) {
+                reduced = Some((chunk.to_vec(), 2));
+                break;
+            }
+        }
+
+        // with two chunks, the complements are the chunks themselves
+        if reduced.is_none() && chunks.len() > 2 {
+            for k in 0..chunks.len() {
+                if runs >= max_runs {
+                    break;
+                }
+
+                let complement: Vec<_> = chunks.iter().enumerate()
+                    .filter(|(j, _)| *j != k)
+                    .flat_map(|(_, chunk)| chunk.iter().cloned())
+                    .collect();
+
+                runs += 1;
+                if fails(&complement) {
+                    reduced = Some((complement, usize::max(chunks.len() - 1, 2)));
+                    break;
+                }
+            }
+        }
+
+        match reduced {
+            Some((subsequence, next_granularity)) => {
+                items = subsequence;
+                granularity = next_granularity;
+            },
+            None if chunks.len() >= items.len() => break,
+            None => granularity = usize::min(2 * chunks.len(), items.len()),
+        }
+    }
+
+    items
+}
+
+/// Kind of a failure, used to tell whether a replay reproduces it: the error
+/// message up to its details, i.e. the first colon or line break.
+fn failure_kind(err: &str) -> &str {
+    err.split([':', '\n']).next().unwrap_or(err).trim()
+}
+
+/// Minimize a log whose replay failed with the given error, writing the
+/// smallest subsequence found which still reproduces the failure to
+/// `output`.
+pub fn minimize(log: &ReplayLog, failure: &str, output: &str, args: &MinimizeArgs, op_timeout_ms: u64, pacing: bool, exit: &ExitFlag) -> anyhow::Result<String> {
+    let reproduces = |err: &str|
+        match &args.matching {
+            Some(matching) => err.contains(matching),
+            None => failure_kind(err) == failure_kind(failure),
+        };
+
+    if !reproduces(failure) {
+        anyhow::bail!("The replay failed with a different error: {failure}");
+    }
+
+    let mut runs = 0;
+    let indices = ddmin((0..log.len()).collect(), args.max_runs, |indices| {
+        if exit.is_exit() {
+            return false;
+        }
+
+        runs += 1;
+        let failed = log.select(indices).replay(op_timeout_ms, pacing, exit)
+            .is_err_and(|err| reproduces(&format!("{err}")));
+        log::info!("Replay {runs}: {} of {} items, {}", indices.len(), log.len(), if failed { "failed" } else { "passed" });
+
+        failed
+    });
+
+    if exit.is_exit() {
+        anyhow::bail!("Minimization interrupted");
+    }
+
+    log.select(&indices).write(output)?;
+
+    Ok(format!("Minimized from {} to {} items in {runs} replays: {output}", log.len(), indices.len()))
+}
+
+/// Replay a failed run's log and, if the failure reproduces, minimize it.
+/// Used after a concurrent or long run, whose failure might not reproduce
+/// when replayed.
+pub fn replay_and_minimize(log: &ReplayLog, output: &str, args: &MinimizeArgs, op_timeout_ms: u64, exit: &ExitFlag) -> anyhow::Result<String> {
+    match log.replay(op_timeout_ms, true, exit) {
+        Ok(_) => Err(anyhow::format_err!("The failure does not reproduce when replaying the log")),
+        Err(err) => minimize(log, &format!("{err}"), output, args, op_timeout_ms, true, exit),
+    }
+}
+
+/// Replay the given log and, if requested, minimize it on failure.
+pub fn main(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<String> {
+    let exit = match ctrlc_flag {
+        Some(exit) => exit,
+        None => create_ctrlc_handler()?,
+    };
+
+    let log = ReplayLog::read(&args.log)?;
+    if log.is_empty() {
+        anyhow::bail!("Nothing to replay in {}", args.log);
+    }
+
+    let err =
+        match log.replay(args.op_timeout_ms, !args.no_pacing, &exit) {
+            Ok(result) => { return Ok(result); },
+            Err(err) => err,
+        };
+
+    if !args.minimize.minimize || exit.is_exit() {
+        return Err(err);
+    }
+
+    let output = minimized_path(&args.log);
+    match minimize(&log, &format!("{err}"), &output, &args.minimize, args.op_timeout_ms, !args.no_pacing, &exit) {
+        Ok(report) => Err(anyhow::format_err!("{err}\n{report}")),
+        Err(min_err) => Err(anyhow::format_err!("{err}\nCannot minimize the log: {min_err}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ddmin_finds_failing_pair() {
+        let items: Vec<u32> = (0..20).collect();
+        let mut runs = 0;
+
+        let minimal = ddmin(items, 1000, |items| {
+            runs += 1;
+            items.contains(&3) && items.contains(&17)
+        });
+
+        assert_eq!(minimal, vec![3, 17]);
+        assert!(runs < 100);
+    }
+
+    #[test]
+    fn ddmin_keeps_order_and_stops_at_max_runs() {
+        let items: Vec<u32> = (0..64).rev().collect();
+
+        let minimal = ddmin(items.clone(), 1000, |items| items.contains(&40) && items.contains(&2));
+        assert_eq!(minimal, vec![40, 2]);
+
+        // the first run already halves the sequence
+        let minimal = ddmin(items.clone(), 1, |items| items.contains(&40));
+        assert_eq!(minimal, items[..32].to_vec());
+
+        let minimal = ddmin(items.clone(), 0, |_| true);
+        assert_eq!(minimal, items);
+    }
+
+    #[test]
+    fn failure_kinds() {
+        assert_eq!(failure_kind("Operation 12 is stuck: set runtime of g0"), "Operation 12 is stuck");
+        assert_eq!(failure_kind("Cgroups left behind\ng0 g1"), "Cgroups left behind");
+        assert_eq!(failure_kind("  Timeout  "), "Timeout");
+        assert_ne!(failure_kind("Operation 12 is stuck: x"), failure_kind("Operation 13 is stuck: x"));
+    }
+
+    #[test]
+    fn minimized_paths() {
+        assert_eq!(minimized_path("/tmp/hcbs_fuzz_7.jsonl"), "/tmp/hcbs_fuzz_7.min.jsonl");
+        assert_eq!(minimized_path("/tmp/run.log"), "/tmp/run.log.min.jsonl");
+        assert_eq!(minimized_path(&minimized_path("a.jsonl")), "a.min.min.jsonl");
+    }
+}
@@ -0,0 +1,380 @@
+use std::io::Write;
+
+use crate::prelude::*;
+use crate::tests::prelude::*;
+use super::replay::{MinimizeArgs, ReplayLog, create_jsonl, minimized_path, read_jsonl, replay_and_minimize};
+use rand::*;
+
+#[derive(clap::Parser, Debug)]
+pub struct MyArgs {
+    /// cgroup's name
+    #[arg(short = 'c', long = "cgroup", default_value = "g0", value_name = "name")]
+    pub cgroup: String,
+
+    /// number of tests to run
+    #[arg(short = 'n', long = "num-tests", value_name = "u64", default_value = "60")]
+    pub num_tests: u64,
+
+    /// max running time per test
+    #[arg(short = 't', long = "max-time", value_name = "sec: u64", default_value = "60")]
+    pub max_time_per_test: u64,
+
+    /// RNG's seed; since version 2 of the log, which added task-churn and
+    /// seeded cgroup-setup tests, a seed generates different tests than in
+    /// older releases, whose runs can only be reproduced from their logs
+    #[arg(long = "seed", value_name = "u64", default_value = "42")]
+    pub seed: u64,
+
+    /// log of the executed tests (by default
+    /// /tmp/hcbs_stress_all_<seed>.jsonl), replayable with `stress replay`
+    #[arg(short = 'l', long = "log", value_name = "path")]
+    pub log: Option<String>,
+
+    #[command(flatten)]
+    pub minimize: MinimizeArgs,
+}
+
+/// Version of the mapping from `--seed` to the generated tests, bumped
+/// whenever a seed stops generating the same tests.
+pub const STRESS_ALL_LOG_VERSION: u32 = 2;
+
+/// First line of the log of `stress all`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct StressAllHeader {
+    /// STRESS_ALL_LOG_VERSION of the run
+    #[serde(default = "unversioned_log")]
+    pub version: u32,
+    pub seed: u64,
+    pub cgroup: String,
+    pub num_tests: u64,
+}
+
+/// Logs written before the version was recorded already used version 2.
+fn unversioned_log() -> u32 {
+    2
+}
+
+/// A test executed by `stress all`, as recorded in its log.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct StressStep {
+    pub step: u64,
+    /// the test's subcommand, e.g. task-migration
+    pub test: String,
+    /// the test's command line arguments, without the subcommand
+    pub args: String,
+    /// None if the test passed or was skipped
+    pub error: Option<String>,
+}
+
+impl StressStep {
+    fn new(step: u64, test: &str, args: &dyn HcbsTest) -> Self {
+        Self { step, test: test.to_owned(), args: args.parameters(), error: None }
+    }
+
+    /// Parse the recorded test and its arguments.
+    pub fn test(&self) -> anyhow::Result<Box<dyn HcbsTest>> {
+        use clap::Parser as _;
+
+        let argv = std::iter::once(self.test.as_str()).chain(self.args.split_whitespace());
+        let test: Box<dyn HcbsTest> =
+            match self.test.as_str() {
+                "cgroup-setup" => Box::new(super::cgroup_make_destroy::MyArgs::try_parse_from(argv)?),
+                "cgroup-runtime" => Box::new(super::change_cgroup_runtime::MyArgs::try_parse_from(argv)?),
+                "task-pinning" => Box::new(super::change_pinning::MyArgs::try_parse_from(argv)?),
+                "task-priority" => Box::new(super::change_priority::MyArgs::try_parse_from(argv)?),
+                "task-migration" => Box::new(super::migrate::MyArgs::try_parse_from(argv)?),
+                "task-sched-class" => Box::new(super::switch_class::MyArgs::try_parse_from(argv)?),
+                "task-churn" => Box::new(super::churn::MyArgs::try_parse_from(argv)?),
+                test => anyhow::bail!("Unknown stress test '{test}'"),
+            };
+
+        Ok(test)
+    }
+}
+
+/// Read the log of `stress all`.
+pub fn read_log(path: &str) -> anyhow::Result<(StressAllHeader, Vec<StressStep>)> {
+    read_jsonl(path)
+}
+
+/// Run the given tests one after the other, stopping at the first failure.
+/// If the kernel log's monitor is enabled, tests logging splats fail as in
+/// the original run.
+pub fn replay(steps: &[StressStep], exit: &ExitFlag) -> anyhow::Result<String> {
+    let kmsg_mode = kmsg_mode();
+
+    for step in steps {
+        if exit.is_exit() {
+            break;
+        }
+
+        log::info!("Step {}: {} {}", step.step, step.test, step.args);
+        let monitor = kmsg_mode.map(|_| crate::kmsg::KmsgMonitor::start()).transpose()?;
+        step.test()?.run(Some(exit.clone()))?;
+
+        if let (Some(mut monitor), Some(mode)) = (monitor, kmsg_mode) {
+            let report = monitor.collect()?;
+            let failures: Vec<_> = report.failures(mode).iter()
+                .map(|event| format!("{event}"))
+                .collect();
+
+            if !failures.is_empty() {
+                anyhow::bail!("Kernel log events during the test:\n{}", failures.join("\n"));
+            }
+        }
+    }
+
+    Ok(format!("Executed {} tests", steps.len()))
+}
+
+/// Run a test as its batch runner would, returning its error, if any,
+/// including the failures reported by the kernel log's monitor. Errors never
+/// stop the run, so that the failed steps are logged and minimized.
+fn run_step(test: &dyn HcbsTest, ctrlc_flag: &ExitFlag) -> Option<String> {
+    batch_test_header(&test.name(), test.category());
+    match batch_test_requires(&test.requirements()) {
+        Ok(true) => (),
+        Ok(false) => { return None; },
+        Err(err) => { return Some(format!("{err}")); },
+    }
+
+    let result = test.run(Some(ctrlc_flag.clone()));
+    let status =
+        match &result {
+            Ok(Skippable::Result(_)) => batch_test_success(),
+            Ok(Skippable::Skipped(err)) => batch_test_skipped(err),
+            Err(err) => batch_test_failure(err),
+        };
+
+    // passed tests are failed by the kernel log's monitor on splats
+    match result {
+        Err(err) => Some(format!("{err}")),
+        Ok(_) if status == TestStatus::Failure => Some("Kernel log events during the test".to_owned()),
+        Ok(_) => None,
+    }
+}
+
+#[derive(Debug)]
+enum TestType {
+    CgroupMakeDestroy,
+    ChangeCgroupRuntime,
+    ChangePinning,
+    ChangePriority,
+    Migrate,
+    SwitchClass,
+    TaskChurn,
+}
+
+impl rand::distr::Distribution<TestType> for rand::distr::StandardUniform {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> TestType {
+        match rng.random_range(0..=12) {
+            0 => TestType::CgroupMakeDestroy,
+            1..=2 => TestType::ChangeCgroupRuntime,
+            3..=4 => TestType::ChangePinning,
+            5..=6 => TestType::ChangePriority,
+            7..=8 => TestType::Migrate,
+            9..=10 => TestType::SwitchClass,
+            11..=12 => TestType::TaskChurn,
+            _ => panic!("unexpected"),
+        }
+    }
+}
+
+pub fn main(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<()> {
+    unsafe { set_batch_test(); }
+
+    let ctrlc_flag = match ctrlc_flag {
+        Some(exit) => exit,
+        None => create_ctrlc_handler()?,
+    };
+
+    let log_path = args.log.clone().unwrap_or_else(|| format!("/tmp/hcbs_stress_all_{}.jsonl", args.seed));
+    let header = StressAllHeader {
+        version: STRESS_ALL_LOG_VERSION,
+        seed: args.seed,
+        cgroup: args.cgroup.clone(),
+        num_tests: args.num_tests,
+    };
+    let mut log = create_jsonl(&log_path, &header)?;
+    let mut steps = Vec::new();
+
+    let mut rand = rand::rngs::StdRng::seed_from_u64(args.seed);
+    for step in 0..args.num_tests {
+        if ctrlc_flag.is_exit() {
+            break;
+        }
+
+        let test_type: TestType = rand.random();
+        let period_ms = rand.random_range(10..=20) * 10;
+        let runtime_max_ms = period_ms * 90 / 100;
+        let runtime_min_ms = 20;
+        let runtime_half_ms = (runtime_max_ms + runtime_min_ms) / 2;
+
+        let (name, test): (&str, Box<dyn HcbsTest>) =
+            match test_type {
+                TestType::CgroupMakeDestroy => {
+                    let _runtime_min_ms = rand.random_range(runtime_min_ms..=runtime_half_ms);
+                    let _runtime_max_ms = rand.random_range(runtime_half_ms..=runtime_max_ms);
+
+                    ("cgroup-setup", Box::new(super::cgroup_make_destroy::MyArgs {
+                        cgroup: args.cgroup.clone(),
+                        runtime_min_ms: _runtime_min_ms,
+                        runtime_max_ms: _runtime_max_ms,
+                        period_ms,
+                        seed: Some(rand.random()),
+                        max_time: Some(args.max_time_per_test),
+                    }))
+                },
+                TestType::ChangePinning => {
+                    let runtime_ms = rand.random_range(runtime_min_ms..runtime_max_ms);
+                    let change_period = rand.random_range(0.5f32..=3f32);
+
+                    ("task-pinning", Box::new(super::change_pinning::MyArgs {
+                        cgroup: args.cgroup.clone(),
+                        runtime_ms,
+                        period_ms,
+                        change_period,
+                        cpu_set1: "0,2".parse()?,
+                        cpu_set2: "1,3".parse()?,
+                        max_time: Some(args.max_time_per_test),
+                    }))
+                },
+                TestType::ChangePriority => {
+                    let runtime_ms = rand.random_range(runtime_min_ms..runtime_max_ms);
+                    let change_period = rand.random_range(0.5f32..=3f32);
+
+                    ("task-priority", Box::new(super::change_priority::MyArgs {
+                        cgroup: args.cgroup.clone(),
+                        runtime_ms,
+                        period_ms,
+                        change_period,
+                        max_time: Some(args.max_time_per_test),
+                    }))
+                },
+                TestType::ChangeCgroupRuntime => {
+                    let runtime1_ms = rand.random_range(runtime_min_ms..=runtime_half_ms);
+                    let runtime2_ms = rand.random_range(runtime_half_ms..=runtime_max_ms);
+                    let change_period = rand.random_range(0.5f32..=3f32);
+
+                    ("cgroup-runtime", Box::new(super::change_cgroup_runtime::MyArgs {
+                        cgroup: args.cgroup.clone(),
+                        runtime1_ms,
+                        runtime2_ms,
+                        period_ms,
+                        change_period,
+                        max_time: Some(args.max_time_per_test),
+                    }))
+                },
+                TestType::Migrate => {
+                    let runtime_ms = rand.random_range(runtime_min_ms..runtime_max_ms);
+                    let change_period = rand.random_range(0.5f32..=3f32);
+
+                    ("task-migration", Box::new(super::migrate::MyArgs {
+                        cgroup: args.cgroup.clone(),
+                        runtime_ms,
+                        period_ms,
+                        change_period,
+                        max_time: Some(args.max_time_per_test),
+                    }))
+                },
+                TestType::SwitchClass => {
+                    let runtime_ms = rand.random_range(runtime_min_ms..runtime_max_ms);
+                    let change_period = rand.random_range(0.5f32..=3f32);
+
+                    ("task-sched-class", Box::new(super::switch_class::MyArgs {
+                        cgroup: args.cgroup.clone(),
+                        runtime_ms,
+                        period_ms,
+                        change_period,
+                        max_time: Some(args.max_time_per_test),
+                    }))
+                },
+                TestType::TaskChurn => {
+                    let runtime_ms = rand.random_range(runtime_min_ms..runtime_max_ms);
+                    let mode = [
+                        super::churn::ChurnMode::Fork,
+                        super::churn::ChurnMode::Thread,
+                        super::churn::ChurnMode::Exec,
+                        super::churn::ChurnMode::Mixed,
+                    ][rand.random_range(0..4)];
+
+                    ("task-churn", Box::new(super::churn::MyArgs {
+                        cgroup: args.cgroup.clone(),
+                        runtime_ms,
+                        period_ms,
+                        cpu_set: None,
+                        mode,
+                        burst: rand.random_range(1..=64),
+                        burst_period_ms: rand.random_range(10..=200),
+                        task_time_us: rand.random_range(100..=5000),
+                        reset_on_fork: rand.random(),
+                        tolerance: 0.02,
+                        max_time: Some(args.max_time_per_test),
+                    }))
+                },
+            };
+
+        let mut step = StressStep::new(step, name, test.as_ref());
+        step.error = run_step(test.as_ref(), &ctrlc_flag);
+
+        writeln!(log, "{}", serde_json::to_string(&step)?)?;
+        steps.push(step);
+    }
+
+    // minimize the tests up to the first failure
+    let first_failure = steps.iter().position(|step| step.error.is_some());
+    if let Some(first_failure) = first_failure && args.minimize.minimize && !ctrlc_flag.is_exit() {
+        steps.truncate(first_failure + 1);
+
+        batch_test_header(&format!("minimize {log_path}"), "stress");
+        let replay_log = ReplayLog::StressAll { header, steps };
+        batch_test_result_details(replay_and_minimize(&replay_log, &minimized_path(&log_path), &args.minimize, 0, &ctrlc_flag))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn logged_steps_roundtrip() {
+        let tests: Vec<(&str, Box<dyn HcbsTest>, &str)> = vec![
+            ("cgroup-runtime", Box::new(super::super::change_cgroup_runtime::MyArgs {
+                cgroup: "g0".to_owned(), runtime1_ms: 30, runtime2_ms: 70, period_ms: 100,
+                change_period: 0.73429, max_time: Some(5),
+            }), "-c g0 -r 30 -R 70 -p 100 -P 0.73429 -t 5"),
+            ("task-migration", Box::new(super::super::migrate::MyArgs {
+                cgroup: "g0".to_owned(), runtime_ms: 30, period_ms: 100,
+                change_period: 2.9999998, max_time: Some(5),
+            }), "-c g0 -r 30 -p 100 -P 2.9999998 -t 5"),
+            ("task-priority", Box::new(super::super::change_priority::MyArgs {
+                cgroup: "g0".to_owned(), runtime_ms: 30, period_ms: 100,
+                change_period: 1.005, max_time: None,
+            }), "-c g0 -r 30 -p 100 -P 1.005"),
+            ("task-sched-class", Box::new(super::super::switch_class::MyArgs {
+                cgroup: "g0".to_owned(), runtime_ms: 30, period_ms: 100,
+                change_period: 0.5, max_time: Some(5),
+            }), "-c g0 -r 30 -p 100 -P 0.5 -t 5"),
+            ("cgroup-setup", Box::new(super::super::cgroup_make_destroy::MyArgs {
+                cgroup: "g0".to_owned(), runtime_min_ms: 20, runtime_max_ms: 80, period_ms: 100,
+                seed: Some(u64::MAX), max_time: Some(5),
+            }), "-c g0 -r 20 -R 80 -p 100 --seed 18446744073709551615 -t 5"),
+            ("task-churn", Box::new(super::super::churn::MyArgs {
+                cgroup: "g0".to_owned(), runtime_ms: 30, period_ms: 100, cpu_set: None,
+                mode: super::super::churn::ChurnMode::Exec, burst: 12, burst_period_ms: 50, task_time_us: 1234,
+                reset_on_fork: true, tolerance: 0.0125, max_time: Some(5),
+            }), "-c g0 -r 30 -p 100 -m exec -b 12 -P 50 --task-time 1234 --reset-on-fork --tolerance 0.0125 -t 5"),
+        ];
+
+        for (step, (name, test, args)) in tests.into_iter().enumerate() {
+            let step = StressStep::new(step as u64, name, test.as_ref());
+            assert_eq!(step.args, args);
+
+            // through the log's format
+            let step: StressStep = serde_json::from_str(&serde_json::to_string(&step).unwrap()).unwrap();
+            assert_eq!(step.test().unwrap().parameters(), test.parameters());
+        }
+    }
+}
@@ -0,0 +1,367 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::time::Duration;
+
+use crate::prelude::*;
+use crate::tests::prelude::*;
+use crate::fs_backend::cgroup;
+
+#[derive(clap::Parser, Debug, Clone)]
+pub struct MyArgs {
+    /// cgroup's name
+    #[arg(short = 'c', long = "cgroup", default_value = "g0", value_name = "name")]
+    pub cgroup: String,
+
+    /// cgroup's runtime, on the runtime cpus
+    #[arg(short = 'r', long = "runtime", value_name = "ms: u64")]
+    pub runtime_ms: u64,
+
+    /// cgroup's period
+    #[arg(short = 'p', long = "period", value_name = "ms: u64")]
+    pub period_ms: u64,
+
+    /// cpus on which the cgroup has runtime, zero on the others
+    #[arg(long = "runtime-cpus", value_parser = <CpuSetUnchecked as std::str::FromStr>::from_str)]
+    pub runtime_cpus: CpuSetUnchecked,
+
+    /// cgroup's cpuset.cpus
+    #[arg(long = "cpuset", value_parser = <CpuSetUnchecked as std::str::FromStr>::from_str)]
+    pub cpuset: CpuSetUnchecked,
+
+    /// cgroup's cpuset.cpus, written while the tasks run after the first
+    /// observation phase
+    #[arg(long = "new-cpuset", value_parser = <CpuSetUnchecked as std::str::FromStr>::from_str)]
+    pub new_cpuset: Option<CpuSetUnchecked>,
+
+    /// number of processes to spawn
+    #[arg(short = 'n', long = "num-tasks", default_value = "2", value_name = "#num")]
+    pub num_tasks: u64,
+
+    /// time after each cpuset change which is not observed
+    #[arg(long = "settle", default_value = "200", value_name = "ms: u64")]
+    pub settle_ms: u64,
+
+    /// maximum execution time allowed on cpus outside of the cpuset or
+    /// without runtime, in each phase
+    #[arg(long = "tolerance", default_value = "1000", value_name = "us: u64")]
+    pub tolerance_us: u64,
+
+    /// observation time of each phase
+    #[arg(short = 't', long = "max-time", value_name = "sec: u64")]
+    pub max_time: Option<u64>,
+
+    #[command(flatten)]
+    pub verdict: VerdictArgs,
+}
+
+fn test_name(args: &MyArgs) -> String {
+    format!("time cpuset c{} r{} p{} runtime{:?} cpuset{:?} new{:?} n{}",
+        args.cgroup, args.runtime_ms, args.period_ms, args.runtime_cpus, args.cpuset, args.new_cpuset, args.num_tasks)
+}
+
+pub fn batch_runner(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<()> {
+    batch_test_header(&test_name(&args), "time");
+    batch_test_param("cgroup", &args.cgroup);
+    batch_test_param("runtime_ms", args.runtime_ms);
+    batch_test_param("period_ms", args.period_ms);
+    batch_test_param("runtime_cpus", format!("{:?}", args.runtime_cpus));
+    batch_test_param("cpuset", format!("{:?}", args.cpuset));
+    batch_test_param("new_cpuset", format!("{:?}", args.new_cpuset));
+    batch_test_param("num_tasks", args.num_tasks);
+    batch_test_param("settle_ms", args.settle_ms);
+    batch_test_param("tolerance_us", args.tolerance_us);
+    batch_test_param("max_time", format!("{:?}", args.max_time));
+    args.verdict.batch_test_params();
+
+    if !batch_test_requires(&args.requirements())? {
+        return Ok(());
+    }
+
+    let result = run(args, ctrlc_flag);
+
+    if is_batch_test() {
+        batch_test_result_skippable(result)
+    } else {
+        batch_test_result_skippable_details(result)
+    }
+}
+
+/// Cpus on which the cgroup's tasks may run in each phase: the ones of the
+/// cpuset with runtime.
+fn allowed_cpus(args: &MyArgs) -> Vec<BTreeSet<CpuID>> {
+    let runtime_cpus: BTreeSet<_> = args.runtime_cpus.iter().copied().collect();
+
+    std::iter::once(&args.cpuset).chain(args.new_cpuset.iter())
+        .map(|cpuset| cpuset.iter().copied().filter(|cpu| runtime_cpus.contains(cpu)).collect())
+        .collect()
+}
+
+pub fn run(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<String>> {
+    if args.runtime_ms == 0 || args.runtime_ms > args.period_ms {
+        anyhow::bail!("The cgroup's runtime must be positive and must not exceed its period");
+    }
+
+    let bw = args.runtime_ms as f64 / args.period_ms as f64;
+    let expected_bws: Vec<_> = allowed_cpus(&args).iter()
+        .map(|allowed| u64::min(allowed.len() as u64, args.num_tasks) as f64 * bw)
+        .collect();
+    let tolerance_ns = args.tolerance_us * 1000;
+    let verdict_args = args.verdict.clone();
+
+    let trials =
+        match verdict_args.run_trials(ctrlc_flag, |ctrlc_flag| main(args.clone(), ctrlc_flag))? {
+            Skippable::Result(trials) => trials,
+            Skippable::Skipped(err) => { return Ok(Skippable::Skipped(err)); },
+        };
+
+    let measurements = expected_bws.iter().enumerate()
+        .map(|(phase, expected_bw)| Measurement::new(&format!("phase{phase}"), *expected_bw)
+            .with_samples(trials.iter().map(|phases| phases[phase].bandwidth)))
+        .collect();
+
+    let verdict = verdict_args.verdict(Tolerance::absolute(0.02), measurements);
+    verdict.batch_test_params();
+
+    let mut report = String::new();
+    for (trial, phases) in trials.iter().enumerate() {
+        for (phase, usage) in phases.iter().enumerate() {
+            report += &format!("\ntrial {trial} phase {phase}: effective cpuset {:?}\n{}", usage.effective_cpus, usage.table());
+        }
+    }
+
+    let wrong_effective: Vec<_> = trials.iter().flat_map(|phases| phases.iter().enumerate())
+        .filter(|(phase, usage)| {
+            let cpuset = if *phase == 0 { &args.cpuset } else { args.new_cpuset.as_ref().unwrap() };
+            usage.effective_cpus != cpuset.iter().copied().collect::<BTreeSet<_>>()
+        })
+        .map(|(phase, usage)| (phase, usage.effective_cpus.clone()))
+        .collect();
+
+    let wrong_affinity: Vec<_> = trials.iter().flat_map(|phases| phases.iter().enumerate())
+        .flat_map(|(phase, usage)| usage.wrong_affinity.iter().map(move |(pid, cpus)| (phase, *pid, cpus.clone())))
+        .collect();
+
+    let forbidden: Vec<_> = trials.iter().flat_map(|phases| phases.iter().enumerate())
+        .map(|(phase, usage)| (phase, usage.forbidden_ns()))
+        .filter(|(_, forbidden_ns)| *forbidden_ns > tolerance_ns)
+        .map(|(phase, forbidden_ns)| (phase, forbidden_ns as f64 / 1_000_000f64))
+        .collect();
+
+    if !wrong_effective.is_empty() {
+        Err(anyhow::format_err!("The cgroup's effective cpuset differs from the requested one (phase, cpus): {wrong_effective:?}{report}\n{verdict}"))
+    } else if !wrong_affinity.is_empty() {
+        Err(anyhow::format_err!("The cgroup's tasks are allowed on cpus outside of the cpuset (phase, pid, cpus): {wrong_affinity:?}{report}\n{verdict}"))
+    } else if !forbidden.is_empty() {
+        Err(anyhow::format_err!("The cgroup's tasks ran on cpus without runtime or outside of the cpuset (phase, ms): {forbidden:?}{report}\n{verdict}"))
+    } else if !verdict.passed() {
+        Err(anyhow::format_err!("The cgroup's tasks did not use the bandwidth of the cpuset's cpus with runtime{report}\n{verdict}"))
+    } else {
+        Ok(Skippable::Result(format!("The cgroup's tasks ran only on the cpuset's cpus with runtime.{report}\n{verdict}")))
+    }
+}
+
+impl HcbsTest for MyArgs {
+    fn name(&self) -> String {
+        test_name(self)
+    }
+
+    fn category(&self) -> &'static str {
+        "time"
+    }
+
+    fn parameters(&self) -> String {
+        let new_cpuset = self.new_cpuset.as_ref()
+            .map_or(String::new(), |cpuset| format!(" --new-cpuset {}", __cpu_set_arg(cpuset.iter())));
+
+        format!("-c {} -r {} -p {} --runtime-cpus {} --cpuset {}{} -n {} --settle {} --tolerance {}{}{}",
+            self.cgroup, self.runtime_ms, self.period_ms, __cpu_set_arg(self.runtime_cpus.iter()),
+            __cpu_set_arg(self.cpuset.iter()), new_cpuset, self.num_tasks, self.settle_ms, self.tolerance_us,
+            __opt_arg("-t", self.max_time), self.verdict.parameters())
+    }
+
+    fn requirements(&self) -> Vec<Capability> {
+        vec![Capability::RtGroup, Capability::MultiCpu, Capability::CpusetController]
+    }
+
+    fn run(&self, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<String>> {
+        run(self.clone(), ctrlc_flag)
+    }
+}
+
+/// Observation of the cgroup's tasks during a phase.
+#[derive(Debug, Clone)]
+pub struct PhaseUsage {
+    pub effective_cpus: BTreeSet<CpuID>,
+    /// cpus on which the tasks may run: the effective cpuset's cpus with
+    /// runtime
+    pub allowed_cpus: BTreeSet<CpuID>,
+    /// total bandwidth of the tasks
+    pub bandwidth: f64,
+    /// execution time of the tasks on each cpu, over the sampling intervals
+    /// in which they did not migrate
+    pub per_cpu_ns: BTreeMap<CpuID, u64>,
+    /// execution time over the sampling intervals in which the tasks migrated
+    pub migrating_ns: u64,
+    /// part of `migrating_ns` over migrations from or to cpus outside of the
+    /// allowed ones, which may have been spent on either cpu
+    pub migrating_forbidden_ns: u64,
+    /// tasks allowed on cpus outside of the effective cpuset
+    pub wrong_affinity: Vec<(Pid, Vec<CpuID>)>,
+}
+
+impl PhaseUsage {
+    /// Execution time on cpus outside of the allowed ones, including the one
+    /// of the migrations involving them.
+    pub fn forbidden_ns(&self) -> u64 {
+        self.per_cpu_ns.iter()
+            .filter(|(cpu, _)| !self.allowed_cpus.contains(cpu))
+            .map(|(_, exec_ns)| exec_ns)
+            .sum::<u64>() + self.migrating_forbidden_ns
+    }
+
+    fn table(&self) -> String {
+        let mut table = format!("{:>5} {:>8} {:>10}", "cpu", "allowed", "exec ms");
+        for (cpu, exec_ns) in self.per_cpu_ns.iter() {
+            table += &format!("\n{:>5} {:>8} {:>10.3}", cpu, self.allowed_cpus.contains(cpu), *exec_ns as f64 / 1_000_000f64);
+        }
+
+        table + &format!("\n{:>5} {:>8} {:>10.3}", "migr", "-", self.migrating_ns as f64 / 1_000_000f64)
+            + &format!("\n{:>5} {:>8} {:>10.3}", "migr", false, self.migrating_forbidden_ns as f64 / 1_000_000f64)
+    }
+}
+
+/// Last cpu and consumed CPU time of a task at a sampling instant.
+#[derive(Debug, Clone, Copy)]
+struct TaskSample {
+    cpu: CpuID,
+    exec_ns: u64,
+}
+
+fn sample_tasks(pids: &[Pid]) -> anyhow::Result<Vec<TaskSample>> {
+    pids.iter()
+        .map(|pid| Ok(TaskSample {
+            cpu: get_task_last_cpu(&format!("/proc/{pid}/stat"))?,
+            exec_ns: get_task_runtime_ns(&format!("/proc/{pid}/schedstat"))?,
+        }))
+        .collect()
+}
+
+/// Observe the cgroup's tasks for a phase, after letting them settle on the
+/// current cpuset: sample the cpu each task is on every millisecond, and
+/// account the CPU time consumed between two samples to that cpu if the task
+/// did not move in the meantime.
+fn observe_phase(args: &MyArgs, pids: &[Pid], ctrlc_flag: ExitFlag) -> anyhow::Result<PhaseUsage> {
+    std::thread::sleep(Duration::from_millis(args.settle_ms));
+
+    let effective_cpus: BTreeSet<_> = cgroup::get_cpuset_cpus_effective(&args.cgroup)?.into_iter().collect();
+    let runtime_cpus: BTreeSet<_> = args.runtime_cpus.iter().copied().collect();
+    let allowed_cpus: BTreeSet<_> = effective_cpus.intersection(&runtime_cpus).copied().collect();
+
+    let mut wrong_affinity = Vec::new();
+    for &pid in pids.iter() {
+        let cpus = get_task_allowed_cpus(pid)?;
+        if cpus.iter().any(|cpu| !effective_cpus.contains(cpu)) {
+            wrong_affinity.push((pid, cpus));
+        }
+    }
+
+    let mut per_cpu_ns = BTreeMap::new();
+    let mut migrating_ns = 0;
+    let mut migrating_forbidden_ns = 0;
+
+    let window = UsageWindow::start_processes(pids)?;
+    let mut last = sample_tasks(pids)?;
+    wait_loop_periodic_fn(0.001, args.max_time, Some(ctrlc_flag), || {
+        let now = sample_tasks(pids)?;
+
+        for (before, after) in last.iter().zip(now.iter()) {
+            let exec_ns = after.exec_ns.saturating_sub(before.exec_ns);
+            if before.cpu == after.cpu {
+                *per_cpu_ns.entry(after.cpu).or_insert(0) += exec_ns;
+            } else {
+                migrating_ns += exec_ns;
+                if !allowed_cpus.contains(&before.cpu) || !allowed_cpus.contains(&after.cpu) {
+                    migrating_forbidden_ns += exec_ns;
+                }
+            }
+        }
+
+        last = now;
+        Ok(())
+    })?;
+    let bandwidth = window.stop()?.bandwidth();
+
+    Ok(PhaseUsage { effective_cpus, allowed_cpus, bandwidth, per_cpu_ns, migrating_ns, migrating_forbidden_ns, wrong_affinity })
+}
+
+/// Run the tasks, unpinned, in a cgroup with runtime only on some cpus and
+/// with a (possibly different) cpuset, observing where they execute. When a
+/// new cpuset is given, it is written while the tasks run and the tasks are
+/// observed again.
+pub fn main(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<Vec<PhaseUsage>>> {
+    if args.max_time.is_none() {
+        anyhow::bail!("The observation requires a maximum running time");
+    }
+
+    // check if the cpus are available
+    let all_cpus: HashSet<_> = CpuSet::all()?.iter().copied().collect();
+    let used_cpus = args.runtime_cpus.iter()
+        .chain(args.cpuset.iter())
+        .chain(args.new_cpuset.iter().flat_map(|cpuset| cpuset.iter()));
+
+    for &cpu in used_cpus {
+        match CpuSet::single(cpu) {
+            Ok(_) => (),
+            Err(err @ CpuSetBuildError::UnavailableCPU(_)) =>
+                { return Ok(Skippable::Skipped(err.into())); },
+            Err(err) =>
+                { return Err(err.into()); },
+        }
+    }
+
+    let exit = match ctrlc_flag {
+        Some(exit) => exit,
+        None => create_ctrlc_handler()?,
+    };
+
+    // the cpuset controller is disabled again at the end, also on errors, if
+    // it was not enabled before. The guard is dropped after the cgroup.
+    let cpuset_controller = cgroup::ControllerGuard::enable(".", "cpuset")?;
+
+    let runtime_cpus: HashSet<_> = args.runtime_cpus.iter().copied().collect();
+    let other_cpus: HashSet<_> = all_cpus.difference(&runtime_cpus).copied().collect();
+    let mut cgroup_runtimes_us = HashMap::from([(args.runtime_ms * 1000, runtime_cpus)]);
+    if !other_cpus.is_empty() {
+        cgroup_runtimes_us.insert(0, other_cpus);
+    }
+
+    let mut cgroup = FsCgroup::new(&args.cgroup)?
+        .with_force_kill(true);
+    cgroup.set_period_us(args.period_ms * 1000)?;
+    cgroup.set_runtime_us_multi(cgroup_runtimes_us)?;
+    cgroup::set_cpuset_cpus(&args.cgroup, &__cpu_set_arg(args.cpuset.iter()))?;
+
+    let pids = (0..args.num_tasks)
+        .map(|_| -> anyhow::Result<Pid> {
+            let proc = cgroup.assign_process(run_yes()?).map_err(|(_, err)| err)?;
+            proc.set_sched_policy(SchedPolicy::RR(50), SchedFlags::empty())?;
+
+            Ok(proc.id())
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let mut phases = vec![observe_phase(&args, &pids, exit.clone())?];
+
+    if let Some(new_cpuset) = &args.new_cpuset && !exit.is_exit() {
+        cgroup::set_cpuset_cpus(&args.cgroup, &__cpu_set_arg(new_cpuset.iter()))?;
+        phases.push(observe_phase(&args, &pids, exit)?);
+    }
+
+    cgroup.force_destroy();
+    cpuset_controller.restore()?;
+
+    if phases.len() < allowed_cpus(&args).len() {
+        anyhow::bail!("The test was interrupted before the cpuset change");
+    }
+
+    Ok(Skippable::Result(phases))
+}
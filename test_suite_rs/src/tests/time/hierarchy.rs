@@ -0,0 +1,324 @@
+use std::str::FromStr;
+
+use crate::prelude::*;
+use crate::tests::prelude::*;
+use crate::fs_backend::cgroup;
+
+#[derive(clap::Parser, Debug, Clone)]
+pub struct MyArgs {
+    /// cgroup of the hierarchy, as path:runtime_ms/period_ms[:num_tasks]
+    /// (e.g. g0:60/100 g0/a:30/100:2 g0/b:20/100:1). Tasks can only be
+    /// placed in leaf cgroups.
+    #[arg(short = 'N', long = "node", value_name = "node", num_args = 1.., value_parser = HierarchyNode::from_str)]
+    pub nodes: Vec<HierarchyNode>,
+
+    /// read the hierarchy from a JSON file (a list of nodes, each with path,
+    /// runtime_ms, period_ms and optionally num_tasks), in addition to the
+    /// nodes given on the command line
+    #[arg(short = 'f', long = "file", value_name = "path")]
+    pub file: Option<String>,
+
+    /// tasks' allowed cpus
+    #[arg(long = "cpu-set", value_parser = <CpuSetUnchecked as std::str::FromStr>::from_str)]
+    pub cpu_set: Option<CpuSetUnchecked>,
+
+    /// max running time
+    #[arg(short = 't', long = "max-time", value_name = "sec: u64")]
+    pub max_time: Option<u64>,
+
+    #[command(flatten)]
+    pub verdict: VerdictArgs,
+}
+
+/// A cgroup of the hierarchy to build.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct HierarchyNode {
+    pub path: String,
+    pub runtime_ms: u64,
+    pub period_ms: u64,
+    #[serde(default)]
+    pub num_tasks: u64,
+}
+
+impl HierarchyNode {
+    pub fn bandwidth(&self) -> f64 {
+        self.runtime_ms as f64 / self.period_ms as f64
+    }
+
+    fn depth(&self) -> usize {
+        self.path.split('/').count()
+    }
+
+    fn is_child_of(&self, parent: &HierarchyNode) -> bool {
+        self.path.strip_prefix(&parent.path)
+            .is_some_and(|rest| rest.starts_with('/'))
+    }
+}
+
+impl FromStr for HierarchyNode {
+    type Err = anyhow::Error;
+
+    fn from_str(node: &str) -> Result<Self, Self::Err> {
+        let err = || anyhow::format_err!("Invalid node \'{node}\', expected path:runtime_ms/period_ms[:num_tasks]");
+
+        let mut fields = node.split(':');
+        let path = fields.next().filter(|path| !path.is_empty()).ok_or_else(err)?;
+        let (runtime_ms, period_ms) = fields.next().and_then(|bw| bw.split_once('/')).ok_or_else(err)?;
+        let num_tasks = fields.next().map_or(Ok(0), |num| num.parse::<u64>()).map_err(|_| err())?;
+
+        if fields.next().is_some() {
+            return Err(err());
+        }
+
+        Ok(Self {
+            path: path.trim_matches('/').to_owned(),
+            runtime_ms: runtime_ms.parse().map_err(|_| err())?,
+            period_ms: period_ms.parse().map_err(|_| err())?,
+            num_tasks,
+        })
+    }
+}
+
+impl std::fmt::Display for HierarchyNode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}/{}:{}", self.path, self.runtime_ms, self.period_ms, self.num_tasks)
+    }
+}
+
+/// All the nodes of the hierarchy, parents before children.
+fn load_nodes(args: &MyArgs) -> anyhow::Result<Vec<HierarchyNode>> {
+    let mut nodes = args.nodes.clone();
+
+    if let Some(file) = &args.file {
+        let data = std::fs::read_to_string(file)
+            .map_err(|err| anyhow::format_err!("Error on reading file {file}, reason {err}"))?;
+
+        let file_nodes: Vec<HierarchyNode> = serde_json::from_str(&data)
+            .map_err(|err| anyhow::format_err!("Hierarchy parser error: {err}"))?;
+
+        nodes.extend(file_nodes);
+    }
+
+    if nodes.is_empty() {
+        anyhow::bail!("Empty cgroup hierarchy");
+    }
+
+    nodes.sort_by_key(|node| node.depth());
+    for node in nodes.iter() {
+        let has_children = nodes.iter().any(|other| other.is_child_of(node));
+        if has_children && node.num_tasks > 0 {
+            anyhow::bail!("Cannot place tasks in non-leaf cgroup {}", node.path);
+        }
+    }
+
+    Ok(nodes)
+}
+
+fn test_name(args: &MyArgs) -> String {
+    let mut test_name = "time hierarchy".to_owned();
+    for node in args.nodes.iter() {
+        test_name += &format!(" N{node}");
+    }
+
+    if let Some(file) = &args.file {
+        test_name += &format!(" f{file}");
+    }
+
+    test_name + &format!(" set{:?}", args.cpu_set)
+}
+
+pub fn batch_runner(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<()> {
+    if is_batch_test() && args.max_time.is_none() {
+        anyhow::bail!("Batch testing requires a maximum running time");
+    }
+
+    let test_header = test_name(&args);
+    let test_header =
+        if is_batch_test() {
+            test_header
+        } else {
+            test_header + " (Ctrl+C to stop)"
+        };
+
+    batch_test_header(&test_header, "time");
+    batch_test_param("nodes", args.nodes.iter()
+        .map(|node| node.to_string())
+        .collect::<Vec<_>>().join(" "));
+    batch_test_param("file", format!("{:?}", args.file));
+    batch_test_param("cpu_set", format!("{:?}", args.cpu_set));
+    batch_test_param("max_time", format!("{:?}", args.max_time));
+    args.verdict.batch_test_params();
+
+    if !batch_test_requires(&args.requirements())? {
+        return Ok(());
+    }
+
+    let result = run(args, ctrlc_flag);
+
+    if is_batch_test() {
+        batch_test_result_skippable(result)
+    } else {
+        batch_test_result_skippable_details(result)
+    }
+}
+
+pub fn run(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<String>> {
+    let nodes = load_nodes(&args)?;
+    let num_cpus = args.cpu_set.as_ref()
+        .map_or(CpuSet::all()?.num_cpus(), |cpu_set| cpu_set.num_cpus());
+
+    // leaves get the minimum between their reservation and the number of
+    // their tasks, inner nodes the minimum between their reservation and the
+    // sum of their children.
+    let mut expected_bws = vec![0f64; nodes.len()];
+    for (i, node) in nodes.iter().enumerate().rev() {
+        let children_bw: f64 = nodes.iter().zip(expected_bws.iter())
+            .filter(|(other, _)| other.is_child_of(node) && other.depth() == node.depth() + 1)
+            .map(|(_, bw)| *bw)
+            .sum();
+
+        expected_bws[i] = f64::min(node.bandwidth() * num_cpus as f64, node.num_tasks as f64 + children_bw);
+    }
+
+    let verdict_args = args.verdict.clone();
+
+    verdict_args.run_trials(ctrlc_flag, |ctrlc_flag| main(args.clone(), ctrlc_flag))
+        .and_then(|trials| {
+            let trials =
+                match trials {
+                    Skippable::Result(trials) => trials,
+                    Skippable::Skipped(err) => { return Ok(Skippable::Skipped(err)); },
+                };
+
+            let measurements = nodes.iter().zip(expected_bws.iter()).enumerate()
+                .map(|(i, (node, expected_bw))|
+                    Measurement::new(&node.path, *expected_bw)
+                        .with_samples(trials.iter().map(|used_bws| used_bws[i])))
+                .collect();
+
+            let verdict = verdict_args.verdict(Tolerance::absolute(0.01), measurements);
+            verdict.batch_test_params();
+
+            let mut table = format!("{:<20} {:>10} {:>10} {:>10} {:>10}", "cgroup", "reserved", "expected", "used", "error");
+            for (node, result) in nodes.iter().zip(verdict.results.iter()) {
+                table += &format!("\n{:<20} {:>10.5} {:>10.5} {:>10.5} {:>+10.5}",
+                    node.path, node.bandwidth() * num_cpus as f64, result.expected, result.mean, result.error());
+            }
+
+            let failed: Vec<_> = verdict.failed().iter()
+                .map(|result| result.name.clone())
+                .collect();
+
+            if failed.is_empty() {
+                Ok(Skippable::Result(format!("All the cgroups used their expected bandwidth.\n{table}\n{verdict}")))
+            } else {
+                Err(anyhow::format_err!("Cgroups {failed:?} did not use their expected bandwidth\n{table}\n{verdict}"))
+            }
+        })
+}
+
+impl HcbsTest for MyArgs {
+    fn name(&self) -> String {
+        test_name(self)
+    }
+
+    fn category(&self) -> &'static str {
+        "time"
+    }
+
+    fn parameters(&self) -> String {
+        let nodes = self.nodes.iter()
+            .map(|node| format!(" -N {node}"))
+            .collect::<String>();
+        let cpu_set = self.cpu_set.as_ref()
+            .map_or(String::new(), |cpu_set| format!(" --cpu-set {}", __cpu_set_arg(cpu_set.iter())));
+
+        format!("{}{}{}{}{}",
+            nodes, __opt_arg("-f", self.file.as_ref()), cpu_set, __opt_arg("-t", self.max_time),
+            self.verdict.parameters())
+            .trim_start()
+            .to_owned()
+    }
+
+    fn run(&self, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<String>> {
+        run(self.clone(), ctrlc_flag)
+    }
+}
+
+/// Build the hierarchy, run the workloads in the leaves, and return the
+/// bandwidth used by each node of the hierarchy (in the order given by
+/// `load_nodes`). Leaves are measured on their tasks, inner nodes on their
+/// cgroup's usage, which accounts for all their descendants.
+pub fn main(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<Vec<f64>>> {
+    let nodes = load_nodes(&args)?;
+
+    // check if the cpu_set is valid
+    let cpu_set = args.cpu_set
+        .map(CpuSet::try_from)
+        .transpose();
+
+    let cpu_set =
+        match cpu_set {
+            Err(err @ CpuSetBuildError::UnavailableCPU(_)) =>
+                { return Ok(Skippable::Skipped(err.into())); },
+            Ok(cpu_set) => cpu_set,
+            Err(err) =>
+                { return Err(err.into()); },
+        };
+
+    assign_pid_to_cgroup(".", std::process::id())?;
+    set_sched_policy(std::process::id(), SchedPolicy::RR(99), SchedFlags::RESET_ON_FORK)?;
+
+    // build the hierarchy, parents first
+    let mut cgroups = Vec::new();
+    let mut node_procs = Vec::new();
+    for node in nodes.iter() {
+        let mut cgroup = FsCgroup::new(&node.path)?
+            .with_force_kill(true);
+        cgroup.set_period_us(node.period_ms * 1000)?;
+        cgroup.set_runtime_us(node.runtime_ms * 1000)?;
+
+        if nodes.iter().any(|other| other.is_child_of(node)) {
+            // enable the cpu controller for the children, it goes away with
+            // the cgroup itself
+            cgroup::set_controller_enabled(&node.path, "cpu", true)?;
+        }
+
+        cgroups.push(cgroup);
+        node_procs.push(Vec::new());
+    }
+
+    for (i, node) in nodes.iter().enumerate() {
+        for _ in 0..node.num_tasks {
+            let proc = cgroups[i].assign_process(run_yes()?).map_err(|(_, err)| err)?;
+            proc.set_sched_policy(SchedPolicy::RR(50), SchedFlags::empty())?;
+            if let Some(cpu_set) = &cpu_set {
+                proc.set_affinity(cpu_set.clone())?;
+            }
+
+            node_procs[i].push(proc.id());
+        }
+    }
+
+    let windows = nodes.iter().zip(node_procs.iter())
+        .map(|(node, procs)|
+            if nodes.iter().any(|other| other.is_child_of(node)) {
+                UsageWindow::start_cgroup(&node.path)
+            } else {
+                UsageWindow::start_processes(procs)
+            })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    wait_loop(args.max_time, ctrlc_flag)?;
+
+    let used_bws = windows.iter()
+        .map(|window| window.usage().map(|usage| usage.bandwidth()))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    // destroy children first
+    while let Some(cgroup) = cgroups.pop() {
+        cgroup.force_destroy();
+    }
+
+    Ok(Skippable::Result(used_bws))
+}
@@ -0,0 +1,8 @@
+pub mod unicpu;
+pub mod multicpu;
+pub mod hierarchy;
+pub mod siblings;
+pub mod rr_fairness;
+pub mod throttling;
+pub mod sampling;
+pub mod cpuset;
@@ -0,0 +1,347 @@
+use std::{collections::{BTreeMap, HashMap, HashSet}, str::FromStr};
+
+use crate::prelude::*;
+use crate::tests::prelude::*;
+use super::sampling::*;
+
+#[derive(clap::Parser, Debug, Clone)]
+pub struct MyArgs {
+    /// cgroup's name
+    #[arg(short = 'c', long = "cgroup", default_value = "g0", value_name = "name")]
+    pub cgroup: String,
+
+    /// cgroup's configuration
+    #[arg(short = 'C', long = "config", value_name = "config-tuple", num_args = 1.., value_parser= CgroupConfigSet::parse_config)]
+    pub config: Vec<CgroupConfigSet>,
+
+    /// number of processes to spawn
+    #[arg(short = 'n', long = "num-tasks", default_value= "1", value_name = "#num")]
+    pub num_tasks: u64,
+
+    /// verify each CPU's server individually, by running one probe task
+    /// pinned on each of the configured CPUs (instead of num-tasks tasks)
+    #[arg(long = "per-cpu")]
+    pub per_cpu: bool,
+
+    /// max running time
+    #[arg(short = 't', long = "max-time", value_name = "sec: u64")]
+    pub max_time: Option<u64>,
+
+    #[command(flatten)]
+    pub sampling: SamplingArgs,
+
+    #[command(flatten)]
+    pub verdict: VerdictArgs,
+}
+
+fn test_name(args: &MyArgs) -> String {
+    let mut test_name =
+        if args.per_cpu {
+            format!("time multi c{} per-cpu", args.cgroup)
+        } else {
+            format!("time multi c{} n{}", args.cgroup, args.num_tasks)
+        };
+
+    for CgroupConfigSet { runtime_ms, period_ms, cpu_set } in args.config.iter() {
+        test_name += &format!(" C{}/{}/{:?}", runtime_ms, period_ms, cpu_set);
+    }
+
+    test_name
+}
+
+pub fn batch_runner(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<()> {
+    if is_batch_test() && args.max_time.is_none() {
+        anyhow::bail!("Batch testing requires a maximum running time");
+    }
+
+    let test_header = test_name(&args);
+    let test_header =
+        if is_batch_test() {
+            test_header
+        } else {
+            test_header + " (Ctrl+C to stop)"
+        };
+
+    batch_test_header(&test_header, "time");
+    batch_test_param("cgroup", &args.cgroup);
+    batch_test_param("num_tasks", args.num_tasks);
+    batch_test_param("per_cpu", args.per_cpu);
+    batch_test_param("config", args.config.iter()
+        .map(|CgroupConfigSet { runtime_ms, period_ms, cpu_set }| format!("{}/{}/{:?}", runtime_ms, period_ms, cpu_set))
+        .collect::<Vec<_>>().join(" "));
+    batch_test_param("max_time", format!("{:?}", args.max_time));
+    batch_test_param("sample_window_ms", format!("{:?}", args.sampling.sample_window_ms));
+    args.verdict.batch_test_params();
+
+    if !batch_test_requires(&args.requirements())? {
+        return Ok(());
+    }
+
+    let result = run(args, ctrlc_flag);
+
+    if is_batch_test() {
+        batch_test_result_skippable(result)
+    } else {
+        batch_test_result_skippable_details(result)
+    }
+}
+
+pub fn run(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<String>> {
+    let single_cpu_bws = per_cpu_bandwidths(&args.config);
+
+    let total_cgroup_bw: f64 = single_cpu_bws.values().sum();
+    let max_expected_bw =
+        if args.per_cpu {
+            total_cgroup_bw
+        } else {
+            f64::min(total_cgroup_bw, args.num_tasks as f64)
+        };
+    let sampling = args.sampling.clone();
+    let verdict_args = args.verdict.clone();
+
+    verdict_args.run_trials(ctrlc_flag, |ctrlc_flag| main(args.clone(), ctrlc_flag))
+        .and_then(|trials| {
+            let trials =
+                match trials {
+                    Skippable::Result(trials) => trials,
+                    Skippable::Skipped(err) => { return Ok(Skippable::Skipped(err)); },
+                };
+
+            // the total bandwidth first, then the one of each CPU, if any
+            let mut measurements = vec![
+                Measurement::new("total", max_expected_bw)
+                    .with_samples(trials.iter().map(|usage| usage.total)),
+            ];
+
+            for (i, (cpu, _)) in trials[0].per_cpu.iter().enumerate() {
+                measurements.push(Measurement::new(&format!("cpu{cpu}"), single_cpu_bws[cpu])
+                    .with_samples(trials.iter().map(|usage| usage.per_cpu[i].1)));
+            }
+
+            let verdict = verdict_args.verdict(Tolerance::absolute(0.01), measurements);
+            verdict.batch_test_params();
+
+            let used_bw = verdict.results[0].mean;
+            let per_cpu: Vec<_> = trials[0].per_cpu.iter().zip(verdict.results[1..].iter())
+                .map(|((cpu, _), result)| (*cpu, result.mean))
+                .collect();
+
+            let table =
+                if per_cpu.is_empty() {
+                    String::new()
+                } else {
+                    format!("\n{}", per_cpu_table(&single_cpu_bws, &per_cpu))
+                };
+
+            let failed_cpus: Vec<_> = per_cpu.iter().zip(verdict.results[1..].iter())
+                .filter(|(_, result)| !result.passed())
+                .map(|((cpu, _), _)| *cpu)
+                .collect();
+
+            if !verdict.results[0].passed() {
+                return Err(anyhow::format_err!("Expected cgroup's task to use {:.2} units of runtime, but used {:.2}{table}\n{verdict}", max_expected_bw, used_bw));
+            } else if !failed_cpus.is_empty() {
+                return Err(anyhow::format_err!("Tasks on CPUs {failed_cpus:?} did not use their CPU's reserved bandwidth{table}\n{verdict}"));
+            }
+
+            let mut windows = String::new();
+            for trial_windows in trials.iter().filter_map(|usage| usage.windows.as_ref()) {
+                sampling.check(trial_windows, total_cgroup_bw)?;
+                windows += &format!(" {trial_windows}");
+            }
+
+            Ok(Skippable::Result(format!("Processes used an average of {used_bw:.5} units of CPU bandwidth.{windows}{table}\n{verdict}")))
+        })
+}
+
+/// Reserved bandwidth of each CPU of the given configuration. Later
+/// configurations override the earlier ones for the same CPU.
+fn per_cpu_bandwidths(config: &[CgroupConfigSet]) -> BTreeMap<CpuID, f64> {
+    let mut single_cpu_bws = BTreeMap::new();
+    for CgroupConfigSet { runtime_ms, period_ms, cpu_set } in config.iter() {
+        for cpu in cpu_set.iter() {
+            let bw = *runtime_ms as f64 / *period_ms as f64;
+            single_cpu_bws.insert(*cpu, bw);
+        }
+    }
+
+    single_cpu_bws
+}
+
+fn per_cpu_table(expected_bws: &BTreeMap<CpuID, f64>, used_bws: &[(CpuID, f64)]) -> String {
+    let mut table = format!("{:>5} {:>10} {:>10} {:>10}", "cpu", "reserved", "used", "error");
+    for (cpu, used_bw) in used_bws.iter() {
+        let expected_bw = expected_bws[cpu];
+
+        table += &format!("\n{:>5} {:>10.5} {:>10.5} {:>+10.5}", cpu, expected_bw, used_bw, used_bw - expected_bw);
+    }
+
+    table
+}
+
+/// Measured bandwidth, in total and, when running per-CPU probes, of each CPU,
+/// along with the per-window statistics when sampling.
+#[derive(Debug, Clone)]
+pub struct MultiCpuUsage {
+    pub total: f64,
+    pub per_cpu: Vec<(CpuID, f64)>,
+    pub windows: Option<WindowStats>,
+}
+
+impl HcbsTest for MyArgs {
+    fn name(&self) -> String {
+        test_name(self)
+    }
+
+    fn category(&self) -> &'static str {
+        "time"
+    }
+
+    fn parameters(&self) -> String {
+        let config = self.config.iter()
+            .map(|CgroupConfigSet { runtime_ms, period_ms, cpu_set }|
+                format!(" -C {}/{}/{}", runtime_ms, period_ms, __cpu_set_arg(cpu_set.iter())))
+            .collect::<String>();
+
+        let per_cpu = if self.per_cpu { " --per-cpu" } else { "" };
+
+        format!("-c {} -n {}{}{}{}{}{}",
+            self.cgroup, self.num_tasks, per_cpu, config, __opt_arg("-t", self.max_time),
+            self.sampling.parameters(), self.verdict.parameters())
+    }
+
+    fn requirements(&self) -> Vec<Capability> {
+        vec![Capability::RtGroup, Capability::MultiCpu]
+    }
+
+    fn run(&self, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<String>> {
+        run(self.clone(), ctrlc_flag)
+    }
+}
+
+pub fn main(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<MultiCpuUsage>> {
+    assert!(args.config.len() >= 1);
+
+    // check if the probes' CPUs are available
+    let mut probe_cpus = Vec::new();
+    if args.per_cpu {
+        for cpu in per_cpu_bandwidths(&args.config).into_keys() {
+            match CpuSet::single(cpu) {
+                Ok(cpu_set) => probe_cpus.push((cpu, cpu_set)),
+                Err(err @ CpuSetBuildError::UnavailableCPU(_)) =>
+                    { return Ok(Skippable::Skipped(err.into())); },
+                Err(err) =>
+                    { return Err(err.into()); },
+            }
+        }
+    }
+
+    // run the tasks
+    let mut cgroup_runtimes_us: HashMap<u64, HashSet<CpuID>> = HashMap::new();
+    let mut cgroup_periods_us: HashMap<u64, HashSet<CpuID>> = HashMap::new();
+
+    for CgroupConfigSet { runtime_ms, period_ms, cpu_set } in args.config.into_iter() {
+        let runtime_us = runtime_ms * 1000;
+        let period_us = period_ms * 1000;
+        let cpu_set: HashSet<_> = cpu_set.into_iter().collect();
+
+        for &cpu in cpu_set.iter() {
+            if let Err(err) = CpuSet::single(cpu) {
+                return Ok(Skippable::Skipped(err.into()));
+            }
+        }
+
+        if let Some(cpus) = cgroup_runtimes_us.get_mut(&runtime_us) {
+            *cpus = cpus.union(&cpu_set).map(|t| *t).collect();
+        } else {
+            cgroup_runtimes_us.insert(runtime_us, cpu_set.clone());
+        }
+
+        if let Some(cpus) = cgroup_periods_us.get_mut(&period_us) {
+            *cpus = cpus.union(&cpu_set).map(|t| *t).collect();
+        } else {
+            cgroup_periods_us.insert(period_us, cpu_set.clone());
+        }
+    }
+
+    let mut cgroup = FsCgroup::new(&args.cgroup)?
+        .with_force_kill(true);
+    cgroup.set_period_us_multi(cgroup_periods_us)?;
+    cgroup.set_runtime_us_multi(cgroup_runtimes_us)?;
+
+    cgroup.assign_process(HCBSProcess::SelfProc).map_err(|(_, err)| err)?
+        .set_sched_policy(SchedPolicy::RR(99), SchedFlags::RESET_ON_FORK)?;
+
+    let procs =
+        if args.per_cpu {
+            probe_cpus.iter()
+            .map(|(_, cpu_set)| -> anyhow::Result<Pid> {
+                let mut probe = run_yes()?;
+                probe.set_affinity(cpu_set.clone())?;
+
+                let proc = cgroup.assign_process(probe).map_err(|(_, err)| err)?;
+                proc.set_sched_policy(SchedPolicy::RR(50), SchedFlags::empty())?;
+
+                Ok(proc.id())
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?
+        } else {
+            (0..args.num_tasks)
+            .map(|_| -> anyhow::Result<Pid> {
+                let proc = cgroup.assign_process(run_yes()?).map_err(|(_, err)| err)?;
+                proc.set_sched_policy(SchedPolicy::RR(50), SchedFlags::empty())?;
+
+                Ok(proc.id())
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?
+        };
+
+    let window = UsageWindow::start_processes(&procs)?;
+    let windows = args.sampling.wait(&procs, args.max_time, ctrlc_flag)?;
+
+    let per_proc_usage = window.usage_per_source()?;
+    let total_usage = per_proc_usage.iter().map(|usage| usage.bandwidth()).sum();
+    let per_cpu_usage =
+        probe_cpus.iter().zip(per_proc_usage.iter())
+            .map(|((cpu, _), usage)| (*cpu, usage.bandwidth()))
+            .collect();
+
+    cgroup.force_destroy();
+
+    Ok(Skippable::Result(MultiCpuUsage { total: total_usage, per_cpu: per_cpu_usage, windows }))
+}
+
+#[derive(Debug, Clone)]
+pub struct CgroupConfigSet {
+    runtime_ms: u64,
+    period_ms: u64,
+    cpu_set: CpuSetUnchecked,
+}
+
+impl CgroupConfigSet {
+    pub fn parse_config(set: &str) -> anyhow::Result<Self> {
+        use nom::Parser;
+        use nom::bytes::complete::*;
+        use nom::multi::*;
+        use nom::character::complete::*;
+        use nom::combinator::*;
+
+        let uint = || {
+            digit1::<&str, ()>
+                .map_res(|num: &str| num.parse::<u64>())
+        };
+
+        let cpu_set = || {
+            recognize(many1(one_of("0123456789-,")))
+                .map_res(|str: &str| CpuSetUnchecked::from_str(str))
+        };
+
+        map(
+            ( uint(), tag("/"), uint(), tag("/"), cpu_set() ),
+            |(runtime_ms, _, period_ms, _, cpu_set)|
+                CgroupConfigSet { runtime_ms, period_ms, cpu_set }
+        ).parse(set)
+            .map(|(_, set)| set)
+            .map_err(|err| err.into())
+    }
+}
\ No newline at end of file
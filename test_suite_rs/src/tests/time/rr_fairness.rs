@@ -0,0 +1,296 @@
+use crate::prelude::*;
+use crate::tests::prelude::*;
+
+const SCHED_RR_TIMESLICE: &str = "/proc/sys/kernel/sched_rr_timeslice_ms";
+
+#[derive(clap::Parser, Debug, Clone)]
+pub struct MyArgs {
+    /// cgroup's name
+    #[arg(short = 'c', long = "cgroup", default_value = "g0", value_name = "name")]
+    pub cgroup: String,
+
+    /// cgroup's runtime
+    #[arg(short = 'r', long = "runtime", value_name = "ms: u64")]
+    pub runtime_ms: u64,
+
+    /// cgroup's period
+    #[arg(short = 'p', long = "period", value_name = "ms: u64")]
+    pub period_ms: u64,
+
+    /// number of equal priority SCHED_RR tasks on each cpu
+    #[arg(short = 'n', long = "num-tasks", default_value = "3", value_name = "#num")]
+    pub num_tasks: u64,
+
+    /// cpus on which the tasks are pinned
+    #[arg(long = "cpu-set", default_value = "0", value_parser = <CpuSetUnchecked as std::str::FromStr>::from_str)]
+    pub cpu_set: CpuSetUnchecked,
+
+    /// set sched_rr_timeslice_ms for the test (restoring it afterwards)
+    #[arg(long = "timeslice", value_name = "ms: u64")]
+    pub timeslice_ms: Option<u64>,
+
+    /// max running time
+    #[arg(short = 't', long = "max-time", value_name = "sec: u64")]
+    pub max_time: Option<u64>,
+
+    #[command(flatten)]
+    pub verdict: VerdictArgs,
+}
+
+fn test_name(args: &MyArgs) -> String {
+    format!("time rr_fairness c{} n{} r{} p{} set{:?} ts{:?}",
+        args.cgroup, args.num_tasks, args.runtime_ms, args.period_ms, args.cpu_set, args.timeslice_ms)
+}
+
+pub fn batch_runner(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<()> {
+    if is_batch_test() && args.max_time.is_none() {
+        anyhow::bail!("Batch testing requires a maximum running time");
+    }
+
+    let test_header = test_name(&args);
+    let test_header =
+        if is_batch_test() {
+            test_header
+        } else {
+            test_header + " (Ctrl+C to stop)"
+        };
+
+    batch_test_header(&test_header, "time");
+    batch_test_param("cgroup", &args.cgroup);
+    batch_test_param("runtime_ms", args.runtime_ms);
+    batch_test_param("period_ms", args.period_ms);
+    batch_test_param("num_tasks", args.num_tasks);
+    batch_test_param("cpu_set", format!("{:?}", args.cpu_set));
+    batch_test_param("timeslice_ms", format!("{:?}", args.timeslice_ms));
+    batch_test_param("max_time", format!("{:?}", args.max_time));
+    args.verdict.batch_test_params();
+
+    if !batch_test_requires(&args.requirements())? {
+        return Ok(());
+    }
+
+    let result = run(args, ctrlc_flag);
+
+    if is_batch_test() {
+        batch_test_result_skippable(result)
+    } else {
+        batch_test_result_skippable_details(result)
+    }
+}
+
+pub fn run(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<String>> {
+    if args.num_tasks < 2 {
+        anyhow::bail!("At least two tasks per cpu are required");
+    }
+
+    let cgroup_bw = args.runtime_ms as f64 / args.period_ms as f64;
+    let task_bw = cgroup_bw / args.num_tasks as f64;
+    let cpus: Vec<CpuID> = args.cpu_set.iter().copied().collect();
+    let num_tasks = args.num_tasks as usize;
+    let verdict_args = args.verdict.clone();
+
+    let trials =
+        match verdict_args.run_trials(ctrlc_flag, |ctrlc_flag| main(args.clone(), ctrlc_flag))? {
+            Skippable::Result(trials) => trials,
+            Skippable::Skipped(err) => { return Ok(Skippable::Skipped(err)); },
+        };
+
+    // each cpu's total first, then each of its tasks
+    let mut measurements = Vec::new();
+    for (i, cpu) in cpus.iter().enumerate() {
+        measurements.push(Measurement::new(&format!("cpu{cpu}.total"), cgroup_bw)
+            .with_samples(trials.iter().map(|trial| trial.tasks[i].iter().map(|task| task.used_bw).sum())));
+
+        for j in 0..num_tasks {
+            measurements.push(Measurement::new(&format!("cpu{cpu}.task{j}"), task_bw)
+                .with_samples(trials.iter().map(|trial| trial.tasks[i][j].used_bw)));
+        }
+    }
+
+    let verdict = verdict_args.verdict(Tolerance { abs: 0.005, rel: 0.1 }, measurements);
+    verdict.batch_test_params();
+
+    // the tasks are preempted when their time slice expires or when the
+    // cgroup is throttled, whichever comes first
+    let timeslice_ms = trials[0].timeslice_ms;
+    let expected_slice_ms = u64::min(timeslice_ms, args.runtime_ms) as f64;
+    let mut wrong_slices = Vec::new();
+    let mut wrong_intervals = Vec::new();
+    for trial in trials.iter() {
+        for (i, cpu) in cpus.iter().enumerate() {
+            for (j, task) in trial.tasks[i].iter().enumerate() {
+                if task.slice_ms < 0.5 * expected_slice_ms || task.slice_ms > 1.5 * expected_slice_ms {
+                    wrong_slices.push((*cpu, j, task.slice_ms));
+                }
+
+                if task.interval_ms != timeslice_ms {
+                    wrong_intervals.push((*cpu, j, task.interval_ms));
+                }
+            }
+        }
+    }
+
+    let table = fairness_table(&cpus, &trials[0]);
+
+    if !verdict.passed() {
+        let failed = verdict.failed().iter()
+            .map(|result| result.name.clone())
+            .collect::<Vec<_>>();
+
+        Err(anyhow::format_err!("The cgroup's bandwidth was not split evenly among its SCHED_RR tasks: {failed:?}\n{table}\n{verdict}"))
+    } else if !wrong_intervals.is_empty() {
+        Err(anyhow::format_err!("Tasks reported a round-robin interval different from sched_rr_timeslice_ms = {timeslice_ms} \
+            (cpu, task, interval_ms): {wrong_intervals:?}\n{table}\n{verdict}"))
+    } else if !wrong_slices.is_empty() {
+        Err(anyhow::format_err!("Tasks ran for slices far from the expected {expected_slice_ms:.0} ms \
+            (cpu, task, slice_ms): {wrong_slices:?}\n{table}\n{verdict}"))
+    } else {
+        Ok(Skippable::Result(format!("The cgroup's bandwidth was split evenly among its SCHED_RR tasks \
+            (time slice {timeslice_ms} ms).\n{table}\n{verdict}")))
+    }
+}
+
+/// Bandwidth and average time slice of each task (rows) on each cpu.
+fn fairness_table(cpus: &[CpuID], usage: &RrUsage) -> String {
+    let mut table = format!("{:>5} {:>5} {:>10} {:>10} {:>10}", "cpu", "task", "bandwidth", "slice_ms", "interval");
+
+    for (cpu, tasks) in cpus.iter().zip(usage.tasks.iter()) {
+        for (j, task) in tasks.iter().enumerate() {
+            table += &format!("\n{:>5} {:>5} {:>10.5} {:>10.3} {:>10}", cpu, j, task.used_bw, task.slice_ms, task.interval_ms);
+        }
+    }
+
+    table
+}
+
+impl HcbsTest for MyArgs {
+    fn name(&self) -> String {
+        test_name(self)
+    }
+
+    fn category(&self) -> &'static str {
+        "time"
+    }
+
+    fn parameters(&self) -> String {
+        format!("-c {} -n {} -r {} -p {} --cpu-set {}{}{}{}",
+            self.cgroup, self.num_tasks, self.runtime_ms, self.period_ms, __cpu_set_arg(self.cpu_set.iter()),
+            __opt_arg("--timeslice", self.timeslice_ms), __opt_arg("-t", self.max_time), self.verdict.parameters())
+    }
+
+    fn run(&self, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<String>> {
+        run(self.clone(), ctrlc_flag)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RrTaskUsage {
+    pub used_bw: f64,
+    /// average CPU time consumed between two preemptions
+    pub slice_ms: f64,
+    /// round-robin interval reported by sched_rr_get_interval
+    pub interval_ms: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct RrUsage {
+    /// effective sched_rr_timeslice_ms during the test
+    pub timeslice_ms: u64,
+    /// usage of each task (inner) on each cpu (outer, in the order of the cpu
+    /// set)
+    pub tasks: Vec<Vec<RrTaskUsage>>,
+}
+
+fn rr_interval_ms(pid: Pid) -> anyhow::Result<u64> {
+    let mut interval: libc::timespec = unsafe { std::mem::zeroed() };
+
+    if unsafe { libc::sched_rr_get_interval(pid as libc::pid_t, &mut interval) } != 0 {
+        anyhow::bail!("Error in reading the round-robin interval of pid {pid}: {}", std::io::Error::last_os_error());
+    }
+
+    Ok((interval.tv_sec as u64 * 1000) + (interval.tv_nsec as u64).div_ceil(1_000_000))
+}
+
+pub fn main(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<RrUsage>> {
+    // check if the cpus are available
+    let mut cpu_sets = Vec::new();
+    for cpu in args.cpu_set.iter() {
+        match CpuSet::single(*cpu) {
+            Ok(cpu_set) => cpu_sets.push(cpu_set),
+            Err(err @ CpuSetBuildError::UnavailableCPU(_)) =>
+                { return Ok(Skippable::Skipped(err.into())); },
+            Err(err) =>
+                { return Err(err.into()); },
+        }
+    }
+
+    // the time slice is restored at the end of the test
+    let snapshot = args.timeslice_ms.map(|_| SnapshotGuard::new()).transpose()?;
+    if let Some(timeslice_ms) = args.timeslice_ms {
+        fs_backend().write(SCHED_RR_TIMESLICE, &format!("{timeslice_ms}"))?;
+    }
+
+    // the kernel rounds the time slice to the scheduler's tick
+    let timeslice_ms: u64 = fs_backend().read_to_string(SCHED_RR_TIMESLICE)?.trim().parse()?;
+
+    let mut cgroup = FsCgroup::new(&args.cgroup)?
+        .with_force_kill(true);
+    cgroup.set_period_us(args.period_ms * 1000)?;
+    cgroup.set_runtime_us(args.runtime_ms * 1000)?;
+
+    cgroup.assign_process(HCBSProcess::SelfProc).map_err(|(_, err)| err)?
+        .set_sched_policy(SchedPolicy::RR(99), SchedFlags::RESET_ON_FORK)?;
+
+    let mut cpu_procs = Vec::new();
+    for cpu_set in cpu_sets.iter() {
+        let procs = (0..args.num_tasks)
+            .map(|_| -> anyhow::Result<Pid> {
+                let mut yes = run_yes()?;
+                yes.set_affinity(cpu_set.clone())?;
+
+                let proc = cgroup.assign_process(yes).map_err(|(_, err)| err)?;
+                proc.set_sched_policy(SchedPolicy::RR(50), SchedFlags::empty())?;
+
+                Ok(proc.id())
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        cpu_procs.push(procs);
+    }
+
+    let start_switches = cpu_procs.iter()
+        .map(|procs| procs.iter().map(|pid| get_nonvoluntary_switches(*pid)).collect())
+        .collect::<anyhow::Result<Vec<Vec<_>>>>()?;
+    let windows = cpu_procs.iter()
+        .map(|procs| UsageWindow::start_processes(procs))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    wait_loop(args.max_time, ctrlc_flag)?;
+
+    let mut tasks = Vec::new();
+    for ((procs, window), start_switches) in cpu_procs.iter().zip(windows.iter()).zip(start_switches.iter()) {
+        let usages = window.usage_per_source()?;
+
+        let cpu_tasks = procs.iter().zip(usages.iter()).zip(start_switches.iter())
+            .map(|((pid, usage), start_switches)| -> anyhow::Result<RrTaskUsage> {
+                let switches = get_nonvoluntary_switches(*pid)? - start_switches;
+
+                Ok(RrTaskUsage {
+                    used_bw: usage.bandwidth(),
+                    slice_ms: usage.usage_ns as f64 / 1_000_000f64 / switches.max(1) as f64,
+                    interval_ms: rr_interval_ms(*pid)?,
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        tasks.push(cpu_tasks);
+    }
+
+    cgroup.force_destroy();
+
+    if let Some(snapshot) = snapshot {
+        snapshot.restore()?;
+    }
+
+    Ok(Skippable::Result(RrUsage { timeslice_ms, tasks }))
+}
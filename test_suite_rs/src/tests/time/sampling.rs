@@ -0,0 +1,104 @@
+use crate::prelude::*;
+
+/// Optional time-windowed sampling of the consumed bandwidth.
+#[derive(clap::Args, Debug, Clone)]
+pub struct SamplingArgs {
+    /// sample the consumed bandwidth over consecutive windows of the given
+    /// length (preferably a multiple of the server's period)
+    #[arg(long = "sample-window", value_name = "ms: u64")]
+    pub sample_window_ms: Option<u64>,
+
+    /// maximum bandwidth above the reserved one allowed in any single window
+    #[arg(long = "window-tolerance", default_value = "0.05", value_name = "bw: f64")]
+    pub window_tolerance: f64,
+}
+
+impl Default for SamplingArgs {
+    fn default() -> Self {
+        Self { sample_window_ms: None, window_tolerance: 0.05 }
+    }
+}
+
+impl SamplingArgs {
+    /// Command line arguments, as in `HcbsTest::parameters`.
+    pub fn parameters(&self) -> String {
+        match self.sample_window_ms {
+            Some(window_ms) => format!(" --sample-window {window_ms} --window-tolerance {}", self.window_tolerance),
+            None => String::new(),
+        }
+    }
+
+    /// Wait for the end of the test, sampling the bandwidth consumed by the
+    /// given processes if requested.
+    pub fn wait(&self, pids: &[Pid], max_time: Option<u64>, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Option<WindowStats>> {
+        let Some(window_ms) = self.sample_window_ms else {
+            wait_loop(max_time, ctrlc_flag)?;
+            return Ok(None);
+        };
+
+        let window = UsageWindow::start_processes(pids)?;
+        let mut last = window.usage()?;
+        let mut samples = Vec::new();
+
+        wait_loop_periodic_fn(window_ms as f32 / 1000f32, max_time, ctrlc_flag, || {
+            let now = window.usage()?;
+
+            samples.push(Usage {
+                elapsed_ns: now.elapsed_ns - last.elapsed_ns,
+                usage_ns: now.usage_ns.saturating_sub(last.usage_ns),
+            }.bandwidth());
+
+            last = now;
+            Ok(())
+        })?;
+
+        Ok(WindowStats::new(samples))
+    }
+
+    /// Fail if any window exceeds the reserved bandwidth beyond tolerance.
+    pub fn check(&self, stats: &WindowStats, reserved_bw: f64) -> anyhow::Result<()> {
+        let (worst_window, worst_bw) = stats.worst;
+        if worst_bw > reserved_bw + self.window_tolerance {
+            anyhow::bail!("Window {worst_window} used {worst_bw:.5} units of CPU bandwidth, \
+                more than the reserved {reserved_bw:.5} (tolerance {}). {stats}", self.window_tolerance);
+        }
+
+        Ok(())
+    }
+}
+
+/// Statistics of the bandwidth consumed over consecutive windows.
+#[derive(Debug, Clone)]
+pub struct WindowStats {
+    pub samples: Vec<f64>,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub stddev: f64,
+    /// index and bandwidth of the window with the highest consumption
+    pub worst: (usize, f64),
+}
+
+impl WindowStats {
+    pub fn new(samples: Vec<f64>) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+
+        let num = samples.len() as f64;
+        let mean = samples.iter().sum::<f64>() / num;
+        let stddev = (samples.iter().map(|bw| (bw - mean).powi(2)).sum::<f64>() / num).sqrt();
+        let min = samples.iter().copied().fold(f64::INFINITY, f64::min);
+        let worst = samples.iter().copied().enumerate()
+            .fold((0, f64::NEG_INFINITY), |worst, (i, bw)| if bw > worst.1 { (i, bw) } else { worst });
+
+        Some(Self { min, max: worst.1, mean, stddev, worst, samples })
+    }
+}
+
+impl std::fmt::Display for WindowStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Windows: {}, min {:.5}, max {:.5}, mean {:.5}, stddev {:.5}, worst window {}.",
+            self.samples.len(), self.min, self.max, self.mean, self.stddev, self.worst.0)
+    }
+}
@@ -0,0 +1,282 @@
+use std::str::FromStr;
+
+use crate::prelude::*;
+use crate::tests::prelude::*;
+
+#[derive(clap::Parser, Debug, Clone)]
+pub struct MyArgs {
+    /// prefix of the sibling cgroups' names (e.g. g for g0, g1, ...)
+    #[arg(short = 'c', long = "cgroup-prefix", default_value = "g", value_name = "prefix")]
+    pub cgroup_prefix: String,
+
+    /// sibling cgroup's configuration, as runtime_ms/period_ms
+    /// (e.g. -G 20/100 30/70 10/45)
+    #[arg(short = 'G', long = "group", value_name = "runtime_ms/period_ms", num_args = 1.., value_parser = SiblingGroup::from_str)]
+    pub groups: Vec<SiblingGroup>,
+
+    /// cpus shared by the sibling cgroups
+    #[arg(long = "cpu-set", default_value = "0", value_parser = <CpuSetUnchecked as std::str::FromStr>::from_str)]
+    pub cpu_set: CpuSetUnchecked,
+
+    /// max running time
+    #[arg(short = 't', long = "max-time", value_name = "sec: u64")]
+    pub max_time: Option<u64>,
+
+    #[command(flatten)]
+    pub verdict: VerdictArgs,
+}
+
+/// Reservation of one of the sibling cgroups.
+#[derive(Debug, Clone, Copy)]
+pub struct SiblingGroup {
+    pub runtime_ms: u64,
+    pub period_ms: u64,
+}
+
+impl SiblingGroup {
+    pub fn bandwidth(&self) -> f64 {
+        self.runtime_ms as f64 / self.period_ms as f64
+    }
+}
+
+impl FromStr for SiblingGroup {
+    type Err = anyhow::Error;
+
+    fn from_str(group: &str) -> Result<Self, Self::Err> {
+        let err = || anyhow::format_err!("Invalid group \'{group}\', expected runtime_ms/period_ms");
+
+        let (runtime_ms, period_ms) = group.split_once('/').ok_or_else(err)?;
+
+        Ok(Self {
+            runtime_ms: runtime_ms.parse().map_err(|_| err())?,
+            period_ms: period_ms.parse().map_err(|_| err())?,
+        })
+    }
+}
+
+impl std::fmt::Display for SiblingGroup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.runtime_ms, self.period_ms)
+    }
+}
+
+fn cgroup_name(args: &MyArgs, group: usize) -> String {
+    format!("{}{}", args.cgroup_prefix, group)
+}
+
+fn test_name(args: &MyArgs) -> String {
+    let mut test_name = format!("time siblings c{}", args.cgroup_prefix);
+    for group in args.groups.iter() {
+        test_name += &format!(" G{group}");
+    }
+
+    test_name + &format!(" set{:?}", args.cpu_set)
+}
+
+pub fn batch_runner(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<()> {
+    if is_batch_test() && args.max_time.is_none() {
+        anyhow::bail!("Batch testing requires a maximum running time");
+    }
+
+    let test_header = test_name(&args);
+    let test_header =
+        if is_batch_test() {
+            test_header
+        } else {
+            test_header + " (Ctrl+C to stop)"
+        };
+
+    batch_test_header(&test_header, "time");
+    batch_test_param("cgroup_prefix", &args.cgroup_prefix);
+    batch_test_param("groups", args.groups.iter()
+        .map(|group| group.to_string())
+        .collect::<Vec<_>>().join(" "));
+    batch_test_param("cpu_set", format!("{:?}", args.cpu_set));
+    batch_test_param("max_time", format!("{:?}", args.max_time));
+    args.verdict.batch_test_params();
+
+    if !batch_test_requires(&args.requirements())? {
+        return Ok(());
+    }
+
+    let result = run(args, ctrlc_flag);
+
+    if is_batch_test() {
+        batch_test_result_skippable(result)
+    } else {
+        batch_test_result_skippable_details(result)
+    }
+}
+
+pub fn run(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<String>> {
+    if args.groups.len() < 2 {
+        anyhow::bail!("At least two sibling cgroups are required");
+    }
+
+    let groups = args.groups.clone();
+    let cpus: Vec<CpuID> = args.cpu_set.iter().copied().collect();
+    let verdict_args = args.verdict.clone();
+
+    verdict_args.run_trials(ctrlc_flag, |ctrlc_flag| main(args.clone(), ctrlc_flag))
+        .and_then(|trials| {
+            let trials =
+                match trials {
+                    Skippable::Result(trials) => trials,
+                    Skippable::Skipped(err) => { return Ok(Skippable::Skipped(err)); },
+                };
+
+            // one measurement per group and cpu, in row-major order
+            let mut measurements = Vec::new();
+            for (i, group) in groups.iter().enumerate() {
+                for (j, cpu) in cpus.iter().enumerate() {
+                    measurements.push(Measurement::new(&format!("group{i}.cpu{cpu}"), group.bandwidth())
+                        .with_samples(trials.iter().map(|used_bws| used_bws[i][j])));
+                }
+            }
+
+            let verdict = verdict_args.verdict(Tolerance::absolute(0.01), measurements);
+            verdict.batch_test_params();
+
+            let used_bws: Vec<Vec<f64>> = verdict.results.chunks(cpus.len())
+                .map(|group_results| group_results.iter().map(|result| result.mean).collect())
+                .collect();
+            let table = sibling_table(&groups, &cpus, &used_bws);
+
+            // a group using more than its reservation is stealing bandwidth
+            // from its siblings, one using less is being starved by them
+            let mut exceeded = Vec::new();
+            let mut starved = Vec::new();
+            for (k, result) in verdict.results.iter().enumerate() {
+                let (i, cpu) = (k / cpus.len(), cpus[k % cpus.len()]);
+                if result.passed() {
+                    continue;
+                } else if result.error() > 0f64 {
+                    exceeded.push((i, cpu));
+                } else {
+                    starved.push((i, cpu));
+                }
+            }
+
+            if !exceeded.is_empty() {
+                Err(anyhow::format_err!("Groups exceeded their reserved bandwidth (group, cpu): {exceeded:?}\n{table}\n{verdict}"))
+            } else if !starved.is_empty() {
+                Err(anyhow::format_err!("Groups did not receive their reserved bandwidth (group, cpu): {starved:?}\n{table}\n{verdict}"))
+            } else {
+                Ok(Skippable::Result(format!("All the sibling cgroups used their reserved bandwidth.\n{table}\n{verdict}")))
+            }
+        })
+}
+
+/// Matrix of the bandwidth used by each group (rows) on each CPU (columns),
+/// along with each group's reservation and total error.
+fn sibling_table(groups: &[SiblingGroup], cpus: &[CpuID], used_bws: &[Vec<f64>]) -> String {
+    let mut table = format!("{:>5} {:>10}", "group", "reserved");
+    for cpu in cpus.iter() {
+        table += &format!(" {:>10}", format!("cpu{cpu}"));
+    }
+    table += &format!(" {:>10}", "error");
+
+    for (i, (group, group_bws)) in groups.iter().zip(used_bws.iter()).enumerate() {
+        table += &format!("\n{:>5} {:>10.5}", i, group.bandwidth());
+        for used_bw in group_bws.iter() {
+            table += &format!(" {:>10.5}", used_bw);
+        }
+
+        let error: f64 = group_bws.iter().map(|used_bw| used_bw - group.bandwidth()).sum();
+        table += &format!(" {:>+10.5}", error);
+    }
+
+    table
+}
+
+impl HcbsTest for MyArgs {
+    fn name(&self) -> String {
+        test_name(self)
+    }
+
+    fn category(&self) -> &'static str {
+        "time"
+    }
+
+    fn parameters(&self) -> String {
+        let groups = self.groups.iter()
+            .map(|group| format!(" -G {group}"))
+            .collect::<String>();
+
+        format!("-c {}{} --cpu-set {}{}{}",
+            self.cgroup_prefix, groups, __cpu_set_arg(self.cpu_set.iter()), __opt_arg("-t", self.max_time),
+            self.verdict.parameters())
+    }
+
+    fn run(&self, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<String>> {
+        run(self.clone(), ctrlc_flag)
+    }
+}
+
+/// Run the sibling cgroups concurrently, with one task pinned on each of the
+/// shared CPUs for each group, and return the bandwidth used by each group
+/// (outer) on each CPU (inner, in the order of the cpu set).
+pub fn main(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<Vec<Vec<f64>>>> {
+    // check if the cpus are available
+    let mut cpu_sets = Vec::new();
+    for cpu in args.cpu_set.iter() {
+        match CpuSet::single(*cpu) {
+            Ok(cpu_set) => cpu_sets.push(cpu_set),
+            Err(err @ CpuSetBuildError::UnavailableCPU(_)) =>
+                { return Ok(Skippable::Skipped(err.into())); },
+            Err(err) =>
+                { return Err(err.into()); },
+        }
+    }
+
+    assign_pid_to_cgroup(".", std::process::id())?;
+    set_sched_policy(std::process::id(), SchedPolicy::RR(99), SchedFlags::RESET_ON_FORK)?;
+
+    let mut cgroups = Vec::new();
+    for (i, group) in args.groups.iter().enumerate() {
+        let mut cgroup = FsCgroup::new(&cgroup_name(&args, i))?
+            .with_force_kill(true);
+        cgroup.set_period_us(group.period_ms * 1000)?;
+        cgroup.set_runtime_us(group.runtime_ms * 1000)?;
+
+        cgroups.push(cgroup);
+    }
+
+    // saturate every group on every cpu
+    let mut group_procs = Vec::new();
+    for cgroup in cgroups.iter_mut() {
+        let procs = cpu_sets.iter()
+            .map(|cpu_set| -> anyhow::Result<Pid> {
+                let mut yes = run_yes()?;
+                yes.set_affinity(cpu_set.clone())?;
+
+                let proc = cgroup.assign_process(yes).map_err(|(_, err)| err)?;
+                proc.set_sched_policy(SchedPolicy::RR(50), SchedFlags::empty())?;
+
+                Ok(proc.id())
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        group_procs.push(procs);
+    }
+
+    let windows = group_procs.iter()
+        .map(|procs| UsageWindow::start_processes(procs))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    wait_loop(args.max_time, ctrlc_flag)?;
+
+    let used_bws = windows.iter()
+        .map(|window| -> anyhow::Result<Vec<f64>> {
+            Ok(window.usage_per_source()?.iter()
+                .map(|usage| usage.bandwidth())
+                .collect())
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    for cgroup in cgroups.into_iter() {
+        cgroup.force_destroy();
+    }
+
+    Ok(Skippable::Result(used_bws))
+}
@@ -0,0 +1,373 @@
+use std::process::{Command, Stdio};
+
+use crate::prelude::*;
+use crate::tests::prelude::*;
+
+#[derive(clap::Parser, Debug, Clone)]
+pub struct MyArgs {
+    /// cgroup's name
+    #[arg(short = 'c', long = "cgroup", default_value = "g0", value_name = "name")]
+    pub cgroup: String,
+
+    /// cgroup's runtime
+    #[arg(short = 'r', long = "runtime", value_name = "ms: u64")]
+    pub runtime_ms: u64,
+
+    /// cgroup's period
+    #[arg(short = 'p', long = "period", value_name = "ms: u64")]
+    pub period_ms: u64,
+
+    /// cpu on which the cgroup's task and the observer run
+    #[arg(long = "cpu", default_value = "0", value_name = "id")]
+    pub cpu: CpuID,
+
+    /// minimum length of the observer's gaps, shorter ones are ignored
+    #[arg(long = "threshold", default_value = "20", value_name = "us: u64")]
+    pub threshold_us: u64,
+
+    /// maximum error allowed on the execution time in each period and on the
+    /// replenishment instants
+    #[arg(long = "tolerance", default_value = "1000", value_name = "us: u64")]
+    pub tolerance_us: u64,
+
+    /// enable the HRTICK_DL scheduler feature during the test (restoring it
+    /// afterwards), for precise throttling
+    #[arg(long = "hrtick")]
+    pub hrtick: bool,
+
+    /// observation time
+    #[arg(short = 't', long = "max-time", value_name = "sec: u64")]
+    pub max_time: Option<u64>,
+}
+
+fn test_name(args: &MyArgs) -> String {
+    format!("time throttling c{} r{} p{} cpu{}", args.cgroup, args.runtime_ms, args.period_ms, args.cpu)
+}
+
+pub fn batch_runner(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<()> {
+    batch_test_header(&test_name(&args), "time");
+    batch_test_param("cgroup", &args.cgroup);
+    batch_test_param("runtime_ms", args.runtime_ms);
+    batch_test_param("period_ms", args.period_ms);
+    batch_test_param("cpu", args.cpu);
+    batch_test_param("threshold_us", args.threshold_us);
+    batch_test_param("tolerance_us", args.tolerance_us);
+    batch_test_param("hrtick", args.hrtick);
+    batch_test_param("max_time", format!("{:?}", args.max_time));
+
+    if !batch_test_requires(&args.requirements())? {
+        return Ok(());
+    }
+
+    let result = run(args, ctrlc_flag);
+
+    if is_batch_test() {
+        batch_test_result_skippable(result)
+    } else {
+        batch_test_result_skippable_details(result)
+    }
+}
+
+pub fn run(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<String>> {
+    if args.runtime_ms == 0 || args.runtime_ms >= args.period_ms {
+        anyhow::bail!("The cgroup's runtime must be positive and lower than its period");
+    }
+
+    let runtime_ns = args.runtime_ms * 1_000_000;
+    let period_ns = args.period_ms * 1_000_000;
+    let tolerance_ns = args.tolerance_us * 1000;
+
+    main(args, ctrlc_flag)
+        .and_then(|trace| {
+            let trace =
+                match trace {
+                    Skippable::Result(trace) => trace,
+                    Skippable::Skipped(err) => { return Ok(Skippable::Skipped(err)); },
+                };
+
+            let Some(periods) = reconstruct_periods(&trace, runtime_ns, period_ns) else {
+                anyhow::bail!("The cgroup's task never ran for a significant amount of time");
+            };
+
+            if periods.is_empty() {
+                anyhow::bail!("No complete server period was observed");
+            }
+
+            let overruns: Vec<_> = periods.iter().enumerate()
+                .filter(|(_, period)| period.exec_ns > runtime_ns + tolerance_ns)
+                .map(|(k, period)| (k, period.exec_ns as f64 / 1_000_000f64))
+                .collect();
+
+            let misaligned: Vec<_> = periods.iter().enumerate()
+                .filter(|(_, period)| period.offset_ns.is_none_or(|offset| offset.unsigned_abs() > tolerance_ns))
+                .map(|(k, period)| (k, period.offset_ns.map(|offset| offset as f64 / 1_000_000f64)))
+                .collect();
+
+            let max_exec_ns = periods.iter().map(|period| period.exec_ns).max().unwrap_or(0);
+            let avg_exec_ns = periods.iter().map(|period| period.exec_ns).sum::<u64>() / periods.len() as u64;
+            let max_offset_ns = periods.iter()
+                .filter_map(|period| period.offset_ns)
+                .map(|offset| offset.unsigned_abs())
+                .max().unwrap_or(0);
+
+            // the gaps should be the cgroup's execution only
+            let gaps_ns: u64 = trace.gaps.iter().map(|(start, end)| end - start).sum();
+
+            let report = format!("Observed {} periods: execution avg {:.3} ms, max {:.3} ms (runtime {} ms), \
+                max replenishment offset {:.3} ms. Gaps {:.3} s, cgroup's task usage {:.3} s.",
+                periods.len(), avg_exec_ns as f64 / 1_000_000f64, max_exec_ns as f64 / 1_000_000f64, runtime_ns / 1_000_000,
+                max_offset_ns as f64 / 1_000_000f64, gaps_ns as f64 / 1e9, trace.task_usage_ns as f64 / 1e9);
+
+            if !overruns.is_empty() {
+                Err(anyhow::format_err!("The cgroup ran for more than its runtime in some periods (period, ms): {overruns:?}\n{report}"))
+            } else if !misaligned.is_empty() {
+                Err(anyhow::format_err!("The cgroup was not replenished at the period boundaries (period, offset ms): {misaligned:?}\n{report}"))
+            } else {
+                Ok(Skippable::Result(report))
+            }
+        })
+}
+
+impl HcbsTest for MyArgs {
+    fn name(&self) -> String {
+        test_name(self)
+    }
+
+    fn category(&self) -> &'static str {
+        "time"
+    }
+
+    fn parameters(&self) -> String {
+        let hrtick = if self.hrtick { " --hrtick" } else { "" };
+
+        format!("-c {} -r {} -p {} --cpu {} --threshold {} --tolerance {}{}{}",
+            self.cgroup, self.runtime_ms, self.period_ms, self.cpu, self.threshold_us, self.tolerance_us,
+            hrtick, __opt_arg("-t", self.max_time))
+    }
+
+    fn requirements(&self) -> Vec<Capability> {
+        if self.hrtick {
+            vec![Capability::RtGroup, Capability::DebugFs, Capability::HrtickDl]
+        } else {
+            vec![Capability::RtGroup]
+        }
+    }
+
+    fn run(&self, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<String>> {
+        run(self.clone(), ctrlc_flag)
+    }
+}
+
+/// Intervals, in ns since the observer's start, in which the observer did
+/// not run, i.e. in which the cgroup's task was running.
+#[derive(Debug, Clone)]
+pub struct ObserverTrace {
+    pub observed_ns: u64,
+    pub gaps: Vec<(u64, u64)>,
+    /// CPU time consumed by the cgroup's task during the observation
+    pub task_usage_ns: u64,
+}
+
+/// Execution of the cgroup within a server period.
+#[derive(Debug, Clone)]
+pub struct PeriodExec {
+    pub exec_ns: u64,
+    /// start of the period's execution burst w.r.t. the period's start, if any
+    pub offset_ns: Option<i64>,
+}
+
+/// Split the trace in server periods, aligned to the first execution burst
+/// after two periods of warm-up, and compute the cgroup's execution in each
+/// of them. Bursts are gaps of at least a fourth of the runtime. Returns
+/// None if no burst was found.
+pub fn reconstruct_periods(trace: &ObserverTrace, runtime_ns: u64, period_ns: u64) -> Option<Vec<PeriodExec>> {
+    let min_burst_ns = runtime_ns / 4;
+    let is_burst = |(start, end): &(u64, u64)| end - start >= min_burst_ns;
+
+    let phase = trace.gaps.iter()
+        .find(|gap| gap.0 >= 2 * period_ns && is_burst(gap))?.0;
+
+    let mut periods = Vec::new();
+    let mut first = 0;
+    let mut period_start = phase;
+    while period_start + period_ns <= trace.observed_ns {
+        let period_end = period_start + period_ns;
+
+        // skip the gaps ending before the previous period's half
+        let half_before = period_start.saturating_sub(period_ns / 2);
+        while first < trace.gaps.len() && trace.gaps[first].1 <= half_before {
+            first += 1;
+        }
+
+        let mut exec_ns = 0;
+        let mut offset_ns = None;
+        for gap in trace.gaps[first..].iter().take_while(|gap| gap.0 < period_end) {
+            let (start, end) = (u64::max(gap.0, period_start), u64::min(gap.1, period_end));
+            if start < end {
+                exec_ns += end - start;
+            }
+
+            // the burst closest to the period's start
+            if offset_ns.is_none() && is_burst(gap) && gap.0 + period_ns / 2 >= period_start && gap.0 < period_start + period_ns / 2 {
+                offset_ns = Some(gap.0 as i64 - period_start as i64);
+            }
+        }
+
+        periods.push(PeriodExec { exec_ns, offset_ns });
+        period_start = period_end;
+    }
+
+    Some(periods)
+}
+
+/// Run a task inside the cgroup and a lower priority SCHED_FIFO observer in
+/// the root cgroup, on the same CPU. The observer runs only while the cgroup
+/// is throttled.
+pub fn main(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<ObserverTrace>> {
+    let Some(max_time) = args.max_time else {
+        anyhow::bail!("The observer requires a maximum running time");
+    };
+
+    let exit = match ctrlc_flag {
+        Some(exit) => exit,
+        None => create_ctrlc_handler()?,
+    };
+
+    // check if the cpu is available
+    let cpu_set =
+        match CpuSet::single(args.cpu) {
+            Ok(cpu_set) => cpu_set,
+            Err(err @ CpuSetBuildError::UnavailableCPU(_)) =>
+                { return Ok(Skippable::Skipped(err.into())); },
+            Err(err) =>
+                { return Err(err.into()); },
+        };
+
+    assign_pid_to_cgroup(".", std::process::id())?;
+    set_sched_policy(std::process::id(), SchedPolicy::RR(99), SchedFlags::RESET_ON_FORK)?;
+
+    // the scheduler features are restored at the end of the test
+    let snapshot = args.hrtick.then(SnapshotGuard::new).transpose()?;
+    if args.hrtick {
+        fs_backend().write("/sys/kernel/debug/sched/features", "HRTICK_DL")?;
+    }
+
+    let mut cgroup = FsCgroup::new(&args.cgroup)?
+        .with_force_kill(true);
+    cgroup.set_period_us(args.period_ms * 1000)?;
+    cgroup.set_runtime_us(args.runtime_ms * 1000)?;
+
+    let mut yes = run_yes()?;
+    yes.set_affinity(cpu_set.clone())?;
+    let yes = cgroup.assign_process(yes).map_err(|(_, err)| err)?;
+    yes.set_sched_policy(SchedPolicy::RR(50), SchedFlags::empty())?;
+    let yes_pid = yes.id();
+
+    let cmd = local_executable_cmd("/root/test_suite", "tools")?;
+    let mut observer = Command::new(cmd)
+        .args(["observer", "-d", &(max_time * 1000).to_string(), "--threshold", &args.threshold_us.to_string()])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    supervise_pid(observer.id());
+
+    // read the observer's output while it runs, so that it never blocks on a
+    // full pipe
+    let mut stdout = observer.stdout.take()
+        .ok_or_else(|| anyhow::format_err!("Missing observer output"))?;
+    let reader = std::thread::spawn(move || -> std::io::Result<String> {
+        let mut output = String::new();
+        std::io::Read::read_to_string(&mut stdout, &mut output)?;
+        Ok(output)
+    });
+
+    let window = UsageWindow::start_processes(&[yes_pid])?;
+    set_cpuset_to_pid(observer.id(), &cpu_set)?;
+    set_sched_policy(observer.id(), SchedPolicy::FIFO(1), SchedFlags::empty())?;
+
+    let status = loop {
+        if let Some(status) = observer.try_wait()? {
+            break status;
+        }
+
+        if exit.is_exit() {
+            let _ = observer.kill();
+            observer.wait()?;
+            anyhow::bail!("The test was stopped before the end of the observation");
+        }
+
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    };
+
+    let output = reader.join()
+        .map_err(|_| anyhow::format_err!("Error in reading the observer's output"))??;
+    let task_usage_ns = window.stop()?.usage_ns;
+
+    cgroup.force_destroy();
+
+    if let Some(snapshot) = snapshot {
+        snapshot.restore()?;
+    }
+
+    if !status.success() {
+        anyhow::bail!("The observer failed: {status}");
+    }
+
+    let mut lines = output.lines();
+    let observed_ns = lines.next()
+        .ok_or_else(|| anyhow::format_err!("Empty observer output"))?
+        .trim().parse()?;
+
+    let gaps = lines
+        .map(|line| -> anyhow::Result<(u64, u64)> {
+            let (start, end) = line.trim().split_once(' ')
+                .ok_or_else(|| anyhow::format_err!("Invalid observer output '{line}'"))?;
+
+            Ok((start.parse()?, end.parse()?))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    Ok(Skippable::Result(ObserverTrace { observed_ns, gaps, task_usage_ns }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: u64 = 1_000_000;
+
+    #[test]
+    fn periods_from_trace() {
+        // runtime 30 ms, period 100 ms: the cgroup is throttled in every period
+        // but the fifth, and replenished 5 ms late in the sixth
+        let trace = ObserverTrace {
+            observed_ns: 600 * MS,
+            gaps: [(0, 30), (100, 130), (200, 230), (300, 330), (350, 351), (400, 500), (505, 535)]
+                .into_iter()
+                .map(|(start, end)| (start * MS, end * MS))
+                .collect(),
+            task_usage_ns: 0,
+        };
+
+        // aligned to the first burst after two periods
+        let periods = reconstruct_periods(&trace, 30 * MS, 100 * MS).unwrap();
+        let periods: Vec<_> = periods.iter()
+            .map(|period| (period.exec_ns / MS, period.offset_ns.map(|offset| offset / MS as i64)))
+            .collect();
+
+        assert_eq!(periods, vec![(30, Some(0)), (31, Some(0)), (100, Some(0)), (30, Some(5))]);
+    }
+
+    #[test]
+    fn no_bursts_in_trace() {
+        // gaps shorter than a fourth of the runtime, or in the first two periods
+        let trace = ObserverTrace {
+            observed_ns: 600 * MS,
+            gaps: vec![(0, 30 * MS), (250 * MS, 251 * MS), (400 * MS, 405 * MS)],
+            task_usage_ns: 0,
+        };
+
+        assert!(reconstruct_periods(&trace, 30 * MS, 100 * MS).is_none());
+    }
+}
@@ -0,0 +1,185 @@
+use crate::prelude::*;
+use crate::tests::prelude::*;
+use super::sampling::*;
+
+#[derive(clap::Parser, Debug, Clone)]
+pub struct MyArgs {
+    /// cgroup's name
+    #[arg(short = 'c', long = "cgroup", default_value = "g0", value_name = "name")]
+    pub cgroup: String,
+
+    /// cgroup's runtime
+    #[arg(short = 'r', long = "runtime", value_name = "ms: u64")]
+    pub runtime_ms: u64,
+
+    /// cgroup's period
+    #[arg(short = 'p', long = "period", value_name = "ms: u64")]
+    pub period_ms: u64,
+
+    /// number of processes to spawn
+    #[arg(short = 'n', long = "num-tasks", default_value= "1", value_name = "#num")]
+    pub num_tasks: u64,
+
+    /// task's allowed cpus
+    #[arg(long = "cpu-set", value_parser = <CpuSetUnchecked as std::str::FromStr>::from_str)]
+    pub cpu_set: Option<CpuSetUnchecked>,
+
+    /// max running time
+    #[arg(short = 't', long = "max-time", value_name = "sec: u64")]
+    pub max_time: Option<u64>,
+
+    #[command(flatten)]
+    pub sampling: SamplingArgs,
+
+    #[command(flatten)]
+    pub verdict: VerdictArgs,
+}
+
+fn test_name(args: &MyArgs) -> String {
+    format!("time uni c{} n{} r{} p{} set{:?}",
+        args.cgroup, args.num_tasks, args.runtime_ms, args.period_ms, args.cpu_set)
+}
+
+pub fn batch_runner(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<()> {
+    if is_batch_test() && args.max_time.is_none() {
+        anyhow::bail!("Batch testing requires a maximum running time");
+    }
+
+    let test_header = test_name(&args);
+    let test_header =
+        if is_batch_test() {
+            test_header
+        } else {
+            test_header + " (Ctrl+C to stop)"
+        };
+
+    batch_test_header(&test_header, "time");
+    batch_test_param("cgroup", &args.cgroup);
+    batch_test_param("runtime_ms", args.runtime_ms);
+    batch_test_param("period_ms", args.period_ms);
+    batch_test_param("num_tasks", args.num_tasks);
+    batch_test_param("cpu_set", format!("{:?}", args.cpu_set));
+    batch_test_param("max_time", format!("{:?}", args.max_time));
+    batch_test_param("sample_window_ms", format!("{:?}", args.sampling.sample_window_ms));
+    args.verdict.batch_test_params();
+
+    if !batch_test_requires(&args.requirements())? {
+        return Ok(());
+    }
+
+    let result = run(args, ctrlc_flag);
+
+    if is_batch_test() {
+        batch_test_result_skippable(result)
+    } else {
+        batch_test_result_skippable_details(result)
+    }
+}
+
+pub fn run(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<String>> {
+    let single_bw = args.runtime_ms as f64 / args.period_ms as f64;
+    let num_cpus = args.cpu_set.as_ref()
+        .map_or(CpuSet::all()?.num_cpus(), |cpu_set| cpu_set.num_cpus());
+
+    let total_cgroup_bw = single_bw * num_cpus as f64;
+    let max_expected_bw = f64::min(total_cgroup_bw, args.num_tasks as f64);
+    let sampling = args.sampling.clone();
+    let verdict_args = args.verdict.clone();
+
+    verdict_args.run_trials(ctrlc_flag, |ctrlc_flag| main(args.clone(), ctrlc_flag))
+        .and_then(|trials| {
+            match trials {
+                Skippable::Result(trials) => {
+                    let verdict = verdict_args.verdict(Tolerance::absolute(0.01), vec![
+                        Measurement::new("bandwidth", max_expected_bw)
+                            .with_samples(trials.iter().map(|(used_bw, _)| *used_bw)),
+                    ]);
+                    verdict.batch_test_params();
+
+                    let used_bw = verdict.results[0].mean;
+                    if !verdict.passed() {
+                        anyhow::bail!("Expected cgroup's task to use {:.2} units of runtime, but used {:.2}\n{verdict}", max_expected_bw, used_bw);
+                    }
+
+                    let mut windows = String::new();
+                    for trial_windows in trials.iter().filter_map(|(_, windows)| windows.as_ref()) {
+                        sampling.check(trial_windows, total_cgroup_bw)?;
+                        windows += &format!(" {trial_windows}");
+                    }
+
+                    Ok(Skippable::Result(format!("Processes used an average of {used_bw:.5} units of CPU bandwidth.{windows}\n{verdict}")))
+                },
+                Skippable::Skipped(err) => Ok(Skippable::Skipped(err)),
+            }
+        })
+}
+
+impl HcbsTest for MyArgs {
+    fn name(&self) -> String {
+        test_name(self)
+    }
+
+    fn category(&self) -> &'static str {
+        "time"
+    }
+
+    fn parameters(&self) -> String {
+        let cpu_set = self.cpu_set.as_ref()
+            .map_or(String::new(), |cpu_set| format!(" --cpu-set {}", __cpu_set_arg(cpu_set.iter())));
+
+        format!("-c {} -n {} -r {} -p {}{}{}{}{}",
+            self.cgroup, self.num_tasks, self.runtime_ms, self.period_ms, cpu_set, __opt_arg("-t", self.max_time),
+            self.sampling.parameters(), self.verdict.parameters())
+    }
+
+    fn run(&self, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<String>> {
+        run(self.clone(), ctrlc_flag)
+    }
+}
+
+pub fn main(args: MyArgs, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<Skippable<(f64, Option<WindowStats>)>> {
+    // check if the cpu_set is valid
+    let cpu_set = args.cpu_set
+        .map(|cpu_set| cpu_set.try_into())
+        .transpose();
+
+    let cpu_set =
+        match cpu_set {
+            Err(err @ CpuSetBuildError::UnavailableCPU(_)) =>
+                { return Ok(Skippable::Skipped(err.into())); },
+            Ok(cpu_set) => cpu_set,
+            Err(err) =>
+                { return Err(err.into()); },
+        };
+
+    // run the tasks
+    let mut cgroup = FsCgroup::new(&args.cgroup)?
+        .with_force_kill(true);
+    cgroup.set_period_us(args.period_ms * 1000)?;
+    cgroup.set_runtime_us(args.runtime_ms * 1000)?;
+
+    cgroup.assign_process(HCBSProcess::SelfProc).map_err(|(_, err)| err)?
+        .set_sched_policy(SchedPolicy::RR(99), SchedFlags::RESET_ON_FORK)?;
+
+    let procs =
+        (0..args.num_tasks)
+        .map(|_| -> anyhow::Result<Pid> {
+            let proc = cgroup.assign_process(run_yes()?).map_err(|(_, err)| err)?;
+            proc.set_sched_policy(SchedPolicy::RR(50), SchedFlags::empty())?;
+            if cpu_set.is_some() {
+                proc.set_affinity(cpu_set.clone().unwrap())?;
+            }
+
+            Ok(proc.id())
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let window = UsageWindow::start_processes(&procs)?;
+    let windows = args.sampling.wait(&procs, args.max_time, ctrlc_flag)?;
+
+    let total_usage = window.stop()?.bandwidth();
+
+    cgroup.force_destroy();
+
+    Ok(Skippable::Result((total_usage, windows)))
+}
\ No newline at end of file
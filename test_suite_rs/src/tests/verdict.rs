@@ -0,0 +1,342 @@
+use crate::prelude::*;
+
+pub mod prelude {
+    pub use super::{
+        VerdictArgs,
+        Tolerance,
+        Confidence,
+        Measurement,
+        MeasurementResult,
+        Verdict,
+    };
+}
+
+/// Tolerances and repeated trials of the tests' pass/fail verdicts.
+#[derive(clap::Args, Debug, Clone)]
+pub struct VerdictArgs {
+    /// maximum absolute error allowed on the measured values (overrides the
+    /// test's default tolerance)
+    #[arg(long = "abs-tolerance", value_name = "f64")]
+    pub abs_tolerance: Option<f64>,
+
+    /// maximum error allowed on the measured values, relative to the
+    /// expected ones (e.g. 0.025 for 2.5%, overrides the test's default
+    /// tolerance)
+    #[arg(long = "rel-tolerance", value_name = "f64")]
+    pub rel_tolerance: Option<f64>,
+
+    /// number of repeated trials, the verdict is based on their mean
+    #[arg(long = "trials", default_value = "1", value_name = "#num")]
+    pub trials: u64,
+
+    /// confidence level of the mean's confidence interval
+    #[arg(long = "confidence", default_value = "95", value_enum)]
+    pub confidence: Confidence,
+}
+
+impl Default for VerdictArgs {
+    fn default() -> Self {
+        Self { abs_tolerance: None, rel_tolerance: None, trials: 1, confidence: Confidence::default() }
+    }
+}
+
+impl VerdictArgs {
+    /// Command line arguments, as in `HcbsTest::parameters`.
+    pub fn parameters(&self) -> String {
+        let mut parameters = String::new();
+        if let Some(abs_tolerance) = self.abs_tolerance {
+            parameters += &format!(" --abs-tolerance {abs_tolerance}");
+        }
+
+        if let Some(rel_tolerance) = self.rel_tolerance {
+            parameters += &format!(" --rel-tolerance {rel_tolerance}");
+        }
+
+        if self.trials != 1 {
+            parameters += &format!(" --trials {} --confidence {}", self.trials, self.confidence);
+        }
+
+        parameters
+    }
+
+    /// Attach the verdict's arguments to the current test.
+    pub fn batch_test_params(&self) {
+        batch_test_param("abs_tolerance", format!("{:?}", self.abs_tolerance));
+        batch_test_param("rel_tolerance", format!("{:?}", self.rel_tolerance));
+        batch_test_param("trials", self.trials);
+        batch_test_param("confidence", self.confidence);
+    }
+
+    /// The user given tolerance, if any, or the test's default one.
+    pub fn tolerance(&self, default: Tolerance) -> Tolerance {
+        if self.abs_tolerance.is_none() && self.rel_tolerance.is_none() {
+            return default;
+        }
+
+        Tolerance {
+            abs: self.abs_tolerance.unwrap_or(0f64),
+            rel: self.rel_tolerance.unwrap_or(0f64),
+        }
+    }
+
+    /// Run the given trial the requested number of times, stopping early on
+    /// Ctrl+C. The whole run is skipped if any of the trials is skipped.
+    pub fn run_trials<T, F>(&self, ctrlc_flag: Option<ExitFlag>, mut trial: F) -> anyhow::Result<Skippable<Vec<T>>>
+        where F: FnMut(Option<ExitFlag>) -> anyhow::Result<Skippable<T>>
+    {
+        if self.trials == 0 {
+            anyhow::bail!("At least one trial is required");
+        }
+
+        // a single handler shared by all the trials
+        let exit = match ctrlc_flag {
+            Some(exit) => exit,
+            None => create_ctrlc_handler()?,
+        };
+
+        let mut results = Vec::new();
+        for _ in 0..self.trials {
+            match trial(Some(exit.clone()))? {
+                Skippable::Result(result) => results.push(result),
+                Skippable::Skipped(err) => { return Ok(Skippable::Skipped(err)); },
+            }
+
+            if exit.is_exit() {
+                break;
+            }
+        }
+
+        Ok(Skippable::Result(results))
+    }
+
+    /// Compute the verdict on the given measurements.
+    pub fn verdict(&self, default_tolerance: Tolerance, measurements: Vec<Measurement>) -> Verdict {
+        let tolerance = self.tolerance(default_tolerance);
+
+        let results = measurements.into_iter()
+            .map(|measurement| MeasurementResult::new(measurement, tolerance, self.confidence))
+            .collect();
+
+        Verdict { tolerance, confidence: self.confidence, results }
+    }
+}
+
+/// Allowed error on a measured value: the largest between the absolute one
+/// and the relative one (w.r.t. the expected value).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tolerance {
+    pub abs: f64,
+    pub rel: f64,
+}
+
+impl Tolerance {
+    pub fn absolute(abs: f64) -> Self {
+        Self { abs, rel: 0f64 }
+    }
+
+    pub fn relative(rel: f64) -> Self {
+        Self { abs: 0f64, rel }
+    }
+
+    pub fn allowed_error(&self, expected: f64) -> f64 {
+        f64::max(self.abs, self.rel * expected.abs())
+    }
+}
+
+impl std::fmt::Display for Tolerance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.abs > 0f64, self.rel > 0f64) {
+            (true, true) => write!(f, "max({}, {:.2}%)", self.abs, self.rel * 100f64),
+            (false, true) => write!(f, "{:.2}%", self.rel * 100f64),
+            _ => write!(f, "{}", self.abs),
+        }
+    }
+}
+
+/// Confidence level of the confidence interval on the mean of the trials.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq)]
+pub enum Confidence {
+    #[value(name = "90")]
+    C90,
+    #[default]
+    #[value(name = "95")]
+    C95,
+    #[value(name = "99")]
+    C99,
+}
+
+impl Confidence {
+    /// Two-sided Student's t quantile for the given degrees of freedom.
+    fn t_quantile(&self, degrees: usize) -> f64 {
+        const T90: [f64; 30] = [
+            6.314, 2.920, 2.353, 2.132, 2.015, 1.943, 1.895, 1.860, 1.833, 1.812,
+            1.796, 1.782, 1.771, 1.761, 1.753, 1.746, 1.740, 1.734, 1.729, 1.725,
+            1.721, 1.717, 1.714, 1.711, 1.708, 1.706, 1.703, 1.701, 1.699, 1.697,
+        ];
+        const T95: [f64; 30] = [
+            12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228,
+            2.201, 2.179, 2.160, 2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086,
+            2.080, 2.074, 2.069, 2.064, 2.060, 2.056, 2.052, 2.048, 2.045, 2.042,
+        ];
+        const T99: [f64; 30] = [
+            63.657, 9.925, 5.841, 4.604, 4.032, 3.707, 3.499, 3.355, 3.250, 3.169,
+            3.106, 3.055, 3.012, 2.977, 2.947, 2.921, 2.898, 2.878, 2.861, 2.845,
+            2.831, 2.819, 2.807, 2.797, 2.787, 2.779, 2.771, 2.763, 2.756, 2.750,
+        ];
+
+        let (table, normal) =
+            match self {
+                Confidence::C90 => (&T90, 1.645),
+                Confidence::C95 => (&T95, 1.960),
+                Confidence::C99 => (&T99, 2.576),
+            };
+
+        match degrees {
+            0 => 0f64,
+            1..=30 => table[degrees - 1],
+            _ => normal,
+        }
+    }
+}
+
+impl std::fmt::Display for Confidence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Confidence::C90 => write!(f, "90"),
+            Confidence::C95 => write!(f, "95"),
+            Confidence::C99 => write!(f, "99"),
+        }
+    }
+}
+
+/// A measured quantity, with one sample per trial.
+#[derive(Debug, Clone)]
+pub struct Measurement {
+    pub name: String,
+    pub expected: f64,
+    pub samples: Vec<f64>,
+}
+
+impl Measurement {
+    pub fn new(name: &str, expected: f64) -> Self {
+        Self { name: name.to_owned(), expected, samples: Vec::new() }
+    }
+
+    pub fn with_samples<I: IntoIterator<Item = f64>>(mut self, samples: I) -> Self {
+        self.samples.extend(samples);
+        self
+    }
+}
+
+/// Statistics of a measured quantity over the trials.
+#[derive(Debug, Clone)]
+pub struct MeasurementResult {
+    pub name: String,
+    pub expected: f64,
+    pub allowed_error: f64,
+    pub samples: Vec<f64>,
+    pub mean: f64,
+    /// half-width of the mean's confidence interval
+    pub ci: f64,
+}
+
+impl MeasurementResult {
+    fn new(measurement: Measurement, tolerance: Tolerance, confidence: Confidence) -> Self {
+        let Measurement { name, expected, samples } = measurement;
+        let num = samples.len();
+
+        let mean = samples.iter().sum::<f64>() / num.max(1) as f64;
+        let ci =
+            if num > 1 {
+                let variance = samples.iter().map(|sample| (sample - mean).powi(2)).sum::<f64>() / (num - 1) as f64;
+                confidence.t_quantile(num - 1) * (variance / num as f64).sqrt()
+            } else {
+                0f64
+            };
+
+        Self { name, expected, allowed_error: tolerance.allowed_error(expected), samples, mean, ci }
+    }
+
+    pub fn error(&self) -> f64 {
+        self.mean - self.expected
+    }
+
+    /// The measurement passes only if its error is within the tolerance with
+    /// the requested confidence, i.e. if the whole confidence interval lies
+    /// inside of the allowed range: noisy trials make the verdict stricter,
+    /// not looser. With a single trial, this is just a check on the measured
+    /// value.
+    pub fn passed(&self) -> bool {
+        !self.samples.is_empty() && self.error().abs() + self.ci < self.allowed_error
+    }
+}
+
+/// Pass/fail verdict on a set of measurements.
+#[derive(Debug, Clone)]
+pub struct Verdict {
+    pub tolerance: Tolerance,
+    pub confidence: Confidence,
+    pub results: Vec<MeasurementResult>,
+}
+
+impl Verdict {
+    pub fn passed(&self) -> bool {
+        self.results.iter().all(|result| result.passed())
+    }
+
+    pub fn failed(&self) -> Vec<&MeasurementResult> {
+        self.results.iter()
+            .filter(|result| !result.passed())
+            .collect()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&MeasurementResult> {
+        self.results.iter().find(|result| result.name == name)
+    }
+
+    /// Attach the raw samples of each measurement to the current test.
+    pub fn batch_test_params(&self) {
+        for result in self.results.iter() {
+            batch_test_param(&format!("samples.{}", result.name), format!("{:?}", result.samples));
+        }
+    }
+}
+
+impl std::fmt::Display for Verdict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Tolerance {}, {}% confidence interval.", self.tolerance, self.confidence)?;
+        write!(f, "\n{:<20} {:>10} {:>10} {:>10} {:>10}  samples", "measurement", "expected", "mean", "ci", "allowed")?;
+
+        for result in self.results.iter() {
+            let samples = result.samples.iter()
+                .map(|sample| format!("{sample:.5}"))
+                .collect::<Vec<_>>().join(" ");
+
+            write!(f, "\n{:<20} {:>10.5} {:>10.5} {:>10.5} {:>10.5}  [{}]",
+                result.name, result.expected, result.mean, result.ci, result.allowed_error, samples)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(samples: &[f64]) -> MeasurementResult {
+        let measurement = Measurement::new("bw", 0.5).with_samples(samples.iter().copied());
+        MeasurementResult::new(measurement, Tolerance::absolute(0.01), Confidence::C95)
+    }
+
+    #[test]
+    fn confidence_interval_inside_tolerance() {
+        assert!(result(&[0.505]).passed());
+        assert!(!result(&[0.515]).passed());
+        assert!(!result(&[]).passed());
+
+        // same mean, tight and wide confidence intervals
+        assert!(result(&[0.504, 0.505, 0.506]).passed());
+        assert!(!result(&[0.490, 0.505, 0.520]).passed());
+    }
+}
@@ -1,4 +1,9 @@
-use std::{io::Write, time::Duration};
+use std::{collections::BTreeMap, io::Write, time::Duration};
+use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
+
+use hcbs_utils::prelude::CpuID;
+
+use crate::results::TestStatus;
 
 pub mod prelude {
     pub use super::{
@@ -11,6 +16,7 @@ pub mod prelude {
         create_ctrlc_handler,
         ExitFlag,
         batch_test_header,
+        batch_test_param,
         batch_test_result,
         batch_test_result_details,
         batch_test_result_skippable,
@@ -20,6 +26,10 @@ pub mod prelude {
         batch_test_skipped,
         batch_test_failure,
         get_fair_server_avg_bw,
+        get_servers_bw,
+        is_sched_ext_enabled,
+        setup_servers,
+        move_rt_tasks_to_root_cgroup,
         Skippable,
     };
 }
@@ -50,28 +60,50 @@ pub fn is_batch_test() -> bool {
     is_env_var_set("BATCH_TEST")
 }
 
+/// Exit request (e.g. Ctrl+C). Once raised, the flag stays raised for all of
+/// its clones.
 #[derive(Clone)]
 pub struct ExitFlag {
     ch: crossbeam::channel::Receiver<()>,
+    raised: Arc<AtomicBool>,
 }
 
 impl ExitFlag {
+    /// New flag, raised by sending on the returned channel.
+    pub fn channel() -> (crossbeam::channel::Sender<()>, Self) {
+        let (send, recv) = crossbeam::channel::bounded(1);
+
+        (send, Self { ch: recv, raised: Arc::new(AtomicBool::new(false)) })
+    }
+
     pub fn is_exit(&self) -> bool {
         use crossbeam::channel::TryRecvError::*;
 
+        if self.raised.load(Ordering::Relaxed) {
+            return true;
+        }
+
         match self.ch.try_recv() {
-            Ok(()) => true,
+            Ok(()) => { self.raise(); true },
             Err(Empty) => false,
             _ => panic!("unexpected"),
         }
     }
+
+    pub(crate) fn receiver(&self) -> &crossbeam::channel::Receiver<()> {
+        &self.ch
+    }
+
+    pub(crate) fn raise(&self) {
+        self.raised.store(true, Ordering::Relaxed);
+    }
 }
 
 pub fn create_ctrlc_handler() -> anyhow::Result<ExitFlag> {
-    let (send, recv) = crossbeam::channel::bounded(1);
+    let (send, flag) = ExitFlag::channel();
 
-    ctrlc::set_handler(move || { send.send(()).unwrap(); })?;
-    Ok(ExitFlag { ch: recv })
+    ctrlc::set_handler(move || { let _ = send.try_send(()); })?;
+    Ok(flag)
 }
 
 pub fn wait_loop(max_time: Option<u64>, ctrlc_flag: Option<ExitFlag>) -> anyhow::Result<()> {
@@ -80,6 +112,10 @@ pub fn wait_loop(max_time: Option<u64>, ctrlc_flag: Option<ExitFlag>) -> anyhow:
         None => create_ctrlc_handler()?,
     };
 
+    if exit.is_exit() {
+        return Ok(());
+    }
+
     let max_time_ch =
         match max_time {
             Some(max_time) => crossbeam::channel::after(Duration::from_secs(max_time)),
@@ -87,7 +123,7 @@ pub fn wait_loop(max_time: Option<u64>, ctrlc_flag: Option<ExitFlag>) -> anyhow:
         };
 
     crossbeam::channel::select! {
-        recv(exit.ch) -> _ => (),
+        recv(exit.ch) -> _ => exit.raise(),
         recv(max_time_ch) -> _ => (),
     };
 
@@ -110,9 +146,9 @@ pub fn wait_loop_periodic_fn<F>(period_secs: f32, max_time: Option<u64>, ctrlc_f
 
     let periodic_ch = crossbeam::channel::tick(Duration::from_secs_f32(period_secs));
 
-    loop {
+    while !exit.is_exit() {
         crossbeam::channel::select! {
-            recv(exit.ch) -> _ => { break; },
+            recv(exit.ch) -> _ => { exit.raise(); break; },
             recv(periodic_ch) -> _ => { fun()?; },
             recv(max_time_ch) -> _ => { break; },
         }
@@ -131,131 +167,203 @@ pub fn __shell(cmd: &str) -> anyhow::Result<std::process::Output> {
         .map_err(|err| anyhow::format_err!("Error in executing \"sh -c {cmd}\": {err}"))
 }
 
-pub fn batch_test_success() {
-    if is_env_var_set("TERM_COLORS") {
-        println!("\x1b[32mSuccess ✔\x1b[0m");
-    } else {
-        println!("Success ✔");
-    }
+/// Report the test's result. The `batch_test_*` reporters return the
+/// reported status, which is a failure if the kernel log monitor found events
+/// failing the test.
+pub fn batch_test_success() -> TestStatus {
+    crate::results::test_finished(TestStatus::Success, None, None)
 }
 
-pub fn batch_test_success_details<T: std::fmt::Display>(msg: T) {
-    batch_test_success();
-
-    if is_env_var_set("TERM_COLORS") {
-        println!("\x1b[32m    Details: \x1b[0m{msg}");
-    } else {
-        println!("    Details: {msg}");
-    }
+pub fn batch_test_success_details<T: std::fmt::Display>(msg: T) -> TestStatus {
+    crate::results::test_finished(TestStatus::Success, None, Some(format!("{msg}")))
 }
 
-pub fn batch_test_failure<E: std::fmt::Display>(err: E) {
-    if is_env_var_set("TERM_COLORS") {
-        println!("\x1b[31mFailure ✖\n    Reason: \x1b[0m{err}")
-    } else {
-        println!("Failure ✖\n    Reason: {err}")
-    }
+pub fn batch_test_failure<E: std::fmt::Display>(err: E) -> TestStatus {
+    crate::results::test_finished(TestStatus::Failure, Some(format!("{err}")), None)
 }
 
-pub fn batch_test_skipped<T: std::fmt::Display>(msg: T) {
-    if is_env_var_set("TERM_COLORS") {
-        println!("\x1b[33mSkipped ⛒\n    Reason: \x1b[0m{msg}");
-    } else {
-        println!("Skipped ⛒\n    Reason: {msg}");
-    }
+pub fn batch_test_skipped<T: std::fmt::Display>(msg: T) -> TestStatus {
+    crate::results::test_finished(TestStatus::Skipped, Some(format!("{msg}")), None)
 }
 
 pub fn batch_test_header(test_name: &str, test_category: &str) {
     match std::env::var("BATCH_TEST_CUSTOM_NAME") {
-        Ok(custom) if custom != "" => print!("[{}] {}: ", test_category, custom),
-        _ => print!("[{}] {}: ", test_category, test_name),
+        Ok(custom) if custom != "" => {
+            crate::results::test_started(test_category, &custom);
+            crate::results::test_parameter("header", test_name.to_owned());
+        },
+        _ => crate::results::test_started(test_category, test_name),
     };
-
-    std::io::stdout().flush().unwrap();
 }
 
-pub fn batch_test_result<T>(result: anyhow::Result<T>) -> anyhow::Result<()> {
-    match &result {
-        Ok(_) => batch_test_success(),
-        Err(err) => batch_test_failure(err),
-    };
+/// Attach a parameter to the test started by the last `batch_test_header`.
+/// Parameters are not shown in the human readable output, but are part of
+/// the structured records (JSON Lines, JUnit).
+pub fn batch_test_param<T: std::fmt::Display>(key: &str, value: T) {
+    crate::results::test_parameter(key, format!("{value}"));
+}
 
+/// Outcome of a reported test for its caller: always Ok in batch mode,
+/// otherwise the test's error, or the kernel log monitor's failure.
+fn batch_test_outcome<T>(result: anyhow::Result<T>, status: TestStatus) -> anyhow::Result<()> {
     if is_batch_test() {
-        Ok(())
-    } else {
-        result.map(|_| ())
+        return Ok(());
     }
+
+    result?;
+
+    if status == TestStatus::Failure {
+        anyhow::bail!("Kernel log events during the test");
+    }
+
+    Ok(())
+}
+
+pub fn batch_test_result<T>(result: anyhow::Result<T>) -> anyhow::Result<()> {
+    let status =
+        match &result {
+            Ok(_) => batch_test_success(),
+            Err(err) => batch_test_failure(err),
+        };
+
+    batch_test_outcome(result, status)
 }
 
 pub fn batch_test_result_skippable<T>(result: anyhow::Result<Skippable<T>>) -> anyhow::Result<()> {
-    match &result {
-        Ok(Skippable::Result(_)) => batch_test_success(),
-        Ok(Skippable::Skipped(err)) => batch_test_skipped(err),
-        Err(err) => batch_test_failure(err),
-    };
+    let status =
+        match &result {
+            Ok(Skippable::Result(_)) => batch_test_success(),
+            Ok(Skippable::Skipped(err)) => batch_test_skipped(err),
+            Err(err) => batch_test_failure(err),
+        };
 
-    if is_batch_test() {
-        Ok(())
-    } else {
-        result.map(|_| ())
-    }
+    batch_test_outcome(result, status)
 }
 
 pub fn batch_test_result_details<T: std::fmt::Display>(result: anyhow::Result<T>) -> anyhow::Result<()> {
-    match &result {
-        Ok(msg) => batch_test_success_details(msg),
-        Err(err) => batch_test_failure(err),
-    };
+    let status =
+        match &result {
+            Ok(msg) => batch_test_success_details(msg),
+            Err(err) => batch_test_failure(err),
+        };
 
-    if is_batch_test() {
-        Ok(())
-    } else {
-        result.map(|_| ())
-    }
+    batch_test_outcome(result, status)
 }
 
 pub fn batch_test_result_skippable_details<T: std::fmt::Display>(result: anyhow::Result<Skippable<T>>) -> anyhow::Result<()> {
-    match &result {
-        Ok(Skippable::Result(msg)) => batch_test_success_details(msg),
-        Ok(Skippable::Skipped(err)) => batch_test_skipped(err),
-        Err(err) => batch_test_failure(err),
-    };
+    let status =
+        match &result {
+            Ok(Skippable::Result(msg)) => batch_test_success_details(msg),
+            Ok(Skippable::Skipped(err)) => batch_test_skipped(err),
+            Err(err) => batch_test_failure(err),
+        };
 
-    if is_batch_test() {
-        Ok(())
+    batch_test_outcome(result, status)
+}
+
+fn servers_dir(ext_servers: bool) -> &'static str {
+    if ext_servers {
+        "/sys/kernel/debug/sched/ext_server"
     } else {
-        result.map(|_| ())
+        "/sys/kernel/debug/sched/fair_server"
     }
 }
 
-pub fn get_fair_server_avg_bw() -> anyhow::Result<f64> {
-    let mut avg_bw = 0f64;
-    let mut num_cpus = 0f64;
+/// Bandwidth of the fair server (or ext server) of each CPU.
+pub fn get_servers_bw(ext_servers: bool) -> anyhow::Result<BTreeMap<CpuID, f64>> {
+    let fs = crate::fs_backend::fs_backend();
+    let server_dir = servers_dir(ext_servers);
+    let mut servers_bw = BTreeMap::new();
 
-    for entry in std::fs::read_dir("/sys/kernel/debug/sched/fair_server")? {
-        let entry = entry?.path();
-        if entry.is_dir() {
-            let entry = entry.into_os_string().into_string().unwrap();
+    for entry in fs.read_dir(server_dir)? {
+        let Some(cpu) = entry.strip_prefix("cpu").and_then(|cpu| cpu.parse::<CpuID>().ok()) else { continue; };
 
+        let entry = format!("{server_dir}/{entry}");
+        if fs.is_dir(&entry) {
             let runtime: u64 =
-                std::fs::read_to_string(format!("{entry}/runtime"))
-                    .map_err(|err| anyhow::format_err!("Error in reading {entry}/runtime: {err}"))
+                fs.read_to_string(&format!("{entry}/runtime"))
                     .and_then(|value| value.trim().parse::<u64>()
                         .map_err(|err| anyhow::format_err!("Error in parsing {entry}/runtime: {err}"))
                     )?;
             let period: u64 =
-                std::fs::read_to_string(format!("{entry}/period"))
-                    .map_err(|err| anyhow::format_err!("Error in reading {entry}/period: {err}"))
+                fs.read_to_string(&format!("{entry}/period"))
                     .and_then(|value| value.trim().parse::<u64>()
                         .map_err(|err| anyhow::format_err!("Error in parsing {entry}/period: {err}"))
                     )?;
 
-            avg_bw += runtime as f64 / period as f64;
-            num_cpus += 1f64;
+            servers_bw.insert(cpu, runtime as f64 / period as f64);
         }
     }
 
-    Ok(avg_bw / num_cpus)
+    Ok(servers_bw)
+}
+
+pub fn get_fair_server_avg_bw() -> anyhow::Result<f64> {
+    let servers_bw = get_servers_bw(false)?;
+
+    Ok(servers_bw.values().sum::<f64>() / servers_bw.len() as f64)
+}
+
+/// Whether a sched_ext scheduler is loaded, in which case non real-time tasks
+/// are served by the ext servers.
+pub fn is_sched_ext_enabled() -> bool {
+    crate::fs_backend::fs_backend().read_to_string("/sys/kernel/sched_ext/state")
+        .is_ok_and(|state| state.trim() == "enabled")
+}
+
+/// Set the runtime and period of all the fair servers, or ext servers.
+pub fn setup_servers(runtime_us: u64, period_us: u64, ext_servers: bool) -> anyhow::Result<()> {
+    let fs = crate::fs_backend::fs_backend();
+    let runtime_ns = runtime_us * 1000;
+    let period_ns = period_us * 1000;
+
+    let server_dir = servers_dir(ext_servers);
+
+    for entry in fs.read_dir(server_dir)? {
+        let entry = format!("{server_dir}/{entry}");
+        if fs.is_dir(&entry) {
+            fs.write(&format!("{entry}/runtime"), "0")?;
+            fs.write(&format!("{entry}/period"), &format!("{period_ns}"))?;
+            fs.write(&format!("{entry}/runtime"), &format!("{runtime_ns}"))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Move all the real-time (and deadline) tasks outside of the root cgroup
+/// into it, returning the moved tasks.
+pub fn move_rt_tasks_to_root_cgroup() -> anyhow::Result<Vec<u32>> {
+    use crate::fs_backend::cgroup;
+
+    // check if cgroup filesystem is mounted
+    if !cgroup::exists(".") {
+        return Ok(Vec::new());
+    }
+
+    let mut moved = Vec::new();
+    for entry in crate::fs_backend::fs_backend().read_dir("/proc")? {
+        let Ok(pid) = entry.parse::<u32>() else { continue; };
+
+        // SCHED_OTHER, SCHED_BATCH, SCHED_IDLE
+        match cgroup::get_pid_policy_id(pid) {
+            Ok(0 | 3 | 5) => { continue; },
+            Ok(_) => (),
+            Err(err) => {
+                println!("Error getting policy for pid {pid}: {err}");
+                continue;
+            }
+        };
+
+        // the task may have already terminated
+        let Ok(current) = cgroup::get_pid_cgroup(pid) else { continue; };
+        if current == "." { continue; };
+
+        cgroup::assign_pid(".", pid)?;
+        moved.push(pid);
+    }
+
+    Ok(moved)
 }
 
 pub enum Skippable<T, E = anyhow::Error> {
@@ -270,4 +378,32 @@ impl<T, E> Into<Result<T, E>> for Skippable<T, E> {
             Skippable::Skipped(err) => Err(err),
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs_backend::{FsBackend, fake::FakeFs};
+
+    #[test]
+    fn servers_setup() {
+        let (_lock, fake) = FakeFs::new(2)
+            .with_ext_servers(0, 1_000_000_000)
+            .install_for_test();
+
+        assert_eq!(get_servers_bw(false).unwrap(), BTreeMap::from([(0, 0.05), (1, 0.05)]));
+        assert_eq!(get_fair_server_avg_bw().unwrap(), 0.05);
+
+        // the new period is shorter than the current runtime
+        setup_servers(10_000, 40_000, false).unwrap();
+        assert_eq!(get_servers_bw(false).unwrap(), BTreeMap::from([(0, 0.25), (1, 0.25)]));
+        assert_eq!(fake.read_to_string("/sys/kernel/debug/sched/fair_server/cpu1/period").unwrap(), "40000000");
+
+        setup_servers(30_000, 100_000, true).unwrap();
+        assert_eq!(get_servers_bw(true).unwrap(), BTreeMap::from([(0, 0.3), (1, 0.3)]));
+        assert_eq!(get_servers_bw(false).unwrap(), BTreeMap::from([(0, 0.25), (1, 0.25)]));
+
+        assert!(setup_servers(200_000, 100_000, false).is_err());
+        assert!(!is_sched_ext_enabled());
+    }
+}